@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use vortex_expr::VortexExpr;
+
+use vfts::bench::{
+    Filters, list_array, list_contains, select_buckets, synthetic_documents, synthetic_frequencies,
+};
+use vfts::common::Segmentation;
+use vfts::vortex::Strategy;
+use vfts::{Query, QueryMode, Tokenizer};
+
+fn tokenization(c: &mut Criterion) {
+    let documents = synthetic_documents(1000, 100_000, 200)
+        .unwrap()
+        .into_iter()
+        .map(|tokens| tokens.join(" "))
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("tokenization");
+    for (name, tokenizer) in [
+        ("default", Tokenizer::default()),
+        (
+            "stemmed",
+            Tokenizer {
+                stop_words: true,
+                stem: true,
+                ..Tokenizer::default()
+            },
+        ),
+        (
+            "edge_ngrams",
+            Tokenizer {
+                edge_ngrams: Some(4),
+                ..Tokenizer::default()
+            },
+        ),
+        (
+            "unicode",
+            Tokenizer {
+                segmentation: Segmentation::Unicode,
+                ..Tokenizer::default()
+            },
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for document in &documents {
+                    tokenizer.term_frequencies(document);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bucket_selection(c: &mut Criterion) {
+    let frequencies = synthetic_frequencies(&synthetic_documents(1000, 100_000, 200).unwrap());
+    let mut group = c.benchmark_group("select_buckets");
+    for strategy in [
+        Strategy::EqualFrequency,
+        Strategy::FrequencyTiered,
+        Strategy::Alphabetic,
+    ] {
+        group.bench_function(format!("{strategy:?}"), |b| {
+            b.iter_batched(
+                || frequencies.clone(),
+                |frequencies| select_buckets(strategy, frequencies, 256),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn filter_creation(c: &mut Criterion) {
+    let documents = synthetic_documents(1000, 100_000, 200).unwrap();
+    let frequencies = synthetic_frequencies(&documents);
+    let filters = Filters::new(&select_buckets(Strategy::EqualFrequency, frequencies, 256));
+    let mut group = c.benchmark_group("create_filter");
+    for token_count in [1, 4, 16] {
+        let tokens = documents[0]
+            .iter()
+            .take(token_count)
+            .cloned()
+            .collect::<HashSet<_>>();
+        for mode in [QueryMode::All, QueryMode::Any] {
+            let query = Query::new(tokens.clone(), mode);
+            group.bench_function(format!("{token_count} tokens ({mode:?})"), |b| {
+                b.iter(|| filters.create_filter(&query))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn list_contains_evaluation(c: &mut Criterion) {
+    let documents = synthetic_documents(8192, 100_000, 20).unwrap();
+    let array = list_array(&documents).unwrap();
+    let mut group = c.benchmark_group("list_contains");
+    // The most frequent token of a Zipf corpus is `a`, while a token outside of the vocabulary
+    // never matches.
+    for (name, token) in [("frequent", "a"), ("absent", "zzzzzz")] {
+        let expr = list_contains(token);
+        group.bench_function(name, |b| {
+            b.iter(|| expr.unchecked_evaluate(array.as_ref()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    tokenization,
+    bucket_selection,
+    filter_creation,
+    list_contains_evaluation
+);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, HashSet};
+
+use vortex_array::arrays::StructArray;
+use vortex_array::builders::{ArrayBuilderExt, builder_with_capacity};
+use vortex_array::validity::Validity;
+use vortex_array::{ArrayRef, IntoArray};
+use vortex_dtype::{DType, FieldName, Nullability, StructDType};
+use vortex_expr::ExprRef;
+
+use crate::common::{Corpus, Query, SyntheticDistribution, Tokenizer};
+use crate::vortex::create_filter;
+use crate::vortex_buckets::Strategy;
+use crate::vortex_list_expr::ListContainsExpr;
+
+///
+/// The name of the list column of the arrays created by `list_array`.
+///
+const LIST_COLUMN: &str = "bucket";
+
+///
+/// Returns the tokenized documents of a reproducible Zipf-distributed corpus, for benchmarks of
+/// the functions which consume them.
+///
+pub fn synthetic_documents(
+    doc_count: usize,
+    vocab: usize,
+    doc_len: usize,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let corpus = Corpus::Synthetic {
+        distribution: SyntheticDistribution::Zipf,
+        vocab,
+        doc_len,
+        seed: 42,
+    };
+    let tokenizer = Tokenizer::default();
+    corpus
+        .texts(doc_count)?
+        .map(|text| Ok(tokenizer.tokens(&text?.body)))
+        .collect()
+}
+
+///
+/// Counts the number of documents containing each token of the given documents.
+///
+pub fn synthetic_frequencies(documents: &[Vec<String>]) -> BTreeMap<String, usize> {
+    let mut frequencies = BTreeMap::new();
+    for tokens in documents {
+        for token in tokens.iter().collect::<HashSet<_>>() {
+            *frequencies.entry(token.clone()).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+///
+/// Selects buckets with the given strategy, and returns their column names.
+///
+pub fn select_buckets(
+    strategy: Strategy,
+    document_frequencies: BTreeMap<String, usize>,
+    bucket_count: u16,
+) -> Vec<String> {
+    strategy
+        .select_buckets(document_frequencies, bucket_count, &[])
+        .into_iter()
+        .map(|(token, btype)| btype.column_name(&token))
+        .collect()
+}
+
+///
+/// The state which `VortexSearcher` shares between the filters of its queries, for an index
+/// with the given bucket columns.
+///
+pub struct Filters {
+    bucket_columns: Vec<FieldName>,
+    bucket_exprs: Vec<ExprRef>,
+    bucket_ranks: Vec<u64>,
+}
+
+impl Filters {
+    pub fn new(bucket_columns: &[String]) -> Self {
+        let bucket_columns = bucket_columns
+            .iter()
+            .map(|column| FieldName::from(column.as_str()))
+            .collect::<Vec<_>>();
+        let bucket_exprs = bucket_columns
+            .iter()
+            .map(|column| vortex_expr::get_item(column.clone(), vortex_expr::ident()))
+            .collect();
+        let bucket_ranks = vec![0; bucket_columns.len()];
+        Filters {
+            bucket_columns,
+            bucket_exprs,
+            bucket_ranks,
+        }
+    }
+
+    pub fn create_filter(&self, query: &Query) -> ExprRef {
+        create_filter(
+            &self.bucket_columns,
+            &self.bucket_exprs,
+            query,
+            None,
+            &self.bucket_ranks,
+            vortex_expr::lit(false),
+        )
+    }
+}
+
+///
+/// Creates a struct array with a single Multi bucket column containing the given lists of
+/// tokens.
+///
+pub fn list_array(documents: &[Vec<String>]) -> anyhow::Result<ArrayRef> {
+    let dtype = DType::List(
+        DType::Utf8(Nullability::NonNullable).into(),
+        Nullability::NonNullable,
+    );
+    let mut builder = builder_with_capacity(&dtype, documents.len());
+    for tokens in documents {
+        builder.append_scalar(&tokens.clone().into())?;
+    }
+    let struct_dtype = StructDType::new([FieldName::from(LIST_COLUMN)].into(), vec![dtype]);
+    Ok(StructArray::try_new_with_dtype(
+        vec![builder.finish()],
+        struct_dtype.into(),
+        documents.len(),
+        Validity::NonNullable,
+    )?
+    .into_array())
+}
+
+///
+/// Creates the expression which checks whether the list column of a `list_array` contains the
+/// given token.
+///
+pub fn list_contains(token: &str) -> ExprRef {
+    ListContainsExpr::new_expr(
+        vortex_expr::get_item(LIST_COLUMN, vortex_expr::ident()),
+        token.into(),
+    )
+}
@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use lru::LruCache;
+use roaring::RoaringTreemap;
+
+use crate::common::{Near, Pattern, Query, QueryExpr, QueryMode, Tokenizer};
+use crate::{QueryTiming, Searcher};
+
+///
+/// A `Query` in a canonical form, so that queries which differ only in the order of their
+/// tokens share a cache entry.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CacheKey {
+    tokens: Vec<String>,
+    excluded: Vec<String>,
+    patterns: Vec<Pattern>,
+    excluded_patterns: Vec<Pattern>,
+    mode: QueryMode,
+    timestamps: Option<RangeInclusive<u64>>,
+    near: Option<Near>,
+    fuzzy: u8,
+    expr: Option<QueryExpr>,
+}
+
+impl CacheKey {
+    fn new(query: &Query) -> Self {
+        let sorted = |tokens: &HashSet<String>| {
+            let mut tokens = tokens.iter().cloned().collect::<Vec<_>>();
+            tokens.sort_unstable();
+            tokens
+        };
+        let sorted_patterns = |patterns: &[Pattern]| {
+            let mut patterns = patterns.to_vec();
+            patterns.sort_unstable();
+            patterns.dedup();
+            patterns
+        };
+        CacheKey {
+            tokens: sorted(&query.tokens),
+            excluded: sorted(&query.excluded),
+            patterns: sorted_patterns(&query.patterns),
+            excluded_patterns: sorted_patterns(&query.excluded_patterns),
+            mode: query.mode,
+            timestamps: query.timestamps.clone(),
+            near: query.near.clone(),
+            fuzzy: query.fuzzy,
+            expr: query.expr.clone(),
+        }
+    }
+}
+
+///
+/// The cached result of a query: either just its number of matches, or the IDs of all of them
+/// (from which the number can also be served).
+///
+#[derive(Clone, Debug)]
+enum Cached {
+    Count(usize),
+    Ids(RoaringTreemap),
+}
+
+///
+/// Wraps a `Searcher` with an LRU cache of the results of its most recent `count` and `ids`
+/// queries, which models workloads in which the same queries are repeated. Other operations are
+/// passed through. A cache with a capacity of zero caches nothing.
+///
+pub struct CachingSearcher<S> {
+    searcher: S,
+    cache: Option<Mutex<LruCache<CacheKey, Cached>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<S: Searcher> CachingSearcher<S> {
+    pub fn new(searcher: S, capacity: usize) -> Self {
+        CachingSearcher {
+            searcher,
+            cache: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    ///
+    /// Returns the number of lookups which were served from the cache, and the number which
+    /// were not.
+    ///
+    pub fn hits_and_misses(&self) -> (usize, usize) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn reset_hits_and_misses(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    ///
+    /// Looks up the key with `f`, and records whether it was a hit.
+    ///
+    fn lookup<T>(&self, key: &CacheKey, f: impl FnOnce(&Cached) -> Option<T>) -> Option<T> {
+        let cache = self.cache.as_ref()?;
+        let found = cache.lock().unwrap().get(key).and_then(f);
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    fn insert(&self, key: CacheKey, cached: Cached) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().put(key, cached);
+        }
+    }
+}
+
+impl<S: Searcher> Searcher for CachingSearcher<S> {
+    ///
+    /// Opens the underlying searcher without a cache: use `CachingSearcher::new` to choose a
+    /// capacity.
+    ///
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        Ok(CachingSearcher::new(S::open(path).await?, 0))
+    }
+
+    fn check_tokenizer(&self, tokenizer: &Tokenizer) -> anyhow::Result<()> {
+        self.searcher.check_tokenizer(tokenizer)
+    }
+
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        self.searcher.check_query(query)
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        Ok(self.count_with_setup(query).await?.0)
+    }
+
+    ///
+    /// A cached count has no setup, since nothing is executed.
+    ///
+    async fn count_with_setup(&self, query: &Query) -> anyhow::Result<(usize, Duration)> {
+        let key = CacheKey::new(query);
+        let cached = self.lookup(&key, |cached| match cached {
+            Cached::Count(count) => Some(*count),
+            Cached::Ids(ids) => Some(ids.len() as usize),
+        });
+        if let Some(count) = cached {
+            return Ok((count, Duration::ZERO));
+        }
+        let (count, setup) = self.searcher.count_with_setup(query).await?;
+        self.insert(key, Cached::Count(count));
+        Ok((count, setup))
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let key = CacheKey::new(query);
+        let cached = self.lookup(&key, |cached| match cached {
+            Cached::Count(_) => None,
+            Cached::Ids(ids) => Some(ids.iter().collect()),
+        });
+        if let Some(ids) = cached {
+            return Ok(ids);
+        }
+        let ids = self.searcher.ids(query).await?;
+        self.insert(key, Cached::Ids(ids.iter().copied().collect()));
+        Ok(ids)
+    }
+
+    async fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        self.searcher.snippets(query, tokenizer, limit).await
+    }
+
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        self.searcher.facet_counts(query, field).await
+    }
+
+    ///
+    /// Delegates to the underlying searcher's implementation when the cache is disabled, so
+    /// that any specialized implementation is still used.
+    ///
+    async fn count_concurrently(
+        &self,
+        queries: Vec<Query>,
+        concurrency: usize,
+    ) -> anyhow::Result<Vec<QueryTiming>> {
+        if self.cache.is_none() {
+            return self.searcher.count_concurrently(queries, concurrency).await;
+        }
+        crate::count_concurrently(self, queries, concurrency).await
+    }
+}
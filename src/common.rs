@@ -1,22 +1,2039 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
 
-pub type Document = (u64, HashSet<String>);
+use anyhow::{Context, anyhow, bail};
+use arrow::array::{AsArray, RecordBatch};
+use arrow::datatypes::{DataType, UInt64Type};
+use arrow::ipc::reader::StreamReader;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Zipf};
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
 
-pub fn tokenize(document: &str) -> HashSet<String> {
-    document
-        .split_whitespace()
-        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
-        .filter(|word| !word.is_empty())
-        .map(|word| word.to_lowercase())
+use crate::warc::warc_texts;
+use crate::wiki::{TITLE_FIELD, wiki_texts};
+
+///
+/// A document ID, and the number of times that each distinct token occurs in the document.
+///
+pub type Document = (u64, HashMap<String, u32>);
+
+///
+/// The original text of a document from a `Corpus`: its body, and the text of any other named
+/// fields (such as a `title` or `tags`).
+///
+#[derive(Clone, Debug, Default)]
+pub struct Text {
+    pub id: u64,
+    /// The external string ID of the document, if it has one. Its `id` is then the `string_id` of
+    /// the key.
+    pub key: Option<String>,
+    pub body: String,
+    pub fields: Vec<(String, String)>,
+    pub timestamp: Option<u64>,
+}
+
+///
+/// Derives the numeric ID of a document from its external string ID. The hash is stable across
+/// runs and versions, so the document can always be found (or deleted) by its key. Distinct keys
+/// are assumed not to collide.
+///
+pub fn string_id(key: &str) -> u64 {
+    crate::vortex_buckets::fnv1a(key)
+}
+
+/// The name of the default field, whose tokens are not qualified with a field name.
+pub const BODY_FIELD: &str = "body";
+
+/// The prefix of a query word which filters by timestamp, as in `ts:[100..200]`.
+const TIMESTAMP_PREFIX: &str = "ts:";
+
+/// Separates a field name from a token of that field, as in `title:henry`.
+pub const FIELD_SEPARATOR: char = ':';
+
+///
+/// Qualifies a token of the named field with the name of the field.
+///
+pub fn field_token(field: &str, token: &str) -> String {
+    format!("{field}{FIELD_SEPARATOR}{token}")
+}
+
+///
+/// Splits a field-qualified token (or query word) into its field name and the remainder.
+///
+pub fn split_field(token: &str) -> Option<(&str, &str)> {
+    let (field, token) = token.split_once(FIELD_SEPARATOR)?;
+    (is_field_name(field) && !token.is_empty()).then_some((field, token))
+}
+
+///
+/// Field names consist of lowercase ASCII letters, digits and underscores, and do not begin
+/// with a digit, so that words like `10:30` are not mistaken for field-scoped query words.
+///
+pub fn is_field_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+///
+/// How the tokens of a query are combined: `All` requires every token to match (a conjunction),
+/// while `Any` requires at least one (a disjunction).
+///
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    Any,
+    #[default]
+    All,
+}
+
+///
+/// A set of tokens to search for and how they should be combined, plus a set of tokens which
+/// must not appear in matching documents, and an optional (inclusive) range of timestamps which
+/// matching documents must fall within.
+///
+/// A query may also require its tokens to occur near one another, which requires an index built
+/// with token positions.
+///
+/// A fuzzy query matches each of its tokens against the indexed tokens which are within `fuzzy`
+/// edits (insertions, deletions, substitutions or transpositions) of it. Excluded tokens are
+/// always matched exactly.
+///
+/// A query may also contain `Pattern`s, each of which matches any of the indexed tokens which
+/// match it, and which are combined with its tokens according to its mode.
+///
+/// A query which cannot be represented by these fields (such as one which nests `AND` within
+/// `OR`) is instead represented by an `expr`, and its other fields are empty.
+///
+#[derive(Clone, Debug)]
+pub struct Query {
+    pub tokens: HashSet<String>,
+    pub excluded: HashSet<String>,
+    pub patterns: Vec<Pattern>,
+    pub excluded_patterns: Vec<Pattern>,
+    pub mode: QueryMode,
+    pub timestamps: Option<RangeInclusive<u64>>,
+    pub near: Option<Near>,
+    pub fuzzy: u8,
+    pub expr: Option<QueryExpr>,
+}
+
+impl Query {
+    pub fn new(tokens: HashSet<String>, mode: QueryMode) -> Self {
+        Query {
+            tokens,
+            excluded: HashSet::new(),
+            patterns: Vec::new(),
+            excluded_patterns: Vec::new(),
+            mode,
+            timestamps: None,
+            near: None,
+            fuzzy: 0,
+            expr: None,
+        }
+    }
+
+    ///
+    /// Whether a document with the given tokens (as returned by `text_term_frequencies`) and
+    /// timestamp matches the query, evaluated directly rather than with an index. Proximity, fuzzy
+    /// and pattern queries must be rejected by `check_extensions` first.
+    ///
+    pub fn matches_document(&self, tokens: &HashMap<String, u32>, timestamp: Option<u64>) -> bool {
+        if let Some(expr) = &self.expr {
+            return expr.matches_document(tokens, timestamp);
+        }
+        // NB: As in the indexes, a query without any tokens matches nothing.
+        let matched = match self.mode {
+            QueryMode::All => {
+                !self.tokens.is_empty() && self.tokens.iter().all(|t| tokens.contains_key(t))
+            }
+            QueryMode::Any => self.tokens.iter().any(|token| tokens.contains_key(token)),
+        };
+        matched
+            && !self.excluded.iter().any(|token| tokens.contains_key(token))
+            && self
+                .timestamps
+                .as_ref()
+                .is_none_or(|range| timestamp.is_some_and(|timestamp| range.contains(&timestamp)))
+    }
+
+    ///
+    /// Additionally requires matching documents to contain the tokens of `near` near one another.
+    /// The tokens are added to the query's tokens, all of which must match (or for a query with
+    /// an `expr`, the proximity query is ANDed with it).
+    ///
+    pub fn with_near(mut self, near: Near) -> anyhow::Result<Self> {
+        if let Some(expr) = self.expr.take() {
+            self.expr = QueryExpr::and(vec![expr, QueryExpr::Phrase(near)]);
+            return Ok(self);
+        }
+        if self.mode == QueryMode::Any && (!self.tokens.is_empty() || !self.patterns.is_empty()) {
+            bail!("A proximity query cannot be combined with a query in `Any` mode.");
+        }
+        self.mode = QueryMode::All;
+        self.tokens.extend(near.tokens.iter().cloned());
+        self.near = Some(near);
+        Ok(self)
+    }
+
+    ///
+    /// Parses the given query text, and tokenizes its words with the `Tokenizer` that documents
+    /// were tokenized with. See `QueryExpr::parse` for the syntax.
+    ///
+    /// A query which is a conjunction or disjunction of tokens and patterns (with any number of
+    /// excluded tokens and patterns, timestamp ranges and a proximity query ANDed to it) is
+    /// represented by the fields of the `Query`, which every backend can evaluate. Any other
+    /// query is represented by its `expr`.
+    ///
+    pub fn parse(query: &str, mode: QueryMode, tokenizer: &Tokenizer) -> Self {
+        let expr = QueryExpr::parse(query, mode, tokenizer);
+        let mut lowered = Query::new(HashSet::new(), mode);
+        match expr {
+            Some(expr) if !lowered.lower(&expr) => Query {
+                expr: Some(expr),
+                ..Query::new(HashSet::new(), mode)
+            },
+            _ => lowered,
+        }
+    }
+
+    ///
+    /// Lowers the expression into the fields of the query, and returns false if it cannot be
+    /// (in which case the fields may have been partially populated).
+    ///
+    fn lower(&mut self, expr: &QueryExpr) -> bool {
+        let conjuncts = match expr {
+            QueryExpr::And(clauses) => clauses.as_slice(),
+            expr => std::slice::from_ref(expr),
+        };
+        let mut positive = Vec::new();
+        for conjunct in conjuncts {
+            match conjunct {
+                QueryExpr::Not(negated) => match &**negated {
+                    QueryExpr::Token(token) => {
+                        self.excluded.insert(token.clone());
+                    }
+                    QueryExpr::Pattern(pattern) => self.excluded_patterns.push(pattern.clone()),
+                    _ => return false,
+                },
+                QueryExpr::Timestamps(range) => {
+                    // Multiple ranges are intersected.
+                    self.timestamps = Some(match &self.timestamps {
+                        Some(existing) => {
+                            *existing.start().max(range.start())..=*existing.end().min(range.end())
+                        }
+                        None => range.clone(),
+                    });
+                }
+                QueryExpr::Phrase(near) if self.near.is_none() => self.near = Some(near.clone()),
+                conjunct => positive.push(conjunct),
+            }
+        }
+        let terms = match positive.as_slice() {
+            [QueryExpr::Or(terms)] if self.near.is_none() => {
+                self.mode = QueryMode::Any;
+                terms.iter().collect()
+            }
+            [_] => positive.clone(),
+            _ => {
+                self.mode = QueryMode::All;
+                positive.clone()
+            }
+        };
+        if let Some(near) = &self.near {
+            self.mode = QueryMode::All;
+            self.tokens.extend(near.tokens.iter().cloned());
+        }
+        for term in terms {
+            match term {
+                QueryExpr::Token(token) => {
+                    self.tokens.insert(token.clone());
+                }
+                QueryExpr::Pattern(pattern) => self.patterns.push(pattern.clone()),
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    ///
+    /// Returns the tokens which the query searches for, including those within its `expr`.
+    ///
+    pub fn all_tokens(&self) -> impl Iterator<Item = &String> {
+        self.tokens
+            .iter()
+            .chain(self.expr.iter().flat_map(QueryExpr::tokens))
+    }
+}
+
+///
+/// A parsed query, which each backend lowers to its own representation, so that the semantics of
+/// a query are identical across them. `Not` matches every document which does not match its
+/// operand.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum QueryExpr {
+    Token(String),
+    Pattern(Pattern),
+    Phrase(Near),
+    Timestamps(RangeInclusive<u64>),
+    And(Vec<QueryExpr>),
+    Or(Vec<QueryExpr>),
+    Not(Box<QueryExpr>),
+}
+
+impl QueryExpr {
+    ///
+    /// Parses query text, in which:
+    ///
+    /// * `AND` and `OR` combine the terms on either side of them, with `AND` binding more
+    ///   tightly. Terms without an operator between them are combined according to `mode`,
+    ///   except that excluded terms and timestamp ranges always restrict the matches.
+    /// * `NOT` or a `-` prefix excludes a term, and parentheses group terms.
+    /// * `"a b"~N` is a proximity query (or an exact phrase, without `~N`).
+    /// * A field name prefix (as in `title:henry` or `title:(henry OR richard)`) matches only
+    ///   tokens of that field.
+    /// * A `*` suffix (as in `hen*`) matches a prefix, and regular expressions (as in `/hen.*/`)
+    ///   and wildcards (as in `h?n*y`) are `Pattern`s.
+    /// * `ts:[100..200]` restricts matches to documents with timestamps in the given inclusive
+    ///   range, either end of which may be omitted.
+    ///
+    /// Parsing is lenient: unbalanced parentheses and quotes are tolerated. Returns `None` for a
+    /// query without any terms.
+    ///
+    pub fn parse(text: &str, mode: QueryMode, tokenizer: &Tokenizer) -> Option<Self> {
+        let mut parser = Parser {
+            lexemes: lex(text).into_iter().peekable(),
+            mode,
+            tokenizer,
+        };
+        parser.or(None)
+    }
+
+    ///
+    /// Combines the clauses into a conjunction, which is flattened into any conjunctions among
+    /// them. Returns `None` if there are no clauses.
+    ///
+    pub fn and(clauses: Vec<QueryExpr>) -> Option<Self> {
+        let mut flattened = Vec::with_capacity(clauses.len());
+        for clause in clauses {
+            match clause {
+                QueryExpr::And(clauses) => flattened.extend(clauses),
+                clause => flattened.push(clause),
+            }
+        }
+        match flattened.len() {
+            0 | 1 => flattened.pop(),
+            _ => Some(QueryExpr::And(flattened)),
+        }
+    }
+
+    ///
+    /// Combines the clauses into a disjunction, which is flattened into any disjunctions among
+    /// them. Returns `None` if there are no clauses.
+    ///
+    pub fn or(clauses: Vec<QueryExpr>) -> Option<Self> {
+        let mut flattened = Vec::with_capacity(clauses.len());
+        for clause in clauses {
+            match clause {
+                QueryExpr::Or(clauses) => flattened.extend(clauses),
+                clause => flattened.push(clause),
+            }
+        }
+        match flattened.len() {
+            0 | 1 => flattened.pop(),
+            _ => Some(QueryExpr::Or(flattened)),
+        }
+    }
+
+    ///
+    /// Returns the terms of the expression: its tokens, patterns, phrases and timestamp ranges.
+    ///
+    pub fn leaves(&self) -> Vec<&QueryExpr> {
+        match self {
+            QueryExpr::And(clauses) | QueryExpr::Or(clauses) => {
+                clauses.iter().flat_map(QueryExpr::leaves).collect()
+            }
+            QueryExpr::Not(clause) => clause.leaves(),
+            leaf => vec![leaf],
+        }
+    }
+
+    ///
+    /// Whether a document with the given tokens and timestamp matches the expression: see
+    /// `Query::matches_document`.
+    ///
+    pub fn matches_document(&self, tokens: &HashMap<String, u32>, timestamp: Option<u64>) -> bool {
+        match self {
+            QueryExpr::Token(token) => tokens.contains_key(token),
+            QueryExpr::Timestamps(range) => {
+                timestamp.is_some_and(|timestamp| range.contains(&timestamp))
+            }
+            QueryExpr::And(clauses) => clauses
+                .iter()
+                .all(|clause| clause.matches_document(tokens, timestamp)),
+            QueryExpr::Or(clauses) => clauses
+                .iter()
+                .any(|clause| clause.matches_document(tokens, timestamp)),
+            QueryExpr::Not(clause) => !clause.matches_document(tokens, timestamp),
+            QueryExpr::Phrase(_) | QueryExpr::Pattern(_) => false,
+        }
+    }
+
+    ///
+    /// Returns the tokens of the expression, including those of its phrases.
+    ///
+    pub fn tokens(&self) -> impl Iterator<Item = &String> {
+        self.leaves().into_iter().flat_map(|leaf| match leaf {
+            QueryExpr::Token(token) => std::slice::from_ref(token),
+            QueryExpr::Phrase(near) => near.tokens.as_slice(),
+            _ => &[],
+        })
+    }
+}
+
+///
+/// The lexical elements of query text.
+///
+#[derive(Debug, PartialEq)]
+enum Lexeme<'a> {
+    Open,
+    Close,
+    /// The text of a phrase (without its quotes), and its slop.
+    Phrase(&'a str, u32),
+    Word(&'a str),
+}
+
+///
+/// Splits query text into lexemes. Closing parentheses without a matching opening parenthesis
+/// are dropped, and an unterminated phrase extends to the end of the text. Parentheses within a
+/// regular expression (as in `/a(b|c)/`) are part of its word.
+///
+fn lex(text: &str) -> Vec<Lexeme<'_>> {
+    let mut lexemes = Vec::new();
+    let mut depth = 0_usize;
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '(' => {
+                depth += 1;
+                lexemes.push(Lexeme::Open);
+                1
+            }
+            ')' => {
+                if depth > 0 {
+                    depth -= 1;
+                    lexemes.push(Lexeme::Close);
+                }
+                1
+            }
+            '"' => {
+                let (phrase, mut len) = match rest[1..].find('"') {
+                    Some(end) => (&rest[1..end + 1], end + 2),
+                    None => (&rest[1..], rest.len()),
+                };
+                let mut slop = 0;
+                if let Some(digits) = rest[len..].strip_prefix('~') {
+                    let digits = &digits[..digits
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(digits.len())];
+                    if !digits.is_empty() {
+                        slop = digits.parse().unwrap_or(u32::MAX);
+                        len += 1 + digits.len();
+                    }
+                }
+                lexemes.push(Lexeme::Phrase(phrase, slop));
+                len
+            }
+            _ => {
+                let mut in_regex = false;
+                let len = rest
+                    .char_indices()
+                    .find(|&(idx, c)| {
+                        let word = &rest[..idx];
+                        if c == '/'
+                            && (in_regex
+                                || word.trim_start_matches('-').is_empty()
+                                || word.ends_with(FIELD_SEPARATOR))
+                        {
+                            in_regex = !in_regex;
+                        }
+                        !in_regex && (c.is_whitespace() || c == '(' || c == ')')
+                    })
+                    .map_or(rest.len(), |(idx, _)| idx);
+                lexemes.push(Lexeme::Word(&rest[..len]));
+                len
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    lexemes
+}
+
+///
+/// A recursive descent parser for `QueryExpr::parse`. Each method parses one level of the
+/// grammar within the given field (or the body), and returns `None` if it found no terms.
+///
+struct Parser<'a> {
+    lexemes: std::iter::Peekable<std::vec::IntoIter<Lexeme<'a>>>,
+    mode: QueryMode,
+    tokenizer: &'a Tokenizer,
+}
+
+impl<'a> Parser<'a> {
+    fn or(&mut self, field: Option<&'a str>) -> Option<QueryExpr> {
+        let mut clauses = Vec::new();
+        loop {
+            clauses.extend(self.and(field));
+            if self.lexemes.next_if_eq(&Lexeme::Word("OR")).is_none() {
+                return QueryExpr::or(clauses);
+            }
+        }
+    }
+
+    fn and(&mut self, field: Option<&'a str>) -> Option<QueryExpr> {
+        let mut clauses = Vec::new();
+        loop {
+            clauses.extend(self.sequence(field));
+            if self.lexemes.next_if_eq(&Lexeme::Word("AND")).is_none() {
+                return QueryExpr::and(clauses);
+            }
+        }
+    }
+
+    ///
+    /// Parses terms without operators between them: the excluded terms and timestamp ranges are
+    /// ANDed with the other terms, which are combined according to the mode.
+    ///
+    fn sequence(&mut self, field: Option<&'a str>) -> Option<QueryExpr> {
+        let mut terms = Vec::new();
+        let mut restrictions = Vec::new();
+        while let Some(lexeme) = self.lexemes.peek() {
+            if matches!(lexeme, Lexeme::Close | Lexeme::Word("AND" | "OR")) {
+                break;
+            }
+            match self.unary(field) {
+                Some(restriction @ (QueryExpr::Not(_) | QueryExpr::Timestamps(_))) => {
+                    restrictions.push(restriction)
+                }
+                Some(term) => terms.push(term),
+                None => {}
+            }
+        }
+        let terms = self.combine(terms);
+        QueryExpr::and(terms.into_iter().chain(restrictions).collect())
+    }
+
+    fn unary(&mut self, field: Option<&'a str>) -> Option<QueryExpr> {
+        match self.lexemes.next()? {
+            Lexeme::Word("NOT" | "-") => Some(QueryExpr::Not(Box::new(self.unary(field)?))),
+            Lexeme::Open => self.group(field),
+            Lexeme::Close => None,
+            // NB: Phrases always match the body.
+            Lexeme::Phrase(phrase, slop) => {
+                let mut tokens = self.tokenizer.tokens(phrase);
+                match tokens.len() {
+                    0 | 1 => tokens.pop().map(QueryExpr::Token),
+                    _ => Some(QueryExpr::Phrase(Near { tokens, slop })),
+                }
+            }
+            Lexeme::Word(word) => self.word(word, field),
+        }
+    }
+
+    ///
+    /// Parses the remainder of a parenthesized group, whose opening parenthesis has been consumed.
+    ///
+    fn group(&mut self, field: Option<&'a str>) -> Option<QueryExpr> {
+        let expr = self.or(field);
+        self.lexemes.next_if_eq(&Lexeme::Close);
+        expr
+    }
+
+    fn word(&mut self, word: &'a str, field: Option<&'a str>) -> Option<QueryExpr> {
+        if let Some(word) = word.strip_prefix('-') {
+            return Some(QueryExpr::Not(Box::new(self.word(word, field)?)));
+        }
+        if let Some(range) = parse_timestamps(word) {
+            return Some(QueryExpr::Timestamps(range));
+        }
+        let (field, word) = match split_field(word) {
+            Some((BODY_FIELD, word)) => (None, word),
+            Some((field, word)) => (Some(field), word),
+            None => match word.strip_suffix(FIELD_SEPARATOR) {
+                Some(name)
+                    if is_field_name(name) && self.lexemes.next_if_eq(&Lexeme::Open).is_some() =>
+                {
+                    return self.group((name != BODY_FIELD).then_some(name));
+                }
+                _ => (field, word),
+            },
+        };
+        if let Some(pattern) = Pattern::parse(field, word, self.tokenizer) {
+            return Some(QueryExpr::Pattern(pattern));
+        }
+        let tokens = match word.strip_suffix(PREFIX_MARKER) {
+            Some(prefix) => self.tokenizer.prefix(prefix).into_iter().collect(),
+            None => self.tokenizer.tokens(word),
+        };
+        self.combine(
+            tokens
+                .into_iter()
+                .map(|token| {
+                    QueryExpr::Token(match field {
+                        Some(field) => field_token(field, &token),
+                        None => token,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    ///
+    /// Combines terms according to the mode.
+    ///
+    fn combine(&self, terms: Vec<QueryExpr>) -> Option<QueryExpr> {
+        match self.mode {
+            QueryMode::All => QueryExpr::and(terms),
+            QueryMode::Any => QueryExpr::or(terms),
+        }
+    }
+}
+
+///
+/// A term which matches the indexed tokens of a field which match a regular expression, written
+/// as `/hen.*/`, or as a wildcard like `h?n*y` (in which `?` matches any one character, and `*`
+/// any number of them). The expression must match a whole token, and is matched against tokens
+/// as they were indexed: normalized (like a word of the query), and possibly stemmed.
+///
+/// Since a trailing `*` marks a prefix (and a trailing `?` is usually punctuation), a wildcard
+/// must contain a `?` or a `*` which is followed by another character.
+///
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Pattern {
+    /// The named field whose tokens the pattern matches, or `None` for the body.
+    pub field: Option<String>,
+    pub regex: String,
+}
+
+impl Pattern {
+    fn parse(field: Option<&str>, word: &str, tokenizer: &Tokenizer) -> Option<Self> {
+        let regex = if let Some(regex) = word
+            .strip_prefix('/')
+            .and_then(|word| word.strip_suffix('/'))
+            .filter(|regex| !regex.is_empty())
+        {
+            tokenizer.normalize(regex)
+        } else if word.trim_end_matches(['?', '*']).contains(['?', '*']) {
+            tokenizer
+                .normalize(word)
+                .split_inclusive(['?', '*'])
+                .map(|part| match part.strip_suffix('?') {
+                    Some(literal) => format!("{}.", regex::escape(literal)),
+                    None => match part.strip_suffix('*') {
+                        Some(literal) => format!("{}.*", regex::escape(literal)),
+                        None => regex::escape(part),
+                    },
+                })
+                .collect()
+        } else {
+            return None;
+        };
+        Some(Pattern {
+            field: field.map(str::to_owned),
+            regex,
+        })
+    }
+
+    ///
+    /// Compiles the pattern into an expression which must match the whole of a token.
+    ///
+    pub fn compile(&self) -> anyhow::Result<regex::Regex> {
+        regex::Regex::new(&format!("^(?:{})$", self.regex))
+            .with_context(|| format!("Invalid pattern {:?}", self.regex))
+    }
+}
+
+///
+/// A proximity query, written as `"a b c"~N`: each of its tokens must follow the previous one
+/// with at most `slop` other tokens between them, as for a Tantivy `PhraseQuery` with a slop.
+/// A slop of zero (or omitting `~N`) matches the exact phrase.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Near {
+    pub tokens: Vec<String>,
+    pub slop: u32,
+}
+
+impl Near {
+    ///
+    /// Parses and tokenizes a phrase like `"a b"~3`. The phrase must contain at least two tokens.
+    ///
+    pub fn parse(text: &str, tokenizer: &Tokenizer) -> anyhow::Result<Self> {
+        let (phrase, slop) = match text.rsplit_once('~') {
+            Some((phrase, slop)) => (
+                phrase,
+                slop.parse()
+                    .with_context(|| format!("Invalid slop in proximity query {text:?}"))?,
+            ),
+            None => (text, 0),
+        };
+        let tokens = tokenizer.tokens(phrase.trim().trim_matches('"'));
+        if tokens.len() < 2 {
+            bail!("A proximity query must contain at least two tokens: {text:?}");
+        }
+        Ok(Near { tokens, slop })
+    }
+
+    ///
+    /// Returns true if there is a chain of the given positions (one list per token, in the order
+    /// of `tokens`) in which each position follows the previous one by at most `slop + 1`.
+    ///
+    pub fn matches<'a>(&self, mut positions: impl Iterator<Item = &'a [u32]>) -> bool {
+        let Some(first) = positions.next() else {
+            return false;
+        };
+        let mut chain = first.to_vec();
+        for next in positions {
+            chain = next
+                .iter()
+                .copied()
+                .filter(|position| {
+                    chain
+                        .iter()
+                        .any(|previous| previous < position && position - previous <= self.slop + 1)
+                })
+                .collect();
+            if chain.is_empty() {
+                return false;
+            }
+        }
+        !chain.is_empty()
+    }
+}
+
+///
+/// Parses a timestamp range query word like `ts:[100..200]`, `ts:[100..]` or `ts:[..200]`.
+///
+fn parse_timestamps(word: &str) -> Option<RangeInclusive<u64>> {
+    let (start, end) = word
+        .strip_prefix(TIMESTAMP_PREFIX)?
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once("..")?;
+    let start = match start {
+        "" => 0,
+        start => start.parse().ok()?,
+    };
+    let end = match end {
+        "" => u64::MAX,
+        end => end.parse().ok()?,
+    };
+    Some(start..=end)
+}
+
+///
+/// A source of documents to index.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corpus {
+    /// The bundled text, with its lines grouped into documents (by default, one document per
+    /// line), cycled to produce as many documents as requested.
+    Bundled { grouping: LineGrouping },
+    /// A directory which is walked recursively for `.txt` files (which may be compressed as
+    /// `.txt.gz` or `.txt.zst`), which each contain either a single document, or (if `per_line`
+    /// is set) documents made of their lines. With the default grouping, each non-empty line is a
+    /// document.
+    Directory {
+        path: PathBuf,
+        per_line: bool,
+        grouping: LineGrouping,
+    },
+    /// A file of newline-delimited JSON objects, each with an ID field (named `id_field`) and a
+    /// `body` field, plus the given named fields. An ID may be a u64 or a string. The value of a
+    /// named field may be a string or an array of strings. Objects may also have a numeric
+    /// `timestamp`.
+    Jsonl {
+        path: PathBuf,
+        fields: Vec<String>,
+        id_field: String,
+    },
+    /// A file containing an Arrow IPC stream of record batches, each with an integer or string ID
+    /// column (named `id_field`) and a string `body` column, plus a string column for each of the
+    /// given named fields. Batches may also have an integer `timestamp` column. Null named fields
+    /// and timestamps are omitted.
+    Arrow {
+        path: PathBuf,
+        fields: Vec<String>,
+        id_field: String,
+    },
+    /// A WARC file (such as a Common Crawl WARC or WET file), with a document for each response
+    /// or conversion record. The target URI of a record is its string ID, and its date is its
+    /// timestamp.
+    Warc { path: PathBuf },
+    /// A MediaWiki XML dump (such as `enwiki-latest-pages-articles.xml.bz2`), with a document for
+    /// each article, whose title is a named `title` field. Redirects and other pages are skipped.
+    Wiki { path: PathBuf },
+    /// Documents of `doc_len` words drawn from a vocabulary of `vocab` words with the given
+    /// distribution. The same `seed` always produces the same documents. The timestamp of each
+    /// document is its ID, as if one document were written per second.
+    Synthetic {
+        distribution: SyntheticDistribution,
+        vocab: usize,
+        doc_len: usize,
+        seed: u64,
+    },
+    /// The documents of another corpus whose IDs are congruent to `shard` modulo `shards`, out
+    /// of the first `doc_count` documents requested from it.
+    Shard {
+        corpus: Box<Corpus>,
+        shard: usize,
+        shards: usize,
+    },
+    /// Documents which have already been read into memory, such as a batch of the documents of a
+    /// corpus which is read from stdin, along with the properties of that corpus. Unlike other
+    /// corpora, it cannot be recorded (and so rebuilt) from a manifest.
+    #[serde(skip)]
+    Texts {
+        texts: Arc<Vec<Text>>,
+        fields: Vec<String>,
+        timestamps: bool,
+        keys: bool,
+    },
+}
+
+impl Default for Corpus {
+    fn default() -> Self {
+        Corpus::Bundled {
+            grouping: LineGrouping::default(),
+        }
+    }
+}
+
+///
+/// How the lines of a line-oriented `Corpus` are grouped into documents. Merging lines into
+/// larger documents produces longer token lists per bucket, and so very different index shapes.
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineGrouping {
+    /// Each line is a document.
+    #[default]
+    Line,
+    /// Documents are separated by lines which (ignoring surrounding whitespace) are equal to the
+    /// delimiter. Consecutive delimiters do not produce empty documents, so an empty delimiter
+    /// splits the text into paragraphs.
+    Delimiter(String),
+    /// Each block of the given number of consecutive lines is a document.
+    Lines(usize),
+}
+
+impl LineGrouping {
+    ///
+    /// Groups the given lines into documents, whose lines are joined with newlines.
+    ///
+    fn group<'a>(
+        &self,
+        lines: impl Iterator<Item = &'a str> + Send + 'a,
+    ) -> Box<dyn Iterator<Item = String> + Send + 'a> {
+        match self {
+            LineGrouping::Line => Box::new(lines.map(str::to_owned)),
+            LineGrouping::Delimiter(delimiter) => {
+                let delimiter = delimiter.trim().to_owned();
+                let mut lines = lines;
+                Box::new(std::iter::from_fn(move || {
+                    let mut document = Vec::new();
+                    for line in lines.by_ref() {
+                        if line.trim() != delimiter {
+                            document.push(line);
+                        } else if !document.is_empty() {
+                            break;
+                        }
+                    }
+                    (!document.is_empty()).then(|| document.join("\n"))
+                }))
+            }
+            LineGrouping::Lines(size) => {
+                let size = (*size).max(1);
+                let mut lines = lines;
+                Box::new(std::iter::from_fn(move || {
+                    let document = lines.by_ref().take(size).collect::<Vec<_>>();
+                    (!document.is_empty()).then(|| document.join("\n"))
+                }))
+            }
+        }
+    }
+}
+
+///
+/// How documents are sampled from a `Corpus` by `Corpus::sample`.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleMethod {
+    /// The first documents of the corpus.
+    #[default]
+    Head,
+    /// A uniform sample of all of the documents of the corpus, which requires reading all of
+    /// them.
+    Reservoir,
+}
+
+///
+/// The distribution of words in a `Corpus::Synthetic`.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyntheticDistribution {
+    /// The word with rank `k` occurs with a frequency proportional to `1/k`, which approximates
+    /// natural language.
+    Zipf,
+}
+
+#[derive(Deserialize)]
+struct JsonlDocument {
+    body: String,
+    timestamp: Option<u64>,
+    /// Includes the ID field.
+    #[serde(flatten)]
+    fields: HashMap<String, serde_json::Value>,
+}
+
+impl JsonlDocument {
+    fn into_text(mut self, id_field: &str, fields: &[String]) -> anyhow::Result<Text> {
+        let (id, key) = match self.fields.remove(id_field) {
+            Some(serde_json::Value::String(key)) => (string_id(&key), Some(key)),
+            Some(serde_json::Value::Number(id)) if id.is_u64() => (id.as_u64().unwrap(), None),
+            Some(value) => {
+                bail!("The `{id_field}` of a document is not a u64 or a string: {value}")
+            }
+            None => bail!("A document does not have an `{id_field}`."),
+        };
+        let mut text = Text {
+            id,
+            key,
+            body: self.body,
+            fields: Vec::with_capacity(fields.len()),
+            timestamp: self.timestamp,
+        };
+        for field in fields {
+            let value = match self.fields.remove(field) {
+                None | Some(serde_json::Value::Null) => continue,
+                Some(serde_json::Value::String(value)) => value,
+                Some(serde_json::Value::Array(values)) => values
+                    .iter()
+                    .map(|value| {
+                        value.as_str().ok_or_else(|| {
+                            anyhow!("Field {field:?} of document {} is not a string.", text.id)
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .join(" "),
+                Some(_) => bail!("Field {field:?} of document {} is not a string.", text.id),
+            };
+            text.fields.push((field.clone(), value));
+        }
+        Ok(text)
+    }
+}
+
+impl Corpus {
+    ///
+    /// Counts the first `doc_count` documents of this corpus which match the query, by tokenizing
+    /// and checking each of them in turn. This is an oracle for the counts of the indexes, which
+    /// does not depend on their buckets or posting lists.
+    ///
+    pub fn scan_count(
+        &self,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+        query: &Query,
+    ) -> anyhow::Result<usize> {
+        crate::check_extensions(query)?;
+        let mut count = 0;
+        for text in self.texts(doc_count)? {
+            let text = text?;
+            let tokens = tokenizer.text_term_frequencies(&text);
+            if query.matches_document(&tokens, text.timestamp) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    ///
+    /// The named fields of the documents of this corpus, other than the body.
+    ///
+    pub fn fields(&self) -> &[String] {
+        match self {
+            Corpus::Jsonl { fields, .. } | Corpus::Arrow { fields, .. } => fields,
+            Corpus::Wiki { .. } => &WIKI_FIELDS,
+            Corpus::Shard { corpus, .. } => corpus.fields(),
+            Corpus::Texts { fields, .. } => fields,
+            _ => &[],
+        }
+    }
+
+    ///
+    /// Whether the documents of this corpus (may) have timestamps.
+    ///
+    pub fn timestamps(&self) -> bool {
+        match self {
+            Corpus::Synthetic { .. }
+            | Corpus::Jsonl { .. }
+            | Corpus::Arrow { .. }
+            | Corpus::Warc { .. }
+            | Corpus::Wiki { .. } => true,
+            Corpus::Shard { corpus, .. } => corpus.timestamps(),
+            Corpus::Texts { timestamps, .. } => *timestamps,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Whether the documents of this corpus (may) have external string IDs.
+    ///
+    pub fn keys(&self) -> bool {
+        match self {
+            Corpus::Jsonl { .. } | Corpus::Arrow { .. } | Corpus::Warc { .. } => true,
+            Corpus::Shard { corpus, .. } => corpus.keys(),
+            Corpus::Texts { keys, .. } => *keys,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Whether this corpus is read from stdin (given the path `-`), in which case its texts can
+    /// only be read once.
+    ///
+    pub fn is_stdin(&self) -> bool {
+        match self {
+            Corpus::Jsonl { path, .. }
+            | Corpus::Arrow { path, .. }
+            | Corpus::Warc { path }
+            | Corpus::Wiki { path } => path == Path::new(STDIN),
+            Corpus::Shard { corpus, .. } => corpus.is_stdin(),
+            _ => false,
+        }
+    }
+
+    ///
+    /// Returns an iterator of up to `doc_count` tokenized documents from this corpus.
+    ///
+    pub fn documents(
+        &self,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<Document>> + Send>> {
+        let tokenizer = tokenizer.clone();
+        Ok(Box::new(self.texts(doc_count)?.map(move |text| {
+            let text = text?;
+            Ok((text.id, tokenizer.text_term_frequencies(&text)))
+        })))
+    }
+
+    ///
+    /// Returns up to `sample_docs` of the first `doc_count` tokenized documents from this
+    /// corpus, selected with the given method. Reservoir sampling is seeded, so a given corpus
+    /// always produces the same sample.
+    ///
+    pub fn sample(
+        &self,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+        sample_docs: usize,
+        method: SampleMethod,
+    ) -> anyhow::Result<Vec<Document>> {
+        match method {
+            SampleMethod::Head => self
+                .documents(tokenizer, doc_count.min(sample_docs))?
+                .collect(),
+            SampleMethod::Reservoir => {
+                let mut rng = ChaCha8Rng::seed_from_u64(0);
+                let mut reservoir = Vec::with_capacity(sample_docs);
+                for (idx, document) in self.documents(tokenizer, doc_count)?.enumerate() {
+                    let document = document?;
+                    if idx < sample_docs {
+                        reservoir.push(document);
+                    } else {
+                        let replace = rng.random_range(0..=idx);
+                        if replace < sample_docs {
+                            reservoir[replace] = document;
+                        }
+                    }
+                }
+                Ok(reservoir)
+            }
+        }
+    }
+
+    ///
+    /// Returns an iterator of the original text of up to `doc_count` documents from this corpus.
+    /// Only the bundled corpus is cycled: other corpora may produce fewer documents than
+    /// requested.
+    ///
+    pub fn texts(
+        &self,
+        doc_count: usize,
+    ) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<Text>> + Send>> {
+        match self {
+            Corpus::Bundled { grouping } => {
+                let grouping = grouping.clone();
+                // NB: Each pass over the text is grouped separately, so that a delimiter which
+                // never occurs cannot merge the cycles into a single endless document.
+                let bodies = std::iter::repeat(())
+                    .flat_map(move |()| grouping.group(BUNDLED.lines()))
+                    .map(Ok);
+                Ok(numbered(bodies, doc_count))
+            }
+            Corpus::Directory {
+                path,
+                per_line,
+                grouping,
+            } => {
+                let per_line = *per_line;
+                let grouping = grouping.clone();
+                let bodies = txt_files(path)?.into_iter().flat_map(
+                    move |file| -> Vec<anyhow::Result<String>> {
+                        match read_input(&file) {
+                            Ok(contents) if per_line && grouping == LineGrouping::Line => contents
+                                .lines()
+                                .filter(|line| !line.trim().is_empty())
+                                .map(|line| Ok(line.to_owned()))
+                                .collect(),
+                            Ok(contents) if per_line => {
+                                grouping.group(contents.lines()).map(Ok).collect()
+                            }
+                            Ok(contents) => vec![Ok(contents)],
+                            Err(e) => vec![Err(e)],
+                        }
+                    },
+                );
+                Ok(numbered(bodies, doc_count))
+            }
+            Corpus::Jsonl {
+                path,
+                fields,
+                id_field,
+            } => {
+                let fields = fields.clone();
+                let id_field = id_field.clone();
+                Ok(Box::new(
+                    BufReader::new(open_input(path)?)
+                        .lines()
+                        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                        .take(doc_count)
+                        .map(move |line| {
+                            let document: JsonlDocument = serde_json::from_str(&line?)?;
+                            document.into_text(&id_field, &fields)
+                        }),
+                ))
+            }
+            Corpus::Arrow {
+                path,
+                fields,
+                id_field,
+            } => {
+                let fields = fields.clone();
+                let id_field = id_field.clone();
+                let reader = StreamReader::try_new(BufReader::new(open_input(path)?), None)
+                    .with_context(|| format!("Reading an Arrow IPC stream from {path:?}"))?;
+                Ok(Box::new(
+                    reader
+                        .flat_map(move |batch| -> Vec<anyhow::Result<Text>> {
+                            let texts = batch
+                                .map_err(anyhow::Error::from)
+                                .and_then(|batch| arrow_texts(&batch, &id_field, &fields));
+                            match texts {
+                                Ok(texts) => texts.into_iter().map(Ok).collect(),
+                                Err(e) => vec![Err(e)],
+                            }
+                        })
+                        .take(doc_count),
+                ))
+            }
+            Corpus::Warc { path } => Ok(Box::new(
+                warc_texts(BufReader::new(open_input(path)?)).take(doc_count),
+            )),
+            Corpus::Wiki { path } => Ok(Box::new(
+                wiki_texts(BufReader::new(open_input(path)?)).take(doc_count),
+            )),
+            Corpus::Synthetic {
+                distribution: SyntheticDistribution::Zipf,
+                vocab,
+                doc_len,
+                seed,
+            } => {
+                let zipf = Zipf::new(*vocab as f64, 1.0)?;
+                let doc_len = *doc_len;
+                // NB: Unlike `StdRng`, `ChaCha8Rng` is guaranteed to be reproducible across
+                // versions.
+                let mut rng = ChaCha8Rng::seed_from_u64(*seed);
+                let bodies = std::iter::repeat_with(move || {
+                    Ok((0..doc_len)
+                        .map(|_| synthetic_word(zipf.sample(&mut rng) as u64))
+                        .collect::<Vec<_>>()
+                        .join(" "))
+                });
+                Ok(Box::new(numbered(bodies, doc_count).map(|text| {
+                    let text = text?;
+                    Ok(Text {
+                        timestamp: Some(text.id),
+                        ..text
+                    })
+                })))
+            }
+            Corpus::Shard {
+                corpus,
+                shard,
+                shards,
+            } => {
+                let (shard, shards) = (*shard as u64, *shards as u64);
+                Ok(Box::new(corpus.texts(doc_count)?.filter(
+                    move |text| !matches!(text, Ok(text) if text.id % shards != shard),
+                )))
+            }
+            Corpus::Texts { texts, .. } => {
+                let texts = texts.clone();
+                let count = texts.len().min(doc_count);
+                Ok(Box::new((0..count).map(move |idx| Ok(texts[idx].clone()))))
+            }
+        }
+    }
+}
+
+/// The named fields of a `Corpus::Wiki`.
+static WIKI_FIELDS: LazyLock<Vec<String>> = LazyLock::new(|| vec![TITLE_FIELD.to_owned()]);
+
+/// The path of an input file which refers to stdin.
+const STDIN: &str = "-";
+
+///
+/// Opens the input file of a corpus, or stdin if its path is `-`. A file with a `.gz`, `.zst`
+/// or `.bz2` extension is transparently decompressed as it is read.
+///
+fn open_input(path: &Path) -> anyhow::Result<Box<dyn Read + Send>> {
+    if path == Path::new(STDIN) {
+        return Ok(Box::new(std::io::stdin()));
+    }
+    let file = File::open(path).with_context(|| format!("Opening {path:?}"))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        // NB: Concatenated gzip members (as written by many dataset tools) are read as one stream.
+        Some("gz") => Ok(Box::new(MultiGzDecoder::new(BufReader::new(file)))),
+        // NB: Wikipedia dumps are multistream bzip2 files.
+        Some("bz2") => Ok(Box::new(MultiBzDecoder::new(BufReader::new(file)))),
+        Some("zst") => Ok(Box::new(
+            zstd::Decoder::new(file).with_context(|| format!("Decompressing {path:?}"))?,
+        )),
+        _ => Ok(Box::new(file)),
+    }
+}
+
+///
+/// Reads the entire (possibly compressed) input file of a corpus into a string.
+///
+fn read_input(path: &Path) -> anyhow::Result<String> {
+    let mut contents = String::new();
+    open_input(path)?
+        .read_to_string(&mut contents)
+        .with_context(|| format!("Reading {path:?}"))?;
+    Ok(contents)
+}
+
+///
+/// Converts a record batch of a `Corpus::Arrow` into texts. Columns are cast to the expected
+/// types, so any integer type may be used for IDs and timestamps, and either string type for the
+/// body and named fields. A string ID column holds external string IDs.
+///
+fn arrow_texts(
+    batch: &RecordBatch,
+    id_field: &str,
+    fields: &[String],
+) -> anyhow::Result<Vec<Text>> {
+    let column = |name: &str, data_type: &DataType| {
+        batch
+            .column_by_name(name)
+            .map(|column| {
+                arrow::compute::cast(column, data_type)
+                    .with_context(|| format!("Converting the `{name}` column to {data_type}"))
+            })
+            .transpose()
+    };
+    let required = |name: &str, data_type: &DataType| {
+        column(name, data_type)?
+            .ok_or_else(|| anyhow!("An Arrow record batch does not have a `{name}` column."))
+    };
+    let string_ids = batch.column_by_name(id_field).is_some_and(|column| {
+        matches!(
+            column.data_type(),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        )
+    });
+    let ids = required(
+        id_field,
+        if string_ids {
+            &DataType::Utf8
+        } else {
+            &DataType::UInt64
+        },
+    )?;
+    let bodies = required("body", &DataType::Utf8)?;
+    let bodies = bodies.as_string::<i32>();
+    let timestamps = column("timestamp", &DataType::UInt64)?;
+    let timestamps = timestamps
+        .as_ref()
+        .map(|timestamps| timestamps.as_primitive::<UInt64Type>());
+    let field_columns = fields
+        .iter()
+        .filter_map(|field| {
+            column(field, &DataType::Utf8)
+                .transpose()
+                .map(|column| (field, column))
+        })
+        .map(|(field, column)| Ok((field, column?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    (0..batch.num_rows())
+        .map(|row| {
+            if ids.is_null(row) {
+                bail!("Row {row} of an Arrow record batch does not have an `{id_field}`.");
+            }
+            let (id, key) = if string_ids {
+                let key = ids.as_string::<i32>().value(row);
+                (string_id(key), Some(key.to_owned()))
+            } else {
+                (ids.as_primitive::<UInt64Type>().value(row), None)
+            };
+            let body = if bodies.is_null(row) {
+                ""
+            } else {
+                bodies.value(row)
+            };
+            let fields = field_columns
+                .iter()
+                .filter_map(|(field, column)| {
+                    let column = column.as_string::<i32>();
+                    (!column.is_null(row)).then(|| ((*field).clone(), column.value(row).to_owned()))
+                })
+                .collect();
+            let timestamp = timestamps
+                .filter(|timestamps| !timestamps.is_null(row))
+                .map(|timestamps| timestamps.value(row));
+            Ok(Text {
+                id,
+                key,
+                body: body.to_owned(),
+                fields,
+                timestamp,
+            })
+        })
         .collect()
 }
 
-pub fn documents(doc_count: usize) -> impl Iterator<Item = Document> {
-    include_str!("./all_the_henries.txt")
+///
+/// Assigns sequential IDs to up to `doc_count` document bodies.
+///
+fn numbered(
+    bodies: impl Iterator<Item = anyhow::Result<String>> + Send + 'static,
+    doc_count: usize,
+) -> Box<dyn Iterator<Item = anyhow::Result<Text>> + Send> {
+    Box::new(bodies.take(doc_count).enumerate().map(|(id, body)| {
+        Ok(Text {
+            id: id.try_into().unwrap(),
+            key: None,
+            body: body?,
+            fields: Vec::new(),
+            timestamp: None,
+        })
+    }))
+}
+
+///
+/// Spells the word of the given (1-based) rank in bijective base-26, so that ranks map to `a`,
+/// `b`, ..., `z`, `aa`, `ab`, and so on.
+///
+fn synthetic_word(mut rank: u64) -> String {
+    let mut word = Vec::new();
+    while rank > 0 {
+        rank -= 1;
+        word.push(b'a' + (rank % 26) as u8);
+        rank /= 26;
+    }
+    word.reverse();
+    String::from_utf8(word).unwrap()
+}
+
+///
+/// Recursively collects the (possibly compressed) `.txt` files under `dir`, sorted so that IDs
+/// are stable across runs.
+///
+fn txt_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Listing {dir:?}"))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.to_str().is_some_and(|path| {
+                [".txt", ".txt.gz", ".txt.zst"]
+                    .iter()
+                    .any(|suffix| path.ends_with(suffix))
+            }) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+///
+/// A collection of per-query latencies, for reporting percentiles.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Latencies {
+    latencies: Vec<Duration>,
+    sorted: bool,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latencies.is_empty()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.latencies.iter().sum()
+    }
+
+    pub fn min(&self) -> Duration {
+        self.latencies.iter().min().copied().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.total()
+            .checked_div(self.latencies.len() as u32)
+            .unwrap_or_default()
+    }
+
+    ///
+    /// The population standard deviation of the latencies.
+    ///
+    pub fn stddev(&self) -> Duration {
+        let mean = self.mean().as_secs_f64();
+        let variance = self
+            .latencies
+            .iter()
+            .map(|latency| (latency.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.latencies.len().max(1) as f64;
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    ///
+    /// Returns the nearest-rank latency at the given percentile (between 0 and 100).
+    ///
+    pub fn percentile(&mut self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        if !self.sorted {
+            self.latencies.sort_unstable();
+            self.sorted = true;
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    ///
+    /// Queries per second, given the wall-clock time taken to execute all of the queries (which
+    /// may have overlapped).
+    ///
+    pub fn qps(&self, elapsed: Duration) -> f64 {
+        self.latencies.len() as f64 / elapsed.as_secs_f64()
+    }
+
+    pub fn summary(&mut self, elapsed: Duration) -> String {
+        format!(
+            "p50={:?} p90={:?} p99={:?} max={:?} qps={:.1}",
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.percentile(100.0),
+            self.qps(elapsed),
+        )
+    }
+}
+
+///
+/// The suffix of the edge n-gram tokens generated by `Tokenizer::edge_ngrams`, which
+/// distinguishes them from whole tokens. Since the pipeline trims non-alphanumeric characters
+/// from words, no whole token can end with the marker.
+///
+pub const PREFIX_MARKER: char = '*';
+
+/// The maximum number of words in a snippet generated by `snippet`.
+const SNIPPET_WORDS: usize = 20;
+
+///
+/// Extracts a window of words from `body` around the first word which matches one of the
+/// query's tokens, and wraps each matching word in `<b>` tags.
+///
+pub fn snippet(body: &str, query: &Query, tokenizer: &Tokenizer) -> String {
+    let tokens = query.all_tokens().collect::<HashSet<_>>();
+    let words = body.split_whitespace().collect::<Vec<_>>();
+    let matches = words
+        .iter()
+        .map(|word| {
+            tokenizer
+                .tokenize(word)
+                .iter()
+                .any(|token| tokens.contains(token))
+        })
+        .collect::<Vec<_>>();
+
+    let first_match = matches.iter().position(|m| *m).unwrap_or(0);
+    let start = first_match.saturating_sub(SNIPPET_WORDS / 2);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let mut snippet = words[start..end]
+        .iter()
+        .zip(&matches[start..end])
+        .map(|(word, m)| {
+            if *m {
+                format!("<b>{word}</b>")
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "... ");
+    }
+    if end < words.len() {
+        snippet.push_str(" ...");
+    }
+    snippet
+}
+
+/// The English stop words which are removed by `Tokenizer::stop_words`, unless the tokenizer has
+/// its own `stop_word_list`.
+pub const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+///
+/// The pipeline which converts text into tokens for both indexing and searching: words are split
+/// (on whitespace, by default), trimmed of non-alphanumeric characters, and normalized (by
+/// default, lowercased), and then optionally filtered and stemmed.
+///
+/// The pipeline is applied before documents reach either backend, so the same settings must be
+/// used at index and search time.
+///
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize, clap::Args)]
+pub struct Tokenizer {
+    /// Remove common English stop words, or the words of `--stop-word-list`. Since queries are
+    /// tokenized in the same way, stop words never need to be matched by either backend.
+    #[arg(long)]
+    pub stop_words: bool,
+    /// A comma-separated list of the stop words to remove, rather than the English stop words.
+    /// Words are compared case-insensitively after normalization, but before stemming.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        requires = "stop_words",
+        value_name = "WORDS"
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_word_list: Vec<String>,
+    /// Reduce tokens to their stems, using the Snowball English (Porter2) stemmer.
+    #[arg(long)]
+    pub stem: bool,
+    /// Remove tokens containing fewer than this many characters.
+    #[arg(long, default_value_t = 1)]
+    pub min_length: usize,
+    /// Additionally emit the prefixes (up to `MAX` characters long) of each token, which allows
+    /// for prefix queries like `hen*`. Prefixes are taken after stemming.
+    #[arg(long, value_name = "MAX")]
+    pub edge_ngrams: Option<usize>,
+    /// How text is split into words.
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    pub segmentation: Segmentation,
+    /// Preserve the case of words, rather than lowercasing them, so that queries are
+    /// case-sensitive.
+    #[arg(long)]
+    #[serde(default)]
+    pub no_lowercase: bool,
+    /// Fold accented Latin characters into their unaccented ASCII equivalents (such as `é` into
+    /// `e`, and `ß` into `ss`), so that queries match regardless of diacritics. Characters
+    /// without an ASCII equivalent are kept.
+    #[arg(long)]
+    #[serde(default)]
+    pub ascii_fold: bool,
+}
+
+///
+/// How a `Tokenizer` splits text into words, before they are normalized.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Segmentation {
+    /// Split on whitespace, and trim the non-alphanumeric characters from each word.
+    #[default]
+    Whitespace,
+    /// Split into the words of Unicode word segmentation (UAX #29), which also splits on
+    /// punctuation such as hyphens and slashes, and splits Han text into single characters.
+    Unicode,
+    /// Like `unicode`, but emit the overlapping bigrams of each run of Chinese, Japanese or
+    /// Korean characters (or a lone character as a unigram), which matches words of those
+    /// languages far more selectively than single characters.
+    Cjk,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Tokenizer {
+            stop_words: false,
+            stop_word_list: Vec::new(),
+            stem: false,
+            min_length: 1,
+            edge_ngrams: None,
+            segmentation: Segmentation::default(),
+            no_lowercase: false,
+            ascii_fold: false,
+        }
+    }
+}
+
+impl Tokenizer {
+    ///
+    /// Whether any option is set which searchers that predate it would ignore when reading the
+    /// tokenizer of an index, rather than failing: such an index is written with a newer format
+    /// version, which those searchers refuse.
+    ///
+    pub(crate) fn has_versioned_options(&self) -> bool {
+        !self.stop_word_list.is_empty()
+            || self.segmentation != Segmentation::default()
+            || self.no_lowercase
+            || self.ascii_fold
+    }
+
+    pub fn tokenize(&self, document: &str) -> HashSet<String> {
+        self.term_frequencies(document).into_keys().collect()
+    }
+
+    ///
+    /// Tokenizes the body and named fields of a document. The tokens of each named field are
+    /// qualified with the name of the field, as in `title:henry`.
+    ///
+    pub fn text_term_frequencies(&self, text: &Text) -> HashMap<String, u32> {
+        let mut frequencies = self.term_frequencies(&text.body);
+        for (field, value) in &text.fields {
+            for (token, tf) in self.term_frequencies(value) {
+                *frequencies.entry(field_token(field, &token)).or_insert(0) += tf;
+            }
+        }
+        frequencies
+    }
+
+    ///
+    /// The number of tokens in the body and named fields of a document, which (unlike the sum
+    /// of its term frequencies) does not count edge n-grams.
+    ///
+    pub fn text_length(&self, text: &Text) -> u32 {
+        let fields = text
+            .fields
+            .iter()
+            .map(|(_, value)| self.tokens(value).len());
+        (self.tokens(&text.body).len() + fields.sum::<usize>()) as u32
+    }
+
+    pub fn term_frequencies(&self, document: &str) -> HashMap<String, u32> {
+        self.positions(document)
+            .into_iter()
+            .map(|(token, positions)| (token, positions.len() as u32))
+            .collect()
+    }
+
+    ///
+    /// Like `text_term_frequencies`, but returns the positions of each token within its field.
+    ///
+    pub fn text_positions(&self, text: &Text) -> HashMap<String, Vec<u32>> {
+        let mut positions = self.positions(&text.body);
+        for (field, value) in &text.fields {
+            for (token, field_positions) in self.positions(value) {
+                positions
+                    .entry(field_token(field, &token))
+                    .or_default()
+                    .extend(field_positions);
+            }
+        }
+        positions
+    }
+
+    ///
+    /// Returns the positions at which each token occurs in the document, counted in whole tokens
+    /// (so stop words do not occupy positions). An edge n-gram occurs at the positions of the
+    /// tokens that it is a prefix of.
+    ///
+    pub fn positions(&self, document: &str) -> HashMap<String, Vec<u32>> {
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        for (position, token) in self.tokens(document).into_iter().enumerate() {
+            let position = position as u32;
+            if let Some(max) = self.edge_ngrams {
+                for (idx, c) in token.char_indices().take(max) {
+                    let gram = format!("{}{PREFIX_MARKER}", &token[..idx + c.len_utf8()]);
+                    positions.entry(gram).or_default().push(position);
+                }
+            }
+            positions.entry(token).or_default().push(position);
+        }
+        positions
+    }
+
+    ///
+    /// Returns the whole tokens of the document in order, without edge n-grams.
+    ///
+    pub fn tokens(&self, document: &str) -> Vec<String> {
+        let stemmer = self.stem.then(|| Stemmer::create(Algorithm::English));
+        let stop_word_list = self.normalized_stop_word_list();
+        self.words(document)
+            .filter(|word| !self.is_stop_word(word, &stop_word_list))
+            .map(|word| match &stemmer {
+                Some(stemmer) => stemmer.stem(&word).into_owned(),
+                None => word,
+            })
+            .collect()
+    }
+
+    ///
+    /// The words of `stop_word_list`, normalized as the words of documents are and then
+    /// lowercased, which is the form that `is_stop_word` compares words in.
+    ///
+    fn normalized_stop_word_list(&self) -> Vec<String> {
+        if !self.stop_words {
+            return Vec::new();
+        }
+        self.stop_word_list
+            .iter()
+            .map(|stop_word| self.normalize(stop_word).to_lowercase())
+            .collect()
+    }
+
+    fn is_stop_word(&self, word: &str, stop_word_list: &[String]) -> bool {
+        if !self.stop_words {
+            return false;
+        }
+        let lowercase;
+        let word = if self.no_lowercase {
+            lowercase = word.to_lowercase();
+            &lowercase
+        } else {
+            word
+        };
+        if stop_word_list.is_empty() {
+            STOP_WORDS.contains(&word)
+        } else {
+            stop_word_list.iter().any(|stop_word| stop_word == word)
+        }
+    }
+
+    ///
+    /// Normalizes a query prefix into the edge n-gram token which would have been emitted for
+    /// it. Prefixes longer than the maximum n-gram length are truncated, and so may over-match.
+    ///
+    pub fn prefix(&self, prefix: &str) -> Option<String> {
+        let prefix = self.words(prefix).next()?;
+        let max = self.edge_ngrams.unwrap_or(usize::MAX);
+        Some(format!(
+            "{}{PREFIX_MARKER}",
+            prefix.chars().take(max).collect::<String>()
+        ))
+    }
+
+    fn words<'a>(&self, document: &'a str) -> impl Iterator<Item = String> + 'a {
+        let min_length = self.min_length;
+        let words: Box<dyn Iterator<Item = &'a str> + 'a> = match self.segmentation {
+            Segmentation::Whitespace => Box::new(
+                document
+                    .split_whitespace()
+                    .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric())),
+            ),
+            Segmentation::Unicode => Box::new(document.unicode_words()),
+            Segmentation::Cjk => Box::new(cjk_words(document)),
+        };
+        let (no_lowercase, ascii_fold) = (self.no_lowercase, self.ascii_fold);
+        words
+            .filter(move |word| !word.is_empty() && word.chars().count() >= min_length)
+            .map(move |word| normalize(word, no_lowercase, ascii_fold))
+    }
+
+    ///
+    /// Normalizes a word (or the literal characters of a pattern): lowercases it unless
+    /// `no_lowercase` is set, and then folds it to ASCII if `ascii_fold` is set.
+    ///
+    pub fn normalize(&self, word: &str) -> String {
+        normalize(word, self.no_lowercase, self.ascii_fold)
+    }
+}
+
+///
+/// Implements `Tokenizer::normalize`, without borrowing the tokenizer.
+///
+fn normalize(word: &str, no_lowercase: bool, ascii_fold: bool) -> String {
+    let word = if no_lowercase {
+        word.to_owned()
+    } else {
+        word.to_lowercase()
+    };
+    if !ascii_fold || word.is_ascii() {
+        return word;
+    }
+    let mut folded = String::with_capacity(word.len());
+    for c in word.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'Æ' => folded.push_str("AE"),
+            'œ' => folded.push_str("oe"),
+            'Œ' => folded.push_str("OE"),
+            'ø' => folded.push('o'),
+            'Ø' => folded.push('O'),
+            'ł' => folded.push('l'),
+            'Ł' => folded.push('L'),
+            'đ' => folded.push('d'),
+            'Đ' => folded.push('D'),
+            'þ' => folded.push_str("th"),
+            'Þ' => folded.push_str("TH"),
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+///
+/// Splits text into the words of Unicode word segmentation, except that each run of CJK
+/// characters is split into its overlapping bigrams.
+///
+fn cjk_words(document: &str) -> impl Iterator<Item = &str> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut chars = document.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let cjk = is_cjk(c);
+        if chars.peek().is_none_or(|(_, next)| is_cjk(*next) != cjk) {
+            let end = idx + c.len_utf8();
+            runs.push((cjk, &document[start..end]));
+            start = end;
+        }
+    }
+    runs.into_iter().flat_map(|(cjk, run)| -> Vec<&str> {
+        if !cjk {
+            return run.unicode_words().collect();
+        }
+        let bounds = run
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .chain([run.len()])
+            .collect::<Vec<_>>();
+        if bounds.len() <= 3 {
+            return vec![run];
+        }
+        bounds
+            .windows(3)
+            .map(|window| &run[window[0]..window[2]])
+            .collect()
+    })
+}
+
+///
+/// Whether the character is a Han ideograph, or a Hiragana, Katakana or Hangul character.
+///
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2A6DF}'
+    )
+}
+
+/// The bundled corpus: one document per line.
+const BUNDLED: &str = include_str!("./all_the_henries.txt");
+
+pub fn documents(tokenizer: &Tokenizer, doc_count: usize) -> impl Iterator<Item = Document> {
+    let tokenizer = tokenizer.clone();
+    BUNDLED
         .lines()
         .cycle()
         .take(doc_count)
-        .map(tokenize)
+        .map(move |line| tokenizer.term_frequencies(line))
         .enumerate()
         .map(|(id, document)| (id.try_into().unwrap(), document))
 }
+
+///
+/// The document frequency stratum from which `QueryGenerator` draws the terms of its queries.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stratum {
+    /// The most frequent tenth of the vocabulary, which produces unselective queries.
+    Common,
+    /// The least frequent half of the vocabulary, which produces highly selective queries.
+    Rare,
+    /// The whole vocabulary.
+    Any,
+}
+
+///
+/// Synthesizes reproducible queries of `terms` distinct tokens, drawn uniformly from a document
+/// frequency stratum of the first `docs` documents of a corpus. Parsed from a spec like
+/// `terms=3 df=rare seed=7`.
+///
+#[derive(Clone, Debug)]
+pub struct QueryGenerator {
+    pub terms: usize,
+    pub df: Stratum,
+    pub docs: usize,
+    pub seed: u64,
+}
+
+impl Default for QueryGenerator {
+    fn default() -> Self {
+        QueryGenerator {
+            terms: 3,
+            df: Stratum::Any,
+            // NB: Enough to cover every line of the bundled corpus.
+            docs: 30_000,
+            seed: 0,
+        }
+    }
+}
+
+impl QueryGenerator {
+    ///
+    /// Parses `key=value` settings (which may also be separated by commas), with defaults for
+    /// any which are omitted.
+    ///
+    pub fn parse<'a>(settings: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Self> {
+        let mut generator = QueryGenerator::default();
+        for setting in settings
+            .into_iter()
+            .flat_map(|settings| settings.split([',', ' ']))
+            .filter(|setting| !setting.is_empty())
+        {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected a `key=value` setting, but got {setting:?}"))?;
+            match key {
+                "terms" => {
+                    generator.terms = value
+                        .parse()
+                        .with_context(|| format!("Invalid term count {value:?}"))?
+                }
+                "df" => {
+                    generator.df = match value {
+                        "common" => Stratum::Common,
+                        "rare" => Stratum::Rare,
+                        "any" => Stratum::Any,
+                        _ => bail!("Invalid stratum {value:?}: expected common, rare or any."),
+                    }
+                }
+                "docs" => {
+                    generator.docs = value
+                        .parse()
+                        .with_context(|| format!("Invalid document count {value:?}"))?
+                }
+                "seed" => {
+                    generator.seed = value
+                        .parse()
+                        .with_context(|| format!("Invalid seed {value:?}"))?
+                }
+                _ => bail!("Unknown setting {key:?}: expected terms, df, docs or seed."),
+            }
+        }
+        if generator.terms == 0 {
+            bail!("A generated query must contain at least one term.");
+        }
+        Ok(generator)
+    }
+
+    ///
+    /// Generates `count` queries from the tokens of a sample of the corpus, which should be the
+    /// corpus that the index was built from.
+    ///
+    pub fn queries(
+        &self,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        mode: QueryMode,
+        count: usize,
+    ) -> anyhow::Result<Vec<Query>> {
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        for (_, document) in corpus.sample(tokenizer, self.docs, self.docs, SampleMethod::Head)? {
+            for token in document.into_keys() {
+                *frequencies.entry(token).or_default() += 1;
+            }
+        }
+        let mut tokens = frequencies.into_iter().collect::<Vec<_>>();
+        tokens.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        let tokens = tokens
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>();
+        let stratum = match self.df {
+            Stratum::Common => &tokens[..tokens.len().div_ceil(10)],
+            Stratum::Rare => &tokens[tokens.len() / 2..],
+            Stratum::Any => &tokens[..],
+        };
+        if stratum.len() < self.terms {
+            bail!(
+                "The {:?} stratum contains only {} tokens, which is fewer than {} terms.",
+                self.df,
+                stratum.len(),
+                self.terms
+            );
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        Ok((0..count)
+            .map(|_| {
+                let terms = rand::seq::index::sample(&mut rng, stratum.len(), self.terms)
+                    .into_iter()
+                    .map(|i| stratum[i].clone())
+                    .collect();
+                Query::new(terms, mode)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use duckdb::types::Value;
+use duckdb::{AccessMode, Config, Connection, params, params_from_iter};
+
+use crate::common::{Corpus, PREFIX_MARKER, Query, QueryMode, Tokenizer, field_token, snippet};
+use crate::{Indexer, Searcher};
+
+///
+/// Loads the `fts` extension, which is installed on first use (and so requires network access
+/// the first time that an index is built or searched).
+///
+const LOAD_FTS: &str = "INSTALL fts; LOAD fts;";
+
+///
+/// Builds a DuckDB database containing a `documents` table, with a full-text index on a column
+/// of each document's tokens. Documents are tokenized by our own `Tokenizer`, so the extension
+/// is configured to split the pre-tokenized text on whitespace, without any stemming, stop
+/// words, case folding or accent stripping.
+///
+#[derive(Clone, Debug, Default)]
+pub struct DuckdbIndexer {
+    pub store_bodies: bool,
+}
+
+impl Indexer for DuckdbIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        if path.exists() {
+            bail!("{path:?} already exists.");
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(LOAD_FTS)?;
+        connection.execute_batch(
+            "CREATE TABLE documents (\
+                id UBIGINT PRIMARY KEY, tokens VARCHAR, timestamp UBIGINT, body VARCHAR\
+            );",
+        )?;
+
+        {
+            let mut appender = connection.appender("documents")?;
+            for text in corpus.texts(doc_count)? {
+                let text = text?;
+                let tokens = tokenizer
+                    .text_term_frequencies(&text)
+                    .into_keys()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let body = self.store_bodies.then_some(&text.body);
+                appender.append_row(params![text.id, tokens, text.timestamp, body])?;
+            }
+            appender.flush()?;
+        }
+
+        connection.execute_batch(
+            "PRAGMA create_fts_index(\
+                'documents', 'id', 'tokens', \
+                stemmer = 'none', stopwords = 'none', ignore = '\\s+', \
+                strip_accents = 0, lower = 0\
+            );",
+        )?;
+        Ok(())
+    }
+}
+
+///
+/// A connection to a DuckDB index. DuckDB parallelizes each query internally, so queries are
+/// executed one at a time on a single connection.
+///
+pub struct DuckdbSearcher {
+    connection: Mutex<Connection>,
+}
+
+impl DuckdbSearcher {
+    ///
+    /// Executes a statement which selects from the documents matching the query (as `matches`),
+    /// and returns the result of applying `f` to each row. A query without any tokens matches
+    /// nothing, as in the other backends.
+    ///
+    /// The tokens are matched with the extension's `match_bm25` macro, which is conjunctive for
+    /// `QueryMode::All`, and any document which matches one of the excluded tokens is removed.
+    ///
+    fn query<T>(
+        &self,
+        select: &str,
+        query: &Query,
+        f: impl FnMut(&duckdb::Row<'_>) -> duckdb::Result<T>,
+    ) -> anyhow::Result<Vec<T>> {
+        if query.tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conjunctive = match query.mode {
+            QueryMode::All => 1,
+            QueryMode::Any => 0,
+        };
+        let mut values = vec![Value::Text(join(&query.tokens))];
+        let mut filter = format!(
+            "fts_main_documents.match_bm25(id, ?, conjunctive := {conjunctive}) IS NOT NULL"
+        );
+        if !query.excluded.is_empty() {
+            filter.push_str(" AND fts_main_documents.match_bm25(id, ?) IS NULL");
+            values.push(Value::Text(join(&query.excluded)));
+        }
+        if let Some(range) = &query.timestamps {
+            // NB: Documents without timestamps have NULL timestamps, which are never between.
+            filter.push_str(" AND timestamp BETWEEN ? AND ?");
+            values.push(Value::UBigInt(*range.start()));
+            values.push(Value::UBigInt(*range.end()));
+        }
+        let sql = format!(
+            "WITH matches AS (SELECT id, tokens, body FROM documents WHERE {filter}) {select}"
+        );
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(&sql)?;
+        let rows = statement
+            .query_map(params_from_iter(values), f)?
+            .collect::<duckdb::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+}
+
+impl Searcher for DuckdbSearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let config = Config::default().access_mode(AccessMode::ReadOnly)?;
+        let connection = Connection::open_with_flags(path, config)?;
+        connection.execute_batch(LOAD_FTS)?;
+        Ok(DuckdbSearcher {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        let counts = self.query("SELECT count(*) FROM matches", query, |row| {
+            row.get::<_, u64>(0)
+        })?;
+        Ok(counts.into_iter().sum::<u64>() as usize)
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        self.query("SELECT id FROM matches", query, |row| row.get::<_, u64>(0))
+    }
+
+    ///
+    /// Matching documents are post-filtered by selecting the stored body column, and then
+    /// highlighting it with our `Tokenizer`.
+    ///
+    async fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        let bodies = self.query(
+            &format!("SELECT id, body FROM matches LIMIT {limit}"),
+            query,
+            |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?)),
+        )?;
+        bodies
+            .into_iter()
+            .map(|(id, body)| match body {
+                Some(body) => Ok((id, snippet(&body, query, tokenizer))),
+                None => bail!("The index was not built with stored bodies."),
+            })
+            .collect()
+    }
+
+    ///
+    /// Selects the tokens of each matching document, and counts the field's tokens among them.
+    ///
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let prefix = field_token(field, "");
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.query("SELECT tokens FROM matches", query, |row| {
+            let tokens: String = row.get(0)?;
+            for token in tokens.split(' ') {
+                if let Some(value) = token.strip_prefix(&prefix)
+                    && !token.ends_with(PREFIX_MARKER)
+                {
+                    *counts.entry(value.to_owned()).or_default() += 1;
+                }
+            }
+            Ok(())
+        })?;
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
+
+///
+/// Joins tokens into a query string for `match_bm25`, which splits it on whitespace.
+///
+fn join<'a>(tokens: impl IntoIterator<Item = &'a String>) -> String {
+    tokens
+        .into_iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,192 @@
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod cache;
+pub mod common;
+pub mod duckdb;
+pub mod memory;
+pub mod metrics;
+pub mod parquet;
+pub mod serve;
+pub mod sqlite;
+pub mod tantivy;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod vortex;
+mod vortex_buckets;
+mod vortex_list_expr;
+mod vortex_manifest;
+mod vortex_near_expr;
+mod vortex_shards;
+mod vortex_simplify;
+mod vortex_terms;
+mod vortex_tombstone_expr;
+#[cfg(target_os = "linux")]
+mod vortex_uring;
+mod vortex_vocabulary;
+mod warc;
+mod wiki;
+
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use futures_util::{StreamExt, TryStreamExt, stream};
+
+use crate::common::QueryExpr;
+pub use crate::common::{Corpus, Document, Query, QueryMode, Tokenizer};
+
+///
+/// Builds an index from the documents of a `Corpus`.
+///
+pub trait Indexer {
+    fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+///
+/// The number of matches for a query, with its total latency and the portion of that latency
+/// which was spent setting the query up.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct QueryTiming {
+    pub count: usize,
+    pub setup: Duration,
+    pub latency: Duration,
+}
+
+///
+/// An opened index, which may be queried repeatedly.
+///
+pub trait Searcher: Sized + Sync {
+    fn open(path: &Path) -> impl Future<Output = anyhow::Result<Self>> + Send;
+
+    ///
+    /// Returns the number of documents which match the given `Query`.
+    ///
+    fn count(&self, query: &Query) -> impl Future<Output = anyhow::Result<usize>> + Send;
+
+    ///
+    /// Returns the number of documents which match the given `Query`, along with the portion of
+    /// its latency which was spent setting the query up (e.g. constructing its filter) before
+    /// executing it.
+    ///
+    fn count_with_setup(
+        &self,
+        query: &Query,
+    ) -> impl Future<Output = anyhow::Result<(usize, Duration)>> + Send {
+        async move { Ok((self.count(query).await?, Duration::ZERO)) }
+    }
+
+    ///
+    /// Fails if the index was built with a `Tokenizer` which differs from the given one. Indexes
+    /// which do not record their tokenizer accept any.
+    ///
+    fn check_tokenizer(&self, _tokenizer: &Tokenizer) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    ///
+    /// Fails if the index cannot evaluate the given `Query`. By default, proximity, fuzzy and
+    /// pattern queries are rejected, since they require token positions or an indexed vocabulary,
+    /// as are queries with an `expr`.
+    ///
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        check_extensions(query)?;
+        if query.expr.is_some() {
+            anyhow::bail!("This backend does not support nested queries.");
+        }
+        Ok(())
+    }
+
+    ///
+    /// Returns the IDs of the documents which match the given `Query`, in no particular order.
+    ///
+    fn ids(&self, query: &Query) -> impl Future<Output = anyhow::Result<Vec<u64>>> + Send;
+
+    ///
+    /// Returns the IDs of up to `limit` documents which match the given `Query`, each with a
+    /// snippet of its original text in which the query's tokens are highlighted. Requires an
+    /// index which was built with stored bodies.
+    ///
+    fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> impl Future<Output = anyhow::Result<Vec<(u64, String)>>> + Send;
+
+    ///
+    /// Returns the number of documents matching the given `Query` which contain each token of
+    /// the named field, in descending order of count.
+    ///
+    fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<(String, usize)>>> + Send;
+
+    ///
+    /// Counts the matches for each of the given queries with up to `concurrency` queries in
+    /// flight at once, and returns each count along with the timings of its query (in no
+    /// particular order).
+    ///
+    fn count_concurrently(
+        &self,
+        queries: Vec<Query>,
+        concurrency: usize,
+    ) -> impl Future<Output = anyhow::Result<Vec<QueryTiming>>> + Send {
+        count_concurrently(self, queries, concurrency)
+    }
+}
+
+///
+/// The default implementation of `Searcher::count_concurrently`, which executes each query with
+/// `Searcher::count_with_setup`.
+///
+pub(crate) fn count_concurrently<S: Searcher>(
+    searcher: &S,
+    queries: Vec<Query>,
+    concurrency: usize,
+) -> impl Future<Output = anyhow::Result<Vec<QueryTiming>>> + Send {
+    stream::iter(queries)
+        .map(move |query| async move {
+            let start = Instant::now();
+            let (count, setup) = searcher.count_with_setup(&query).await?;
+            anyhow::Ok(QueryTiming {
+                count,
+                setup,
+                latency: start.elapsed(),
+            })
+        })
+        .buffer_unordered(concurrency)
+        .try_collect()
+}
+
+///
+/// Fails if the query (or its `expr`) contains a proximity query, fuzzy matching or patterns.
+///
+pub(crate) fn check_extensions(query: &Query) -> anyhow::Result<()> {
+    let leaves = query.expr.iter().flat_map(QueryExpr::leaves);
+    if query.near.is_some()
+        || leaves
+            .clone()
+            .any(|leaf| matches!(leaf, QueryExpr::Phrase(_)))
+    {
+        anyhow::bail!("This backend does not support proximity queries.");
+    }
+    if query.fuzzy > 0 {
+        anyhow::bail!("This backend does not support fuzzy queries.");
+    }
+    if !query.patterns.is_empty()
+        || !query.excluded_patterns.is_empty()
+        || leaves.any(|leaf| matches!(leaf, QueryExpr::Pattern(_)))
+    {
+        anyhow::bail!("This backend does not support pattern queries.");
+    }
+    Ok(())
+}
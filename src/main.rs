@@ -1,17 +1,49 @@
-mod common;
-mod tantivy;
-mod vortex;
-mod vortex_list_expr;
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use std::path::PathBuf;
-use std::time::Instant;
+use anyhow::{Context, anyhow, bail};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
-use clap::{Parser, Subcommand};
+use vfts::cache::CachingSearcher;
+use vfts::common::{
+    BODY_FIELD, Latencies, LineGrouping, Near, PREFIX_MARKER, QueryGenerator, SampleMethod,
+    SyntheticDistribution, is_field_name, string_id,
+};
+use vfts::duckdb::{DuckdbIndexer, DuckdbSearcher};
+use vfts::memory::{MemoryIndexer, MemorySearcher};
+use vfts::metrics::{
+    CountingAllocator, Metrics, file_sizes_by_extension, index_bytes, read_ids, write_ids,
+};
+use vfts::parquet::{ParquetIndexer, ParquetSearcher};
+use vfts::sqlite::{SqliteIndexer, SqliteSearcher};
+use vfts::tantivy::{DEFAULT_HEAP_BYTES, TantivyIndexer, TantivySearcher, tantivy_delete};
+use vfts::vortex::{
+    Compression, DEFAULT_CHUNK_SIZE, DEFAULT_FLUSH_EVERY, DEFAULT_MAX_BUCKET_SHARE, Io,
+    ShardedVortexSearcher, Strategy, VortexIndexer, VortexSearcher, vortex_index_shards,
+    vortex_index_stdin,
+};
+use vfts::{Corpus, Indexer, Query, QueryMode, QueryTiming, Searcher, Tokenizer};
 
 #[derive(Parser, Debug)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    /// Write structured metrics for the command (including the latency and hit count of each
+    /// query) to the given path: as CSV if it ends with `.csv`, and otherwise as JSON.
+    #[arg(long, global = true, value_name = "PATH")]
+    metrics_out: Option<PathBuf>,
+    /// Record the spans of the command (such as building each chunk, creating filters and
+    /// scanning) to the given path: as folded stacks (for flamegraphs) if it ends with `.folded`,
+    /// and otherwise as a Chrome trace, which may be opened with `chrome://tracing` or Perfetto.
+    #[arg(long, global = true, value_name = "PATH")]
+    trace_out: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -22,61 +54,2750 @@ enum Command {
     Search(Search),
     #[command(subcommand)]
     SearchMany(SearchMany),
+    #[command(subcommand)]
+    Info(Info),
+    /// Index documents into an existing index, replacing any existing documents with the same
+    /// IDs.
+    #[command(subcommand)]
+    Upsert(Upsert),
+    /// Delete documents from an index by ID.
+    #[command(subcommand)]
+    Delete(Delete),
+    /// Rewrite an index without the documents which have been deleted from it.
+    #[command(subcommand)]
+    Compact(Compact),
+    /// Print the original text of a document, from an index built with `--store-bodies`.
+    #[command(subcommand)]
+    Get(Get),
+    /// Print the most frequent tokens of an index with their document frequencies, which helps
+    /// to choose `--hot-tokens` and stop words.
+    #[command(subcommand)]
+    DumpTerms(DumpTerms),
+    /// Report the average list length, distinct tokens and skew of each Multi bucket of a Vortex
+    /// index, flagging the buckets which should be split or have a token promoted.
+    AnalyzeBuckets { path: PathBuf },
+    /// Print the on-disk size of an index, broken down by the kind of file, and for a Vortex
+    /// index, by column.
+    #[command(subcommand)]
+    Size(Size),
+    /// Serve `GET /search?q=...` over HTTP, responding with the count and IDs of the matching
+    /// documents as JSON.
+    #[command(subcommand)]
+    Serve(Serve),
+    /// Write the exact bucket boundaries, tokenizer, build parameters and corpus of a Vortex
+    /// index to a JSON config file, from which `import-config` can rebuild an identical index.
+    ExportConfig { path: PathBuf, config: PathBuf },
+    /// Build a new Vortex index at `path` from a config written by `export-config`, using its
+    /// buckets rather than selecting them. The corpus must be available at the same location.
+    ImportConfig {
+        config: PathBuf,
+        path: PathBuf,
+        /// The number of chunks of documents to tokenize and build concurrently. Defaults to the
+        /// available parallelism.
+        #[arg(long, value_name = "N")]
+        index_threads: Option<usize>,
+    },
+    /// Run identical queries against a Tantivy and a Vortex index, and report any queries for
+    /// which they match different documents.
+    Verify {
+        tantivy_path: PathBuf,
+        vortex_path: PathBuf,
+        #[arg(long)]
+        queries: usize,
+        #[arg(long, value_enum, default_value_t)]
+        mode: QueryMode,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+    },
+    /// Compare two files of matching IDs written by `search --ids-out` (by different backends,
+    /// or different versions of an index), and report the IDs which are in only one of them for
+    /// each query.
+    DiffResults {
+        a: PathBuf,
+        b: PathBuf,
+        /// The number of the IDs which are in only one of the files to print for each query.
+        #[arg(long, default_value_t = 10, value_name = "N")]
+        examples: usize,
+    },
+    /// Build a memory index, a DuckDB index, an SQLite index, two Tantivy indexes (searched with
+    /// posting lists and with fast fields), and a Vortex index and a Parquet file for each bucket
+    /// count in a new directory, run the same queries against each, and print a comparison table.
+    Bench {
+        out_dir: PathBuf,
+        #[arg(long)]
+        documents: usize,
+        /// A comma-separated list of bucket counts, each of which a Vortex index and a Parquet
+        /// file are built with.
+        #[arg(long, value_delimiter = ',', default_value = "256")]
+        buckets: Vec<u16>,
+        /// The number of queries, which are synthesized from the bundled corpus.
+        #[arg(long, default_value_t = 1000)]
+        queries: usize,
+        #[arg(long, value_enum, default_value_t)]
+        mode: QueryMode,
+        /// The number of queries to execute concurrently.
+        #[arg(long, default_value_t = 1)]
+        concurrency: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+    },
+    /// Build an index in a temporary directory, run `search-many` against it, and then delete
+    /// it, reporting the build and query metrics of a single configuration.
+    #[command(subcommand)]
+    Run(Run),
+    /// Build an index for each of several settings in a temporary directory, run the same
+    /// queries against each, and print a table of their sizes and latencies.
+    #[command(subcommand)]
+    Sweep(Sweep),
+}
+
+#[derive(Debug, Subcommand)]
+enum Sweep {
+    /// Build a Vortex index for each bucket count.
+    Vortex {
+        /// A comma-separated list of bucket counts.
+        #[arg(long, value_delimiter = ',', default_value = "64,256,1024,4096")]
+        buckets: Vec<u16>,
+        #[command(flatten)]
+        args: RunArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Run {
+    Memory {
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    Duckdb {
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    Parquet {
+        #[arg(long, default_value_t = DEFAULT_BUCKETS)]
+        buckets: u16,
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    Sqlite {
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    Tantivy {
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    Vortex {
+        #[arg(long, default_value_t = DEFAULT_BUCKETS)]
+        buckets: u16,
+        #[command(flatten)]
+        args: RunArgs,
+    },
+}
+
+impl Run {
+    ///
+    /// Returns the name of the index within the directory of the run, and its backend.
+    ///
+    fn backend(&self) -> anyhow::Result<(&'static str, Box<dyn SearchBackend>)> {
+        Ok(match self {
+            Run::Memory { .. } => ("memory", Backend::<_, MemorySearcher>::boxed(MemoryIndexer)),
+            Run::Duckdb { .. } => (
+                "duckdb.db",
+                Backend::<_, DuckdbSearcher>::boxed(DuckdbIndexer::default()),
+            ),
+            Run::Parquet { buckets, .. } => (
+                "parquet.parquet",
+                Backend::<_, ParquetSearcher>::boxed(default_parquet_indexer(*buckets)),
+            ),
+            Run::Sqlite { .. } => (
+                "sqlite.db",
+                Backend::<_, SqliteSearcher>::boxed(SqliteIndexer::default()),
+            ),
+            Run::Tantivy { .. } => ("tantivy", tantivy_backend(TantivyIndexer::default(), false)),
+            Run::Vortex { buckets, .. } => ("vortex", VortexBackend::new(*buckets)?.boxed()),
+        })
+    }
+
+    fn args(&self) -> &RunArgs {
+        match self {
+            Run::Memory { args }
+            | Run::Duckdb { args }
+            | Run::Parquet { args, .. }
+            | Run::Sqlite { args }
+            | Run::Tantivy { args }
+            | Run::Vortex { args, .. } => args,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    #[arg(long)]
+    documents: usize,
+    /// The number of queries, which are synthesized from the bundled corpus.
+    #[arg(long, default_value_t = 1000)]
+    queries: usize,
+    #[arg(long, value_enum, default_value_t)]
+    mode: QueryMode,
+    /// The number of queries to execute concurrently.
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+    /// Keep the indexes (in the printed directory) rather than deleting them afterward.
+    #[arg(long)]
+    keep: bool,
+    #[command(flatten)]
+    corpus: CorpusArgs,
+    #[command(flatten)]
+    tokenizer: Tokenizer,
+}
+
+impl RunArgs {
+    fn search_many_args(&self) -> SearchManyArgs {
+        SearchManyArgs {
+            queries: self.queries,
+            queries_file: None,
+            generate: None,
+            mode: self.mode,
+            concurrency: self.concurrency,
+            result_cache: 0,
+            cache: CacheArgs {
+                cold: false,
+                warm: 0,
+                repeat: 1,
+                discard_first: false,
+            },
+            corpus: self.corpus.clone(),
+            tokenizer: self.tokenizer.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
 enum Index {
+    /// An in-memory inverted index with a roaring bitmap per token, which is a baseline for the
+    /// other backends.
+    Memory {
+        path: PathBuf,
+        documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+    },
     Tantivy {
         path: PathBuf,
         documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+        /// Store the original text of each document, which is required for `get`.
+        #[arg(long)]
+        store_bodies: bool,
+        /// Record the position of each token, which is required for `--near`.
+        #[arg(long)]
+        positions: bool,
+        /// Also store the tokens of each document in a multi-valued fast field, which is required
+        /// for `--fast-fields`.
+        #[arg(long)]
+        fast_tokens: bool,
+        /// The memory budget of the index writer, which is divided between its threads: each
+        /// thread flushes a segment when its share is full, and needs at least 15 MB.
+        #[arg(long, default_value_t = DEFAULT_HEAP_BYTES, value_name = "BYTES")]
+        heap_bytes: usize,
+        /// The number of indexing threads. Defaults to Tantivy's choice, based on the available
+        /// parallelism.
+        #[arg(long, value_name = "N")]
+        writer_threads: Option<usize>,
+        /// Commit after every `N` documents, rather than once at the end.
+        #[arg(long, value_name = "N")]
+        commit_every: Option<usize>,
+    },
+    /// A DuckDB database with a full-text index created by its `fts` extension.
+    Duckdb {
+        path: PathBuf,
+        documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+        /// Store the original text of each document, which is required for `--snippets`.
+        #[arg(long)]
+        store_bodies: bool,
+    },
+    /// A Parquet file with the same bucketed layout as a Vortex index, which is searched with
+    /// Arrow compute kernels.
+    Parquet {
+        path: PathBuf,
+        documents: usize,
+        buckets: u16,
+        /// How tokens are assigned to buckets.
+        #[arg(long, value_enum, default_value_t)]
+        strategy: Strategy,
+        /// The number of documents to sample tokens from when selecting buckets.
+        #[arg(long, default_value_t = 1000, value_name = "N")]
+        sample_docs: usize,
+        /// How the documents to select buckets from are sampled.
+        #[arg(long, value_enum, default_value_t)]
+        sample: SampleMethod,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+    },
+    /// An SQLite database containing an FTS5 full-text index.
+    Sqlite {
+        path: PathBuf,
+        documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+        /// Store the original text of each document, which is required for `--snippets`.
+        #[arg(long)]
+        store_bodies: bool,
     },
     Vortex {
         path: PathBuf,
         documents: usize,
         buckets: u16,
+        /// How tokens are assigned to buckets.
+        #[arg(long, value_enum, default_value_t)]
+        strategy: Strategy,
+        /// The number of documents to sample tokens from when selecting buckets.
+        #[arg(long, default_value_t = 1000, value_name = "N")]
+        sample_docs: usize,
+        /// How the documents to select buckets from are sampled.
+        #[arg(long, value_enum, default_value_t)]
+        sample: SampleMethod,
+        /// Read the corpus twice: first to select buckets using the exact document frequency of
+        /// every token, and then to build the index.
+        #[arg(long, conflicts_with_all = ["sample_docs", "sample"])]
+        two_pass: bool,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+        /// Record per-bucket term frequencies, which are required for `search vortex --top-k`.
+        #[arg(long)]
+        term_frequencies: bool,
+        /// Store the original text of each document, which is required for `get`.
+        #[arg(long)]
+        store_bodies: bool,
+        /// Store the number of tokens in each document, for the length normalization of scores.
+        #[arg(long)]
+        doc_lengths: bool,
+        /// Dictionary-encode the tokens in Multi buckets as integer codes.
+        #[arg(long)]
+        dictionary: bool,
+        /// Store each Single bucket as a roaring bitmap of the documents containing its token,
+        /// rather than as a column, so that queries for common tokens intersect bitmaps instead of
+        /// scanning.
+        #[arg(long, conflicts_with = "term_frequencies")]
+        posting_lists: bool,
+        /// Record the positions of the tokens in each bucket, which is required for `--near`.
+        #[arg(long, requires = "term_frequencies")]
+        positions: bool,
+        /// The number of chunks of documents to tokenize and build concurrently. Defaults to the
+        /// available parallelism.
+        #[arg(long, value_name = "N")]
+        index_threads: Option<usize>,
+        /// The number of documents in each chunk, which is also the row group size used for
+        /// pruning: smaller chunks prune more precisely, but have more overhead.
+        #[arg(long, default_value_t = DEFAULT_CHUNK_SIZE, value_name = "N")]
+        chunk_size: usize,
+        /// How the columns of each chunk are encoded.
+        #[arg(long, value_enum, default_value_t)]
+        compression: Compression,
+        /// Split any Multi bucket which receives more than this share of the token occurrences of
+        /// a new index into sub-buckets, and rebuild the index with them. 1 disables splitting.
+        #[arg(long, default_value_t = DEFAULT_MAX_BUCKET_SHARE, value_name = "SHARE")]
+        max_bucket_share: f64,
+        /// Give each of the K most frequent sampled tokens its own Single bucket, in addition to
+        /// those selected by the strategy. Not supported by the `hash` strategy.
+        #[arg(long, default_value_t = 0, value_name = "K")]
+        hot_tokens: usize,
+        /// Order the documents of the index by the Single buckets that they contain (most
+        /// frequent first) before writing them, for longer runs in the Single columns and more
+        /// row group pruning. The whole corpus is read into memory.
+        #[arg(long)]
+        sort_by_tokens: bool,
+        /// Skip each document whose set of tokens exactly matches that of an earlier document in
+        /// the same segment (as the cycled bundled corpus does), and count them in the manifest.
+        #[arg(long)]
+        dedup: bool,
+        /// With `--dedup`, also record the ID of the canonical document for the ID of each
+        /// skipped duplicate, alongside each segment.
+        #[arg(long, requires = "dedup")]
+        dedup_ids: bool,
+        /// Partition the documents by ID across `N` indexes in subdirectories of `path`, which
+        /// share the buckets selected for the first of them. Search them with `vortex-shards`.
+        #[arg(long, value_name = "N")]
+        shards: Option<usize>,
+        /// When reading `--input FORMAT -` from stdin, flush each `N` documents into a new
+        /// segment as they arrive. The buckets are selected from the first `N` documents.
+        #[arg(long, default_value_t = DEFAULT_FLUSH_EVERY, value_name = "N")]
+        flush_every: usize,
     },
+    /// Compact the segments of a Vortex index into a single segment.
+    VortexMerge { path: PathBuf },
+}
+
+///
+/// The arguments of an `index` subcommand which every backend shares.
+///
+struct IndexTarget {
+    path: PathBuf,
+    documents: usize,
+    buckets: Option<u16>,
+    corpus: CorpusArgs,
+    tokenizer: Tokenizer,
+}
+
+impl Index {
+    ///
+    /// Returns the shared arguments of the subcommand, and the backend which builds its index
+    /// with the options of the subcommand.
+    ///
+    fn backend(self) -> anyhow::Result<(IndexTarget, Box<dyn SearchBackend>)> {
+        Ok(match self {
+            Index::Memory {
+                path,
+                documents,
+                corpus,
+                tokenizer,
+            } => (
+                IndexTarget {
+                    path,
+                    documents,
+                    buckets: None,
+                    corpus,
+                    tokenizer,
+                },
+                Backend::<_, MemorySearcher>::boxed(MemoryIndexer),
+            ),
+            Index::Tantivy {
+                path,
+                documents,
+                corpus,
+                tokenizer,
+                store_bodies,
+                positions,
+                fast_tokens,
+                heap_bytes,
+                writer_threads,
+                commit_every,
+            } => (
+                IndexTarget {
+                    path,
+                    documents,
+                    buckets: None,
+                    corpus,
+                    tokenizer,
+                },
+                tantivy_backend(
+                    TantivyIndexer {
+                        store_bodies,
+                        positions,
+                        fast_tokens,
+                        heap_bytes: Some(heap_bytes),
+                        writer_threads,
+                        commit_every,
+                    },
+                    false,
+                ),
+            ),
+            Index::Duckdb {
+                path,
+                documents,
+                corpus,
+                tokenizer,
+                store_bodies,
+            } => (
+                IndexTarget {
+                    path,
+                    documents,
+                    buckets: None,
+                    corpus,
+                    tokenizer,
+                },
+                Backend::<_, DuckdbSearcher>::boxed(DuckdbIndexer { store_bodies }),
+            ),
+            Index::Parquet {
+                path,
+                documents,
+                buckets,
+                strategy,
+                sample_docs,
+                sample,
+                corpus,
+                tokenizer,
+            } => (
+                IndexTarget {
+                    path,
+                    documents,
+                    buckets: Some(buckets),
+                    corpus,
+                    tokenizer,
+                },
+                Backend::<_, ParquetSearcher>::boxed(ParquetIndexer {
+                    buckets,
+                    strategy,
+                    sample_docs,
+                    sample,
+                }),
+            ),
+            Index::Sqlite {
+                path,
+                documents,
+                corpus,
+                tokenizer,
+                store_bodies,
+            } => (
+                IndexTarget {
+                    path,
+                    documents,
+                    buckets: None,
+                    corpus,
+                    tokenizer,
+                },
+                Backend::<_, SqliteSearcher>::boxed(SqliteIndexer { store_bodies }),
+            ),
+            Index::Vortex {
+                path,
+                documents,
+                buckets,
+                strategy,
+                sample_docs,
+                sample,
+                two_pass,
+                corpus,
+                tokenizer,
+                term_frequencies,
+                store_bodies,
+                doc_lengths,
+                dictionary,
+                posting_lists,
+                positions,
+                index_threads,
+                chunk_size,
+                compression,
+                max_bucket_share,
+                hot_tokens,
+                sort_by_tokens,
+                dedup,
+                dedup_ids,
+                shards,
+                flush_every,
+            } => {
+                let indexer = VortexIndexer {
+                    buckets,
+                    strategy,
+                    sample_docs,
+                    sample,
+                    two_pass,
+                    term_frequencies,
+                    store_bodies,
+                    doc_lengths,
+                    dictionary,
+                    posting_lists,
+                    positions,
+                    index_threads: match index_threads {
+                        Some(index_threads) => index_threads,
+                        None => std::thread::available_parallelism()?.get(),
+                    },
+                    chunk_size,
+                    compression,
+                    max_bucket_share,
+                    hot_tokens,
+                    sort_by_tokens,
+                    dedup,
+                    duplicate_ids: dedup_ids,
+                };
+                let backend = VortexBackend {
+                    indexer,
+                    shards,
+                    flush_every,
+                    ..VortexBackend::new(buckets)?
+                };
+                (
+                    IndexTarget {
+                        path,
+                        documents,
+                        buckets: Some(buckets),
+                        corpus,
+                        tokenizer,
+                    },
+                    backend.boxed(),
+                )
+            }
+            Index::VortexMerge { .. } => unreachable!("vortex-merge does not build an index"),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+struct CorpusArgs {
+    /// A directory of `.txt` files (or `.txt.gz` or `.txt.zst` files) to index, rather than the
+    /// bundled text.
+    #[arg(long, conflicts_with_all = ["input", "synthetic"])]
+    corpus: Option<PathBuf>,
+    /// Treat each line of a `--corpus` file as a separate document.
+    #[arg(long, requires = "corpus")]
+    per_line: bool,
+    /// Rather than one document per line, separate the documents of the bundled text (or of a
+    /// `--corpus` file) by lines equal to the delimiter. An empty delimiter (`""`) makes each
+    /// paragraph a document.
+    #[arg(long, conflicts_with_all = ["input", "synthetic", "doc_size_lines"])]
+    doc_delimiter: Option<String>,
+    /// Rather than one document per line, merge each block of `N` lines of the bundled text (or
+    /// of a `--corpus` file) into a document.
+    #[arg(long, value_name = "N", conflicts_with_all = ["input", "synthetic"])]
+    doc_size_lines: Option<usize>,
+    /// A file to index in the given format, rather than the bundled text, or `-` to read it from
+    /// stdin. Supported formats: `jsonl`, `arrow` (an Arrow IPC stream), and `warc` (a WARC or
+    /// WET file, whose target URIs become string IDs), and `wiki` (a MediaWiki XML dump of
+    /// articles, with a `title` field). A file ending in `.gz`, `.zst` or `.bz2` is decompressed
+    /// as it is read.
+    #[arg(long, num_args = 2, value_names = ["FORMAT", "PATH"], conflicts_with = "synthetic")]
+    input: Option<Vec<String>>,
+    /// A comma-separated list of named fields (such as `title,tags`) to index from each `--input`
+    /// document in addition to its body, which may then be queried as `title:henry`.
+    #[arg(long, value_delimiter = ',', requires = "input")]
+    fields: Vec<String>,
+    /// The field of each `--input` document which holds its ID: either a u64, or a string (which
+    /// is stored alongside the document, and may be used to `get` or `delete` it).
+    #[arg(long, default_value = "id", requires = "input")]
+    id_field: String,
+    /// Generate a reproducible corpus of words with the given distribution, rather than indexing
+    /// the bundled text.
+    #[arg(long, value_enum)]
+    synthetic: Option<SyntheticDistribution>,
+    /// The number of distinct words in a `--synthetic` corpus.
+    #[arg(long, default_value_t = 100_000, requires = "synthetic")]
+    vocab: usize,
+    /// The number of words in each document of a `--synthetic` corpus.
+    #[arg(long, default_value_t = 200, requires = "synthetic")]
+    doc_len: usize,
+    /// The random seed for a `--synthetic` corpus.
+    #[arg(long, default_value_t = 42, requires = "synthetic")]
+    seed: u64,
+}
+
+impl CorpusArgs {
+    fn into_corpus(self) -> anyhow::Result<Corpus> {
+        let grouping = match (self.doc_delimiter, self.doc_size_lines) {
+            (Some(delimiter), _) => LineGrouping::Delimiter(delimiter),
+            (None, Some(0)) => bail!("--doc-size-lines must be at least 1."),
+            (None, Some(size)) => LineGrouping::Lines(size),
+            (None, None) => LineGrouping::Line,
+        };
+        if let Some(path) = self.corpus {
+            return Ok(Corpus::Directory {
+                path,
+                // NB: Grouping lines implies that a file is split into documents.
+                per_line: self.per_line || grouping != LineGrouping::Line,
+                grouping,
+            });
+        }
+        if let Some(distribution) = self.synthetic {
+            return Ok(Corpus::Synthetic {
+                distribution,
+                vocab: self.vocab,
+                doc_len: self.doc_len,
+                seed: self.seed,
+            });
+        }
+        for field in &self.fields {
+            if !is_field_name(field) || field == BODY_FIELD {
+                bail!(
+                    "Invalid field name {field:?}: field names may only contain lowercase \
+                    letters, digits and underscores, and may not be `{BODY_FIELD}`."
+                );
+            }
+        }
+        if [BODY_FIELD, "timestamp"].contains(&self.id_field.as_str())
+            || self.fields.contains(&self.id_field)
+        {
+            bail!(
+                "The ID field {:?} may not be `{BODY_FIELD}`, `timestamp` or a named field.",
+                self.id_field
+            );
+        }
+        match self.input.as_deref() {
+            Some([format, path]) => match format.as_str() {
+                "jsonl" => Ok(Corpus::Jsonl {
+                    path: path.into(),
+                    fields: self.fields,
+                    id_field: self.id_field,
+                }),
+                "arrow" => Ok(Corpus::Arrow {
+                    path: path.into(),
+                    fields: self.fields,
+                    id_field: self.id_field,
+                }),
+                "warc" if !self.fields.is_empty() => {
+                    bail!("WARC input does not have named fields.")
+                }
+                "warc" => Ok(Corpus::Warc { path: path.into() }),
+                "wiki" if !self.fields.is_empty() => {
+                    bail!("Wiki input always has exactly one named field: `title`.")
+                }
+                "wiki" => Ok(Corpus::Wiki { path: path.into() }),
+                format => Err(anyhow!("Unsupported input format: {format}")),
+            },
+            Some(_) => unreachable!("clap requires exactly two values for --input"),
+            None => Ok(Corpus::Bundled { grouping }),
+        }
+    }
+}
+
+///
+/// Other than for Vortex, `info` prints the total size of an index and of each kind of file.
+///
+#[derive(Debug, Subcommand)]
+enum Info {
+    Memory {
+        path: PathBuf,
+    },
+    Duckdb {
+        path: PathBuf,
+    },
+    Parquet {
+        path: PathBuf,
+    },
+    Sqlite {
+        path: PathBuf,
+    },
+    Tantivy {
+        path: PathBuf,
+    },
+    /// Print bucket, row group and column size statistics for a Vortex index.
+    Vortex {
+        path: PathBuf,
+    },
+}
+
+impl Info {
+    ///
+    /// Returns the backend which describes the index.
+    ///
+    fn backend(&self) -> anyhow::Result<Box<dyn SearchBackend>> {
+        Ok(match self {
+            Info::Memory { .. } => Backend::<_, MemorySearcher>::boxed(MemoryIndexer),
+            Info::Duckdb { .. } => Backend::<_, DuckdbSearcher>::boxed(DuckdbIndexer::default()),
+            Info::Parquet { .. } => {
+                Backend::<_, ParquetSearcher>::boxed(default_parquet_indexer(DEFAULT_BUCKETS))
+            }
+            Info::Sqlite { .. } => Backend::<_, SqliteSearcher>::boxed(SqliteIndexer::default()),
+            Info::Tantivy { .. } => tantivy_backend(TantivyIndexer::default(), false),
+            Info::Vortex { .. } => VortexBackend::new(DEFAULT_BUCKETS)?.boxed(),
+        })
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Info::Memory { path }
+            | Info::Duckdb { path }
+            | Info::Parquet { path }
+            | Info::Sqlite { path }
+            | Info::Tantivy { path }
+            | Info::Vortex { path } => path,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Upsert {
+    Tantivy {
+        path: PathBuf,
+        documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+        /// Store the original text of each document, which is required for `get`.
+        #[arg(long)]
+        store_bodies: bool,
+    },
+    /// The documents are written to a new segment using the existing buckets and settings of the
+    /// index, and the documents which they replace are deleted from the existing segments.
+    Vortex {
+        path: PathBuf,
+        documents: usize,
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[command(flatten)]
+        tokenizer: Tokenizer,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Delete {
+    Tantivy {
+        path: PathBuf,
+        #[command(flatten)]
+        ids: DeleteIds,
+    },
+    /// Deleted documents are recorded in a tombstone file, and excluded from searches until the
+    /// index is compacted.
+    Vortex {
+        path: PathBuf,
+        #[command(flatten)]
+        ids: DeleteIds,
+    },
+}
+
+#[derive(Debug, Args)]
+struct DeleteIds {
+    /// A comma-separated list of the IDs of the documents to delete.
+    #[arg(long, value_delimiter = ',', required_unless_present = "keys")]
+    ids: Vec<u64>,
+    /// A comma-separated list of the external string IDs of the documents to delete.
+    #[arg(long, value_delimiter = ',')]
+    keys: Vec<String>,
+}
+
+impl DeleteIds {
+    fn ids(&self) -> Vec<u64> {
+        let keys = self.keys.iter().map(|key| string_id(key));
+        self.ids.iter().copied().chain(keys).collect()
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Compact {
+    /// Rewrite the segments of a Vortex index into a single segment without deleted documents.
+    Vortex { path: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum Get {
+    Tantivy {
+        path: PathBuf,
+        #[command(flatten)]
+        id: GetId,
+    },
+    Vortex {
+        path: PathBuf,
+        #[command(flatten)]
+        id: GetId,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Size {
+    Memory { path: PathBuf },
+    Tantivy { path: PathBuf },
+    Duckdb { path: PathBuf },
+    Parquet { path: PathBuf },
+    Sqlite { path: PathBuf },
+    Vortex { path: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum DumpTerms {
+    Tantivy {
+        path: PathBuf,
+        #[command(flatten)]
+        args: DumpTermsArgs,
+    },
+    Vortex {
+        path: PathBuf,
+        #[command(flatten)]
+        args: DumpTermsArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+struct DumpTermsArgs {
+    /// The number of tokens to print.
+    #[arg(long, default_value_t = 100, value_name = "K")]
+    top: usize,
+}
+
+#[derive(Debug, Args)]
+struct GetId {
+    /// The ID of the document.
+    #[arg(required_unless_present = "key")]
+    id: Option<u64>,
+    /// The external string ID of the document, rather than its ID.
+    #[arg(long, conflicts_with = "id")]
+    key: Option<String>,
+}
+
+impl GetId {
+    fn id(&self) -> u64 {
+        match &self.key {
+            Some(key) => string_id(key),
+            None => self.id.expect("clap requires an ID or a key"),
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
 enum Search {
-    Tantivy { path: PathBuf, query: String },
-    Vortex { path: PathBuf, query: String },
+    Memory {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+    Duckdb {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+    Parquet {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+    Sqlite {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+    Tantivy {
+        path: PathBuf,
+        /// Evaluate the query by scanning a fast field of each document's tokens, rather than by
+        /// reading posting lists. Requires an index built with `--fast-tokens`.
+        #[arg(long)]
+        fast_fields: bool,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+    Vortex {
+        #[arg(required_unless_present = "uri")]
+        path: Option<PathBuf>,
+        /// Search an index in an object store (such as `s3://bucket/path/to/index`) rather than
+        /// on the local filesystem.
+        #[arg(long, conflicts_with = "path")]
+        uri: Option<String>,
+        /// How the segments of a local index are read.
+        #[arg(long, value_enum, default_value_t)]
+        io: Io,
+        #[command(flatten)]
+        args: SearchArgs,
+        #[command(flatten)]
+        vortex: VortexSearchArgs,
+    },
+    /// A Vortex index built with `--shards`, whose shards are searched concurrently.
+    VortexShards {
+        path: PathBuf,
+        /// How the segments of the shards are read.
+        #[arg(long, value_enum, default_value_t)]
+        io: Io,
+        #[command(flatten)]
+        args: SearchArgs,
+    },
+}
+
+impl Search {
+    ///
+    /// Returns the path and arguments of the search, and the backend which executes it with the
+    /// options of the subcommand.
+    ///
+    fn backend(self) -> anyhow::Result<(PathBuf, SearchArgs, Box<dyn SearchBackend>)> {
+        Ok(match self {
+            Search::Memory { path, args } => (
+                path,
+                args,
+                Backend::<_, MemorySearcher>::boxed(MemoryIndexer),
+            ),
+            Search::Duckdb { path, args } => (
+                path,
+                args,
+                Backend::<_, DuckdbSearcher>::boxed(DuckdbIndexer::default()),
+            ),
+            Search::Parquet { path, args } => (
+                path,
+                args,
+                Backend::<_, ParquetSearcher>::boxed(default_parquet_indexer(DEFAULT_BUCKETS)),
+            ),
+            Search::Sqlite { path, args } => (
+                path,
+                args,
+                Backend::<_, SqliteSearcher>::boxed(SqliteIndexer::default()),
+            ),
+            Search::Tantivy {
+                path,
+                fast_fields,
+                args,
+            } => (
+                path,
+                args,
+                tantivy_backend(TantivyIndexer::default(), fast_fields),
+            ),
+            Search::Vortex {
+                path,
+                uri,
+                io,
+                args,
+                vortex,
+            } => {
+                let backend = VortexBackend {
+                    uri,
+                    io,
+                    search: vortex,
+                    ..VortexBackend::new(DEFAULT_BUCKETS)?
+                };
+                // NB: clap requires either a path or `--uri`, which is opened instead of it.
+                (path.unwrap_or_default(), args, backend.boxed())
+            }
+            Search::VortexShards { path, io, args } => (path, args, vortex_shards_backend(io)?),
+        })
+    }
+}
+
+///
+/// The options of `search vortex` which the other backends do not support.
+///
+#[derive(Debug, Default, Args)]
+struct VortexSearchArgs {
+    /// The maximum number of scan tasks (each decoding part of a row group) in flight at once
+    /// for the query. By default, all of them are started at once.
+    #[arg(long, value_name = "N")]
+    scan_concurrency: Option<usize>,
+    /// Divide the rows to scan into splits of at most `N` rows, each of which is decoded by
+    /// its own scan tasks. By default, each run of unpruned row groups is a split.
+    #[arg(long, value_name = "N")]
+    split_size_rows: Option<u64>,
+    /// Rank matches with BM25, and print the IDs of the best `N`.
+    #[arg(long, value_name = "N", conflicts_with = "limit")]
+    top_k: Option<usize>,
+    /// Print the IDs of up to `N` matches. Unless `--no-score` is set, the matches are ranked
+    /// as for `--top-k`.
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// Print the first `--limit` matches without ranking them, which allows the scan to stop
+    /// as soon as enough matches have been observed.
+    #[arg(long, requires = "limit")]
+    no_score: bool,
+    /// Rank matches by the number of query tokens that they contain rather than with BM25,
+    /// and print each ID with its count. Usually combined with `--mode any`.
+    #[arg(long, conflicts_with = "no_score")]
+    match_counts: bool,
+    /// Before executing the query, print the bucket that each token maps to, the estimated
+    /// cost of each token (in the order they are evaluated, and whether via a column or a
+    /// posting list), the filter expression, and how many row groups will be skipped using
+    /// the row group statistics.
+    /// After counting its matches, print the rows and row groups scanned and the bytes read.
+    #[arg(long)]
+    explain: bool,
+}
+
+#[derive(Debug, Args)]
+struct SearchArgs {
+    /// The query, in which words may be combined with `AND`, `OR` and `NOT` (or `-`), grouped
+    /// with parentheses, quoted as `"phrases"`, and prefixed with a field name as in `title:a`.
+    #[arg(required_unless_present = "near")]
+    query: Option<String>,
+    #[arg(long, value_enum, default_value_t)]
+    mode: QueryMode,
+    /// Treat every word of the query as a prefix, as if it were suffixed with `*`. Requires an
+    /// index built with `--edge-ngrams`.
+    #[arg(long)]
+    prefix: bool,
+    /// Print a highlighted snippet of each of up to `N` matching documents. Requires an index
+    /// built with `--store-bodies`.
+    #[arg(long, value_name = "N")]
+    snippets: Option<usize>,
+    /// Print the number of matching documents which contain each token of the given named
+    /// field.
+    #[arg(long, value_name = "FIELD")]
+    facet: Option<String>,
+    /// Write the IDs of all of the matching documents (and the query) to a `.parquet` or `.csv`
+    /// file, which `diff-results` can compare to the results of another backend.
+    #[arg(long, value_name = "PATH")]
+    ids_out: Option<PathBuf>,
+    /// Only match documents in which each word of the phrase follows the previous one with at
+    /// most `N` other words between them, as in `"a b"~3`. Requires an index built with
+    /// `--positions`.
+    #[arg(long, value_name = "PHRASE")]
+    near: Option<String>,
+    /// Match each word of the query against the indexed words which are within `N` edits
+    /// (insertions, deletions, substitutions or transpositions) of it.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=2)
+    )]
+    fuzzy: u8,
+    /// Also count the matches by tokenizing and scanning the first `N` documents of the corpus
+    /// (from which the index must have been built), and fail if the counts differ.
+    #[arg(long, value_name = "N")]
+    check: Option<usize>,
+    #[command(flatten)]
+    cache: CacheArgs,
+    #[command(flatten)]
+    corpus: CorpusArgs,
+    #[command(flatten)]
+    tokenizer: Tokenizer,
+}
+
+#[derive(Debug, Args)]
+struct CacheArgs {
+    /// Drop the OS page cache (which requires root on Linux) and reopen the index before each
+    /// timed query, so that latencies reflect reads from storage.
+    #[arg(long, conflicts_with = "warm")]
+    cold: bool,
+    /// Execute the queries `N` times untimed before timing them, so that latencies reflect a
+    /// warm page cache.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    warm: usize,
+    /// Execute the timed workload `N` times, and report the mean, standard deviation and minimum
+    /// of its latency across the runs. Other results are reported for the final run.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    repeat: u32,
+    /// Exclude the first of the repeated runs from the mean, standard deviation and minimum.
+    #[arg(long, requires = "repeat")]
+    discard_first: bool,
+}
+
+impl CacheArgs {
+    ///
+    /// Prints the mean, standard deviation and minimum of the latencies of repeated runs, unless
+    /// there was only one run.
+    ///
+    fn report_runs(&self, runs: &[Duration]) {
+        let kept = match runs {
+            [_, rest @ ..] if self.discard_first => rest,
+            runs => runs,
+        };
+        if runs.len() < 2 {
+            return;
+        }
+        let mut latencies = Latencies::default();
+        for &latency in kept {
+            latencies.record(latency);
+        }
+        println!(
+            ">>> {} runs: mean={:?} stddev={:?} min={:?}",
+            kept.len(),
+            latencies.mean(),
+            latencies.stddev(),
+            latencies.min(),
+        );
+    }
+}
+
+///
+/// Executes a timed run (which returns its result and latency) `--repeat` times, and returns
+/// the result and latency of the final run after reporting the latencies of all of the runs.
+///
+async fn repeated<T>(
+    cache: &CacheArgs,
+    mut run: impl AsyncFnMut() -> anyhow::Result<(T, Duration)>,
+) -> anyhow::Result<(T, Duration)> {
+    let mut runs = Vec::with_capacity(cache.repeat as usize);
+    let mut last = None;
+    for _ in 0..cache.repeat {
+        let (result, latency) = run().await?;
+        runs.push(latency);
+        last = Some((result, latency));
+    }
+    cache.report_runs(&runs);
+    Ok(last.expect("--repeat is at least one"))
+}
+
+impl SearchArgs {
+    fn query(&self) -> anyhow::Result<Query> {
+        let text = self.query.as_deref().unwrap_or_default();
+        let mut query = if self.prefix {
+            // NB: Operators, and the words of phrases, are not prefixes.
+            let mut in_phrase = false;
+            let text = text
+                .split_whitespace()
+                .map(|word| {
+                    let was_in_phrase = in_phrase;
+                    in_phrase ^= word.matches('"').count() % 2 == 1;
+                    let trimmed = word.trim_end_matches(')');
+                    let closing = &word[trimmed.len()..];
+                    let trimmed = trimmed.trim_end_matches(PREFIX_MARKER);
+                    if was_in_phrase
+                        || trimmed.contains('"')
+                        || matches!(trimmed, "" | "-" | "AND" | "OR" | "NOT")
+                    {
+                        word.to_owned()
+                    } else {
+                        format!("{trimmed}{PREFIX_MARKER}{closing}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            Query::parse(&text, self.mode, &self.tokenizer)
+        } else {
+            Query::parse(text, self.mode, &self.tokenizer)
+        };
+        query.fuzzy = self.fuzzy;
+        match &self.near {
+            Some(near) => query.with_near(Near::parse(near, &self.tokenizer)?),
+            None => Ok(query),
+        }
+    }
+
+    ///
+    /// The text of the query (and of its `--near` phrase, if any), which identifies it in the
+    /// `--ids-out` file.
+    ///
+    fn text(&self) -> String {
+        let query = self.query.as_deref().unwrap_or_default();
+        match &self.near {
+            Some(near) => format!("{query} --near {near}").trim_start().to_owned(),
+            None => query.to_owned(),
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
 enum SearchMany {
-    Tantivy { path: PathBuf, queries: usize },
-    Vortex { path: PathBuf, queries: usize },
+    Memory {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    Duckdb {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    Parquet {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    Sqlite {
+        path: PathBuf,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    Tantivy {
+        path: PathBuf,
+        /// Evaluate the queries by scanning a fast field of each document's tokens, rather than
+        /// by reading posting lists. Requires an index built with `--fast-tokens`.
+        #[arg(long)]
+        fast_fields: bool,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    Vortex {
+        path: PathBuf,
+        /// How the segments of the index are read.
+        #[arg(long, value_enum, default_value_t)]
+        io: Io,
+        /// Count the matches for `B` queries at a time with a single scan of the index, rather
+        /// than scanning it once per query. Each query's latency is that of its batch.
+        #[arg(
+            long,
+            value_name = "B",
+            conflicts_with_all = ["cold", "concurrency", "result_cache"]
+        )]
+        batch: Option<usize>,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+    /// A Vortex index built with `--shards`, whose shards are searched concurrently.
+    VortexShards {
+        path: PathBuf,
+        /// How the segments of the shards are read.
+        #[arg(long, value_enum, default_value_t)]
+        io: Io,
+        #[command(flatten)]
+        args: SearchManyArgs,
+    },
+}
+
+impl SearchMany {
+    ///
+    /// Returns the path and arguments of the searches, and the backend which executes them with
+    /// the options of the subcommand.
+    ///
+    fn backend(self) -> anyhow::Result<(PathBuf, SearchManyArgs, Box<dyn SearchBackend>)> {
+        Ok(match self {
+            SearchMany::Memory { path, args } => (
+                path,
+                args,
+                Backend::<_, MemorySearcher>::boxed(MemoryIndexer),
+            ),
+            SearchMany::Duckdb { path, args } => (
+                path,
+                args,
+                Backend::<_, DuckdbSearcher>::boxed(DuckdbIndexer::default()),
+            ),
+            SearchMany::Parquet { path, args } => (
+                path,
+                args,
+                Backend::<_, ParquetSearcher>::boxed(default_parquet_indexer(DEFAULT_BUCKETS)),
+            ),
+            SearchMany::Sqlite { path, args } => (
+                path,
+                args,
+                Backend::<_, SqliteSearcher>::boxed(SqliteIndexer::default()),
+            ),
+            SearchMany::Tantivy {
+                path,
+                fast_fields,
+                args,
+            } => (
+                path,
+                args,
+                tantivy_backend(TantivyIndexer::default(), fast_fields),
+            ),
+            SearchMany::Vortex {
+                path,
+                io,
+                batch,
+                args,
+            } => {
+                let backend = VortexBackend {
+                    io,
+                    batch,
+                    ..VortexBackend::new(DEFAULT_BUCKETS)?
+                };
+                (path, args, backend.boxed())
+            }
+            SearchMany::VortexShards { path, io, args } => (path, args, vortex_shards_backend(io)?),
+        })
+    }
 }
 
+#[derive(Debug, Args)]
+struct SearchManyArgs {
+    queries: usize,
+    /// Replay queries from a file containing one query per line (in the syntax of `search`),
+    /// cycling through it until `queries` have been executed, rather than synthesizing queries
+    /// from the bundled corpus.
+    #[arg(long, value_name = "PATH")]
+    queries_file: Option<PathBuf>,
+    /// Generate queries of random tokens from a document frequency stratum of the first `docs`
+    /// documents of the corpus, with settings like `terms=3 df=rare|common|any docs=1000 seed=7`,
+    /// rather than using whole documents of the bundled corpus as queries.
+    #[arg(
+        long = "gen",
+        value_name = "KEY=VALUE",
+        num_args = 1..,
+        conflicts_with = "queries_file"
+    )]
+    generate: Option<Vec<String>>,
+    #[arg(long, value_enum, default_value_t)]
+    mode: QueryMode,
+    /// The number of queries to execute concurrently.
+    #[arg(long, default_value_t = 1, conflicts_with = "cold")]
+    concurrency: usize,
+    /// Serve repeated queries from an LRU cache of the results of up to `N` distinct queries,
+    /// and report its hit rate. The cache is populated by any warm-up iterations.
+    #[arg(
+        long = "cache",
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "cold"
+    )]
+    result_cache: usize,
+    #[command(flatten)]
+    cache: CacheArgs,
+    #[command(flatten)]
+    corpus: CorpusArgs,
+    #[command(flatten)]
+    tokenizer: Tokenizer,
+}
+
+#[derive(Debug, Subcommand)]
+enum Serve {
+    Tantivy {
+        path: PathBuf,
+        #[command(flatten)]
+        args: ServeArgs,
+    },
+    Vortex {
+        path: PathBuf,
+        #[command(flatten)]
+        args: ServeArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// The address to listen on. Only local connections are accepted by default.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: IpAddr,
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+    /// The mode for requests which do not specify a `mode` parameter.
+    #[arg(long, value_enum, default_value_t)]
+    mode: QueryMode,
+    #[command(flatten)]
+    tokenizer: Tokenizer,
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The bucket count of the indexes built by `run`, unless otherwise specified.
+const DEFAULT_BUCKETS: u16 = 256;
+
+/// The number of bytes in a mebibyte, for reporting memory usage.
+const MIB: f64 = (1 << 20) as f64;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+    // NB: The trace is flushed when the guard is dropped, at the end of the command.
+    let _trace = cli.trace_out.as_deref().map(trace).transpose()?;
+    let mut metrics = Metrics::default();
+    if let Some((command, subcommand)) = matches.subcommand() {
+        metrics.command = command.to_owned();
+        metrics.backend = subcommand.subcommand_name().map(str::to_owned);
+    }
 
     let start = Instant::now();
     match cli.command {
-        Command::Index(Index::Tantivy { path, documents }) => {
-            crate::tantivy::tantivy_index(&path, documents)?
+        Command::Index(Index::VortexMerge { path }) => {
+            vfts::vortex::vortex_merge(&path).await?;
+            metrics.index_bytes = Some(index_bytes(&path)?);
+        }
+        Command::Index(index) => {
+            let (target, backend) = index.backend()?;
+            let corpus = target.corpus.into_corpus()?;
+            backend
+                .index(&target.path, &corpus, &target.tokenizer, target.documents)
+                .await?;
+            metrics.documents = Some(target.documents);
+            metrics.buckets = target.buckets;
+            metrics.index_bytes = Some(index_bytes(&target.path)?);
+        }
+        Command::Search(search) => {
+            let (path, args, backend) = search.backend()?;
+            backend.search(&path, &args, &mut metrics).await?
+        }
+        Command::SearchMany(search_many) => {
+            let (path, args, backend) = search_many.backend()?;
+            backend.search_many(&path, args, &mut metrics).await?
+        }
+        Command::Info(info) => info.backend()?.info(info.path()).await?,
+        Command::Upsert(Upsert::Tantivy {
+            path,
+            documents,
+            corpus,
+            tokenizer,
+            store_bodies,
+        }) => {
+            // NB: Whether positions and fast tokens are recorded follows the existing index.
+            TantivyIndexer {
+                store_bodies,
+                ..TantivyIndexer::default()
+            }
+            .upsert(&path, &corpus.into_corpus()?, &tokenizer, documents)
+            .await?
         }
-        Command::Index(Index::Vortex {
+        Command::Upsert(Upsert::Vortex {
             path,
             documents,
+            corpus,
+            tokenizer,
+        }) => {
+            vfts::vortex::vortex_upsert(&path, &corpus.into_corpus()?, &tokenizer, documents)
+                .await?
+        }
+        Command::Delete(Delete::Tantivy { path, ids }) => {
+            let ids = ids.ids();
+            tantivy_delete(&path, &ids)?;
+            println!(">>> deleted {} documents from {path:?}", ids.len());
+        }
+        Command::Delete(Delete::Vortex { path, ids }) => {
+            vfts::vortex::vortex_delete(&path, &ids.ids()).await?
+        }
+        Command::Compact(Compact::Vortex { path }) => vfts::vortex::vortex_compact(&path).await?,
+        Command::Get(Get::Tantivy { path, id }) => {
+            let id = id.id();
+            let body = TantivySearcher::open(&path).await?.body(id).await?;
+            println!(
+                "{}",
+                body.ok_or_else(|| anyhow!("No stored body for document {id}"))?
+            );
+        }
+        Command::Get(Get::Vortex { path, id }) => {
+            let id = id.id();
+            let body = VortexSearcher::open(&path).await?.body(id).await?;
+            println!(
+                "{}",
+                body.ok_or_else(|| anyhow!("No stored body for document {id}"))?
+            );
+        }
+        Command::DumpTerms(DumpTerms::Tantivy { path, args }) => {
+            let terms = TantivySearcher::open(&path)
+                .await?
+                .term_frequencies()
+                .await?;
+            print_terms(&terms, args.top);
+        }
+        Command::DumpTerms(DumpTerms::Vortex { path, args }) => {
+            let terms = VortexSearcher::open(&path)
+                .await?
+                .term_frequencies()
+                .await?;
+            print_terms(&terms, args.top);
+        }
+        Command::AnalyzeBuckets { path } => vfts::vortex::vortex_analyze_buckets(&path).await?,
+        Command::Size(Size::Vortex { path }) => vfts::vortex::vortex_size(&path).await?,
+        Command::Size(
+            Size::Memory { path }
+            | Size::Tantivy { path }
+            | Size::Duckdb { path }
+            | Size::Parquet { path }
+            | Size::Sqlite { path },
+        ) => print_file_sizes(&path)?,
+        Command::Serve(Serve::Tantivy { path, args }) => {
+            let searcher = TantivySearcher::open(&path).await?;
+            vfts::serve::serve(searcher, args.host, args.port, args.tokenizer, args.mode).await?
+        }
+        Command::Serve(Serve::Vortex { path, args }) => {
+            let searcher = VortexSearcher::open(&path).await?;
+            vfts::serve::serve(searcher, args.host, args.port, args.tokenizer, args.mode).await?
+        }
+        Command::ExportConfig { path, config } => {
+            vfts::vortex::vortex_export_config(&path, &config).await?
+        }
+        Command::ImportConfig {
+            config,
+            path,
+            index_threads,
+        } => {
+            let index_threads = match index_threads {
+                Some(index_threads) => index_threads,
+                None => std::thread::available_parallelism()?.get(),
+            };
+            vfts::vortex::vortex_import_config(&config, &path, index_threads).await?
+        }
+        Command::Verify {
+            tantivy_path,
+            vortex_path,
+            queries,
+            mode,
+            tokenizer,
+        } => verify(&tantivy_path, &vortex_path, queries, mode, &tokenizer).await?,
+        Command::DiffResults { a, b, examples } => diff_results(&a, &b, examples)?,
+        Command::Bench {
+            out_dir,
+            documents,
             buckets,
-        }) => crate::vortex::vortex_index(&path, documents, buckets).await?,
-        Command::Search(Search::Tantivy { path, query }) => {
-            crate::tantivy::tantivy_search(&path, &query)?
+            queries,
+            mode,
+            concurrency,
+            corpus,
+            tokenizer,
+        } => {
+            let corpus = corpus.into_corpus()?;
+            let queries = vfts::common::documents(&tokenizer, queries)
+                .map(|(_, document)| Query::new(document.into_keys().collect(), mode))
+                .collect::<Vec<_>>();
+            bench(
+                &out_dir,
+                &corpus,
+                &tokenizer,
+                documents,
+                &buckets,
+                &queries,
+                concurrency.max(1),
+            )
+            .await?
+        }
+        Command::Run(command) => run(command, &mut metrics).await?,
+        Command::Sweep(Sweep::Vortex { buckets, args }) => {
+            metrics.documents = Some(args.documents);
+            sweep_vortex(&buckets, &args).await?
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(">>> elapsed: {elapsed:?}");
+    metrics.record_memory();
+    if let (Some(rss), Some(allocated)) = (metrics.peak_rss_bytes, metrics.peak_allocated_bytes) {
+        println!(
+            ">>> peak memory: {:.1} MiB resident, {:.1} MiB allocated",
+            rss as f64 / MIB,
+            allocated as f64 / MIB
+        );
+    }
+
+    if let Some(metrics_out) = cli.metrics_out {
+        metrics.elapsed_us = elapsed.as_micros() as u64;
+        metrics.write(&metrics_out)?;
+    }
+
+    Ok(())
+}
+
+///
+/// A backend which the CLI can build, search and describe. Each subcommand builds the backend of
+/// its variant (with the options of that backend), and then dispatches through the
+/// `Box<dyn SearchBackend>`, rather than a match arm per backend.
+///
+trait SearchBackend {
+    fn index<'a>(
+        &'a self,
+        path: &'a Path,
+        corpus: &'a Corpus,
+        tokenizer: &'a Tokenizer,
+        doc_count: usize,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
+    fn search<'a>(
+        &'a self,
+        path: &'a Path,
+        args: &'a SearchArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
+    fn search_many<'a>(
+        &'a self,
+        path: &'a Path,
+        args: SearchManyArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
+    fn info<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+}
+
+///
+/// Opens an index as an `S`, with the options of its backend.
+///
+type Opener<S> = Box<dyn Fn(&Path) -> LocalBoxFuture<'_, anyhow::Result<S>>>;
+
+///
+/// The `SearchBackend` which builds indexes with an `I`, opens them as an `S`, and prints the
+/// sizes of their files for `info`.
+///
+struct Backend<I, S> {
+    indexer: I,
+    open: Opener<S>,
+}
+
+impl<I: Indexer + 'static, S: Searcher + 'static> Backend<I, S> {
+    fn boxed(indexer: I) -> Box<dyn SearchBackend> {
+        Self::with_open(indexer, |path| S::open(path).boxed_local())
+    }
+
+    fn with_open(
+        indexer: I,
+        open: impl Fn(&Path) -> LocalBoxFuture<'_, anyhow::Result<S>> + 'static,
+    ) -> Box<dyn SearchBackend> {
+        Box::new(Backend {
+            indexer,
+            open: Box::new(open),
+        })
+    }
+}
+
+impl<I: Indexer, S: Searcher> SearchBackend for Backend<I, S> {
+    fn index<'a>(
+        &'a self,
+        path: &'a Path,
+        corpus: &'a Corpus,
+        tokenizer: &'a Tokenizer,
+        doc_count: usize,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        self.indexer
+            .index(path, corpus, tokenizer, doc_count)
+            .boxed_local()
+    }
+
+    fn search<'a>(
+        &'a self,
+        path: &'a Path,
+        args: &'a SearchArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            if args.cache.cold {
+                drop_page_cache();
+            }
+            let searcher = (self.open)(path).await?;
+            searcher.check_tokenizer(&args.tokenizer)?;
+            search_with(&searcher, args, metrics).await
+        }
+        .boxed_local()
+    }
+
+    fn search_many<'a>(
+        &'a self,
+        path: &'a Path,
+        args: SearchManyArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        search_many(path, args, metrics, async |path: &Path| {
+            (self.open)(path).await
+        })
+        .boxed_local()
+    }
+
+    fn info<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move { print_file_sizes(path) }.boxed_local()
+    }
+}
+
+///
+/// The Tantivy backend, which evaluates queries by scanning fast fields if `fast_fields` is set.
+///
+fn tantivy_backend(indexer: TantivyIndexer, fast_fields: bool) -> Box<dyn SearchBackend> {
+    Backend::<_, TantivySearcher>::with_open(indexer, move |path| {
+        async move {
+            let searcher = TantivySearcher::open(path).await?;
+            if fast_fields {
+                searcher.with_fast_fields()
+            } else {
+                Ok(searcher)
+            }
+        }
+        .boxed_local()
+    })
+}
+
+///
+/// The backend of a Vortex index built with `--shards`, whose segments are read with `io`.
+///
+fn vortex_shards_backend(io: Io) -> anyhow::Result<Box<dyn SearchBackend>> {
+    Ok(Backend::<_, ShardedVortexSearcher>::with_open(
+        default_vortex_indexer(DEFAULT_BUCKETS)?,
+        move |path| ShardedVortexSearcher::open_with_io(path, io).boxed_local(),
+    ))
+}
+
+///
+/// The Vortex backend, which may index from stdin or into shards, may rank or limit the matches
+/// of a search, and whose `info` describes its buckets, row groups and columns.
+///
+struct VortexBackend {
+    indexer: VortexIndexer,
+    shards: Option<usize>,
+    flush_every: usize,
+    uri: Option<String>,
+    io: Io,
+    batch: Option<usize>,
+    search: VortexSearchArgs,
+}
+
+impl VortexBackend {
+    ///
+    /// The Vortex backend with the default settings of each of its subcommands.
+    ///
+    fn new(buckets: u16) -> anyhow::Result<Self> {
+        Ok(VortexBackend {
+            indexer: default_vortex_indexer(buckets)?,
+            shards: None,
+            flush_every: DEFAULT_FLUSH_EVERY,
+            uri: None,
+            io: Io::default(),
+            batch: None,
+            search: VortexSearchArgs::default(),
+        })
+    }
+
+    fn boxed(self) -> Box<dyn SearchBackend> {
+        Box::new(self)
+    }
+
+    ///
+    /// Opens the index at `path` (or at `--uri`, if it was given).
+    ///
+    async fn open(&self, path: &Path) -> anyhow::Result<VortexSearcher> {
+        let searcher = match &self.uri {
+            Some(uri) => VortexSearcher::open_uri(uri).await?,
+            None => VortexSearcher::open_with_io(path, self.io).await?,
+        };
+        let searcher = match self.search.scan_concurrency {
+            Some(concurrency) => searcher.with_scan_concurrency(concurrency),
+            None => searcher,
+        };
+        Ok(match self.search.split_size_rows {
+            Some(rows) => searcher.with_split_size_rows(rows),
+            None => searcher,
+        })
+    }
+
+    async fn build(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        let buckets = self.indexer.buckets;
+        if corpus.is_stdin() && (self.shards.is_some() || self.indexer.two_pass) {
+            bail!("Reading from stdin cannot be combined with --shards or --two-pass.");
+        }
+        match self.shards {
+            None if corpus.is_stdin() => {
+                vortex_index_stdin(
+                    &self.indexer,
+                    path,
+                    corpus,
+                    tokenizer,
+                    doc_count,
+                    self.flush_every,
+                )
+                .await?;
+                println!(">>> created {path:?}, with up to {buckets} buckets");
+            }
+            Some(shards) => {
+                vortex_index_shards(&self.indexer, path, corpus, tokenizer, doc_count, shards)
+                    .await?;
+                println!(">>> created {path:?}, with {shards} shards of up to {buckets} buckets");
+            }
+            None => {
+                self.indexer
+                    .index(path, corpus, tokenizer, doc_count)
+                    .await?;
+                println!(">>> created {path:?}, with up to {buckets} buckets");
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Counts the matches for the query, or if `--top-k` or `--limit` is set, prints the IDs of
+    /// (the best of) them.
+    ///
+    async fn search_query(
+        &self,
+        path: &Path,
+        args: &SearchArgs,
+        metrics: &mut Metrics,
+    ) -> anyhow::Result<()> {
+        let VortexSearchArgs {
+            top_k,
+            limit,
+            no_score,
+            match_counts,
+            explain,
+            ..
+        } = self.search;
+        if match_counts && top_k.is_none() && limit.is_none() {
+            bail!("--match-counts requires --top-k or --limit.");
+        }
+        if args.check.is_some() && (top_k.is_some() || limit.is_some()) {
+            bail!("--check cannot be combined with --top-k or --limit.");
+        }
+        if args.cache.cold {
+            drop_page_cache();
+        }
+        let searcher = self.open(path).await?;
+        searcher.check_tokenizer(&args.tokenizer)?;
+        let query = args.query()?;
+        searcher.check_query(&query)?;
+        if explain {
+            for line in searcher.explain(&query).lines() {
+                println!(">>> {line}");
+            }
+        }
+        let top_k = if no_score { top_k } else { top_k.or(limit) };
+        match (top_k, limit) {
+            (Some(k), _) if match_counts => {
+                for _ in 0..args.cache.warm {
+                    searcher.match_counts(&query, k).await?;
+                }
+                let (counted, latency) = repeated(&args.cache, async || {
+                    let start = Instant::now();
+                    let counted = searcher.match_counts(&query, k).await?;
+                    Ok((counted, start.elapsed()))
+                })
+                .await?;
+                metrics.record_query(counted.len(), latency);
+                for (id, count) in counted {
+                    println!(">>> {id}\t{count}");
+                }
+                println!(">>> latency: {latency:?}");
+            }
+            (Some(k), _) => {
+                for _ in 0..args.cache.warm {
+                    searcher.top_k(&query, k).await?;
+                }
+                let (top_k, latency) = repeated(&args.cache, async || {
+                    let start = Instant::now();
+                    let top_k = searcher.top_k(&query, k).await?;
+                    Ok((top_k, start.elapsed()))
+                })
+                .await?;
+                metrics.record_query(top_k.len(), latency);
+                for (id, score) in top_k {
+                    println!(">>> {id}\t{score:.4}");
+                }
+                println!(">>> latency: {latency:?}");
+            }
+            (None, Some(limit)) => {
+                for _ in 0..args.cache.warm {
+                    searcher.first_ids(&query, limit).await?;
+                }
+                let (ids, latency) = repeated(&args.cache, async || {
+                    let start = Instant::now();
+                    let ids = searcher.first_ids(&query, limit).await?;
+                    Ok((ids, start.elapsed()))
+                })
+                .await?;
+                metrics.record_query(ids.len(), latency);
+                for id in ids {
+                    println!(">>> {id}");
+                }
+                println!(">>> latency: {latency:?}");
+            }
+            (None, None) => {
+                for _ in 0..args.cache.warm {
+                    searcher.count(&query).await?;
+                }
+                let ((count, stats), latency) = repeated(&args.cache, async || {
+                    let start = Instant::now();
+                    let counted = searcher.count_with_stats(&query).await?;
+                    Ok((counted, start.elapsed()))
+                })
+                .await?;
+                metrics.record_scan(count, latency, stats);
+                println!(">>> {count}");
+                println!(">>> latency: {latency:?}");
+                check_count(args, &query, count)?;
+                if explain {
+                    println!(
+                        ">>> scanned {} rows in {} row groups, reading {} bytes",
+                        stats.rows, stats.row_groups, stats.bytes_read
+                    );
+                }
+                print_snippets(&searcher, args, &query).await?;
+                print_facets(&searcher, args, &query).await?;
+            }
+        }
+        write_ids_out(&searcher, args, &query).await
+    }
+
+    async fn search_queries(
+        &self,
+        path: &Path,
+        args: SearchManyArgs,
+        metrics: &mut Metrics,
+    ) -> anyhow::Result<()> {
+        match self.batch {
+            Some(batch) => {
+                let searcher = self.open(path).await?;
+                search_many_batched(&searcher, args, batch.max(1), metrics).await
+            }
+            None => {
+                search_many(path, args, metrics, async |path: &Path| {
+                    self.open(path).await
+                })
+                .await
+            }
+        }
+    }
+}
+
+impl SearchBackend for VortexBackend {
+    fn index<'a>(
+        &'a self,
+        path: &'a Path,
+        corpus: &'a Corpus,
+        tokenizer: &'a Tokenizer,
+        doc_count: usize,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        self.build(path, corpus, tokenizer, doc_count).boxed_local()
+    }
+
+    fn search<'a>(
+        &'a self,
+        path: &'a Path,
+        args: &'a SearchArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        self.search_query(path, args, metrics).boxed_local()
+    }
+
+    fn search_many<'a>(
+        &'a self,
+        path: &'a Path,
+        args: SearchManyArgs,
+        metrics: &'a mut Metrics,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        self.search_queries(path, args, metrics).boxed_local()
+    }
+
+    fn info<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        vfts::vortex::vortex_info(path).boxed_local()
+    }
+}
+
+///
+/// Prints the total size of the index at `path`, and of each kind of file in it.
+///
+fn print_file_sizes(path: &Path) -> anyhow::Result<()> {
+    println!(">>> total: {} bytes", index_bytes(path)?);
+    for (kind, bytes) in file_sizes_by_extension(path)? {
+        println!(">>> {kind}\t{bytes}");
+    }
+    Ok(())
+}
+
+///
+/// Opens the index at `path`, and checks that it is compatible with the tokenizer which queries
+/// will be parsed with.
+///
+async fn open<S: Searcher>(path: &Path, tokenizer: &Tokenizer) -> anyhow::Result<S> {
+    let searcher = S::open(path).await?;
+    searcher.check_tokenizer(tokenizer)?;
+    Ok(searcher)
+}
+
+///
+/// Executes the query against an opened searcher, and prints its results.
+///
+async fn search_with<S: Searcher>(
+    searcher: &S,
+    args: &SearchArgs,
+    metrics: &mut Metrics,
+) -> anyhow::Result<()> {
+    let query = args.query()?;
+    searcher.check_query(&query)?;
+
+    timed_count(searcher, args, &query, metrics).await?;
+    print_snippets(searcher, args, &query).await?;
+    print_facets(searcher, args, &query).await?;
+    write_ids_out(searcher, args, &query).await
+}
+
+///
+/// Counts the matches for the query after any warm-up iterations, and prints the count and the
+/// latency of the (final) timed iteration.
+///
+async fn timed_count<S: Searcher>(
+    searcher: &S,
+    args: &SearchArgs,
+    query: &Query,
+    metrics: &mut Metrics,
+) -> anyhow::Result<()> {
+    for _ in 0..args.cache.warm {
+        searcher.count(query).await?;
+    }
+    let (count, latency) = repeated(&args.cache, async || {
+        let start = Instant::now();
+        let count = searcher.count(query).await?;
+        Ok((count, start.elapsed()))
+    })
+    .await?;
+    metrics.record_query(count, latency);
+
+    println!(">>> {count}");
+    println!(">>> latency: {latency:?}");
+    check_count(args, query, count)
+}
+
+///
+/// If `--check` is set, counts the matches for the query by scanning the corpus, and fails if
+/// the count of the index differs.
+///
+fn check_count(args: &SearchArgs, query: &Query, count: usize) -> anyhow::Result<()> {
+    let Some(documents) = args.check else {
+        return Ok(());
+    };
+    let corpus = args.corpus.clone().into_corpus()?;
+    let expected = corpus.scan_count(&args.tokenizer, documents, query)?;
+    if count != expected {
+        bail!("The index matched {count} documents, but a scan of the corpus matched {expected}.");
+    }
+    println!(">>> check: a scan of the corpus also matched {expected} docs");
+    Ok(())
+}
+
+///
+/// Installs a subscriber which records spans to the given path, and returns a guard which
+/// flushes the file when it is dropped.
+///
+fn trace(path: &Path) -> anyhow::Result<Box<dyn Any>> {
+    let guard: Box<dyn Any> = if path.extension().is_some_and(|ext| ext == "folded") {
+        let (layer, guard) = tracing_flame::FlameLayer::with_file(path)?;
+        tracing_subscriber::registry().with(layer).init();
+        Box::new(guard)
+    } else {
+        let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+            .file(path)
+            .include_args(true)
+            .build();
+        tracing_subscriber::registry().with(layer).init();
+        Box::new(guard)
+    };
+    Ok(guard)
+}
+
+///
+/// Attempts to drop the OS page cache, so that subsequent reads come from storage. This requires
+/// root on Linux (and is unsupported elsewhere), so a failure is reported once and otherwise
+/// ignored: the index is still reopened.
+///
+fn drop_page_cache() {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    let dropped = std::process::Command::new("sync")
+        .status()
+        .and_then(|_| std::fs::write("/proc/sys/vm/drop_caches", "3"));
+    if let Err(e) = dropped
+        && !WARNED.swap(true, Ordering::Relaxed)
+    {
+        println!(">>> could not drop the page cache: {e}");
+    }
+}
+
+async fn print_snippets<S: Searcher>(
+    searcher: &S,
+    args: &SearchArgs,
+    query: &Query,
+) -> anyhow::Result<()> {
+    let Some(limit) = args.snippets else {
+        return Ok(());
+    };
+    for (id, snippet) in searcher.snippets(query, &args.tokenizer, limit).await? {
+        println!(">>> {id}\t{snippet}");
+    }
+    Ok(())
+}
+
+async fn print_facets<S: Searcher>(
+    searcher: &S,
+    args: &SearchArgs,
+    query: &Query,
+) -> anyhow::Result<()> {
+    let Some(field) = &args.facet else {
+        return Ok(());
+    };
+    for (value, count) in searcher.facet_counts(query, field).await? {
+        println!(">>> {field}:{value}\t{count}");
+    }
+    Ok(())
+}
+
+async fn write_ids_out<S: Searcher>(
+    searcher: &S,
+    args: &SearchArgs,
+    query: &Query,
+) -> anyhow::Result<()> {
+    let Some(path) = &args.ids_out else {
+        return Ok(());
+    };
+    let ids = searcher.ids(query).await?;
+    write_ids(path, &args.text(), &ids)?;
+    println!(">>> wrote {} IDs to {path:?}", ids.len());
+    Ok(())
+}
+
+fn print_terms(terms: &[(String, usize)], top: usize) {
+    for (token, count) in terms.iter().take(top) {
+        println!("{token}\t{count}");
+    }
+    println!(">>> {} distinct tokens", terms.len());
+}
+
+///
+/// Reads the queries for `search-many` from `--queries-file`, or synthesizes them from the
+/// bundled corpus.
+///
+fn search_many_queries(args: &SearchManyArgs) -> anyhow::Result<Vec<Query>> {
+    Ok(match &args.queries_file {
+        Some(queries_file) => {
+            let contents = std::fs::read_to_string(queries_file)
+                .with_context(|| format!("Reading {queries_file:?}"))?;
+            let lines = contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect::<Vec<_>>();
+            if lines.is_empty() {
+                bail!("{queries_file:?} does not contain any queries.");
+            }
+            lines
+                .into_iter()
+                .cycle()
+                .take(args.queries)
+                .map(|line| Query::parse(line, args.mode, &args.tokenizer))
+                .collect::<Vec<_>>()
+        }
+        None => match &args.generate {
+            Some(settings) => {
+                let corpus = args.corpus.clone().into_corpus()?;
+                QueryGenerator::parse(settings.iter().map(String::as_str))?.queries(
+                    &corpus,
+                    &args.tokenizer,
+                    args.mode,
+                    args.queries,
+                )?
+            }
+            None => vfts::common::documents(&args.tokenizer, args.queries)
+                .map(|(_, document)| Query::new(document.into_keys().collect(), args.mode))
+                .collect::<Vec<_>>(),
+        },
+    })
+}
+
+///
+/// Executes the queries concurrently against a single searcher, or if `--cold` is set, executes
+/// each query sequentially against a freshly opened searcher. Searchers are opened with `open`.
+///
+async fn search_many<S: Searcher>(
+    path: &Path,
+    args: SearchManyArgs,
+    metrics: &mut Metrics,
+    open: impl AsyncFn(&Path) -> anyhow::Result<S>,
+) -> anyhow::Result<()> {
+    let queries = search_many_queries(&args)?;
+
+    let (results, elapsed) = if args.cache.cold {
+        repeated(&args.cache, async || {
+            let mut results = Vec::with_capacity(queries.len());
+            for query in &queries {
+                drop_page_cache();
+                let searcher = open(path).await?;
+                searcher.check_tokenizer(&args.tokenizer)?;
+                let start = Instant::now();
+                let (count, setup) = searcher.count_with_setup(query).await?;
+                results.push(QueryTiming {
+                    count,
+                    setup,
+                    latency: start.elapsed(),
+                });
+            }
+            // NB: Time spent reopening the index is excluded.
+            let elapsed = results.iter().map(|timing| timing.latency).sum();
+            Ok((results, elapsed))
+        })
+        .await?
+    } else {
+        let searcher = CachingSearcher::new(open(path).await?, args.result_cache);
+        searcher.check_tokenizer(&args.tokenizer)?;
+        for _ in 0..args.cache.warm {
+            searcher
+                .count_concurrently(queries.clone(), args.concurrency.max(1))
+                .await?;
+        }
+        let (results, elapsed) = repeated(&args.cache, async || {
+            searcher.reset_hits_and_misses();
+            let queries = queries.clone();
+            let start = Instant::now();
+            let results = searcher
+                .count_concurrently(queries, args.concurrency.max(1))
+                .await?;
+            Ok((results, start.elapsed()))
+        })
+        .await?;
+        if args.result_cache > 0 {
+            let (hits, misses) = searcher.hits_and_misses();
+            println!(
+                ">>> cache: {hits} hits, {misses} misses ({:.1}% hit rate)",
+                100.0 * hits as f64 / (hits + misses).max(1) as f64
+            );
+        }
+        (results, elapsed)
+    };
+    report_search_many(args.queries, results, elapsed, metrics);
+    Ok(())
+}
+
+///
+/// Counts the matches for the queries in batches of `batch` queries, each of which is evaluated
+/// with a single scan of the index.
+///
+async fn search_many_batched(
+    searcher: &VortexSearcher,
+    args: SearchManyArgs,
+    batch: usize,
+    metrics: &mut Metrics,
+) -> anyhow::Result<()> {
+    searcher.check_tokenizer(&args.tokenizer)?;
+    let queries = search_many_queries(&args)?;
+    for query in &queries {
+        searcher.check_query(query)?;
+    }
+    for _ in 0..args.cache.warm {
+        for queries in queries.chunks(batch) {
+            searcher.count_batch(queries).await?;
+        }
+    }
+
+    let (results, elapsed) = repeated(&args.cache, async || {
+        let mut results = Vec::with_capacity(queries.len());
+        let start = Instant::now();
+        for queries in queries.chunks(batch) {
+            let batch_start = Instant::now();
+            let (counts, setup) = searcher.count_batch(queries).await?;
+            let latency = batch_start.elapsed();
+            results.extend(counts.into_iter().map(|count| QueryTiming {
+                count,
+                setup,
+                latency,
+            }));
+        }
+        Ok((results, start.elapsed()))
+    })
+    .await?;
+    println!(
+        ">>> {} scans of up to {batch} queries",
+        queries.len().div_ceil(batch)
+    );
+    report_search_many(args.queries, results, elapsed, metrics);
+    Ok(())
+}
+
+///
+/// Prints the number of matches and the latencies of the queries of `search-many`.
+///
+fn report_search_many(
+    queries: usize,
+    results: Vec<QueryTiming>,
+    elapsed: Duration,
+    metrics: &mut Metrics,
+) {
+    let mut matches = 0;
+    let mut latencies = Latencies::default();
+    let mut setups = Latencies::default();
+    let mut executions = Latencies::default();
+    for timing in results {
+        matches += timing.count;
+        latencies.record(timing.latency);
+        setups.record(timing.setup);
+        executions.record(timing.latency.saturating_sub(timing.setup));
+        metrics.record_timing(timing);
+    }
+
+    println!(">>> {queries} queries matched {matches} docs");
+    println!(">>> latency: {}", latencies.summary(elapsed));
+    for (name, latencies) in [("setup", &mut setups), ("execution", &mut executions)] {
+        println!(
+            ">>> {name}: p50={:?} p99={:?} total={:?}",
+            latencies.percentile(50.0),
+            latencies.percentile(99.0),
+            latencies.total(),
+        );
+    }
+}
+
+///
+/// The measurements of one index built by `bench`.
+///
+struct BenchResult {
+    name: String,
+    index_bytes: u64,
+    build: Duration,
+    latencies: Latencies,
+    elapsed: Duration,
+}
+
+async fn bench(
+    out_dir: &Path,
+    corpus: &Corpus,
+    tokenizer: &Tokenizer,
+    documents: usize,
+    buckets: &[u16],
+    queries: &[Query],
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    if out_dir.exists() && std::fs::read_dir(out_dir)?.next().is_some() {
+        bail!("{out_dir:?} is not empty.");
+    }
+
+    let mut results = Vec::with_capacity(2 * buckets.len() + 5);
+    let path = out_dir.join("memory");
+    let start = Instant::now();
+    MemoryIndexer
+        .index(&path, corpus, tokenizer, documents)
+        .await?;
+    let build = start.elapsed();
+    results.push(
+        bench_queries(
+            "memory".to_owned(),
+            &path,
+            build,
+            queries,
+            concurrency,
+            MemorySearcher::open,
+        )
+        .await?,
+    );
+
+    std::fs::create_dir_all(out_dir)?;
+    let path = out_dir.join("duckdb.db");
+    let start = Instant::now();
+    DuckdbIndexer::default()
+        .index(&path, corpus, tokenizer, documents)
+        .await?;
+    let build = start.elapsed();
+    results.push(
+        bench_queries(
+            "duckdb".to_owned(),
+            &path,
+            build,
+            queries,
+            concurrency,
+            DuckdbSearcher::open,
+        )
+        .await?,
+    );
+
+    let path = out_dir.join("sqlite.db");
+    let start = Instant::now();
+    SqliteIndexer::default()
+        .index(&path, corpus, tokenizer, documents)
+        .await?;
+    let build = start.elapsed();
+    results.push(
+        bench_queries(
+            "sqlite".to_owned(),
+            &path,
+            build,
+            queries,
+            concurrency,
+            SqliteSearcher::open,
+        )
+        .await?,
+    );
+
+    let path = out_dir.join("tantivy");
+    std::fs::create_dir_all(&path)?;
+    let start = Instant::now();
+    TantivyIndexer::default()
+        .index(&path, corpus, tokenizer, documents)
+        .await?;
+    let build = start.elapsed();
+    results.push(
+        bench_queries(
+            "tantivy".to_owned(),
+            &path,
+            build,
+            queries,
+            concurrency,
+            TantivySearcher::open,
+        )
+        .await?,
+    );
+
+    let path = out_dir.join("tantivy-fast-fields");
+    std::fs::create_dir_all(&path)?;
+    let start = Instant::now();
+    TantivyIndexer {
+        fast_tokens: true,
+        ..TantivyIndexer::default()
+    }
+    .index(&path, corpus, tokenizer, documents)
+    .await?;
+    let build = start.elapsed();
+    let open = async |path: &Path| TantivySearcher::open(path).await?.with_fast_fields();
+    let name = "tantivy (fast fields)".to_owned();
+    results.push(bench_queries(name, &path, build, queries, concurrency, open).await?);
+
+    for buckets in buckets {
+        let path = out_dir.join(format!("vortex-{buckets}"));
+        let start = Instant::now();
+        default_vortex_indexer(*buckets)?
+            .index(&path, corpus, tokenizer, documents)
+            .await?;
+        let build = start.elapsed();
+        let name = format!("vortex ({buckets} buckets)");
+        results.push(
+            bench_queries(
+                name,
+                &path,
+                build,
+                queries,
+                concurrency,
+                VortexSearcher::open,
+            )
+            .await?,
+        );
+
+        let path = out_dir.join(format!("parquet-{buckets}.parquet"));
+        let start = Instant::now();
+        default_parquet_indexer(*buckets)
+            .index(&path, corpus, tokenizer, documents)
+            .await?;
+        let build = start.elapsed();
+        let name = format!("parquet ({buckets} buckets)");
+        results.push(
+            bench_queries(
+                name,
+                &path,
+                build,
+                queries,
+                concurrency,
+                ParquetSearcher::open,
+            )
+            .await?,
+        );
+    }
+
+    print_bench_results(results);
+    Ok(())
+}
+
+///
+/// Prints a table of the size, build time and query latencies of each index.
+///
+fn print_bench_results(results: Vec<BenchResult>) {
+    println!(">>> index\tsize\tbuild\tp50\tp99\tqps");
+    for mut result in results {
+        println!(
+            ">>> {}\t{}\t{:?}\t{:?}\t{:?}\t{:.1}",
+            result.name,
+            result.index_bytes,
+            result.build,
+            result.latencies.percentile(50.0),
+            result.latencies.percentile(99.0),
+            result.latencies.qps(result.elapsed),
+        );
+    }
+}
+
+///
+/// A Vortex indexer with the default settings of `index vortex` for the given bucket count.
+///
+fn default_vortex_indexer(buckets: u16) -> anyhow::Result<VortexIndexer> {
+    Ok(VortexIndexer {
+        buckets,
+        strategy: Strategy::default(),
+        sample_docs: 1000,
+        sample: SampleMethod::default(),
+        two_pass: false,
+        term_frequencies: false,
+        store_bodies: false,
+        doc_lengths: false,
+        dictionary: false,
+        posting_lists: false,
+        positions: false,
+        index_threads: std::thread::available_parallelism()?.get(),
+        chunk_size: DEFAULT_CHUNK_SIZE,
+        compression: Compression::default(),
+        max_bucket_share: DEFAULT_MAX_BUCKET_SHARE,
+        hot_tokens: 0,
+        sort_by_tokens: false,
+        dedup: false,
+        duplicate_ids: false,
+    })
+}
+
+///
+/// A Parquet indexer with the default settings of `index parquet` for the given bucket count.
+///
+fn default_parquet_indexer(buckets: u16) -> ParquetIndexer {
+    ParquetIndexer {
+        buckets,
+        strategy: Strategy::default(),
+        sample_docs: 1000,
+        sample: SampleMethod::default(),
+    }
+}
+
+///
+/// Builds an index in a temporary directory and runs `search-many` against it, and then deletes
+/// the directory (whether or not the run succeeded) unless `--keep` is set.
+///
+async fn run(command: Run, metrics: &mut Metrics) -> anyhow::Result<()> {
+    let dir = create_temp_dir("run")?;
+    let keep = command.args().keep;
+    let result = run_in(&dir, command, metrics).await;
+    remove_temp_dir(&dir, keep)?;
+    result
+}
+
+///
+/// Builds a Vortex index with each bucket count in a temporary directory, runs the same queries
+/// against each, and prints a table of the results. The directory is deleted unless `--keep`
+/// is set.
+///
+async fn sweep_vortex(buckets: &[u16], args: &RunArgs) -> anyhow::Result<()> {
+    let dir = create_temp_dir("sweep")?;
+    let result = sweep_vortex_in(&dir, buckets, args).await;
+    remove_temp_dir(&dir, args.keep)?;
+    print_bench_results(result?);
+    Ok(())
+}
+
+async fn sweep_vortex_in(
+    dir: &Path,
+    buckets: &[u16],
+    args: &RunArgs,
+) -> anyhow::Result<Vec<BenchResult>> {
+    let corpus = args.corpus.clone().into_corpus()?;
+    let queries = vfts::common::documents(&args.tokenizer, args.queries)
+        .map(|(_, document)| Query::new(document.into_keys().collect(), args.mode))
+        .collect::<Vec<_>>();
+    let mut results = Vec::with_capacity(buckets.len());
+    for buckets in buckets {
+        let path = dir.join(format!("vortex-{buckets}"));
+        let start = Instant::now();
+        default_vortex_indexer(*buckets)?
+            .index(&path, &corpus, &args.tokenizer, args.documents)
+            .await?;
+        let build = start.elapsed();
+        let name = format!("vortex ({buckets} buckets)");
+        let concurrency = args.concurrency.max(1);
+        let open = VortexSearcher::open;
+        results.push(bench_queries(name, &path, build, &queries, concurrency, open).await?);
+    }
+    Ok(results)
+}
+
+///
+/// Creates a new directory for the indexes of `run` or `sweep`, named for the command and the
+/// current process.
+///
+fn create_temp_dir(command: &str) -> anyhow::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("vfts-{command}-{}", std::process::id()));
+    if dir.exists() {
+        bail!("{dir:?} already exists.");
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+///
+/// Deletes a directory created by `create_temp_dir`, unless `--keep` is set.
+///
+fn remove_temp_dir(dir: &Path, keep: bool) -> anyhow::Result<()> {
+    if keep {
+        println!(">>> kept the indexes in {dir:?}");
+        return Ok(());
+    }
+    std::fs::remove_dir_all(dir).with_context(|| format!("Removing {dir:?}"))
+}
+
+async fn run_in(dir: &Path, command: Run, metrics: &mut Metrics) -> anyhow::Result<()> {
+    let args = command.args();
+    let corpus = args.corpus.clone().into_corpus()?;
+    let (tokenizer, documents) = (&args.tokenizer, args.documents);
+    let (name, backend) = command.backend()?;
+    let path = dir.join(name);
+    if matches!(command, Run::Tantivy { .. }) {
+        std::fs::create_dir_all(&path)?;
+    }
+    let start = Instant::now();
+    backend.index(&path, &corpus, tokenizer, documents).await?;
+    let build = start.elapsed();
+    let bytes = index_bytes(&path)?;
+    metrics.documents = Some(documents);
+    metrics.index_bytes = Some(bytes);
+    if let Run::Parquet { buckets, .. } | Run::Vortex { buckets, .. } = command {
+        metrics.buckets = Some(buckets);
+    }
+    println!(">>> built {documents} docs in {build:?} ({bytes} bytes)");
+
+    backend
+        .search_many(&path, args.search_many_args(), metrics)
+        .await
+}
+
+async fn bench_queries<S: Searcher>(
+    name: String,
+    path: &Path,
+    build: Duration,
+    queries: &[Query],
+    concurrency: usize,
+    open: impl AsyncFn(&Path) -> anyhow::Result<S>,
+) -> anyhow::Result<BenchResult> {
+    let searcher = open(path).await?;
+    let start = Instant::now();
+    let results = searcher
+        .count_concurrently(queries.to_vec(), concurrency)
+        .await?;
+    let elapsed = start.elapsed();
+
+    let mut latencies = Latencies::default();
+    for timing in results {
+        latencies.record(timing.latency);
+    }
+    Ok(BenchResult {
+        name,
+        index_bytes: index_bytes(path)?,
+        build,
+        latencies,
+        elapsed,
+    })
+}
+
+async fn verify(
+    tantivy_path: &Path,
+    vortex_path: &Path,
+    queries: usize,
+    mode: QueryMode,
+    tokenizer: &Tokenizer,
+) -> anyhow::Result<()> {
+    let tantivy = TantivySearcher::open(tantivy_path).await?;
+    let vortex = open::<VortexSearcher>(vortex_path, tokenizer).await?;
+
+    let mut disagreements = 0;
+    for (_, document) in vfts::common::documents(tokenizer, queries) {
+        let query = Query::new(document.into_keys().collect(), mode);
+        let tantivy_ids: BTreeSet<u64> = tantivy.ids(&query).await?.into_iter().collect();
+        let vortex_ids: BTreeSet<u64> = vortex.ids(&query).await?.into_iter().collect();
+        if tantivy_ids == vortex_ids {
+            continue;
         }
-        Command::Search(Search::Vortex { path, query }) => {
-            crate::vortex::vortex_search(&path, &query).await?
+
+        disagreements += 1;
+        let mut tokens = query.tokens.into_iter().collect::<Vec<_>>();
+        tokens.sort_unstable();
+        println!(">>> query {tokens:?}:");
+        for (name, ids) in [
+            ("tantivy", tantivy_ids.difference(&vortex_ids)),
+            ("vortex", vortex_ids.difference(&tantivy_ids)),
+        ] {
+            let ids = ids.collect::<Vec<_>>();
+            println!(
+                ">>>   only in {name}: {} docs, e.g. {:?}",
+                ids.len(),
+                &ids[..ids.len().min(10)]
+            );
         }
-        Command::SearchMany(SearchMany::Tantivy { path, queries }) => {
-            crate::tantivy::tantivy_search_many(&path, queries)?
+    }
+
+    if disagreements > 0 {
+        bail!("{disagreements} of {queries} queries disagreed");
+    }
+    println!(">>> all {queries} queries agreed");
+    Ok(())
+}
+
+fn diff_results(a_path: &Path, b_path: &Path, examples: usize) -> anyhow::Result<()> {
+    let a = read_ids(a_path)?;
+    let b = read_ids(b_path)?;
+    let queries = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+    let empty = BTreeSet::new();
+
+    let mut disagreements = 0;
+    for query in &queries {
+        let a_ids = a.get(*query).unwrap_or(&empty);
+        let b_ids = b.get(*query).unwrap_or(&empty);
+        if a_ids == b_ids {
+            continue;
         }
-        Command::SearchMany(SearchMany::Vortex { path, queries }) => {
-            crate::vortex::vortex_search_many(&path, queries).await?
+
+        disagreements += 1;
+        println!(
+            ">>> query {query:?}: {} in both, {} in either",
+            a_ids.intersection(b_ids).count(),
+            a_ids.union(b_ids).count()
+        );
+        for (path, ids) in [
+            (a_path, a_ids.difference(b_ids)),
+            (b_path, b_ids.difference(a_ids)),
+        ] {
+            let ids = ids.collect::<Vec<_>>();
+            println!(
+                ">>>   only in {path:?}: {} docs, e.g. {:?}",
+                ids.len(),
+                &ids[..ids.len().min(examples)]
+            );
         }
     }
-    println!(">>> elapsed: {:?}", start.elapsed());
 
+    if disagreements > 0 {
+        bail!("{disagreements} of {} queries disagreed", queries.len());
+    }
+    println!(">>> all {} queries agreed", queries.len());
     Ok(())
 }
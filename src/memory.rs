@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::bail;
+use roaring::{MultiOps, RoaringBitmap};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Corpus, PREFIX_MARKER, Query, QueryExpr, QueryMode, Tokenizer, field_token};
+use crate::{Indexer, Searcher};
+
+/// The file containing the ID and timestamp of each document, in ordinal order.
+const DOCUMENTS_FILE: &str = "documents.json";
+/// The file containing the posting list of each token.
+const POSTINGS_FILE: &str = "postings.bin";
+
+///
+/// The documents of an index, in the order of the ordinals used by its posting lists.
+///
+#[derive(Deserialize, Serialize)]
+struct Documents {
+    ids: Vec<u64>,
+    /// The timestamp of each document, if the corpus had timestamps.
+    timestamps: Option<Vec<Option<u64>>>,
+}
+
+///
+/// Builds an inverted index with a `RoaringBitmap` of document ordinals per token, which is
+/// loaded entirely into memory to be searched. It has no bucketing, compression tradeoffs or
+/// I/O at query time, and so serves as a speed-of-light baseline for the other backends.
+///
+/// The index directory contains a file of document IDs (and timestamps), and a file of
+/// length-prefixed tokens each followed by its serialized bitmap.
+///
+#[derive(Clone, Debug, Default)]
+pub struct MemoryIndexer;
+
+impl Indexer for MemoryIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        let mut documents = Documents {
+            ids: Vec::new(),
+            timestamps: corpus.timestamps().then(Vec::new),
+        };
+        let mut postings: BTreeMap<String, RoaringBitmap> = BTreeMap::new();
+        for text in corpus.texts(doc_count)? {
+            let text = text?;
+            let Ok(ordinal) = u32::try_from(documents.ids.len()) else {
+                bail!(
+                    "The memory backend supports at most {} documents.",
+                    u32::MAX
+                );
+            };
+            for token in tokenizer.text_term_frequencies(&text).into_keys() {
+                postings.entry(token).or_default().insert(ordinal);
+            }
+            documents.ids.push(text.id);
+            if let Some(timestamps) = &mut documents.timestamps {
+                timestamps.push(text.timestamp);
+            }
+        }
+
+        std::fs::create_dir_all(path)?;
+        std::fs::write(path.join(DOCUMENTS_FILE), serde_json::to_vec(&documents)?)?;
+        let mut writer = BufWriter::new(std::fs::File::create(path.join(POSTINGS_FILE))?);
+        for (token, bitmap) in &postings {
+            writer.write_all(&(token.len() as u32).to_le_bytes())?;
+            writer.write_all(token.as_bytes())?;
+            bitmap.serialize_into(&mut writer)?;
+        }
+        writer.flush()?;
+        println!(
+            ">>> created {path:?}, with {} tokens for {} documents",
+            postings.len(),
+            documents.ids.len()
+        );
+        Ok(())
+    }
+}
+
+pub struct MemorySearcher {
+    documents: Documents,
+    postings: BTreeMap<String, RoaringBitmap>,
+}
+
+impl MemorySearcher {
+    ///
+    /// Returns the ordinals of the documents which match the query. A query without any tokens
+    /// matches nothing, as in the other backends.
+    ///
+    fn matches(&self, query: &Query) -> RoaringBitmap {
+        if let Some(expr) = &query.expr {
+            return self.expr_matches(expr);
+        }
+        let mut postings = query
+            .tokens
+            .iter()
+            .map(|token| self.postings.get(token))
+            .collect::<Vec<_>>();
+        let mut matches = match query.mode {
+            QueryMode::All if postings.iter().any(Option::is_none) => RoaringBitmap::new(),
+            QueryMode::All => {
+                // Intersect from the shortest posting list, so that the intermediate results
+                // are as small as possible.
+                postings.sort_by_key(|bitmap| bitmap.map(RoaringBitmap::len));
+                let mut postings = postings.into_iter().flatten();
+                match postings.next() {
+                    Some(first) => postings.fold(first.clone(), |acc, bitmap| acc & bitmap),
+                    None => RoaringBitmap::new(),
+                }
+            }
+            QueryMode::Any => postings.into_iter().flatten().union(),
+        };
+        for token in &query.excluded {
+            if let Some(bitmap) = self.postings.get(token) {
+                matches -= bitmap;
+            }
+        }
+        if let Some(range) = &query.timestamps {
+            let Some(timestamps) = &self.documents.timestamps else {
+                return RoaringBitmap::new();
+            };
+            matches = matches
+                .iter()
+                .filter(|ordinal| {
+                    timestamps[*ordinal as usize]
+                        .is_some_and(|timestamp| range.contains(&timestamp))
+                })
+                .collect();
+        }
+        matches
+    }
+
+    ///
+    /// Returns the ordinals of the documents which match the expression, in which `Not` is the
+    /// complement within all of the documents.
+    ///
+    fn expr_matches(&self, expr: &QueryExpr) -> RoaringBitmap {
+        match expr {
+            QueryExpr::Token(token) => self.postings.get(token).cloned().unwrap_or_default(),
+            QueryExpr::Timestamps(range) => {
+                let Some(timestamps) = &self.documents.timestamps else {
+                    return RoaringBitmap::new();
+                };
+                (0..timestamps.len() as u32)
+                    .filter(|ordinal| {
+                        timestamps[*ordinal as usize]
+                            .is_some_and(|timestamp| range.contains(&timestamp))
+                    })
+                    .collect()
+            }
+            QueryExpr::And(clauses) => clauses
+                .iter()
+                .map(|clause| self.expr_matches(clause))
+                .intersection(),
+            QueryExpr::Or(clauses) => clauses
+                .iter()
+                .map(|clause| self.expr_matches(clause))
+                .union(),
+            QueryExpr::Not(clause) => {
+                let mut matches = RoaringBitmap::new();
+                matches.insert_range(0..self.documents.ids.len() as u32);
+                matches - self.expr_matches(clause)
+            }
+            // NB: Phrases and patterns are rejected by `check_query`.
+            QueryExpr::Phrase(_) | QueryExpr::Pattern(_) => RoaringBitmap::new(),
+        }
+    }
+}
+
+impl Searcher for MemorySearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let documents = serde_json::from_slice(&std::fs::read(path.join(DOCUMENTS_FILE))?)?;
+        let mut reader = BufReader::new(std::fs::File::open(path.join(POSTINGS_FILE))?);
+        let mut postings = BTreeMap::new();
+        let mut len = [0; 4];
+        loop {
+            match reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let mut token = vec![0; u32::from_le_bytes(len) as usize];
+            reader.read_exact(&mut token)?;
+            let bitmap = RoaringBitmap::deserialize_from(&mut reader)?;
+            postings.insert(String::from_utf8(token)?, bitmap);
+        }
+        Ok(MemorySearcher {
+            documents,
+            postings,
+        })
+    }
+
+    ///
+    /// Nested queries are evaluated by combining posting lists, but proximity, fuzzy and pattern
+    /// queries are not supported.
+    ///
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        crate::check_extensions(query)
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        Ok(self.matches(query).len() as usize)
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        Ok(self
+            .matches(query)
+            .iter()
+            .map(|ordinal| self.documents.ids[ordinal as usize])
+            .collect())
+    }
+
+    async fn snippets(
+        &self,
+        _query: &Query,
+        _tokenizer: &Tokenizer,
+        _limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        bail!("The memory backend does not store bodies.");
+    }
+
+    ///
+    /// Intersects the matches with the posting list of each of the field's tokens.
+    ///
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let prefix = field_token(field, "");
+        let matches = self.matches(query);
+        let mut counts = self
+            .postings
+            .range(prefix.clone()..)
+            .take_while(|(token, _)| token.starts_with(&prefix))
+            .filter(|(token, _)| !token.ends_with(PREFIX_MARKER))
+            .filter_map(|(token, bitmap)| {
+                let count = matches.intersection_len(bitmap) as usize;
+                (count > 0).then(|| (token[prefix.len()..].to_owned(), count))
+            })
+            .collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
@@ -0,0 +1,406 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arrow::array::{AsArray, RecordBatch, StringArray, UInt64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, UInt64Type};
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+
+use crate::QueryTiming;
+use crate::vortex::ScanStats;
+
+///
+/// Structured measurements of a single command, which may be written to a file with `write` so
+/// that runs can be compared without scraping stdout.
+///
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Metrics {
+    pub command: String,
+    pub backend: Option<String>,
+    /// The number of documents requested when building an index.
+    pub documents: Option<usize>,
+    /// The maximum number of buckets requested when building a Vortex index.
+    pub buckets: Option<u16>,
+    /// The total size of the files of the index, after building it.
+    pub index_bytes: Option<u64>,
+    /// The wall-clock duration of the entire command, including opening or building the index.
+    pub elapsed_us: u64,
+    /// The peak resident set size of the process, as reported by the OS.
+    pub peak_rss_bytes: Option<u64>,
+    /// The peak number of bytes allocated on the heap, if the `CountingAllocator` is installed.
+    pub peak_allocated_bytes: Option<u64>,
+    pub queries: Vec<QueryMetrics>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryMetrics {
+    pub hits: usize,
+    pub latency_us: u64,
+    /// The portion of the latency which was spent setting up the query, if it was measured.
+    pub setup_us: Option<u64>,
+    /// The bytes read from the index by the query, if it was measured.
+    pub bytes_read: Option<u64>,
+    /// The row groups and rows which were scanned (rather than pruned), if they were measured.
+    pub row_groups: Option<usize>,
+    pub rows: Option<u64>,
+}
+
+impl Metrics {
+    ///
+    /// Records the peak memory usage of the process so far.
+    ///
+    pub fn record_memory(&mut self) {
+        self.peak_rss_bytes = peak_rss_bytes();
+        self.peak_allocated_bytes = CountingAllocator::peak_bytes().map(|bytes| bytes as u64);
+    }
+
+    pub fn record_query(&mut self, hits: usize, latency: Duration) {
+        self.queries.push(QueryMetrics {
+            hits,
+            latency_us: latency.as_micros() as u64,
+            setup_us: None,
+            bytes_read: None,
+            row_groups: None,
+            rows: None,
+        });
+    }
+
+    pub fn record_scan(&mut self, hits: usize, latency: Duration, stats: ScanStats) {
+        self.queries.push(QueryMetrics {
+            hits,
+            latency_us: latency.as_micros() as u64,
+            setup_us: None,
+            bytes_read: Some(stats.bytes_read),
+            row_groups: Some(stats.row_groups),
+            rows: Some(stats.rows),
+        });
+    }
+
+    pub fn record_timing(&mut self, timing: QueryTiming) {
+        self.queries.push(QueryMetrics {
+            hits: timing.count,
+            latency_us: timing.latency.as_micros() as u64,
+            setup_us: Some(timing.setup.as_micros() as u64),
+            bytes_read: None,
+            row_groups: None,
+            rows: None,
+        });
+    }
+
+    ///
+    /// Writes the metrics as CSV if the path has a `.csv` extension (with one row per query,
+    /// or a single row if there were no queries), and otherwise as JSON.
+    ///
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            std::fs::write(path, self.to_csv())?;
+        } else {
+            std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        }
+        Ok(())
+    }
+
+    fn to_csv(&self) -> String {
+        fn field<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+
+        let mut csv = String::from(
+            "command,backend,documents,buckets,index_bytes,elapsed_us,peak_rss_bytes,\
+            peak_allocated_bytes,hits,latency_us,setup_us,bytes_read,row_groups,rows\n",
+        );
+        let prefix = format!(
+            "{},{},{},{},{},{},{},{}",
+            self.command,
+            field(&self.backend),
+            field(&self.documents),
+            field(&self.buckets),
+            field(&self.index_bytes),
+            self.elapsed_us,
+            field(&self.peak_rss_bytes),
+            field(&self.peak_allocated_bytes),
+        );
+        if self.queries.is_empty() {
+            writeln!(csv, "{prefix},,,,,,").unwrap();
+        }
+        for query in &self.queries {
+            writeln!(
+                csv,
+                "{prefix},{},{},{},{},{},{}",
+                query.hits,
+                query.latency_us,
+                field(&query.setup_us),
+                field(&query.bytes_read),
+                field(&query.row_groups),
+                field(&query.rows)
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
+
+///
+/// Writes the IDs of the documents matched by a query to a file, in ascending order, along with
+/// the text of the query: as a Parquet file with `query` and `id` columns if the path has a
+/// `.parquet` extension, or as CSV with a `query,id` header if it has a `.csv` extension.
+///
+pub fn write_ids(path: &Path, query: &str, ids: &[u64]) -> anyhow::Result<()> {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("parquet") => {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new(QUERY_COLUMN, DataType::Utf8, false),
+                Field::new(ID_COLUMN, DataType::UInt64, false),
+            ]));
+            let queries = StringArray::from(vec![query; ids.len()]);
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(queries), Arc::new(UInt64Array::from(ids))],
+            )?;
+            let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+        }
+        Some("csv") => {
+            let mut csv = format!("{QUERY_COLUMN},{ID_COLUMN}\n");
+            let query = csv_field(query);
+            for id in ids {
+                writeln!(csv, "{query},{id}").unwrap();
+            }
+            std::fs::write(path, csv)?;
+        }
+        _ => bail!("{path:?} must have a `.parquet` or `.csv` extension."),
+    }
+    Ok(())
+}
+
+///
+/// Reads a file of IDs written by `write_ids` (or any `.parquet` or `.csv` file with an `id`
+/// column), grouping the IDs by the value of its `query` column. A file without a `query`
+/// column is grouped under an empty query.
+///
+pub fn read_ids(path: &Path) -> anyhow::Result<BTreeMap<String, BTreeSet<u64>>> {
+    let mut results: BTreeMap<String, BTreeSet<u64>> = BTreeMap::new();
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("parquet") => {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+            for batch in reader {
+                let batch = batch?;
+                let ids = batch
+                    .column_by_name(ID_COLUMN)
+                    .ok_or_else(|| anyhow!("{path:?} does not have an `{ID_COLUMN}` column."))?;
+                let ids = cast(ids, &DataType::UInt64)?;
+                let queries = batch
+                    .column_by_name(QUERY_COLUMN)
+                    .map(|queries| cast(queries, &DataType::Utf8))
+                    .transpose()?;
+                for (row, id) in ids.as_primitive::<UInt64Type>().iter().enumerate() {
+                    let Some(id) = id else {
+                        continue;
+                    };
+                    let query = queries
+                        .as_ref()
+                        .map(|queries| queries.as_string::<i32>().value(row).to_owned())
+                        .unwrap_or_default();
+                    results.entry(query).or_default().insert(id);
+                }
+            }
+        }
+        Some("csv") => {
+            let contents = std::fs::read_to_string(path)?;
+            let mut lines = contents.lines();
+            let header = csv_fields(lines.next().unwrap_or_default());
+            let column = |name: &str| header.iter().position(|column| column == name);
+            let id_column = column(ID_COLUMN)
+                .ok_or_else(|| anyhow!("{path:?} does not have an `{ID_COLUMN}` column."))?;
+            let query_column = column(QUERY_COLUMN);
+            for line in lines.filter(|line| !line.is_empty()) {
+                let fields = csv_fields(line);
+                let id = fields
+                    .get(id_column)
+                    .and_then(|id| id.parse::<u64>().ok())
+                    .ok_or_else(|| anyhow!("Invalid row in {path:?}: {line:?}"))?;
+                let query = query_column
+                    .and_then(|column| fields.get(column).cloned())
+                    .unwrap_or_default();
+                results.entry(query).or_default().insert(id);
+            }
+        }
+        _ => bail!("{path:?} must have a `.parquet` or `.csv` extension."),
+    }
+    Ok(results)
+}
+
+/// The column of a file of IDs which contains the text of the query which matched them.
+const QUERY_COLUMN: &str = "query";
+/// The column of a file of IDs which contains the IDs.
+const ID_COLUMN: &str = "id";
+
+///
+/// Quotes a CSV field if it contains a delimiter, a quote or a newline.
+///
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+///
+/// Splits a line of CSV into its (unquoted) fields.
+///
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+///
+/// Returns the peak resident set size of the process, or `None` if it cannot be determined.
+///
+pub fn peak_rss_bytes() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `getrusage` initializes the struct when it succeeds.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let max_rss = u64::try_from(unsafe { usage.assume_init() }.ru_maxrss).ok()?;
+    // NB: macOS reports bytes, while other platforms report kilobytes.
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        Some(max_rss * 1024)
+    }
+}
+
+/// The bytes currently allocated through the `CountingAllocator`.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+/// The most bytes which have been allocated through the `CountingAllocator` at once.
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// A global allocator which delegates to the system allocator, while tracking the number of
+/// bytes allocated (and its peak), so that the heap usage of backends can be compared. Install
+/// it with `#[global_allocator]`.
+///
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    ///
+    /// The most bytes which have been allocated at once, or `None` if the allocator has not
+    /// been installed.
+    ///
+    pub fn peak_bytes() -> Option<usize> {
+        match PEAK_ALLOCATED.load(Ordering::Relaxed) {
+            0 => None,
+            peak => Some(peak),
+        }
+    }
+
+    fn allocated(size: usize) {
+        let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+    }
+}
+
+// SAFETY: All allocation is delegated to `System`.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            CountingAllocator::allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            CountingAllocator::allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            CountingAllocator::allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+///
+/// Returns the total size of the files (recursively) at `path`, grouped by their extensions and
+/// largest first. A file without an extension is grouped by its name.
+///
+pub fn file_sizes_by_extension(path: &Path) -> anyhow::Result<Vec<(String, u64)>> {
+    fn visit(path: &Path, sizes: &mut BTreeMap<String, u64>) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                visit(&entry?.path(), sizes)?;
+            }
+            return Ok(());
+        }
+        let kind = path
+            .extension()
+            .or_else(|| path.file_name())
+            .map(|kind| kind.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        *sizes.entry(kind).or_default() += metadata.len();
+        Ok(())
+    }
+
+    let mut sizes = BTreeMap::new();
+    visit(path, &mut sizes)?;
+    let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+    sizes.sort_by(|(a, a_bytes), (b, b_bytes)| b_bytes.cmp(a_bytes).then_with(|| a.cmp(b)));
+    Ok(sizes)
+}
+
+///
+/// Returns the total size of the file or directory (recursively) at `path`.
+///
+pub fn index_bytes(path: &Path) -> anyhow::Result<u64> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(path)? {
+        bytes += index_bytes(&entry?.path())?;
+    }
+    Ok(bytes)
+}
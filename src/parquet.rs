@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, BooleanBuilder, ListArray, ListBuilder, RecordBatch,
+    StringArray, StringBuilder, UInt64Array, UInt64Builder,
+};
+use arrow::compute::kernels::boolean::{and, not, or};
+use arrow::compute::kernels::cmp::{eq, gt_eq, lt_eq};
+use arrow::compute::{filter, prep_null_mask_filter};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::{ArrowReaderMetadata, ParquetRecordBatchReaderBuilder};
+use parquet::file::properties::WriterProperties;
+use vortex_dtype::FieldName;
+
+use crate::common::{
+    Corpus, PREFIX_MARKER, Query, QueryMode, SampleMethod, Tokenizer, field_token,
+};
+use crate::vortex::{
+    BucketType, DEFAULT_CHUNK_SIZE, ID_COLUMN, Strategy, TIMESTAMP_COLUMN, check_counts,
+    document_frequencies, facet_columns, find_bucket,
+};
+use crate::{Indexer, Searcher};
+
+///
+/// Builds a Parquet file with the same bucketed layout as a Vortex index: an ID column, an
+/// optional timestamp column, and then one column per bucket (a boolean for a `Single` bucket,
+/// and a list of tokens for a `Multi` bucket). Each chunk of documents is written as a row
+/// group.
+///
+/// `ParquetSearcher` evaluates filters with Arrow compute kernels after decoding the projected
+/// columns, without any pruning or filter pushdown. Comparing it to Vortex separates the benefit
+/// of columnar buckets from the benefits of Vortex's encodings and pushdown.
+///
+#[derive(Clone, Debug)]
+pub struct ParquetIndexer {
+    pub buckets: u16,
+    pub strategy: Strategy,
+    /// The number of documents to sample tokens from when selecting buckets.
+    pub sample_docs: usize,
+    pub sample: SampleMethod,
+}
+
+impl Indexer for ParquetIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        check_counts(doc_count, Some(self.buckets))?;
+        let sample = corpus.sample(tokenizer, doc_count, self.sample_docs, self.sample)?;
+        let buckets = self.strategy.select_buckets(
+            document_frequencies(sample.into_iter().map(Ok))?,
+            self.buckets,
+            corpus.fields(),
+        );
+        let bucket_columns = buckets
+            .iter()
+            .map(|(token, btype)| FieldName::from(btype.column_name(token)))
+            .collect::<Vec<_>>();
+
+        let mut fields = vec![Field::new(ID_COLUMN, DataType::UInt64, false)];
+        let timestamps = corpus.timestamps();
+        if timestamps {
+            fields.push(Field::new(TIMESTAMP_COLUMN, DataType::UInt64, true));
+        }
+        for (column, (_, btype)) in bucket_columns.iter().zip(&buckets) {
+            let data_type = match btype {
+                BucketType::Single => DataType::Boolean,
+                BucketType::Multi => {
+                    DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true)))
+                }
+            };
+            fields.push(Field::new(column.as_ref(), data_type, false));
+        }
+        let schema: SchemaRef = Arc::new(Schema::new(fields));
+
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(DEFAULT_CHUNK_SIZE)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))?;
+        let mut texts = corpus.texts(doc_count)?.peekable();
+        while texts.peek().is_some() {
+            let mut ids = UInt64Builder::new();
+            let mut timestamp_builder = UInt64Builder::new();
+            let mut bucket_builders = buckets
+                .iter()
+                .map(|(_, btype)| BucketBuilder::new(*btype))
+                .collect::<Vec<_>>();
+            let mut tokens_by_bucket: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+            for text in texts.by_ref().take(DEFAULT_CHUNK_SIZE) {
+                let text = text?;
+                ids.append_value(text.id);
+                timestamp_builder.append_option(text.timestamp);
+                for token in tokenizer.text_term_frequencies(&text).into_keys() {
+                    let (idx, _) = find_bucket(&bucket_columns, &token);
+                    tokens_by_bucket.entry(idx).or_default().push(token);
+                }
+                for (idx, builder) in bucket_builders.iter_mut().enumerate() {
+                    builder.append(tokens_by_bucket.remove(&idx).unwrap_or_default());
+                }
+            }
+
+            let mut columns: Vec<ArrayRef> = vec![Arc::new(ids.finish())];
+            if timestamps {
+                columns.push(Arc::new(timestamp_builder.finish()));
+            }
+            columns.extend(bucket_builders.into_iter().map(BucketBuilder::finish));
+            writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+        }
+        writer.close()?;
+        println!(">>> created {path:?}, with {} buckets", buckets.len());
+        Ok(())
+    }
+}
+
+enum BucketBuilder {
+    Single(BooleanBuilder),
+    Multi(ListBuilder<StringBuilder>),
+}
+
+impl BucketBuilder {
+    fn new(btype: BucketType) -> Self {
+        match btype {
+            BucketType::Single => BucketBuilder::Single(BooleanBuilder::new()),
+            BucketType::Multi => BucketBuilder::Multi(ListBuilder::new(StringBuilder::new())),
+        }
+    }
+
+    fn append(&mut self, tokens: Vec<String>) {
+        match self {
+            BucketBuilder::Single(builder) => builder.append_value(!tokens.is_empty()),
+            BucketBuilder::Multi(builder) => {
+                builder.append_value(tokens.into_iter().map(Some));
+            }
+        }
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            BucketBuilder::Single(mut builder) => Arc::new(builder.finish()),
+            BucketBuilder::Multi(mut builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+pub struct ParquetSearcher {
+    file: File,
+    metadata: ArrowReaderMetadata,
+    /// The sorted names of the bucket columns, excluding the ID and timestamp columns.
+    bucket_columns: Vec<FieldName>,
+}
+
+impl ParquetSearcher {
+    ///
+    /// Reads the given columns (in addition to the columns referenced by the query) of every row
+    /// group, and returns the result of applying `f` to each batch and its mask of matches.
+    ///
+    fn scan<T>(
+        &self,
+        query: &Query,
+        columns: &[&str],
+        mut f: impl FnMut(&RecordBatch, &BooleanArray) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Vec<T>> {
+        let schema = self.metadata.schema();
+        if query.timestamps.is_some() && schema.index_of(TIMESTAMP_COLUMN).is_err() {
+            // An index without timestamps cannot match a range of timestamps.
+            return Ok(Vec::new());
+        }
+        let tokens_by_column = self.tokens_by_column(query);
+        let mut indices = columns
+            .iter()
+            .copied()
+            .chain(tokens_by_column.keys().map(|column| column.as_ref()))
+            .chain(query.timestamps.is_some().then_some(TIMESTAMP_COLUMN))
+            .map(|column| schema.index_of(column))
+            .collect::<Result<Vec<_>, _>>()?;
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mask = ProjectionMask::roots(self.metadata.parquet_schema(), indices);
+        let reader = ParquetRecordBatchReaderBuilder::new_with_metadata(
+            self.file.try_clone()?,
+            self.metadata.clone(),
+        )
+        .with_projection(mask)
+        .with_batch_size(DEFAULT_CHUNK_SIZE)
+        .build()?;
+        let mut results = Vec::new();
+        for batch in reader {
+            let batch = batch?;
+            let matches = self.evaluate(&batch, query, &tokens_by_column)?;
+            results.push(f(&batch, &matches)?);
+        }
+        Ok(results)
+    }
+
+    ///
+    /// Groups the query's tokens (and excluded tokens) by the bucket column they fall into.
+    ///
+    fn tokens_by_column<'a>(
+        &self,
+        query: &'a Query,
+    ) -> BTreeMap<FieldName, (Vec<&'a str>, Vec<&'a str>)> {
+        let mut tokens_by_column: BTreeMap<FieldName, (Vec<&str>, Vec<&str>)> = BTreeMap::new();
+        for (excluded, token) in query
+            .tokens
+            .iter()
+            .map(|token| (false, token))
+            .chain(query.excluded.iter().map(|token| (true, token)))
+        {
+            let (idx, _) = find_bucket(&self.bucket_columns, token);
+            let entry = tokens_by_column
+                .entry(self.bucket_columns[idx].clone())
+                .or_default();
+            if excluded {
+                entry.1.push(token);
+            } else {
+                entry.0.push(token);
+            }
+        }
+        tokens_by_column
+    }
+
+    ///
+    /// Computes the mask of the rows of the batch which match the query. A query without any
+    /// tokens matches nothing, as in the other backends.
+    ///
+    fn evaluate(
+        &self,
+        batch: &RecordBatch,
+        query: &Query,
+        tokens_by_column: &BTreeMap<FieldName, (Vec<&str>, Vec<&str>)>,
+    ) -> anyhow::Result<BooleanArray> {
+        let combine = match query.mode {
+            QueryMode::All => and,
+            QueryMode::Any => or,
+        };
+        let mut included: Option<BooleanArray> = None;
+        let mut excluded: Option<BooleanArray> = None;
+        for (column, (tokens, excluded_tokens)) in tokens_by_column {
+            let array = batch
+                .column_by_name(column)
+                .ok_or_else(|| anyhow!("Missing bucket column {column}."))?;
+            for token in tokens {
+                let contains = contains(array, token)?;
+                included = Some(match included {
+                    Some(included) => combine(&included, &contains)?,
+                    None => contains,
+                });
+            }
+            for token in excluded_tokens {
+                let contains = contains(array, token)?;
+                excluded = Some(match excluded {
+                    Some(excluded) => or(&excluded, &contains)?,
+                    None => contains,
+                });
+            }
+        }
+        let mut matches =
+            included.unwrap_or_else(|| BooleanArray::from(vec![false; batch.num_rows()]));
+        if let Some(excluded) = excluded {
+            matches = and(&matches, &not(&excluded)?)?;
+        }
+        if let Some(range) = &query.timestamps {
+            let timestamps = batch
+                .column_by_name(TIMESTAMP_COLUMN)
+                .ok_or_else(|| anyhow!("Missing timestamp column."))?;
+            let in_range = and(
+                &gt_eq(timestamps, &UInt64Array::new_scalar(*range.start()))?,
+                &lt_eq(timestamps, &UInt64Array::new_scalar(*range.end()))?,
+            )?;
+            matches = and(&matches, &in_range)?;
+        }
+        // NB: Documents without timestamps have null comparisons, which never match.
+        Ok(prep_null_mask_filter(&matches))
+    }
+}
+
+///
+/// Returns a mask of the rows of a bucket column which contain the token: a `Single` bucket
+/// column is its own mask, while the values of a `Multi` bucket column are compared to the token
+/// and then reduced to one value per list.
+///
+fn contains(array: &ArrayRef, token: &str) -> anyhow::Result<BooleanArray> {
+    if let Some(array) = array.as_any().downcast_ref::<BooleanArray>() {
+        return Ok(array.clone());
+    }
+    let Some(list) = array.as_any().downcast_ref::<ListArray>() else {
+        bail!("Unexpected bucket column type: {}", array.data_type());
+    };
+    let matches = eq(list.values(), &StringArray::new_scalar(token))?;
+    Ok(list
+        .value_offsets()
+        .windows(2)
+        .map(|offsets| Some((offsets[0]..offsets[1]).any(|idx| matches.value(idx as usize))))
+        .collect())
+}
+
+impl Searcher for ParquetSearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = ArrowReaderMetadata::load(&file, Default::default())?;
+        let mut bucket_columns = metadata
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .filter(|name| ![ID_COLUMN, TIMESTAMP_COLUMN].contains(name))
+            .map(FieldName::from)
+            .collect::<Vec<_>>();
+        bucket_columns.sort_unstable();
+        if bucket_columns.is_empty() {
+            bail!("{path:?} does not contain any buckets.");
+        }
+        Ok(ParquetSearcher {
+            file,
+            metadata,
+            bucket_columns,
+        })
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        let counts = self.scan(query, &[], |_, matches| Ok(matches.true_count()))?;
+        Ok(counts.into_iter().sum())
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let batches = self.scan(query, &[ID_COLUMN], |batch, matches| {
+            let ids = batch
+                .column_by_name(ID_COLUMN)
+                .ok_or_else(|| anyhow!("Missing ID column."))?;
+            let ids = filter(ids, matches)?;
+            let ids = ids
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .ok_or_else(|| anyhow!("Unexpected ID column type."))?;
+            Ok(ids.values().to_vec())
+        })?;
+        Ok(batches.into_iter().flatten().collect())
+    }
+
+    async fn snippets(
+        &self,
+        _query: &Query,
+        _tokenizer: &Tokenizer,
+        _limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        bail!("The parquet backend does not store bodies.");
+    }
+
+    ///
+    /// Reads the buckets which might contain tokens of the field, and counts the field's tokens
+    /// in the matching rows of each batch.
+    ///
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let prefix = field_token(field, "");
+        let columns = facet_columns(&self.bucket_columns, &prefix);
+        let column_names = columns
+            .iter()
+            .map(|column| column.as_ref())
+            .collect::<Vec<_>>();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.scan(query, &column_names, |batch, matches| {
+            let mut count = |token: &str, matches: usize| {
+                if let Some(value) = token.strip_prefix(&prefix)
+                    && !value.ends_with(PREFIX_MARKER)
+                    && matches > 0
+                {
+                    *counts.entry(value.to_owned()).or_default() += matches;
+                }
+            };
+            for column in &column_names {
+                let array = batch
+                    .column_by_name(column)
+                    .ok_or_else(|| anyhow!("Missing bucket column {column}."))?;
+                let array = filter(array, matches)?;
+                if let Some(array) = array.as_any().downcast_ref::<BooleanArray>() {
+                    let token = column.rsplit_once(':').map_or(*column, |(token, _)| token);
+                    count(token, array.true_count());
+                } else if let Some(list) = array.as_any().downcast_ref::<ListArray>() {
+                    let values = list
+                        .values()
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .ok_or_else(|| anyhow!("Unexpected bucket column type."))?;
+                    let start = list.value_offsets()[0] as usize;
+                    let end = list.value_offsets()[list.len()] as usize;
+                    for idx in start..end {
+                        count(values.value(idx), 1);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
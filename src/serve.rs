@@ -0,0 +1,80 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::extract::{Query as Params, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::{Query, QueryMode, Searcher, Tokenizer};
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    mode: Option<QueryMode>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    count: usize,
+    ids: Vec<u64>,
+}
+
+struct ServeState<S> {
+    searcher: S,
+    tokenizer: Tokenizer,
+    mode: QueryMode,
+}
+
+///
+/// Serves `GET /search?q=...` on the given host and port, which responds with the number and IDs
+/// of the documents matching the query. A `mode` parameter (`any` or `all`) overrides the default
+/// mode. A query which the index cannot evaluate is rejected with a 400.
+///
+pub async fn serve<S: Searcher + Send + 'static>(
+    searcher: S,
+    host: IpAddr,
+    port: u16,
+    tokenizer: Tokenizer,
+    mode: QueryMode,
+) -> anyhow::Result<()> {
+    searcher.check_tokenizer(&tokenizer)?;
+    let state = Arc::new(ServeState {
+        searcher,
+        tokenizer,
+        mode,
+    });
+    let app = Router::new()
+        .route("/search", get(search::<S>))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind((host, port)).await?;
+    println!(">>> listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn search<S: Searcher>(
+    State(state): State<Arc<ServeState<S>>>,
+    Params(params): Params<SearchParams>,
+) -> Result<Json<SearchResponse>, (StatusCode, String)> {
+    let query = Query::parse(
+        &params.q,
+        params.mode.unwrap_or(state.mode),
+        &state.tokenizer,
+    );
+    state
+        .searcher
+        .check_query(&query)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let ids = state
+        .searcher
+        .ids(&query)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(SearchResponse {
+        count: ids.len(),
+        ids,
+    }))
+}
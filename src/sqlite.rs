@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags, params, params_from_iter};
+
+use crate::common::{Corpus, PREFIX_MARKER, Query, QueryMode, Tokenizer, field_token, snippet};
+use crate::{Indexer, Searcher};
+
+///
+/// Documents are tokenized by our own `Tokenizer` before they reach SQLite, so the FTS5
+/// tokenizer only needs to split the pre-tokenized text on whitespace. The field separator and
+/// prefix marker are declared as token characters so that field tokens and edge n-grams are kept
+/// whole, and diacritics are left alone since our `Tokenizer` has already normalized the text.
+///
+const TOKENIZE: &str = "unicode61 remove_diacritics 0 tokenchars ':*'";
+
+///
+/// Fails if the tokenizer preserves case, since the `unicode61` tokenizer always folds case, and
+/// so would match case-insensitively where the other backends would not.
+///
+fn check_case(tokenizer: &Tokenizer) -> anyhow::Result<()> {
+    if tokenizer.no_lowercase {
+        bail!("The SQLite backend always folds case, and so does not support --no-lowercase.");
+    }
+    Ok(())
+}
+
+///
+/// Builds an SQLite database containing an FTS5 virtual table, with one row per document whose
+/// rowid is the document's ID. The document's timestamp and (if `store_bodies` is set) its
+/// original text are stored in unindexed columns.
+///
+#[derive(Clone, Debug, Default)]
+pub struct SqliteIndexer {
+    pub store_bodies: bool,
+}
+
+impl Indexer for SqliteIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        if path.exists() {
+            bail!("{path:?} already exists.");
+        }
+        check_case(tokenizer)?;
+        let mut connection = Connection::open(path)?;
+        // NB: A partially built index is useless, so durability is not worth paying for.
+        connection.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+        connection.execute(
+            &format!(
+                "CREATE VIRTUAL TABLE documents \
+                USING fts5(tokens, timestamp UNINDEXED, body UNINDEXED, tokenize = \"{TOKENIZE}\")"
+            ),
+            [],
+        )?;
+
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO documents (rowid, tokens, timestamp, body) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for text in corpus.texts(doc_count)? {
+                let text = text?;
+                let tokens = tokenizer
+                    .text_term_frequencies(&text)
+                    .into_keys()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let body = self.store_bodies.then_some(&text.body);
+                insert.execute(params![text.id as i64, tokens, text.timestamp, body])?;
+            }
+        }
+        transaction.commit()?;
+
+        // Merge all of the b-trees of the full-text index into one, as for a read-only index.
+        connection.execute("INSERT INTO documents (documents) VALUES ('optimize')", [])?;
+        Ok(())
+    }
+}
+
+///
+/// A connection to an SQLite index. Connections cannot be shared between threads, so queries
+/// are executed one at a time.
+///
+pub struct SqliteSearcher {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteSearcher {
+    ///
+    /// Executes a statement which selects from the documents matching the query (as `matches`),
+    /// and returns the result of applying `f` to each row. A query without any tokens matches
+    /// nothing, as in the other backends.
+    ///
+    fn query<T>(
+        &self,
+        select: &str,
+        query: &Query,
+        f: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    ) -> anyhow::Result<Vec<T>> {
+        let Some(expression) = match_expression(query) else {
+            return Ok(Vec::new());
+        };
+        let mut values = vec![Value::Text(expression)];
+        let mut filter = String::from("documents MATCH ?1");
+        if let Some(range) = &query.timestamps {
+            // NB: Documents without timestamps have NULL timestamps, which are never between.
+            filter.push_str(" AND timestamp BETWEEN ?2 AND ?3");
+            for bound in [range.start(), range.end()] {
+                values.push(Value::Integer(i64::try_from(*bound).unwrap_or(i64::MAX)));
+            }
+        }
+        let sql = format!(
+            "WITH matches AS (SELECT rowid, tokens, body FROM documents WHERE {filter}) {select}"
+        );
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(&sql)?;
+        let rows = statement
+            .query_map(params_from_iter(values), f)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+}
+
+impl Searcher for SqliteSearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(SqliteSearcher {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn check_tokenizer(&self, tokenizer: &Tokenizer) -> anyhow::Result<()> {
+        check_case(tokenizer)
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        let counts = self.query("SELECT count(*) FROM matches", query, |row| {
+            row.get::<_, i64>(0)
+        })?;
+        Ok(counts.into_iter().sum::<i64>() as usize)
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        self.query("SELECT rowid FROM matches", query, |row| {
+            Ok(row.get::<_, i64>(0)? as u64)
+        })
+    }
+
+    ///
+    /// Matching documents are post-filtered by selecting the stored body column, and then
+    /// highlighting it with our `Tokenizer`.
+    ///
+    async fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        let bodies = self.query(
+            &format!("SELECT rowid, body FROM matches LIMIT {limit}"),
+            query,
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u64,
+                    row.get::<_, Option<String>>(1)?,
+                ))
+            },
+        )?;
+        bodies
+            .into_iter()
+            .map(|(id, body)| match body {
+                Some(body) => Ok((id, snippet(&body, query, tokenizer))),
+                None => bail!("The index was not built with stored bodies."),
+            })
+            .collect()
+    }
+
+    ///
+    /// Selects the tokens of each matching document, and counts the field's tokens among them.
+    ///
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let prefix = field_token(field, "");
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.query("SELECT tokens FROM matches", query, |row| {
+            let tokens: String = row.get(0)?;
+            for token in tokens.split(' ') {
+                if let Some(value) = token.strip_prefix(&prefix)
+                    && !token.ends_with(PREFIX_MARKER)
+                {
+                    *counts.entry(value.to_owned()).or_default() += 1;
+                }
+            }
+            Ok(())
+        })?;
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
+
+///
+/// Creates an FTS5 MATCH expression which either ANDs or ORs the query's tokens together, and
+/// then excludes its excluded tokens. Each token is quoted as a string, so that FTS5 does not
+/// interpret it as syntax.
+///
+fn match_expression(query: &Query) -> Option<String> {
+    fn quote(token: &String) -> String {
+        format!("\"{}\"", token.replace('"', "\"\""))
+    }
+
+    if query.tokens.is_empty() {
+        return None;
+    }
+    let operator = match query.mode {
+        QueryMode::All => " AND ",
+        QueryMode::Any => " OR ",
+    };
+    let tokens = query.tokens.iter().map(quote).collect::<Vec<_>>();
+    let mut expression = format!("({})", tokens.join(operator));
+    for token in &query.excluded {
+        expression.push_str(" NOT ");
+        expression.push_str(&quote(token));
+    }
+    Some(expression)
+}
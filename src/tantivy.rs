@@ -1,92 +1,1008 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::ops::{Bound, RangeInclusive};
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
-use tantivy::collector::Count;
-use tantivy::query::{BooleanQuery, Query, QueryParser, TermQuery};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use tantivy::collector::{
+    Collector, Count, DocSetCollector, FacetCollector, SegmentCollector, TopDocs,
+};
+use tantivy::columnar::{Column, StrColumn};
+use tantivy::query::{
+    AllQuery, BooleanQuery, EmptyQuery, FuzzyTermQuery, Occur, PhraseQuery, Query as TantivyQuery,
+    RangeQuery, RegexQuery, TermQuery,
+};
 use tantivy::schema::*;
-use tantivy::tokenizer::SimpleTokenizer;
-use tantivy::{Index, IndexWriter, Searcher};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::tokenizer::{NgramTokenizer, WhitespaceTokenizer};
+use tantivy::{
+    DocAddress, DocId, Index, IndexWriter, Score, Searcher, SegmentOrdinal, SegmentReader,
+};
+use tokio::sync::oneshot;
+
+use crate::Indexer;
+use crate::common::{
+    Corpus, Near, PREFIX_MARKER, Pattern, Query, QueryExpr, QueryMode, Tokenizer, field_token,
+    split_field,
+};
+
+///
+/// Documents are tokenized by our own `Tokenizer` before they reach Tantivy, so Tantivy only
+/// needs to split the pre-tokenized text on whitespace.
+///
+const TOKENIZER: &str = "whitespace";
+
+///
+/// When edge n-grams are enabled, each whole token is also added as a value of the `prefix`
+/// field, which Tantivy splits into prefixes with an `NgramTokenizer`.
+///
+const PREFIX_TOKENIZER: &str = "edge_ngram";
+
+///
+/// Each named field is accompanied by a facet field, whose facets are the field's tokens.
+///
+fn facet_field_name(field: &str) -> String {
+    format!("{field}:facet")
+}
+
+///
+/// The multi-valued fast field which holds every token of each document (including the tokens of
+/// named fields, and edge n-grams), for indexes built with `fast_tokens`.
+///
+const TOKENS_FIELD: &str = "tokens";
 
-fn schema() -> Schema {
+/// The fields of every index, which named fields may not use.
+const RESERVED_FIELDS: &[&str] = &[
+    "id",
+    "key",
+    "body",
+    "prefix",
+    "text",
+    "timestamp",
+    TOKENS_FIELD,
+];
+
+///
+/// Named fields are indexed like `body`, except that any edge n-grams from our `Tokenizer` are
+/// indexed in the same field rather than in a `prefix` field. If `positions` is set, the `body`
+/// field records the position of each token. If `fast_tokens` is set, the tokens of each document
+/// are also stored in the `tokens` fast field.
+///
+fn schema(fields: &[String], positions: bool, fast_tokens: bool) -> anyhow::Result<Schema> {
     let mut schema_builder = Schema::builder();
-    schema_builder.add_u64_field("id", NumericOptions::default().set_stored());
+    schema_builder.add_u64_field("id", NumericOptions::default().set_stored().set_indexed());
+    // NB: Documents are found by the `id` derived from their key, so the key is only stored.
+    schema_builder.add_text_field("key", STRING | STORED);
     schema_builder.add_text_field(
         "body",
         TextOptions::default().set_indexing_options(
             TextFieldIndexing::default()
-                .set_tokenizer("simple")
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(if positions {
+                    IndexRecordOption::WithFreqsAndPositions
+                } else {
+                    IndexRecordOption::Basic
+                }),
+        ),
+    );
+    schema_builder.add_text_field(
+        "prefix",
+        TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(PREFIX_TOKENIZER)
                 .set_index_option(IndexRecordOption::Basic),
         ),
     );
-    schema_builder.build()
-}
-
-pub fn tantivy_index(path: &Path, doc_count: usize) -> tantivy::Result<()> {
-    let schema = schema();
-    let index = Index::create_in_dir(path, schema.clone())?;
-    index
-        .tokenizers()
-        .register("simple", SimpleTokenizer::default());
-    let mut index_writer: IndexWriter = index.writer(50_000_000)?;
-
-    let id_field = schema.get_field("id").unwrap();
-    let body_field = schema.get_field("body").unwrap();
-    for (id, document) in crate::common::documents(doc_count) {
-        let mut doc = TantivyDocument::default();
-        doc.add_u64(id_field, id);
-        doc.add_text(
-            body_field,
-            document.into_iter().collect::<Vec<_>>().join(" "),
+    // NB: The `body` field contains pre-tokenized text, so the original text is stored
+    // separately. It is indexed with Tantivy's default tokenizer only so that `SnippetGenerator`
+    // can highlight it: matching always uses the `body` field.
+    schema_builder.add_text_field("text", TEXT | STORED);
+    schema_builder.add_u64_field("timestamp", INDEXED | FAST);
+    if fast_tokens {
+        // NB: Without a tokenizer, each value of the fast field is a single token.
+        schema_builder.add_text_field(TOKENS_FIELD, TextOptions::default().set_fast(None));
+    }
+    for field in fields {
+        if RESERVED_FIELDS.contains(&field.as_str()) {
+            anyhow::bail!("The field name {field:?} is reserved.");
+        }
+        schema_builder.add_text_field(
+            field,
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(TOKENIZER)
+                    .set_index_option(IndexRecordOption::Basic),
+            ),
         );
-        index_writer.add_document(doc)?;
+        schema_builder.add_facet_field(&facet_field_name(field), FacetOptions::default());
+    }
+    Ok(schema_builder.build())
+}
+
+///
+/// Whether the given text field records the positions of its tokens.
+///
+fn has_positions(schema: &Schema, field: Field) -> bool {
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .is_some_and(|indexing| indexing.index_option().has_positions()),
+        _ => false,
+    }
+}
+
+///
+/// Repeats each token by its frequency, so that Tantivy's scoring sees the same term frequencies
+/// as the Vortex index.
+///
+fn repeated_tokens(tokens: impl IntoIterator<Item = (String, u32)>) -> String {
+    tokens
+        .into_iter()
+        .flat_map(|(token, tf)| std::iter::repeat_n(token, tf as usize))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+///
+/// Builds a Tantivy index containing a stored `id` field, an indexed `body` field, (if the
+/// `Tokenizer` emits edge n-grams) an indexed `prefix` field, and an indexed field for each named
+/// field of the corpus. Documents with timestamps have an indexed `timestamp` field, and
+/// documents with external string IDs have a stored `key` field.
+///
+/// If `store_bodies` is set, the original text of each document is stored in a `text` field.
+///
+/// If `positions` is set, the tokens of the `body` field are indexed in order along with their
+/// positions, which allows for proximity queries. If `fast_tokens` is set, all of the tokens of
+/// each document are also stored in a multi-valued fast field, which can be scanned instead of
+/// reading posting lists (see `TantivySearcher::with_fast_fields`). Upserts follow the existing
+/// index for both.
+///
+/// Documents are written by an `IndexWriter` with `heap_bytes` of memory (shared by its
+/// `writer_threads`), which flushes a segment whenever a thread's share is full. If
+/// `commit_every` is set, the writer commits after each batch of that many documents, so that
+/// they become durable (and visible to searchers) incrementally.
+///
+#[derive(Clone, Debug, Default)]
+pub struct TantivyIndexer {
+    pub store_bodies: bool,
+    pub positions: bool,
+    pub fast_tokens: bool,
+    /// The memory budget of the writer. Defaults to `DEFAULT_HEAP_BYTES`.
+    pub heap_bytes: Option<usize>,
+    /// The number of indexing threads of the writer. Defaults to Tantivy's choice, based on the
+    /// available parallelism.
+    pub writer_threads: Option<usize>,
+    pub commit_every: Option<usize>,
+}
+
+/// The default memory budget of an `IndexWriter`.
+pub const DEFAULT_HEAP_BYTES: usize = 50_000_000;
+
+impl Indexer for TantivyIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        let schema = schema(corpus.fields(), self.positions, self.fast_tokens)?;
+        let index = Index::create_in_dir(path, schema)?;
+        self.write(&index, corpus, tokenizer, doc_count, false)
+    }
+}
+
+impl TantivyIndexer {
+    ///
+    /// Adds the documents of the corpus to the existing index at `path`, replacing any documents
+    /// which have the same IDs.
+    ///
+    pub async fn upsert(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        let index = Index::open_in_dir(path)?;
+        self.write(&index, corpus, tokenizer, doc_count, true)
     }
 
+    ///
+    /// Writes the documents of the corpus to the index in a single commit. If `replace` is set,
+    /// any existing document with the same ID as a new document is first deleted.
+    ///
+    fn write(
+        &self,
+        index: &Index,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+        replace: bool,
+    ) -> anyhow::Result<()> {
+        let schema = index.schema();
+        index
+            .tokenizers()
+            .register(TOKENIZER, WhitespaceTokenizer::default());
+        if let Some(max) = tokenizer.edge_ngrams {
+            index
+                .tokenizers()
+                .register(PREFIX_TOKENIZER, NgramTokenizer::prefix_only(1, max)?);
+        }
+        let heap_bytes = self.heap_bytes.unwrap_or(DEFAULT_HEAP_BYTES);
+        let mut index_writer: IndexWriter = match self.writer_threads {
+            Some(threads) => index.writer_with_num_threads(threads, heap_bytes)?,
+            None => index.writer(heap_bytes)?,
+        };
+
+        let id_field = schema.get_field("id").unwrap();
+        let body_field = schema.get_field("body").unwrap();
+        let prefix_field = schema.get_field("prefix").unwrap();
+        let text_field = schema.get_field("text").unwrap();
+        let timestamp_field = schema.get_field("timestamp")?;
+        let key_field = schema.get_field("key").ok();
+        let tokens_field = schema.get_field(TOKENS_FIELD).ok();
+        let positions = has_positions(&schema, body_field);
+        let named_fields = corpus
+            .fields()
+            .iter()
+            .map(|name| {
+                let field = schema.get_field(name).map_err(|_| {
+                    anyhow::anyhow!("The index does not have a field named {name:?}.")
+                })?;
+                let facet_field = schema.get_field(&facet_field_name(name))?;
+                Ok((name.as_str(), (field, facet_field)))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        for (written, text) in corpus.texts(doc_count)?.enumerate() {
+            let text = text?;
+            if replace {
+                index_writer.delete_term(Term::from_field_u64(id_field, text.id));
+            }
+            let document = tokenizer.term_frequencies(&text.body);
+            let mut doc = TantivyDocument::default();
+            doc.add_u64(id_field, text.id);
+            if let Some(key) = &text.key {
+                let key_field = key_field.ok_or_else(|| {
+                    anyhow::anyhow!("The index does not have a field for string IDs.")
+                })?;
+                doc.add_text(key_field, key);
+            }
+            if let Some(timestamp) = text.timestamp {
+                doc.add_u64(timestamp_field, timestamp);
+            }
+            for (name, value) in &text.fields {
+                let (field, facet_field) = named_fields[name.as_str()];
+                let tokens = tokenizer.term_frequencies(value);
+                for token in tokens.keys() {
+                    if !token.ends_with(PREFIX_MARKER) {
+                        doc.add_facet(facet_field, Facet::from_path([token]));
+                    }
+                    if let Some(tokens_field) = tokens_field {
+                        doc.add_text(tokens_field, field_token(name, token));
+                    }
+                }
+                doc.add_text(field, repeated_tokens(tokens));
+            }
+            if self.store_bodies {
+                doc.add_text(text_field, &text.body);
+            }
+            if let Some(tokens_field) = tokens_field {
+                for token in document.keys() {
+                    doc.add_text(tokens_field, token);
+                }
+            }
+            // Tantivy generates its own edge n-grams, so skip ours.
+            let tokens = document
+                .into_iter()
+                .filter(|(token, _)| !token.ends_with(PREFIX_MARKER))
+                .collect::<Vec<_>>();
+            if tokenizer.edge_ngrams.is_some() {
+                for (token, _) in &tokens {
+                    doc.add_text(prefix_field, token);
+                }
+            }
+            if positions {
+                // NB: The tokens must be in order for their positions to be recorded.
+                doc.add_text(body_field, tokenizer.tokens(&text.body).join(" "));
+            } else {
+                doc.add_text(body_field, repeated_tokens(tokens));
+            }
+            index_writer.add_document(doc)?;
+            if self
+                .commit_every
+                .is_some_and(|every| (written + 1) % every.max(1) == 0)
+            {
+                index_writer.commit()?;
+            }
+        }
+
+        index_writer.commit()?;
+        Ok(())
+    }
+}
+
+///
+/// Deletes the documents with the given IDs from the Tantivy index at `path`.
+///
+pub fn tantivy_delete(path: &Path, ids: &[u64]) -> anyhow::Result<()> {
+    let index = Index::open_in_dir(path)?;
+    let id_field = index.schema().get_field("id").unwrap();
+    let mut index_writer: IndexWriter = index.writer(DEFAULT_HEAP_BYTES)?;
+    for id in ids {
+        index_writer.delete_term(Term::from_field_u64(id_field, *id));
+    }
     index_writer.commit()?;
     Ok(())
 }
 
-pub fn tantivy_search(path: &Path, query: &str) -> tantivy::Result<()> {
-    let (searcher, index, body_field) = searcher(path)?;
-    let query_parser = QueryParser::for_index(&index, vec![body_field]);
-    let query = query_parser.parse_query(query)?;
+#[derive(Clone)]
+pub struct TantivySearcher {
+    searcher: Searcher,
+    schema: Schema,
+    id_field: Field,
+    body_field: Field,
+    prefix_field: Field,
+    text_field: Field,
+    positions: bool,
+    /// Whether queries are evaluated by scanning the `tokens` fast field.
+    fast_fields: bool,
+    /// The threads which execute searches: see `spawn`.
+    pool: &'static ThreadPool,
+}
 
-    let count = searcher.search(&query, &Count)?;
+impl TantivySearcher {
+    ///
+    /// Evaluates queries by scanning the `tokens` fast field of every document, rather than by
+    /// reading posting lists, which allows Tantivy's columnar storage to be compared to Vortex.
+    /// Snippets and facet counts are still found with posting lists. Requires an index built with
+    /// `fast_tokens`.
+    ///
+    pub fn with_fast_fields(mut self) -> anyhow::Result<Self> {
+        if self.schema.get_field(TOKENS_FIELD).is_err() {
+            anyhow::bail!("The index was not built with a fast field of its tokens.");
+        }
+        self.fast_fields = true;
+        Ok(self)
+    }
 
-    println!(">>> {count}");
-    Ok(())
+    ///
+    /// Counts the matches for the query, and returns the time spent setting the query up.
+    ///
+    fn count_query(&self, query: &Query) -> anyhow::Result<(usize, Duration)> {
+        let start = Instant::now();
+        if self.fast_fields {
+            let filter = FastFieldFilter::new(query, false);
+            let setup = start.elapsed();
+            return Ok((self.searcher.search(&AllQuery, &filter)?.0, setup));
+        }
+        let query = self.query(query);
+        let setup = start.elapsed();
+        Ok((self.searcher.search(&query, &Count)?, setup))
+    }
+
+    ///
+    /// Returns every token of the index with its document frequency, from the term dictionaries
+    /// of the `body` field and the named fields, most frequent first. The tokens of named fields
+    /// are qualified with the name of the field, and edge n-grams are skipped. Deleted documents
+    /// are counted until their segments are merged.
+    ///
+    pub async fn term_frequencies(&self) -> anyhow::Result<Vec<(String, usize)>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (field, entry) in self.schema.fields() {
+            let qualifier = match entry.name() {
+                "body" => None,
+                name if RESERVED_FIELDS.contains(&name) => continue,
+                name if entry.is_indexed() && matches!(entry.field_type(), FieldType::Str(_)) => {
+                    Some(name)
+                }
+                _ => continue,
+            };
+            for segment_reader in self.searcher.segment_readers() {
+                let inverted_index = segment_reader.inverted_index(field)?;
+                let mut terms = inverted_index.terms().stream()?;
+                while terms.advance() {
+                    let Ok(token) = std::str::from_utf8(terms.key()) else {
+                        continue;
+                    };
+                    if token.ends_with(PREFIX_MARKER) {
+                        continue;
+                    }
+                    let token = match qualifier {
+                        Some(field) => field_token(field, token),
+                        None => token.to_owned(),
+                    };
+                    *counts.entry(token).or_default() += terms.value().doc_freq as usize;
+                }
+            }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+
+    ///
+    /// Returns the original text of the document with the given ID, if it exists and the index
+    /// was built with `store_bodies`.
+    ///
+    pub async fn body(&self, id: u64) -> anyhow::Result<Option<String>> {
+        let query = TermQuery::new(
+            Term::from_field_u64(self.id_field, id),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, address)) = self
+            .searcher
+            .search(&query, &TopDocs::with_limit(1))?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        let doc: TantivyDocument = self.searcher.doc(address)?;
+        Ok(doc
+            .get_first(self.text_field)
+            .and_then(|text| text.as_str())
+            .map(|text| text.to_owned()))
+    }
+
+    fn query(&self, query: &Query) -> BooleanQuery {
+        if let Some(expr) = &query.expr {
+            return BooleanQuery::new(vec![(Occur::Must, self.expr_query(expr, query.fuzzy))]);
+        }
+        let occur = match query.mode {
+            QueryMode::Any => Occur::Should,
+            QueryMode::All => Occur::Must,
+        };
+        let included = query
+            .tokens
+            .iter()
+            .map(|term| (occur, self.term_query(term, query.fuzzy)));
+        let excluded = query
+            .excluded
+            .iter()
+            .map(|term| (Occur::MustNot, self.term_query(term, 0)));
+        let included_patterns = query
+            .patterns
+            .iter()
+            .map(|pattern| (occur, self.pattern_query(pattern)));
+        let excluded_patterns = query
+            .excluded_patterns
+            .iter()
+            .map(|pattern| (Occur::MustNot, self.pattern_query(pattern)));
+        let mut clauses = included
+            .chain(included_patterns)
+            .chain(excluded)
+            .chain(excluded_patterns)
+            .collect::<Vec<_>>();
+        if let Some(near) = &query.near {
+            clauses.push((Occur::Must, self.phrase_query(near)));
+        }
+        let Some(range) = &query.timestamps else {
+            return BooleanQuery::new(clauses);
+        };
+
+        // NB: The text clauses are nested, so that in `Any` mode they remain required.
+        BooleanQuery::new(vec![
+            (Occur::Must, Box::new(BooleanQuery::new(clauses))),
+            (Occur::Must, self.range_query(range)),
+        ])
+    }
+
+    ///
+    /// Lowers a nested query expression, in which each token matches terms within `fuzzy` edits
+    /// of it.
+    ///
+    fn expr_query(&self, expr: &QueryExpr, fuzzy: u8) -> Box<dyn TantivyQuery> {
+        let clauses = |clauses: &[QueryExpr], occur: Occur| {
+            let clauses = clauses
+                .iter()
+                .map(|clause| (occur, self.expr_query(clause, fuzzy)))
+                .collect();
+            Box::new(BooleanQuery::new(clauses))
+        };
+        match expr {
+            QueryExpr::Token(token) => self.term_query(token, fuzzy),
+            QueryExpr::Pattern(pattern) => self.pattern_query(pattern),
+            QueryExpr::Phrase(near) => self.phrase_query(near),
+            QueryExpr::Timestamps(range) => self.range_query(range),
+            QueryExpr::And(and) => clauses(and, Occur::Must),
+            QueryExpr::Or(or) => clauses(or, Occur::Should),
+            // NB: A `BooleanQuery` which only has `MustNot` clauses matches nothing.
+            QueryExpr::Not(negated) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, Box::new(AllQuery)),
+                (Occur::MustNot, self.expr_query(negated, 0)),
+            ])),
+        }
+    }
+
+    ///
+    /// Creates a query for the tokens of `near`, within its slop of one another.
+    ///
+    fn phrase_query(&self, near: &Near) -> Box<dyn TantivyQuery> {
+        let terms = near
+            .tokens
+            .iter()
+            .map(|token| Term::from_field_text(self.body_field, token))
+            .collect();
+        let mut phrase = PhraseQuery::new(terms);
+        phrase.set_slop(near.slop);
+        Box::new(phrase)
+    }
+
+    ///
+    /// Creates a query for the documents with timestamps in the (inclusive) range.
+    ///
+    fn range_query(&self, range: &RangeInclusive<u64>) -> Box<dyn TantivyQuery> {
+        match self.schema.get_field("timestamp") {
+            Ok(field) => Box::new(RangeQuery::new(
+                Bound::Included(Term::from_field_u64(field, *range.start())),
+                Bound::Included(Term::from_field_u64(field, *range.end())),
+            )),
+            // An index built before timestamps were introduced cannot match a range of them.
+            Err(_) => Box::new(EmptyQuery),
+        }
+    }
+
+    ///
+    /// Returns the named field with the given name, if the index has one.
+    ///
+    fn named_field(&self, name: &str) -> Option<Field> {
+        if RESERVED_FIELDS.contains(&name) {
+            return None;
+        }
+        self.schema.get_field(name).ok()
+    }
+
+    ///
+    /// Creates a query for the term, which matches terms within `fuzzy` edits of it if `fuzzy` is
+    /// non-zero.
+    ///
+    fn term_query(&self, term: &str, fuzzy: u8) -> Box<dyn TantivyQuery> {
+        let named =
+            split_field(term).and_then(|(name, token)| Some((self.named_field(name)?, token)));
+        let term = match (named, term.strip_suffix(PREFIX_MARKER)) {
+            (Some((field, token)), _) => Term::from_field_text(field, token),
+            (None, Some(prefix)) => Term::from_field_text(self.prefix_field, prefix),
+            (None, None) => Term::from_field_text(self.body_field, term),
+        };
+        if fuzzy > 0 {
+            return Box::new(FuzzyTermQuery::new(term, fuzzy, true));
+        }
+        Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+    }
+
+    ///
+    /// Creates a query for the tokens which match the pattern. A pattern for a field which the
+    /// index does not have matches nothing.
+    ///
+    fn pattern_query(&self, pattern: &Pattern) -> Box<dyn TantivyQuery> {
+        let field = match &pattern.field {
+            Some(name) => self.named_field(name),
+            None => Some(self.body_field),
+        };
+        // NB: Invalid patterns are rejected by `check_query`.
+        match field.map(|field| RegexQuery::from_pattern(&pattern.regex, field)) {
+            Some(Ok(query)) => Box::new(query),
+            _ => Box::new(EmptyQuery),
+        }
+    }
+}
+
+///
+/// Returns the pool which executes the searches of every `TantivySearcher`, creating it on first
+/// use, so that reopening an index (as `search-many --cold` does for each query) reuses its
+/// threads. It has a thread per available core, as Rayon's global pool does.
+///
+fn search_pool() -> anyhow::Result<&'static ThreadPool> {
+    static POOL: OnceLock<Result<ThreadPool, String>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .thread_name(|i| format!("tantivy-search-{i}"))
+            .build()
+            .map_err(|e| e.to_string())
+    })
+    .as_ref()
+    .map_err(|e| anyhow::anyhow!("Could not create the search pool: {e}"))
 }
 
-pub fn tantivy_search_many(path: &Path, queries: usize) -> tantivy::Result<()> {
-    let (searcher, _, body_field) = searcher(path)?;
+///
+/// Returns the message of a panic payload, which is usually a `&str` or a `String`.
+///
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("(no message)", String::as_str),
+    }
+}
+
+impl crate::Searcher for TantivySearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut index = Index::open_in_dir(path)?;
+        index.set_default_multithread_executor()?;
+        index
+            .tokenizers()
+            .register(TOKENIZER, WhitespaceTokenizer::default());
+
+        let reader = index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+
+        let schema = index.schema();
+        let body_field = schema.get_field("body").unwrap();
+        Ok(TantivySearcher {
+            searcher,
+            id_field: schema.get_field("id").unwrap(),
+            body_field,
+            prefix_field: schema.get_field("prefix").unwrap(),
+            text_field: schema.get_field("text").unwrap(),
+            positions: has_positions(&schema, body_field),
+            fast_fields: false,
+            schema,
+            pool: search_pool()?,
+        })
+    }
+
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        if self.fast_fields {
+            crate::check_extensions(query)?;
+            if query.expr.is_some() {
+                anyhow::bail!("Nested queries cannot be evaluated with fast fields.");
+            }
+            return Ok(());
+        }
+        let leaves = query
+            .expr
+            .iter()
+            .flat_map(QueryExpr::leaves)
+            .collect::<Vec<_>>();
+        let phrases = leaves
+            .iter()
+            .any(|leaf| matches!(leaf, QueryExpr::Phrase(_)));
+        if (query.near.is_some() || phrases) && !self.positions {
+            anyhow::bail!(
+                "The index was not built with positions, and so cannot evaluate proximity queries."
+            );
+        }
+        let expr_patterns = leaves.iter().filter_map(|leaf| match leaf {
+            QueryExpr::Pattern(pattern) => Some(pattern),
+            _ => None,
+        });
+        for pattern in query
+            .patterns
+            .iter()
+            .chain(&query.excluded_patterns)
+            .chain(expr_patterns)
+        {
+            RegexQuery::from_pattern(&pattern.regex, self.body_field)?;
+        }
+        Ok(())
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        Ok(self.count_with_setup(query).await?.0)
+    }
+
+    async fn count_with_setup(&self, query: &Query) -> anyhow::Result<(usize, Duration)> {
+        let query = query.clone();
+        self.spawn(move |searcher| searcher.count_query(&query))
+            .await
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let query = query.clone();
+        self.spawn(move |searcher| searcher.ids_blocking(&query))
+            .await
+    }
+
+    async fn snippets(
+        &self,
+        query: &Query,
+        _tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        let query = query.clone();
+        self.spawn(move |searcher| searcher.snippets_blocking(&query, limit))
+            .await
+    }
+
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let (query, field) = (query.clone(), field.to_owned());
+        self.spawn(move |searcher| searcher.facet_counts_blocking(&query, &field))
+            .await
+    }
+}
 
-    let mut matches = 0;
-    for (_, doc) in crate::common::documents(queries) {
-        let query = BooleanQuery::intersection(
-            doc.into_iter()
-                .map(|term| -> Box<dyn Query> {
-                    Box::new(TermQuery::new(
-                        Term::from_field_text(body_field, &term),
-                        IndexRecordOption::Basic,
+impl TantivySearcher {
+    ///
+    /// Executes a synchronous search on the dedicated search pool, and awaits its result (or an
+    /// error, if it panicked), so that searches neither block the async runtime nor occupy its
+    /// blocking threads.
+    /// This allows Tantivy to be driven by the same async harness as the other backends (with
+    /// queries interleaved with those of other backends, for example).
+    ///
+    async fn spawn<T: Send + 'static>(
+        &self,
+        search: impl FnOnce(&TantivySearcher) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let searcher = self.clone();
+        let (sender, receiver) = oneshot::channel();
+        self.pool.spawn(move || {
+            // NB: A panic would otherwise abort the process, since the pool has no panic handler.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| search(&searcher)))
+                .unwrap_or_else(|panic| {
+                    Err(anyhow::anyhow!(
+                        "The search panicked: {}",
+                        panic_message(&*panic)
                     ))
+                });
+            // NB: The receiver is only dropped if the search was cancelled.
+            let _ = sender.send(result);
+        });
+        receiver.await?
+    }
+
+    fn ids_blocking(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let addresses = if self.fast_fields {
+            let (_, addresses) = self
+                .searcher
+                .search(&AllQuery, &FastFieldFilter::new(query, true))?;
+            addresses
+        } else {
+            let addresses = self.searcher.search(&self.query(query), &DocSetCollector)?;
+            addresses.into_iter().collect()
+        };
+        addresses
+            .into_iter()
+            .map(|address| {
+                let doc: TantivyDocument = self.searcher.doc(address)?;
+                doc.get_first(self.id_field)
+                    .and_then(|id| id.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Document {address:?} does not have an ID."))
+            })
+            .collect()
+    }
+
+    ///
+    /// Snippets are generated by Tantivy's `SnippetGenerator`, which tokenizes the stored text
+    /// with Tantivy's default tokenizer rather than ours: stemmed and prefix tokens will not be
+    /// highlighted.
+    ///
+    fn snippets_blocking(&self, query: &Query, limit: usize) -> anyhow::Result<Vec<(u64, String)>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let top_docs = self
+            .searcher
+            .search(&self.query(query), &TopDocs::with_limit(limit))?;
+
+        let text_query = BooleanQuery::new(
+            query
+                .tokens
+                .iter()
+                .filter(|token| !token.ends_with(PREFIX_MARKER))
+                .map(|token| -> (Occur, Box<dyn TantivyQuery>) {
+                    let term = Term::from_field_text(self.text_field, token);
+                    (
+                        Occur::Should,
+                        Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                    )
                 })
                 .collect(),
         );
-        matches += searcher.search(&query, &Count)?;
+        let generator = SnippetGenerator::create(&self.searcher, &text_query, self.text_field)?;
+        top_docs
+            .into_iter()
+            .map(|(_, address)| {
+                let doc: TantivyDocument = self.searcher.doc(address)?;
+                let id = doc
+                    .get_first(self.id_field)
+                    .and_then(|id| id.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Document {address:?} does not have an ID."))?;
+                Ok((id, generator.snippet_from_doc(&doc).to_html()))
+            })
+            .collect()
     }
 
-    println!(">>> {queries} queries matched {matches} docs");
-    Ok(())
+    ///
+    /// Counts are collected by a `FacetCollector` over the field's facet field, which only exists
+    /// for named fields.
+    ///
+    fn facet_counts_blocking(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let facet_field = facet_field_name(field);
+        if self.schema.get_field(&facet_field).is_err() {
+            anyhow::bail!("The index does not have a named field {field:?}.");
+        }
+        let mut collector = FacetCollector::for_field(facet_field);
+        collector.add_facet(Facet::root());
+        let facet_counts = self.searcher.search(&self.query(query), &collector)?;
+        let mut counts = facet_counts
+            .get(Facet::root())
+            .filter_map(|(facet, count)| {
+                let value = facet.to_path().last()?.to_string();
+                Some((value, count as usize))
+            })
+            .collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
 }
 
-fn searcher(path: &Path) -> tantivy::Result<(Searcher, Index, Field)> {
-    let mut index = Index::open_in_dir(path)?;
-    index.set_default_multithread_executor()?;
-    index
-        .tokenizers()
-        .register("simple", SimpleTokenizer::default());
+///
+/// Collects the documents whose `tokens` fast field satisfies a query, along with their number.
+/// Each document is checked by scanning the term ordinals of its tokens, so the query must be
+/// executed for all documents (with `AllQuery`). The addresses of the matches are only collected
+/// if `addresses` is set.
+///
+struct FastFieldFilter {
+    tokens: Vec<String>,
+    excluded: Vec<String>,
+    mode: QueryMode,
+    timestamps: Option<RangeInclusive<u64>>,
+    addresses: bool,
+}
+
+impl FastFieldFilter {
+    fn new(query: &Query, addresses: bool) -> Self {
+        FastFieldFilter {
+            tokens: query.tokens.iter().cloned().collect(),
+            excluded: query.excluded.iter().cloned().collect(),
+            mode: query.mode,
+            timestamps: query.timestamps.clone(),
+            addresses,
+        }
+    }
+}
 
-    let reader = index.reader_builder().try_into()?;
-    let searcher = reader.searcher();
+impl Collector for FastFieldFilter {
+    type Fruit = (usize, Vec<DocAddress>);
+    type Child = FastFieldSegmentFilter;
 
-    let body_field = schema().get_field("body").unwrap();
-    Ok((searcher, index, body_field))
+    fn for_segment(
+        &self,
+        segment_ord: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        // NB: A segment in which no document has any tokens does not have the column.
+        let column = segment.fast_fields().str(TOKENS_FIELD)?;
+        let ordinals = |tokens: &[String]| -> tantivy::Result<Vec<Option<u64>>> {
+            let Some(column) = &column else {
+                return Ok(vec![None; tokens.len()]);
+            };
+            tokens
+                .iter()
+                .map(|token| Ok(column.dictionary().term_ord(token)?))
+                .collect()
+        };
+        let tokens = ordinals(&self.tokens)?;
+        // A token which the segment does not contain cannot match, and so it short-circuits an
+        // AND, and is dropped from an OR.
+        let mut matchable = !(self.mode == QueryMode::All && tokens.contains(&None));
+        let mut tokens = tokens.into_iter().flatten().collect::<Vec<_>>();
+        tokens.sort_unstable();
+        let mut excluded = ordinals(&self.excluded)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        excluded.sort_unstable();
+        let timestamps = match &self.timestamps {
+            Some(range) => match segment.fast_fields().u64("timestamp") {
+                Ok(column) => Some((column, range.clone())),
+                // An index built before timestamps were introduced cannot match a range of them.
+                Err(_) => {
+                    matchable = false;
+                    None
+                }
+            },
+            None => None,
+        };
+        Ok(FastFieldSegmentFilter {
+            segment_ord,
+            column,
+            tokens,
+            excluded,
+            mode: self.mode,
+            timestamps,
+            matchable: matchable && !self.tokens.is_empty(),
+            addresses: self.addresses,
+            count: 0,
+            matches: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<(usize, Vec<DocAddress>)>,
+    ) -> tantivy::Result<(usize, Vec<DocAddress>)> {
+        let count = fruits.iter().map(|(count, _)| count).sum();
+        let addresses = fruits
+            .into_iter()
+            .flat_map(|(_, addresses)| addresses)
+            .collect();
+        Ok((count, addresses))
+    }
+}
+
+struct FastFieldSegmentFilter {
+    segment_ord: SegmentOrdinal,
+    column: Option<StrColumn>,
+    /// The sorted term ordinals of the query's tokens which the segment contains.
+    tokens: Vec<u64>,
+    excluded: Vec<u64>,
+    mode: QueryMode,
+    timestamps: Option<(Column<u64>, RangeInclusive<u64>)>,
+    /// False if no document of the segment can match.
+    matchable: bool,
+    addresses: bool,
+    count: usize,
+    matches: Vec<DocAddress>,
+}
+
+impl FastFieldSegmentFilter {
+    fn matches(&self, doc: DocId) -> bool {
+        let Some(column) = &self.column else {
+            return false;
+        };
+        if !self.matchable {
+            return false;
+        }
+        if let Some((column, range)) = &self.timestamps
+            && !column
+                .first(doc)
+                .is_some_and(|timestamp| range.contains(&timestamp))
+        {
+            return false;
+        }
+        // NB: Each token is added to the fast field of a document once.
+        let mut matched = 0;
+        for ordinal in column.term_ords(doc) {
+            if self.excluded.binary_search(&ordinal).is_ok() {
+                return false;
+            }
+            if self.tokens.binary_search(&ordinal).is_ok() {
+                matched += 1;
+            }
+        }
+        match self.mode {
+            QueryMode::Any => matched > 0,
+            QueryMode::All => matched == self.tokens.len(),
+        }
+    }
+}
+
+impl SegmentCollector for FastFieldSegmentFilter {
+    type Fruit = (usize, Vec<DocAddress>);
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        if self.matches(doc) {
+            self.count += 1;
+            if self.addresses {
+                self.matches.push(DocAddress::new(self.segment_ord, doc));
+            }
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        (self.count, self.matches)
+    }
 }
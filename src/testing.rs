@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use vortex_dtype::FieldName;
+
+use crate::common::field_token;
+use crate::vortex::{BucketType, find_bucket};
+use crate::vortex_buckets::{HASH_BUCKET_PREFIX, Strategy, hash_bucket};
+
+///
+/// The characters of random tokens. The alphabet is small (and includes the field separator), so
+/// that tokens often share prefixes or are prefixes of one another.
+///
+const ALPHABET: &[u8] = b"abc0:";
+
+///
+/// Returns a random token of between one and eight characters.
+///
+pub fn random_token(rng: &mut impl Rng) -> String {
+    let len = rng.random_range(1..=8);
+    (0..len)
+        .map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())] as char)
+        .collect()
+}
+
+///
+/// Generates a reproducible random distribution of document frequencies, for up to
+/// `max_tokens` random tokens (a quarter of which are qualified with one of the given fields).
+/// Depending on the seed, the frequencies are either uniformly random or Zipf-like.
+///
+pub fn random_frequencies(
+    seed: u64,
+    max_tokens: usize,
+    fields: &[String],
+) -> BTreeMap<String, usize> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let skewed = rng.random_bool(0.5);
+    let token_count = rng.random_range(0..=max_tokens);
+    (0..token_count)
+        .map(|rank| {
+            let token = random_token(&mut rng);
+            let token = match fields.choose(&mut rng) {
+                Some(field) if rng.random_bool(0.25) => field_token(field, &token),
+                _ => token,
+            };
+            let frequency = if skewed {
+                (max_tokens / (rank + 1)).max(1)
+            } else {
+                rng.random_range(1..=100)
+            };
+            (token, frequency)
+        })
+        .collect()
+}
+
+///
+/// Selects buckets for the frequencies with the strategy, and checks the invariants which the
+/// routing of tokens to buckets by `find_bucket` (at index time, and in `create_filter`)
+/// depends on:
+///
+/// * The bucket columns are strictly sorted, so that they may be binary searched.
+/// * Other than for `Hash`, the first bucket is a Multi bucket, and each Single bucket is
+///   followed by a Multi bucket for the same token.
+/// * Every token of the frequencies (and each of the `probes`, which need not have been
+///   sampled) routes to the bucket which a linear scan of the columns finds, and the bucket has
+///   the type that it was routed as. A token is routed to a Single bucket if and only if the
+///   strategy selected one for it.
+///
+pub fn check_buckets(
+    strategy: Strategy,
+    frequencies: &BTreeMap<String, usize>,
+    bucket_count: u16,
+    fields: &[String],
+    probes: &[String],
+) -> anyhow::Result<()> {
+    let buckets = strategy.select_buckets(frequencies.clone(), bucket_count, fields);
+    if buckets.is_empty() {
+        bail!("No buckets were selected.");
+    }
+    let columns = buckets
+        .iter()
+        .map(|(token, btype)| btype.column_name(token))
+        .collect::<Vec<_>>();
+    if let Some(pair) = columns.windows(2).find(|pair| pair[0] >= pair[1]) {
+        bail!(
+            "The bucket columns are not strictly sorted: {:?} precedes {:?}.",
+            pair[0],
+            pair[1]
+        );
+    }
+
+    let hashed = columns[0].starts_with(HASH_BUCKET_PREFIX);
+    if !hashed {
+        if buckets[0].1 != BucketType::Multi {
+            bail!("The first bucket, {:?}, is not a Multi bucket.", columns[0]);
+        }
+        for (idx, (token, btype)) in buckets.iter().enumerate() {
+            if *btype == BucketType::Single
+                && buckets.get(idx + 1) != Some(&(token.clone(), BucketType::Multi))
+            {
+                bail!("The Single bucket {token:?} is not followed by a Multi bucket for it.");
+            }
+        }
+    }
+
+    let bucket_columns = columns
+        .iter()
+        .map(|column| FieldName::from(column.as_str()))
+        .collect::<Vec<_>>();
+    for token in frequencies.keys().chain(probes) {
+        let routed = find_bucket(&bucket_columns, token);
+        let expected = if hashed {
+            (hash_bucket(token, buckets.len()), BucketType::Multi)
+        } else {
+            linear_route(&columns, token)
+        };
+        if routed != expected {
+            bail!("{token:?} was routed to {routed:?}, but a linear scan found {expected:?}.");
+        }
+        let (idx, btype) = routed;
+        if buckets[idx].1 != btype {
+            bail!(
+                "{token:?} was routed to the bucket {:?} as a {btype:?} bucket.",
+                columns[idx]
+            );
+        }
+        let single = !hashed && buckets.contains(&(token.clone(), BucketType::Single));
+        if single != (btype == BucketType::Single) {
+            bail!("{token:?} was routed to a {btype:?} bucket, but single={single}.");
+        }
+    }
+    Ok(())
+}
+
+///
+/// Routes a token by scanning the (sorted) bucket columns for its Single bucket, or otherwise
+/// the last column which sorts before it: a reference for the binary search of `find_bucket`.
+///
+fn linear_route(columns: &[String], token: &str) -> (usize, BucketType) {
+    let needle = BucketType::Single.column_name(token);
+    if let Some(idx) = columns.iter().position(|column| *column == needle) {
+        return (idx, BucketType::Single);
+    }
+    let idx = columns
+        .iter()
+        .rposition(|column| *column < needle)
+        .unwrap_or(0);
+    (idx, BucketType::Multi)
+}
+
+///
+/// Runs `check_buckets` for every strategy and a range of bucket counts, with and without a
+/// named field, against the random distribution of each of the given seeds. Returns the number
+/// of cases which were checked, or the first failure along with its seed and parameters.
+///
+pub fn check_random_buckets(seeds: Range<u64>) -> anyhow::Result<usize> {
+    let title = ["title".to_owned()];
+    let mut cases = 0;
+    for seed in seeds {
+        let frequencies = random_frequencies(seed, 500, &title);
+        let mut rng = ChaCha8Rng::seed_from_u64(!seed);
+        let probes = (0..100).map(|_| random_token(&mut rng)).collect::<Vec<_>>();
+        for strategy in Strategy::value_variants() {
+            for bucket_count in [1, 2, 3, 16, 256] {
+                for fields in [&[][..], &title[..]] {
+                    check_buckets(*strategy, &frequencies, bucket_count, fields, &probes)
+                        .with_context(|| {
+                            format!(
+                                "Seed {seed}, {strategy:?} with {bucket_count} buckets and \
+                                fields {fields:?}"
+                            )
+                        })?;
+                    cases += 1;
+                }
+            }
+        }
+    }
+    Ok(cases)
+}
@@ -1,68 +1,123 @@
-use std::collections::HashSet;
-use std::path::Path;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow, bail};
 use async_stream::stream;
-use futures_util::{StreamExt, future};
+use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt, future};
+use object_store::ObjectStore;
+use object_store::path::Path as ObjectPath;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
 use tokio::fs::OpenOptions;
 use tokio::runtime::Handle;
+use url::Url;
 
 use vortex_array::arrays::StructArray;
 use vortex_array::builders::{ArrayBuilderExt, builder_with_capacity};
 use vortex_array::stream::{ArrayStream, ArrayStreamAdapter};
 use vortex_array::validity::Validity;
-use vortex_array::{Array, IntoArray};
-use vortex_dtype::{DType, Nullability, PType, StructDType};
+use vortex_array::{Array, ArrayRef, IntoArray, ToCanonical};
+use vortex_buffer::{Alignment, Buffer, ByteBuffer};
+use vortex_dtype::{DType, FieldName, Nullability, PType, StructDType};
+use vortex_error::VortexResult;
 use vortex_expr::ExprRef;
 use vortex_file::{VortexFile, VortexOpenOptions, VortexWriteOptions, scan::ScanBuilder};
-use vortex_io::TokioFile;
+use vortex_io::{ObjectStoreReadAt, TokioFile, VortexReadAt};
+use vortex_layout::LayoutReader;
+use vortex_layout::layouts::flat::writer::FlatLayoutStrategy;
+use vortex_mask::Mask;
+use vortex_scalar::Scalar;
 
-use crate::vortex_list_expr::ListContainsExpr;
+use crate::Indexer;
+use crate::common::{
+    Corpus, Document, Near, PREFIX_MARKER, Query, QueryExpr, QueryMode, SampleMethod, Text,
+    Tokenizer, field_token, snippet,
+};
+use crate::metrics::{file_sizes_by_extension, index_bytes};
+use crate::vortex_buckets::{
+    HASH_BUCKET_PREFIX, fnv1a, hash_bucket, hot_tokens, promote_hot_tokens,
+};
+use crate::vortex_list_expr::{ListContainsExpr, ListContainsSetExpr};
+use crate::vortex_manifest::{
+    BucketManifest, BuildParameters, IndexConfig, MANIFEST_FILE, Manifest, format_version,
+    read_manifest, write_manifest,
+};
+use crate::vortex_near_expr::{NearExpr, NearTerm};
+use crate::vortex_simplify::{literal_bool, simplify};
+use crate::vortex_terms::Terms;
+use crate::vortex_tombstone_expr::LiveIdsExpr;
+#[cfg(target_os = "linux")]
+use crate::vortex_uring::UringFile;
+use crate::vortex_vocabulary::Vocabulary;
 
-const ID_COLUMN: &str = "::id::";
+pub use crate::vortex_buckets::Strategy;
+pub use crate::vortex_shards::{ShardedVortexSearcher, vortex_index_shards};
 
-const CHUNK_SIZE: usize = 8192;
+pub(crate) const ID_COLUMN: &str = "::id::";
+/// The optional column containing the original text of each document, which follows the ID.
+const BODY_COLUMN: &str = "::body::";
+/// The optional column containing the timestamp of each document, which follows the body.
+pub(crate) const TIMESTAMP_COLUMN: &str = "::timestamp::";
+/// The optional column containing the external string ID of each document (or null, for a
+/// document without one), which follows the timestamp.
+const KEY_COLUMN: &str = "::key::";
+/// The optional column containing the length of each document (the number of tokens that it was
+/// indexed with, counting repeats), which follows the key.
+const LENGTH_COLUMN: &str = "::length::";
 
-///
-/// Given a non-unique sample of tokens from a dataset, select `pivot_count` bucket values which
-/// will roughly equally divide the sample.
-///
-fn select_buckets_from(
-    mut sample_tokens: Vec<String>,
-    pivot_count: u16,
-) -> Vec<(String, BucketType)> {
-    assert!(!sample_tokens.is_empty());
-    assert!(pivot_count > 0);
-    sample_tokens.sort_unstable();
+/// The suffix of the term-frequency column which follows each bucket column, if enabled.
+const TF_SUFFIX: &str = ":tf";
+/// The suffix of the positions column which follows each term-frequency column, if enabled.
+const POSITIONS_SUFFIX: &str = ":pos";
 
-    let bucket_width = sample_tokens.len() as f64 / pivot_count as f64;
-    let mut candidates = (0..pivot_count)
-        .map(|idx| sample_tokens[(idx as f64 * bucket_width).floor() as usize].clone());
+/// The default number of documents in each chunk of a segment, which is also its row group size.
+pub const DEFAULT_CHUNK_SIZE: usize = 8192;
 
-    let mut previous_candidate = candidates.next().unwrap();
-    let mut single_value_emitted = false;
-    let mut buckets = Vec::with_capacity(pivot_count as usize);
-    for candidate in candidates {
-        if candidate != previous_candidate {
-            // Emit the previous candidate as a multi-valued bucket.
-            buckets.push((previous_candidate, BucketType::Multi));
-            single_value_emitted = false;
-        } else if !single_value_emitted {
-            // Emit the previous candidate as a single-valued bucket.
-            buckets.push((previous_candidate, BucketType::Single));
-            single_value_emitted = true;
-        }
-        previous_candidate = candidate;
-    }
-    // Finally, cap with a multi-valued bucket.
-    buckets.push((previous_candidate, BucketType::Multi));
-    buckets
-}
+/// The default share of the token occurrences of a new index above which a Multi bucket is split.
+pub const DEFAULT_MAX_BUCKET_SHARE: f64 = 0.25;
+
+/// The default number of documents read from stdin before they are flushed as a new segment.
+pub const DEFAULT_FLUSH_EVERY: usize = 100_000;
+
+/// The cost of filtering a row by a Multi bucket, whose list must be searched, relative to
+/// filtering it by a Single bucket.
+const MULTI_BUCKET_ROW_COST: f64 = 4.0;
+/// The cost of a row of a posting list, which is combined with the candidate rows without reading
+/// any columns, relative to filtering a row by a Single bucket.
+const POSTING_LIST_ROW_COST: f64 = 0.01;
+
+/// Describes how `VortexSearcher::ids` materializes the IDs of the matches, for `explain`.
+const LATE_MATERIALIZATION: &str = "ids: the filter is evaluated per row group first, and IDs \
+    are only read from the row groups with matches";
 
-#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".vortex";
+/// The extension of the row group statistics file which accompanies each segment.
+const STATS_EXTENSION: &str = "stats.json";
+/// The extension of the vocabulary bloom filter file which accompanies each segment.
+const VOCABULARY_EXTENSION: &str = "vocabulary.json";
+/// The extension of the sorted list of indexed tokens which accompanies each segment.
+const TERMS_EXTENSION: &str = "terms.json";
+/// The extension of the posting lists file which accompanies each segment of an index built with
+/// `posting_lists`.
+const POSTINGS_EXTENSION: &str = "postings.bin";
+/// The extension of the map from the IDs of skipped duplicate documents to the IDs of their
+/// canonical documents, which accompanies each segment of an index built with `duplicate_ids`.
+const DUPLICATES_EXTENSION: &str = "duplicates.json";
+/// The file in the index directory which maps codes to tokens, if the index is dictionary-encoded.
+const DICTIONARY_FILE: &str = "dictionary.json";
+/// The file in the index directory which records deleted documents, if there are any.
+const TOMBSTONES_FILE: &str = "tombstones.json";
+
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Deserialize, Serialize)]
 #[repr(u8)]
-enum BucketType {
+pub(crate) enum BucketType {
     // NB: `Single` must sort first, since we always attempt our binary searches with an exact
     // match.
     Single = 0,
@@ -70,214 +125,4887 @@ enum BucketType {
 }
 
 impl BucketType {
-    fn column_name(&self, token: &str) -> String {
+    pub(crate) fn column_name(&self, token: &str) -> String {
         format!("{token}:{}", (*self) as u8)
     }
+
+    ///
+    /// A `Multi` bucket in a dictionary-encoded index contains lists of codes rather than lists
+    /// of tokens.
+    ///
+    fn dtype(&self, dictionary: bool) -> DType {
+        match self {
+            BucketType::Single => DType::Bool(Nullability::NonNullable),
+            BucketType::Multi if dictionary => DType::List(
+                DType::Primitive(PType::U32, Nullability::NonNullable).into(),
+                Nullability::NonNullable,
+            ),
+            BucketType::Multi => DType::List(
+                DType::Utf8(Nullability::NonNullable).into(),
+                Nullability::NonNullable,
+            ),
+        }
+    }
+
+    ///
+    /// A `Single` bucket records the frequency of its token (or zero), while a `Multi` bucket
+    /// records a list of frequencies parallel to its list of tokens.
+    ///
+    fn tf_dtype(&self) -> DType {
+        match self {
+            BucketType::Single => DType::Primitive(PType::U32, Nullability::NonNullable),
+            BucketType::Multi => DType::List(
+                DType::Primitive(PType::U32, Nullability::NonNullable).into(),
+                Nullability::NonNullable,
+            ),
+        }
+    }
 }
 
-pub async fn vortex_index(path: &Path, doc_count: usize, buckets: u16) -> anyhow::Result<()> {
-    let document_stream = document_array_stream(doc_count, buckets).await?;
-    vortex_index_array(path, document_stream).await?;
-    println!(">>> created {path:?}, with up to {buckets} buckets");
-    Ok(())
+fn tf_column_name(column_name: &str) -> String {
+    format!("{column_name}{TF_SUFFIX}")
 }
 
-async fn document_array_stream(
-    doc_count: usize,
-    buckets: u16,
-) -> anyhow::Result<impl ArrayStream + Unpin> {
-    let buckets = select_buckets_from(
-        crate::common::documents(1000)
-            .map(|(_, doc)| doc.into_iter())
-            .flatten()
-            .collect(),
-        buckets,
-    );
+fn positions_column_name(column_name: &str) -> String {
+    format!("{column_name}{POSITIONS_SUFFIX}")
+}
 
-    // Construct the `DType` for the `StructArray` that we will be emitting.
-    // There is one prefixed `ID_COLUMN`, followed by one column per bucket. The Vortex DType of
-    // each bucket is decided by its `BucketType`.
-    let column_dtypes: Vec<DType> =
-        std::iter::once(DType::Primitive(PType::U64, Nullability::NonNullable).into())
-            .chain(buckets.iter().map(|(_, btype)| {
-                match btype {
-                    BucketType::Single => DType::Bool(Nullability::NonNullable).into(),
-                    BucketType::Multi => DType::List(
-                        DType::Utf8(Nullability::NonNullable).into(),
-                        Nullability::NonNullable,
-                    )
-                    .into(),
-                }
-            }))
-            .collect();
-    let struct_dtype = StructDType::new(
-        std::iter::once(ID_COLUMN.into())
-            .chain(buckets.iter().map(|(t, btype)| btype.column_name(t).into()))
-            .collect(),
-        column_dtypes.clone(),
-    );
-    let dtype = DType::Struct(struct_dtype.clone().into(), Nullability::NonNullable);
+///
+/// The positions of a bucket's tokens in each document: the positions of a Single bucket's token,
+/// or the positions of each of the tokens in a Multi bucket, concatenated in the order of the
+/// tokens (and split by their term frequencies).
+///
+fn positions_dtype() -> DType {
+    DType::List(
+        DType::Primitive(PType::U32, Nullability::NonNullable).into(),
+        Nullability::NonNullable,
+    )
+}
 
-    // Create a stream that emits batches of documents as StructArrays.
-    let stream = stream! {
-        let mut entries_to_append: Vec<Vec<String>> = buckets.iter().map(|_| Vec::new()).collect();
-        let mut documents = crate::common::documents(doc_count);
-        let mut might_have_more_docs = true;
-        while might_have_more_docs {
-            let mut builders = column_dtypes
-                .iter()
-                .map(|dtype| builder_with_capacity(dtype.into(), 1024))
-                .collect::<Vec<_>>();
-            let mut doc_count = 0;
-            while doc_count < CHUNK_SIZE {
-                let Some((id, document)) = documents.next() else {
-                    // There are no more documents. Finish flushing the current chunk, and then
-                    // complete the stream.
-                    might_have_more_docs = false;
-                    break;
-                };
-                builders[0].append_scalar(&id.into())?;
-                // Group the tokens by the bucket that they will be appended to.
-                for token in document {
-                    let idx = match buckets
-                        .binary_search_by_key(&(&token, &BucketType::Single), |(token, btype)| {
-                            (token, btype)
-                        }) {
-                        Ok(idx) => idx,
-                        Err(idx) if idx == 0 => 0,
-                        Err(idx) => idx - 1,
-                    };
-                    entries_to_append[idx].push(token);
-                }
-                // Drain all buckets into the builders. Many of them will be empty, and that is ok.
-                for (idx, entries) in entries_to_append.iter_mut().enumerate() {
-                    match buckets[idx].1 {
-                        BucketType::Single => {
-                            let set = !entries.is_empty();
-                            builders[idx + 1].append_scalar(&set.into())?;
-                            entries.clear();
-                        }
-                        BucketType::Multi => builders[idx + 1]
-                            .append_scalar(&entries.drain(..).collect::<Vec<_>>().into())?,
-                    }
-                }
-                doc_count += 1;
+fn has_positions(dtype: &StructDType) -> bool {
+    dtype
+        .names()
+        .iter()
+        .any(|name| name.ends_with(POSITIONS_SUFFIX))
+}
+
+///
+/// The range of tokens present in each bucket of a group of consecutive rows in a segment, which
+/// allows scans to skip row groups that cannot possibly match a query.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RowGroupStats {
+    rows: u64,
+    /// The minimum and maximum tokens present in each bucket, or `None` if the bucket is empty
+    /// for every row in the group.
+    buckets: Vec<Option<(String, String)>>,
+    /// The number of rows in the group for which each bucket is non-empty. Absent for segments
+    /// written before document frequencies were recorded.
+    #[serde(default)]
+    document_frequencies: Vec<u64>,
+    /// The maximum term frequency of any token of each bucket in any row of the group, which
+    /// bounds the scores of its rows for `top_k`. Empty for segments without term frequencies,
+    /// and for segments written before the maximums were recorded.
+    #[serde(default)]
+    max_term_frequencies: Vec<u32>,
+}
+
+impl RowGroupStats {
+    fn might_contain(&self, bucket_columns: &[FieldName], token: &str) -> bool {
+        let (idx, _) = find_bucket(bucket_columns, token);
+        matches!(
+            &self.buckets[idx],
+            Some((min, max)) if min.as_str() <= token && token <= max.as_str()
+        )
+    }
+
+    ///
+    /// Excluded tokens are ignored, since their absence cannot be proven by a range.
+    ///
+    fn might_match(&self, bucket_columns: &[FieldName], query: &Query) -> bool {
+        if let Some(expr) = &query.expr {
+            return self.might_match_expr(bucket_columns, expr);
+        }
+        let mut tokens = query.tokens.iter();
+        match query.mode {
+            QueryMode::Any => tokens.any(|token| self.might_contain(bucket_columns, token)),
+            QueryMode::All => {
+                !query.tokens.is_empty()
+                    && tokens.all(|token| self.might_contain(bucket_columns, token))
             }
+        }
+    }
 
-            let fields = builders.into_iter().map(|mut b| b.finish()).collect();
+    ///
+    /// Negations, patterns and timestamp ranges might match any row group.
+    ///
+    fn might_match_expr(&self, bucket_columns: &[FieldName], expr: &QueryExpr) -> bool {
+        match expr {
+            QueryExpr::Token(token) => self.might_contain(bucket_columns, token),
+            QueryExpr::Phrase(near) => near
+                .tokens
+                .iter()
+                .all(|token| self.might_contain(bucket_columns, token)),
+            QueryExpr::And(clauses) => clauses
+                .iter()
+                .all(|clause| self.might_match_expr(bucket_columns, clause)),
+            QueryExpr::Or(clauses) => clauses
+                .iter()
+                .any(|clause| self.might_match_expr(bucket_columns, clause)),
+            QueryExpr::Not(_) | QueryExpr::Pattern(_) | QueryExpr::Timestamps(_) => true,
+        }
+    }
 
-            yield Ok(StructArray::try_new_with_dtype(
-                fields,
-                struct_dtype.clone().into(),
-                doc_count,
-                Validity::NonNullable,
-            )?
-            .into_array());
+    fn record(&mut self, idx: usize, tokens: &[String]) {
+        let (Some(min), Some(max)) = (tokens.iter().min(), tokens.iter().max()) else {
+            return;
+        };
+        self.document_frequencies[idx] += 1;
+        let (bucket_min, bucket_max) =
+            self.buckets[idx].get_or_insert_with(|| (min.clone(), max.clone()));
+        if min < bucket_min {
+            *bucket_min = min.clone();
         }
-    };
+        if max > bucket_max {
+            *bucket_max = max.clone();
+        }
+    }
 
-    Ok(ArrayStreamAdapter::new(dtype, stream.boxed()))
+    fn record_term_frequencies(&mut self, idx: usize, tfs: &[u32]) {
+        if let Some(max) = tfs.iter().max() {
+            let bucket_max = &mut self.max_term_frequencies[idx];
+            *bucket_max = (*bucket_max).max(*max);
+        }
+    }
 }
 
-async fn vortex_index_array(
-    path: &Path,
-    array_stream: impl ArrayStream + Unpin,
-) -> anyhow::Result<()> {
-    let f = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&path)
-        .await?;
+///
+/// Assigns a `u32` code to each distinct token in the Multi buckets of a dictionary-encoded
+/// index. The codes are shared by all of the segments of the index, and codes are only ever
+/// appended, so segments can be merged without re-encoding them.
+///
+#[derive(Clone, Debug, Default)]
+struct Dictionary {
+    tokens: Vec<String>,
+    codes: HashMap<String, u32>,
+}
+
+impl Dictionary {
+    fn from_tokens(tokens: Vec<String>) -> Self {
+        let codes = tokens
+            .iter()
+            .enumerate()
+            .map(|(code, token)| (token.clone(), code as u32))
+            .collect();
+        Dictionary { tokens, codes }
+    }
+
+    fn code(&self, token: &str) -> Option<u32> {
+        self.codes.get(token).copied()
+    }
+
+    fn encode(&mut self, token: &str) -> u32 {
+        if let Some(code) = self.code(token) {
+            return code;
+        }
+        let code = u32::try_from(self.tokens.len()).expect("Too many distinct tokens.");
+        self.tokens.push(token.to_owned());
+        self.codes.insert(token.to_owned(), code);
+        code
+    }
+}
 
-    VortexWriteOptions::default().write(f, array_stream).await?;
+///
+/// Reads the dictionary of the index at `path`, if it is dictionary-encoded.
+///
+async fn read_dictionary(path: &Path) -> anyhow::Result<Option<Dictionary>> {
+    if path.is_file() {
+        return Ok(None);
+    }
+    match tokio::fs::read(path.join(DICTIONARY_FILE)).await {
+        Ok(bytes) => Ok(Some(Dictionary::from_tokens(serde_json::from_slice(
+            &bytes,
+        )?))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
 
+async fn write_dictionary(path: &Path, dictionary: &Dictionary) -> anyhow::Result<()> {
+    let bytes = serde_json::to_vec(&dictionary.tokens)?;
+    tokio::fs::write(path.join(DICTIONARY_FILE), bytes).await?;
     Ok(())
 }
 
-pub async fn vortex_search(path: &Path, query: &str) -> anyhow::Result<()> {
-    let (file, dtype) = vortex_file(path).await?;
+///
+/// Maps the ID of each deleted document to the number of the first segment which it was _not_
+/// deleted from: a document is deleted from every segment numbered below that. This allows a
+/// document to be replaced by deleting it from the existing segments, and then writing it to a
+/// new segment.
+///
+type Tombstones = BTreeMap<u64, u64>;
+
+///
+/// Reads the documents which have been deleted from the index at `path`, but which have not yet
+/// been compacted away.
+///
+async fn read_tombstones(path: &Path) -> anyhow::Result<Tombstones> {
+    if path.is_file() {
+        return Ok(Tombstones::new());
+    }
+    match tokio::fs::read(path.join(TOMBSTONES_FILE)).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Tombstones::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn write_tombstones(path: &Path, tombstones: &Tombstones) -> anyhow::Result<()> {
+    tokio::fs::write(path.join(TOMBSTONES_FILE), serde_json::to_vec(tombstones)?).await?;
+    Ok(())
+}
 
-    let filter = create_filter(&dtype, crate::common::tokenize(query));
+///
+/// Returns a filter which excludes the documents deleted from the given segment, or `None` if
+/// there are none. A legacy index file is treated as segment zero.
+///
+fn tombstone_filter(tombstones: &Tombstones, segment_number: Option<u64>) -> Option<ExprRef> {
+    // NB: The IDs of a `BTreeMap` are already sorted.
+    let deleted = tombstones
+        .iter()
+        .filter(|(_, first_live)| segment_number.unwrap_or(0) < **first_live)
+        .map(|(id, _)| *id)
+        .collect::<Arc<[u64]>>();
+    (!deleted.is_empty()).then(|| {
+        LiveIdsExpr::new_expr(
+            vortex_expr::get_item(ID_COLUMN, vortex_expr::ident()),
+            deleted,
+        )
+    })
+}
 
-    let counts = future::try_join_all(
-        file.scan()?
-            .with_filter(filter)
-            .with_projection(vortex_expr::lit(true))
-            .map(|array| Ok(array.len()))
-            .build()?,
-    )
-    .await?;
+fn stats_path(segment: &Path) -> PathBuf {
+    segment.with_extension(STATS_EXTENSION)
+}
 
-    let count = counts.into_iter().map(|c| c.unwrap_or(0)).sum::<usize>();
-    println!(">>> {count}");
+///
+/// Reads the row group statistics for a segment, if it has any: segments written before
+/// statistics were introduced do not.
+///
+async fn read_stats(segment: &Path) -> anyhow::Result<Option<Vec<RowGroupStats>>> {
+    match tokio::fs::read(stats_path(segment)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
 
+async fn write_stats(segment: &Path, row_groups: &[RowGroupStats]) -> anyhow::Result<()> {
+    tokio::fs::write(stats_path(segment), serde_json::to_vec(row_groups)?).await?;
     Ok(())
 }
 
-pub async fn vortex_search_many(path: &Path, queries: usize) -> anyhow::Result<()> {
-    let (file, dtype) = vortex_file(path).await?;
-    let layout_reader = file.layout_reader()?;
+fn vocabulary_path(segment: &Path) -> PathBuf {
+    segment.with_extension(VOCABULARY_EXTENSION)
+}
+
+///
+/// Reads the vocabulary of a segment, if it has one: segments written before vocabularies were
+/// introduced do not.
+///
+async fn read_vocabulary(segment: &Path) -> anyhow::Result<Option<Vocabulary>> {
+    match tokio::fs::read(vocabulary_path(segment)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn write_vocabulary(segment: &Path, vocabulary: &Vocabulary) -> anyhow::Result<()> {
+    tokio::fs::write(vocabulary_path(segment), serde_json::to_vec(vocabulary)?).await?;
+    Ok(())
+}
 
-    let mut matches = 0;
-    for (_, doc) in crate::common::documents(queries) {
-        let filter = create_filter(&dtype, doc);
+fn terms_path(segment: &Path) -> PathBuf {
+    segment.with_extension(TERMS_EXTENSION)
+}
 
-        let counts = future::try_join_all(
-            ScanBuilder::new(layout_reader.clone())
-                .with_filter(filter)
-                .with_projection(vortex_expr::lit(true))
-                .with_tokio_executor(Handle::current())
-                .map(|array| Ok(array.len()))
-                .build()?,
-        )
-        .await?;
-        matches += counts.into_iter().map(|c| c.unwrap_or(0)).sum::<usize>();
+///
+/// Reads the indexed tokens of a segment, if it has them: segments written before they were
+/// recorded do not.
+///
+async fn read_terms(segment: &Path) -> anyhow::Result<Option<Terms>> {
+    match tokio::fs::read(terms_path(segment)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
     }
+}
 
-    println!(">>> {queries} queries matched {matches} docs");
+async fn write_terms(segment: &Path, terms: &Terms) -> anyhow::Result<()> {
+    tokio::fs::write(terms_path(segment), serde_json::to_vec(terms)?).await?;
     Ok(())
 }
 
+fn duplicates_path(segment: &Path) -> PathBuf {
+    segment.with_extension(DUPLICATES_EXTENSION)
+}
+
 ///
-/// Binary search on field names to find the bins that we'll be scanning in, and create a filter.
+/// Reads the map from the IDs of the duplicate documents which were skipped while building a
+/// segment to the IDs of their canonical documents, if it was recorded.
 ///
-fn create_filter(dtype: &Arc<StructDType>, tokens: HashSet<String>) -> ExprRef {
-    tokens
-        .into_iter()
-        .map(|token| {
-            let needle: Arc<str> = BucketType::Single.column_name(&token).into();
-            let result = dtype.names().binary_search(&needle);
-            let (idx, btype) = match result {
-                Ok(idx) => (idx, BucketType::Single),
-                Err(idx) if idx < 1 => {
-                    // NB: Our ID_COLUMN is the first field, so an insertion position of `1`
-                    // matches our first bucket.
-                    (1, BucketType::Multi)
-                }
-                Err(idx) => (idx - 1, BucketType::Multi),
-            };
+async fn read_duplicates(segment: &Path) -> anyhow::Result<Option<BTreeMap<u64, u64>>> {
+    match tokio::fs::read(duplicates_path(segment)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
 
-            let get_item = vortex_expr::get_item(dtype.names()[idx].clone(), vortex_expr::ident());
-            match btype {
-                BucketType::Single => get_item,
-                BucketType::Multi => ListContainsExpr::new_expr(get_item, token.into()),
+async fn write_duplicates(segment: &Path, duplicates: &BTreeMap<u64, u64>) -> anyhow::Result<()> {
+    tokio::fs::write(duplicates_path(segment), serde_json::to_vec(duplicates)?).await?;
+    Ok(())
+}
+
+///
+/// The posting lists of the Single buckets of a segment which are not stored as columns, keyed by
+/// bucket column: each is a bitmap of the ordinals of the rows which contain the bucket's token.
+///
+type Postings = BTreeMap<String, RoaringBitmap>;
+
+fn postings_path(segment: &Path) -> PathBuf {
+    segment.with_extension(POSTINGS_EXTENSION)
+}
+
+///
+/// Reads the posting lists of a segment, if it has any: only segments of indexes built with
+/// `posting_lists` do.
+///
+async fn read_postings(segment: &Path) -> anyhow::Result<Option<Postings>> {
+    match tokio::fs::read(postings_path(segment)).await {
+        Ok(bytes) => Ok(Some(decode_postings(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn write_postings(segment: &Path, postings: &Postings) -> anyhow::Result<()> {
+    let mut bytes = Vec::new();
+    for (column, bitmap) in postings {
+        bytes.extend_from_slice(&(column.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(column.as_bytes());
+        bitmap.serialize_into(&mut bytes)?;
+    }
+    tokio::fs::write(postings_path(segment), bytes).await?;
+    Ok(())
+}
+
+///
+/// Decodes posting lists which were written as a sequence of length-prefixed column names, each
+/// followed by its serialized bitmap.
+///
+fn decode_postings(mut bytes: &[u8]) -> anyhow::Result<Postings> {
+    let mut postings = Postings::new();
+    while let Some((len, rest)) = bytes.split_first_chunk::<4>() {
+        let Some((column, mut rest)) = rest.split_at_checked(u32::from_le_bytes(*len) as usize)
+        else {
+            bail!("Truncated posting lists.");
+        };
+        let bitmap = RoaringBitmap::deserialize_from(&mut rest)?;
+        postings.insert(String::from_utf8(column.to_vec())?, bitmap);
+        bytes = rest;
+    }
+    if !bytes.is_empty() {
+        bail!("Truncated posting lists.");
+    }
+    Ok(postings)
+}
+
+///
+/// Reads an object from an object store, if it exists.
+///
+async fn get_bytes(store: &dyn ObjectStore, location: &Path) -> anyhow::Result<Option<Bytes>> {
+    let location = ObjectPath::from(location.to_string_lossy().as_ref());
+    match store.get(&location).await {
+        Ok(result) => Ok(Some(result.bytes().await?)),
+        Err(object_store::Error::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///
+/// Reads and parses a JSON object from an object store, if it exists.
+///
+async fn get_json<T: serde::de::DeserializeOwned>(
+    store: &dyn ObjectStore,
+    location: &Path,
+) -> anyhow::Result<Option<T>> {
+    match get_bytes(store, location).await? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+fn has_column(dtype: &StructDType, column: &str) -> bool {
+    dtype.names().iter().any(|name| name.as_ref() == column)
+}
+
+///
+/// Recovers the buckets of an existing index from its column names, along with whether it
+/// contains term frequency columns.
+///
+fn buckets_from_dtype(dtype: &StructDType) -> anyhow::Result<(Vec<(String, BucketType)>, bool)> {
+    let mut term_frequencies = false;
+    let mut buckets = Vec::new();
+    for name in dtype.names().iter() {
+        if [
+            ID_COLUMN,
+            BODY_COLUMN,
+            TIMESTAMP_COLUMN,
+            KEY_COLUMN,
+            LENGTH_COLUMN,
+        ]
+        .contains(&name.as_ref())
+        {
+            continue;
+        }
+        if name.ends_with(TF_SUFFIX) {
+            term_frequencies = true;
+            continue;
+        }
+        if name.ends_with(POSITIONS_SUFFIX) {
+            continue;
+        }
+        let (token, btype) = match name.rsplit_once(':') {
+            Some((token, "0")) => (token, BucketType::Single),
+            Some((token, "1")) => (token, BucketType::Multi),
+            _ => bail!("Unrecognized column in index: {name}"),
+        };
+        buckets.push((token.to_owned(), btype));
+    }
+    Ok((buckets, term_frequencies))
+}
+
+///
+/// Builds a Vortex index containing an ID column, followed by up to `buckets` columns which
+/// each contain some of the tokens in the corpus, as selected by the `strategy`.
+///
+/// If `term_frequencies` is set, each bucket column is followed by a column recording the
+/// frequency of its tokens in each document, which allows for ranking with `top_k`. If
+/// `store_bodies` is set, the ID column is followed by a column containing the original text of
+/// each document.
+///
+/// The index is a directory of segment files: indexing into an existing index appends a new
+/// segment which reuses the buckets (and other settings) of the existing segments.
+/// Document IDs are assigned by the `Corpus`, and so are not deduplicated across segments.
+///
+/// Each segment is accompanied by a file recording the range of tokens in each bucket for each
+/// chunk of rows, which `VortexSearcher` uses to prune row groups.
+///
+/// If `dictionary` is set, Multi buckets contain lists of integer codes rather than strings, and
+/// the index directory contains a dictionary mapping the codes to tokens.
+///
+/// If `posting_lists` is set, Single buckets do not have columns: instead, each segment is
+/// accompanied by a file containing a roaring bitmap of the rows which contain each Single
+/// bucket's token. This is incompatible with `term_frequencies`.
+///
+/// If `positions` is set, each term frequency column is followed by a column recording the
+/// positions of the bucket's tokens in each document, which allows for proximity queries. This
+/// requires `term_frequencies`.
+///
+/// The index directory also contains a manifest describing its buckets, build parameters,
+/// tokenizer and layout version, which is updated as segments are added and rewritten. Appending
+/// to an index requires the same tokenizer that it was built with.
+///
+/// Since buckets are selected from a sample, a skewed sample can funnel most tokens into a single
+/// Multi bucket. When building the first segment of an index, any Multi bucket which receives more
+/// than `max_bucket_share` of the token occurrences is split into sub-buckets using the exact
+/// document frequencies of its tokens, and the segment is rebuilt (once) with the refined buckets.
+///
+#[derive(Clone, Debug)]
+pub struct VortexIndexer {
+    pub buckets: u16,
+    pub strategy: Strategy,
+    /// The number of documents to sample tokens from when selecting buckets.
+    pub sample_docs: usize,
+    pub sample: SampleMethod,
+    /// Rather than sampling, read the entire corpus an additional time to select buckets using
+    /// the exact document frequency of every token.
+    pub two_pass: bool,
+    pub term_frequencies: bool,
+    pub store_bodies: bool,
+    /// Store the length of each document in a column, for the length normalization of scores.
+    pub doc_lengths: bool,
+    pub dictionary: bool,
+    pub posting_lists: bool,
+    pub positions: bool,
+    /// The number of chunks of documents to tokenize and build concurrently.
+    pub index_threads: usize,
+    /// The number of documents in each chunk, which is also the granularity of the row group
+    /// statistics used for pruning.
+    pub chunk_size: usize,
+    pub compression: Compression,
+    /// The share of the token occurrences of a new index above which a Multi bucket is split. A
+    /// share of 1 disables splitting.
+    pub max_bucket_share: f64,
+    /// The number of the most frequent sampled tokens which are given their own Single bucket,
+    /// in addition to those selected by the strategy.
+    pub hot_tokens: usize,
+    /// Order the documents of each segment by the Single buckets that they contain before writing
+    /// them, which lengthens the runs of the Single columns and tightens the row group
+    /// statistics. Requires reading the whole corpus into memory.
+    pub sort_by_tokens: bool,
+    /// Skip each document whose set of tokens exactly matches that of an earlier document in the
+    /// same segment, and count the skipped documents in the manifest.
+    pub dedup: bool,
+    /// Additionally record the ID of the canonical document for the ID of each skipped duplicate,
+    /// in a file alongside each segment.
+    pub duplicate_ids: bool,
+}
+
+///
+/// How the columns of a segment are encoded when it is written.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// The default Vortex layout, which compresses each column of each chunk with BtrBlocks.
+    #[default]
+    Btrblocks,
+    /// Each chunk is written as a single flat layout, using canonical (uncompressed) encodings.
+    /// Files are larger, but no decompression is required to scan them.
+    None,
+}
+
+impl Compression {
+    fn write_options(self) -> VortexWriteOptions {
+        match self {
+            Compression::Btrblocks => VortexWriteOptions::default(),
+            Compression::None => {
+                VortexWriteOptions::default().with_strategy(Arc::new(FlatLayoutStrategy::default()))
             }
-        })
-        .reduce(vortex_expr::and)
-        .unwrap_or_else(|| vortex_expr::lit(false))
+        }
+    }
 }
 
-async fn vortex_file(path: &Path) -> anyhow::Result<(VortexFile, Arc<StructDType>)> {
-    let file = VortexOpenOptions::file()
-        .open_read_at(TokioFile::open(path)?)
-        .await?;
+impl Indexer for VortexIndexer {
+    async fn index(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+    ) -> anyhow::Result<()> {
+        self.index_with_buckets(path, corpus, tokenizer, doc_count, None)
+            .await
+    }
+}
 
-    let dtype = file
-        .dtype()
-        .as_struct()
-        .ok_or_else(|| anyhow!("Does not appear to be an index!"))?
-        .clone();
+impl VortexIndexer {
+    ///
+    /// Indexes the corpus into a new segment at `path`. The buckets of a new index are selected
+    /// from the corpus, unless `preset` buckets (from an exported config) are given, in which
+    /// case they are used exactly as given.
+    ///
+    #[tracing::instrument(name = "vortex_index", skip_all, fields(path = ?path))]
+    pub(crate) async fn index_with_buckets(
+        &self,
+        path: &Path,
+        corpus: &Corpus,
+        tokenizer: &Tokenizer,
+        doc_count: usize,
+        preset: Option<&[BucketManifest]>,
+    ) -> anyhow::Result<()> {
+        if self.posting_lists && self.term_frequencies {
+            bail!("Posting lists cannot be combined with term frequencies.");
+        }
+        if self.positions && !self.term_frequencies {
+            bail!("Positions require term frequencies.");
+        }
+        if self.hot_tokens > 0 && self.strategy == Strategy::Hash {
+            bail!("Hot tokens cannot be promoted with the Hash strategy.");
+        }
+        if self.duplicate_ids && !self.dedup {
+            bail!("Duplicate IDs can only be recorded when deduplicating.");
+        }
+        check_counts(doc_count, preset.is_none().then_some(self.buckets))?;
+        tokio::fs::create_dir_all(path).await?;
+        let segments = segment_paths(path)?;
+        let mut existing_dictionary = read_dictionary(path).await?;
+        let mut manifest = read_manifest(path).await?;
+        let mut buckets = match (segments.first(), preset) {
+            (Some(segment), _) => {
+                let file = open_segment(segment).await?;
+                let dtype = struct_dtype(&file)?;
+                let (buckets, term_frequencies) = buckets_from_dtype(dtype)?;
+                let store_bodies = has_column(dtype, BODY_COLUMN);
+                let doc_lengths = has_column(dtype, LENGTH_COLUMN);
+                let dictionary = existing_dictionary.is_some();
+                let posting_lists = manifest
+                    .as_ref()
+                    .is_some_and(|manifest| manifest.parameters.posting_lists);
+                let positions = has_positions(dtype);
+                if (
+                    term_frequencies,
+                    store_bodies,
+                    doc_lengths,
+                    dictionary,
+                    posting_lists,
+                    positions,
+                ) != (
+                    self.term_frequencies,
+                    self.store_bodies,
+                    self.doc_lengths,
+                    self.dictionary,
+                    self.posting_lists,
+                    self.positions,
+                ) {
+                    bail!(
+                        "The existing segments in {path:?} were built with \
+                        term_frequencies={term_frequencies}, store_bodies={store_bodies}, \
+                        doc_lengths={doc_lengths}, dictionary={dictionary}, \
+                        posting_lists={posting_lists} and positions={positions}."
+                    );
+                }
+                if has_column(dtype, TIMESTAMP_COLUMN) != corpus.timestamps() {
+                    bail!(
+                        "The existing segments in {path:?} have a timestamp column only if the \
+                        corpus has timestamps."
+                    );
+                }
+                if has_column(dtype, KEY_COLUMN) != corpus.keys() {
+                    bail!(
+                        "The existing segments in {path:?} have a key column only if the corpus \
+                        may have string IDs."
+                    );
+                }
+                match &manifest {
+                    Some(manifest) => {
+                        let location = format!("{path:?}");
+                        manifest.check_version(&location)?;
+                        manifest.check_tokenizer(&location, tokenizer)?;
+                        manifest.bucket_types()
+                    }
+                    None => buckets,
+                }
+            }
+            (None, Some(preset)) => {
+                let buckets = preset
+                    .iter()
+                    .map(|bucket| (bucket.token.clone(), bucket.btype))
+                    .collect::<Vec<_>>();
+                let mut imported = self.manifest(&buckets, &[], tokenizer, corpus, doc_count);
+                for (bucket, preset) in imported.buckets.iter_mut().zip(preset) {
+                    bucket.tokens = preset.tokens;
+                }
+                manifest = Some(imported);
+                buckets
+            }
+            (None, None) => {
+                let document_frequencies = if self.two_pass {
+                    document_frequencies(corpus.documents(tokenizer, doc_count)?)?
+                } else {
+                    let sample =
+                        corpus.sample(tokenizer, doc_count, self.sample_docs, self.sample)?;
+                    document_frequencies(sample.into_iter().map(Ok))?
+                };
+                let tokens = document_frequencies.keys().cloned().collect::<Vec<_>>();
+                let hot = hot_tokens(&document_frequencies, self.hot_tokens);
+                let buckets = self.strategy.select_buckets(
+                    document_frequencies,
+                    self.buckets,
+                    corpus.fields(),
+                );
+                let buckets = promote_hot_tokens(buckets, hot);
+                manifest = Some(self.manifest(&buckets, &tokens, tokenizer, corpus, doc_count));
+                buckets
+            }
+        };
+
+        let segment = next_segment_path(path, &segments);
+        let mut rebuilt = false;
+        let (sidecars, dictionary) = loop {
+            let sidecars = Arc::new(Sidecars::default());
+            let dictionary = self
+                .dictionary
+                .then(|| Arc::new(Mutex::new(existing_dictionary.take().unwrap_or_default())));
+            let document_stream = document_array_stream(
+                corpus,
+                tokenizer,
+                doc_count,
+                buckets.clone(),
+                self,
+                sidecars.clone(),
+                dictionary.clone(),
+            )?;
+            vortex_index_array(&segment, document_stream, self.compression).await?;
+            if segments.is_empty() && preset.is_none() && !rebuilt {
+                let frequencies = sidecars.document_frequencies.lock().unwrap();
+                if let Some(refined) = self.split_buckets(&buckets, &frequencies) {
+                    println!(
+                        ">>> split oversized buckets into {} buckets: rebuilding {segment:?}",
+                        refined.len()
+                    );
+                    let tokens = frequencies.keys().cloned().collect::<Vec<_>>();
+                    manifest = Some(self.manifest(&refined, &tokens, tokenizer, corpus, doc_count));
+                    buckets = refined;
+                    rebuilt = true;
+                    continue;
+                }
+            }
+            break (sidecars, dictionary);
+        };
+
+        let bucket_columns = buckets
+            .iter()
+            .map(|(token, btype)| btype.column_name(token))
+            .collect::<Vec<_>>();
+        write_stats(&segment, &sidecars.row_groups.lock().unwrap()).await?;
+        let (vocabulary, terms) = {
+            let tokens = sidecars.document_frequencies.lock().unwrap();
+            (
+                Vocabulary::new(tokens.keys().map(String::as_str)),
+                Terms::new(tokens.keys().map(String::as_str)),
+            )
+        };
+        write_vocabulary(&segment, &vocabulary).await?;
+        write_terms(&segment, &terms).await?;
+        if self.posting_lists {
+            let postings = std::mem::take(&mut *sidecars.postings.lock().unwrap())
+                .into_iter()
+                .map(|(idx, bitmap)| (bucket_columns[idx].clone(), bitmap))
+                .collect::<Postings>();
+            write_postings(&segment, &postings).await?;
+        }
+        if let Some(dictionary) = dictionary {
+            write_dictionary(path, &dictionary.lock().unwrap()).await?;
+        }
+        let duplicates = std::mem::take(&mut *sidecars.duplicates.lock().unwrap());
+        if self.dedup {
+            println!(">>> skipped {} duplicate documents", duplicates.len());
+        }
+        if self.duplicate_ids {
+            write_duplicates(&segment, &duplicates).await?;
+        }
+        if let Some(mut manifest) = manifest {
+            manifest.duplicates += duplicates.len() as u64;
+            update_manifest(path, manifest).await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Splits each Multi bucket which holds more than `max_bucket_share` of the occurrences of
+    /// the given tokens into as many sub-buckets as an average bucket would fill, using the
+    /// strategy of the index. Returns `None` if no bucket needs to be split.
+    ///
+    fn split_buckets(
+        &self,
+        buckets: &[(String, BucketType)],
+        document_frequencies: &HashMap<String, usize>,
+    ) -> Option<Vec<(String, BucketType)>> {
+        if self.strategy == Strategy::Hash || self.max_bucket_share >= 1.0 {
+            return None;
+        }
+        let bucket_columns = buckets
+            .iter()
+            .map(|(token, btype)| FieldName::from(btype.column_name(token)))
+            .collect::<Vec<_>>();
+        let mut groups = vec![BTreeMap::new(); buckets.len()];
+        for (token, frequency) in document_frequencies {
+            let (idx, _) = find_bucket(&bucket_columns, token);
+            groups[idx].insert(token.clone(), *frequency);
+        }
+        let total = document_frequencies.values().sum::<usize>();
+
+        let mut refined = buckets.to_vec();
+        for ((token, btype), group) in buckets.iter().zip(groups) {
+            let occurrences = group.values().sum::<usize>();
+            if *btype != BucketType::Multi
+                || group.len() < 2
+                || occurrences as f64 <= self.max_bucket_share * total as f64
+            {
+                continue;
+            }
+            let count = (occurrences * buckets.len())
+                .div_ceil(total)
+                .clamp(2, u16::MAX as usize);
+            // NB: The sub-buckets begin with a Multi bucket for the empty token, which falls
+            // before (and is replaced by) the bucket being split.
+            refined.extend(
+                self.strategy
+                    .select_buckets(group, count as u16, &[])
+                    .into_iter()
+                    .filter(|(sub_token, _)| sub_token >= token),
+            );
+        }
+        if refined.len() == buckets.len() {
+            return None;
+        }
+        refined.sort_by_cached_key(|(token, btype)| btype.column_name(token));
+        refined.dedup();
+        Some(refined)
+    }
+
+    ///
+    /// Creates the manifest of a new index with the given buckets, counting the distinct tokens
+    /// (from the sample used to select them) which fall into each bucket.
+    ///
+    fn manifest(
+        &self,
+        buckets: &[(String, BucketType)],
+        tokens: &[String],
+        tokenizer: &Tokenizer,
+        corpus: &Corpus,
+        doc_count: usize,
+    ) -> Manifest {
+        let bucket_columns = buckets
+            .iter()
+            .map(|(token, btype)| FieldName::from(btype.column_name(token)))
+            .collect::<Vec<_>>();
+        let mut token_counts = vec![0; buckets.len()];
+        for token in tokens {
+            let (idx, _) = find_bucket(&bucket_columns, token);
+            if let Some(count) = token_counts.get_mut(idx) {
+                *count += 1;
+            }
+        }
+        Manifest {
+            format_version: format_version(self.posting_lists, tokenizer),
+            tokenizer: Some(tokenizer.clone()),
+            buckets: buckets
+                .iter()
+                .zip(token_counts)
+                .map(|((token, btype), tokens)| BucketManifest {
+                    column: btype.column_name(token),
+                    token: token.clone(),
+                    btype: *btype,
+                    posting_list: self.posting_lists && *btype == BucketType::Single,
+                    documents: 0,
+                    tokens,
+                })
+                .collect(),
+            documents: 0,
+            parameters: BuildParameters {
+                buckets: self.buckets,
+                strategy: self.strategy,
+                sample_docs: self.sample_docs,
+                sample: self.sample,
+                two_pass: self.two_pass,
+                term_frequencies: self.term_frequencies,
+                store_bodies: self.store_bodies,
+                doc_lengths: self.doc_lengths,
+                dictionary: self.dictionary,
+                posting_lists: self.posting_lists,
+                positions: self.positions,
+                timestamps: corpus.timestamps(),
+                chunk_size: self.chunk_size,
+                compression: self.compression,
+                max_bucket_share: Some(self.max_bucket_share),
+                hot_tokens: self.hot_tokens,
+                sort_by_tokens: self.sort_by_tokens,
+                dedup: self.dedup,
+                duplicate_ids: self.duplicate_ids,
+            },
+            // NB: Documents which were already read into memory cannot be read again.
+            corpus: (!matches!(corpus, Corpus::Texts { .. })).then(|| corpus.clone()),
+            doc_count: Some(doc_count),
+            duplicates: 0,
+        }
+    }
+}
+
+///
+/// Recounts the documents in each bucket of the index at `path` from the row group statistics
+/// of its segments, and then writes its manifest. A segment without statistics only contributes
+/// to the total number of documents.
+///
+async fn update_manifest(path: &Path, mut manifest: Manifest) -> anyhow::Result<()> {
+    manifest.documents = 0;
+    for bucket in &mut manifest.buckets {
+        bucket.documents = 0;
+    }
+    for segment in segment_paths(path)? {
+        let Some(row_groups) = read_stats(&segment).await? else {
+            manifest.documents += open_segment(&segment).await?.row_count();
+            continue;
+        };
+        for row_group in row_groups {
+            manifest.documents += row_group.rows;
+            for (bucket, frequency) in manifest
+                .buckets
+                .iter_mut()
+                .zip(&row_group.document_frequencies)
+            {
+                bucket.documents += frequency;
+            }
+        }
+    }
+    write_manifest(path, &manifest).await
+}
 
-    Ok((file, dtype))
+///
+/// Indexes the documents of the corpus into a new segment of the existing index at `path`,
+/// replacing any documents in the existing segments which have the same IDs. The documents are
+/// assigned to the existing buckets, using the existing settings of the index.
+///
+pub async fn vortex_upsert(
+    path: &Path,
+    corpus: &Corpus,
+    tokenizer: &Tokenizer,
+    doc_count: usize,
+) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        bail!("{path:?} is not an index directory, and so does not support upserts.");
+    }
+    let segments = segment_paths(path)?;
+    let Some(segment) = segments.first() else {
+        bail!("{path:?} does not contain any segments to upsert into.");
+    };
+    let file = open_segment(segment).await?;
+    let dtype = struct_dtype(&file)?;
+    let (_, term_frequencies) = buckets_from_dtype(dtype)?;
+    let store_bodies = has_column(dtype, BODY_COLUMN);
+    let doc_lengths = has_column(dtype, LENGTH_COLUMN);
+    let positions = has_positions(dtype);
+    let parameters = read_manifest(path)
+        .await?
+        .map(|manifest| manifest.parameters);
+    let posting_lists = parameters
+        .as_ref()
+        .is_some_and(|parameters| parameters.posting_lists);
+    // NB: An index without a manifest was built with the defaults.
+    let (chunk_size, compression) = match &parameters {
+        Some(parameters) => (parameters.chunk_size, parameters.compression),
+        None => (DEFAULT_CHUNK_SIZE, Compression::default()),
+    };
+
+    let mut existing_ids = HashSet::new();
+    for segment in &segments {
+        existing_ids.extend(segment_ids(segment).await?);
+    }
+
+    // NB: The buckets are recovered from the existing segments, so the bucket selection settings
+    // are unused. Indexing appends exactly one segment.
+    VortexIndexer {
+        buckets: 0,
+        strategy: Strategy::default(),
+        sample_docs: 0,
+        sample: SampleMethod::default(),
+        two_pass: false,
+        term_frequencies,
+        store_bodies,
+        doc_lengths,
+        dictionary: read_dictionary(path).await?.is_some(),
+        posting_lists,
+        positions,
+        index_threads: std::thread::available_parallelism()?.get(),
+        chunk_size,
+        compression,
+        max_bucket_share: 1.0,
+        hot_tokens: 0,
+        sort_by_tokens: false,
+        dedup: false,
+        duplicate_ids: false,
+    }
+    .index(path, corpus, tokenizer, doc_count)
+    .await?;
+    let upserted = next_segment_path(path, &segments);
+    let first_live = next_segment_number(&segments);
+
+    let mut tombstones = read_tombstones(path).await?;
+    let mut replaced = 0;
+    let mut added = 0;
+    for id in segment_ids(&upserted).await? {
+        if existing_ids.contains(&id) {
+            tombstones.insert(id, first_live);
+            replaced += 1;
+        } else {
+            added += 1;
+        }
+    }
+    write_tombstones(path, &tombstones).await?;
+    println!(">>> replaced {replaced} and added {added} documents in {path:?}");
+    Ok(())
+}
+
+///
+/// Indexes a corpus which is read from stdin (such as the end of a pipe) as documents arrive,
+/// flushing each `flush_every` documents into a new segment of the index at `path`. Buckets are
+/// selected from the first batch, and are then reused by the segments of the later batches.
+///
+pub async fn vortex_index_stdin(
+    indexer: &VortexIndexer,
+    path: &Path,
+    corpus: &Corpus,
+    tokenizer: &Tokenizer,
+    doc_count: usize,
+    flush_every: usize,
+) -> anyhow::Result<()> {
+    if flush_every == 0 {
+        bail!("At least one document must be flushed at a time.");
+    }
+    let mut texts = corpus.texts(doc_count)?;
+    let mut flushed = 0;
+    loop {
+        let batch = texts
+            .by_ref()
+            .take(flush_every)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if batch.is_empty() {
+            break;
+        }
+        let count = batch.len();
+        let batch = Corpus::Texts {
+            texts: Arc::new(batch),
+            fields: corpus.fields().to_vec(),
+            timestamps: corpus.timestamps(),
+            keys: corpus.keys(),
+        };
+        indexer.index(path, &batch, tokenizer, count).await?;
+        flushed += count;
+        println!(">>> flushed {flushed} documents to {path:?}");
+    }
+    if flushed == 0 {
+        bail!("No documents were read from stdin.");
+    }
+    Ok(())
+}
+
+///
+/// Fails with a descriptive error if an index would be built from no documents, or with no
+/// buckets. The bucket count is `None` if the buckets are preset, rather than selected.
+///
+pub(crate) fn check_counts(doc_count: usize, buckets: Option<u16>) -> anyhow::Result<()> {
+    if doc_count == 0 {
+        bail!("An index must be built from at least one document.");
+    }
+    if buckets == Some(0) {
+        bail!("An index must have at least one bucket.");
+    }
+    Ok(())
+}
+
+///
+/// Counts the number of documents which contain each token.
+///
+pub(crate) fn document_frequencies(
+    documents: impl Iterator<Item = anyhow::Result<Document>>,
+) -> anyhow::Result<BTreeMap<String, usize>> {
+    let mut frequencies = BTreeMap::new();
+    for document in documents {
+        for token in document?.1.into_keys() {
+            *frequencies.entry(token).or_insert(0) += 1;
+        }
+    }
+    Ok(frequencies)
+}
+
+fn document_array_stream(
+    corpus: &Corpus,
+    tokenizer: &Tokenizer,
+    doc_count: usize,
+    buckets: Vec<(String, BucketType)>,
+    indexer: &VortexIndexer,
+    sidecars: Arc<Sidecars>,
+    dictionary: Option<Arc<Mutex<Dictionary>>>,
+) -> anyhow::Result<impl ArrayStream + Unpin> {
+    let term_frequencies = indexer.term_frequencies;
+    let positions = indexer.positions;
+    let store_bodies = indexer.store_bodies;
+    let doc_lengths = indexer.doc_lengths;
+    let timestamps = corpus.timestamps();
+    let keys = corpus.keys();
+
+    // Construct the `DType` for the `StructArray` that we will be emitting.
+    // There is one prefixed `ID_COLUMN` (and `BODY_COLUMN`, `TIMESTAMP_COLUMN`, `KEY_COLUMN` and
+    // `LENGTH_COLUMN`, if enabled), followed by one column per bucket (plus its term frequency
+    // and positions columns, if enabled). The Vortex DType of each bucket is decided by its
+    // `BucketType`. A Single bucket which is stored as a posting list has no columns.
+    let mut column_names: Vec<FieldName> = vec![ID_COLUMN.into()];
+    let mut column_dtypes = vec![DType::Primitive(PType::U64, Nullability::NonNullable)];
+    if store_bodies {
+        column_names.push(BODY_COLUMN.into());
+        column_dtypes.push(DType::Utf8(Nullability::NonNullable));
+    }
+    let timestamp_column = column_names.len();
+    if timestamps {
+        column_names.push(TIMESTAMP_COLUMN.into());
+        column_dtypes.push(DType::Primitive(PType::U64, Nullability::Nullable));
+    }
+    let key_column = column_names.len();
+    if keys {
+        column_names.push(KEY_COLUMN.into());
+        column_dtypes.push(DType::Utf8(Nullability::Nullable));
+    }
+    let length_column = column_names.len();
+    if doc_lengths {
+        column_names.push(LENGTH_COLUMN.into());
+        column_dtypes.push(DType::Primitive(PType::U32, Nullability::NonNullable));
+    }
+    let mut bucket_column_indices = Vec::with_capacity(buckets.len());
+    for (token, btype) in &buckets {
+        if indexer.posting_lists && *btype == BucketType::Single {
+            bucket_column_indices.push(None);
+            continue;
+        }
+        bucket_column_indices.push(Some(column_names.len()));
+        // NB: A term frequency column sorts immediately after its bucket column.
+        let column_name = btype.column_name(token);
+        if term_frequencies {
+            column_names.push(column_name.as_str().into());
+            column_names.push(tf_column_name(&column_name).into());
+            column_dtypes.push(btype.dtype(dictionary.is_some()));
+            column_dtypes.push(btype.tf_dtype());
+            if positions {
+                column_names.push(positions_column_name(&column_name).into());
+                column_dtypes.push(positions_dtype());
+            }
+        } else {
+            column_names.push(column_name.into());
+            column_dtypes.push(btype.dtype(dictionary.is_some()));
+        }
+    }
+    let bucket_columns = buckets
+        .iter()
+        .map(|(token, btype)| FieldName::from(btype.column_name(token)))
+        .collect::<Vec<_>>();
+    let mut texts = corpus.texts(doc_count)?;
+    if indexer.dedup {
+        texts = Box::new(skip_duplicates(texts, tokenizer.clone(), sidecars.clone()));
+    }
+    if indexer.sort_by_tokens {
+        let sorted = sort_by_tokens(texts, tokenizer, &bucket_columns)?;
+        texts = Box::new(sorted.into_iter().map(Ok));
+    }
+    let struct_dtype = StructDType::new(column_names.into(), column_dtypes.clone());
+    let dtype = DType::Struct(struct_dtype.clone().into(), Nullability::NonNullable);
+    let chunk_builder = Arc::new(ChunkBuilder {
+        tokenizer: tokenizer.clone(),
+        buckets,
+        bucket_columns,
+        column_dtypes,
+        struct_dtype,
+        bucket_column_indices,
+        timestamp_column,
+        key_column,
+        length_column,
+        term_frequencies,
+        positions,
+        store_bodies,
+        doc_lengths,
+        timestamps,
+        keys,
+        sidecars: sidecars.clone(),
+        dictionary,
+    });
+
+    // Read the corpus in chunks. A chunk which is shorter than `chunk_size` (possibly empty) is
+    // the last one.
+    let chunk_size = indexer.chunk_size.max(1);
+    let mut might_have_more_docs = true;
+    let chunks = std::iter::from_fn(move || {
+        if !might_have_more_docs {
+            return None;
+        }
+        let chunk = texts
+            .by_ref()
+            .take(chunk_size)
+            .collect::<anyhow::Result<Vec<_>>>();
+        might_have_more_docs = matches!(&chunk, Ok(chunk) if chunk.len() == chunk_size);
+        Some(chunk)
+    });
+
+    // Build up to `index_threads` chunks concurrently on the blocking pool, and then emit them as
+    // StructArrays in the order they were read.
+    let mut arrays = futures_util::stream::iter(chunks)
+        .map(move |chunk| {
+            let chunk_builder = chunk_builder.clone();
+            // NB: The chunk is built on the blocking pool, so its span is parented explicitly.
+            let span = tracing::Span::current();
+            async move {
+                let chunk = chunk?;
+                tokio::task::spawn_blocking(move || span.in_scope(|| chunk_builder.build(chunk)))
+                    .await?
+            }
+        })
+        .buffered(indexer.index_threads.max(1));
+    let stream = stream! {
+        let mut rows = 0;
+        while let Some(built) = arrays.next().await {
+            let (array, stats, postings) = built?;
+            // Offset the chunk's posting lists by the number of rows which preceded it.
+            let offset = u32::try_from(rows)
+                .map_err(|_| anyhow!("Too many rows in a segment with posting lists."))?;
+            let mut segment_postings = sidecars.postings.lock().unwrap();
+            for (idx, bitmap) in postings {
+                segment_postings
+                    .entry(idx)
+                    .or_default()
+                    .extend(bitmap.iter().map(|row| row + offset));
+            }
+            drop(segment_postings);
+            rows += stats.rows;
+            sidecars.row_groups.lock().unwrap().push(stats);
+            yield Ok(array);
+        }
+    };
+
+    Ok(ArrayStreamAdapter::new(dtype, stream.boxed()))
+}
+
+///
+/// Skips each text whose set of tokens (including the tokens of its named fields) matches that
+/// of an earlier text, and records the ID of the earlier, canonical, text for the ID of each
+/// skipped duplicate. Texts are grouped by a hash of their tokens, and the tokens of a text are
+/// compared to those of each earlier text with the same hash, so that a collision never skips a
+/// distinct text.
+///
+fn skip_duplicates(
+    texts: impl Iterator<Item = anyhow::Result<Text>> + Send + 'static,
+    tokenizer: Tokenizer,
+    sidecars: Arc<Sidecars>,
+) -> impl Iterator<Item = anyhow::Result<Text>> + Send {
+    let mut canonical_ids: HashMap<u64, Vec<(Vec<String>, u64)>> = HashMap::new();
+    texts.filter(move |text| {
+        let Ok(text) = text else {
+            return true;
+        };
+        let mut tokens = tokenizer
+            .text_term_frequencies(text)
+            .into_keys()
+            .collect::<Vec<_>>();
+        tokens.sort_unstable();
+        // NB: Tokens never contain NUL, so the joined tokens are unambiguous.
+        let canonicals = canonical_ids.entry(fnv1a(&tokens.join("\0"))).or_default();
+        let canonical_id = match canonicals
+            .iter()
+            .find(|(canonical_tokens, _)| *canonical_tokens == tokens)
+        {
+            Some((_, canonical_id)) => *canonical_id,
+            None => {
+                canonicals.push((tokens, text.id));
+                text.id
+            }
+        };
+        if canonical_id == text.id {
+            return true;
+        }
+        sidecars
+            .duplicates
+            .lock()
+            .unwrap()
+            .insert(text.id, canonical_id);
+        false
+    })
+}
+
+///
+/// Reads all of the texts, and orders them by a clustering key derived from the Single buckets
+/// that they contain. The Single buckets are ranked by the number of documents containing them,
+/// and each document's key is the ascending list of the ranks of its Single buckets: documents
+/// containing the most frequent token sort first, followed (within them) by those containing the
+/// second most frequent token, and so on. The sort is stable, so documents with the same key
+/// retain the order of the corpus.
+///
+fn sort_by_tokens(
+    texts: impl Iterator<Item = anyhow::Result<Text>>,
+    tokenizer: &Tokenizer,
+    bucket_columns: &[FieldName],
+) -> anyhow::Result<Vec<Text>> {
+    let mut documents = Vec::new();
+    let mut frequencies = vec![0_usize; bucket_columns.len()];
+    for text in texts {
+        let text = text?;
+        let mut singles = tokenizer
+            .text_term_frequencies(&text)
+            .into_keys()
+            .filter_map(|token| match find_bucket(bucket_columns, &token) {
+                (idx, BucketType::Single) => Some(idx),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        singles.sort_unstable();
+        singles.dedup();
+        for idx in &singles {
+            frequencies[*idx] += 1;
+        }
+        documents.push((singles, text));
+    }
+
+    let mut by_frequency = (0..bucket_columns.len()).collect::<Vec<_>>();
+    by_frequency.sort_by_key(|idx| std::cmp::Reverse(frequencies[*idx]));
+    let mut ranks = vec![0; bucket_columns.len()];
+    for (rank, idx) in by_frequency.into_iter().enumerate() {
+        ranks[idx] = rank;
+    }
+    let mut keyed = documents
+        .into_iter()
+        .map(|(singles, text)| {
+            let mut key = singles
+                .into_iter()
+                .map(|idx| ranks[idx])
+                .collect::<Vec<_>>();
+            key.sort_unstable();
+            (key, text)
+        })
+        .collect::<Vec<_>>();
+    // NB: A document without any Single buckets has an empty key, which is sorted last.
+    keyed.sort_by(|(a, _), (b, _)| a.is_empty().cmp(&b.is_empty()).then_with(|| a.cmp(b)));
+    Ok(keyed.into_iter().map(|(_, text)| text).collect())
+}
+
+///
+/// The files which accompany a segment, which are accumulated as its chunks are built.
+///
+#[derive(Default)]
+struct Sidecars {
+    row_groups: Mutex<Vec<RowGroupStats>>,
+    /// The number of documents containing each token which has been indexed in the segment.
+    document_frequencies: Mutex<HashMap<String, usize>>,
+    /// The posting list of each Single bucket which is not stored as a column, keyed by bucket.
+    postings: Mutex<BTreeMap<usize, RoaringBitmap>>,
+    /// The ID of the canonical document for the ID of each duplicate document which was skipped.
+    duplicates: Mutex<BTreeMap<u64, u64>>,
+}
+
+///
+/// The state shared by the tasks which build the chunks of a segment: each chunk becomes one row
+/// group.
+///
+struct ChunkBuilder {
+    tokenizer: Tokenizer,
+    buckets: Vec<(String, BucketType)>,
+    bucket_columns: Vec<FieldName>,
+    column_dtypes: Vec<DType>,
+    struct_dtype: StructDType,
+    /// The index of the column of each bucket, or `None` for a bucket stored as a posting list.
+    bucket_column_indices: Vec<Option<usize>>,
+    timestamp_column: usize,
+    key_column: usize,
+    length_column: usize,
+    term_frequencies: bool,
+    positions: bool,
+    store_bodies: bool,
+    doc_lengths: bool,
+    timestamps: bool,
+    keys: bool,
+    sidecars: Arc<Sidecars>,
+    dictionary: Option<Arc<Mutex<Dictionary>>>,
+}
+
+///
+/// The posting lists of a chunk, keyed by bucket, containing the ordinals of rows in the chunk.
+///
+type ChunkPostings = BTreeMap<usize, RoaringBitmap>;
+
+impl ChunkBuilder {
+    ///
+    /// Tokenizes the given texts, and populates a StructArray (and the stats and posting lists of
+    /// its row group) with them.
+    ///
+    #[tracing::instrument(skip_all, fields(documents = texts.len()))]
+    fn build(&self, texts: Vec<Text>) -> anyhow::Result<(ArrayRef, RowGroupStats, ChunkPostings)> {
+        let buckets = &self.buckets;
+        let mut builders = self
+            .column_dtypes
+            .iter()
+            .map(|dtype| builder_with_capacity(dtype.into(), texts.len()))
+            .collect::<Vec<_>>();
+        let mut stats = RowGroupStats {
+            rows: texts.len() as u64,
+            buckets: vec![None; buckets.len()],
+            document_frequencies: vec![0; buckets.len()],
+            max_term_frequencies: if self.term_frequencies {
+                vec![0; buckets.len()]
+            } else {
+                Vec::new()
+            },
+        };
+        let mut entries_to_append: Vec<Vec<(String, u32)>> =
+            buckets.iter().map(|_| Vec::new()).collect();
+        let mut document_frequencies = HashMap::new();
+        let mut postings = ChunkPostings::new();
+        let doc_count = texts.len();
+        for (row, text) in texts.into_iter().enumerate() {
+            let document = self.tokenizer.text_term_frequencies(&text);
+            let length = self.doc_lengths.then(|| self.tokenizer.text_length(&text));
+            let positions = if self.positions {
+                self.tokenizer.text_positions(&text)
+            } else {
+                HashMap::new()
+            };
+            builders[0].append_scalar(&text.id.into())?;
+            if self.timestamps {
+                builders[self.timestamp_column].append_scalar(&text.timestamp.into())?;
+            }
+            if self.keys {
+                let key = match &text.key {
+                    Some(key) => Scalar::utf8(key.as_str(), Nullability::Nullable),
+                    None => Scalar::null(DType::Utf8(Nullability::Nullable)),
+                };
+                builders[self.key_column].append_scalar(&key)?;
+            }
+            if let Some(length) = length {
+                builders[self.length_column].append_scalar(&length.into())?;
+            }
+            if self.store_bodies {
+                builders[1].append_scalar(&text.body.into())?;
+            }
+            // Group the tokens by the bucket that they will be appended to.
+            for (token, tf) in document {
+                let (idx, _) = find_bucket(&self.bucket_columns, &token);
+                match document_frequencies.get_mut(&token) {
+                    Some(frequency) => *frequency += 1,
+                    None => {
+                        document_frequencies.insert(token.clone(), 1);
+                    }
+                }
+                entries_to_append[idx].push((token, tf));
+            }
+            // Drain all buckets into the builders. Many of them will be empty, and that is ok.
+            for (idx, entries) in entries_to_append.iter_mut().enumerate() {
+                let (tokens, tfs): (Vec<String>, Vec<u32>) = entries.drain(..).unzip();
+                stats.record(idx, &tokens);
+                if self.term_frequencies {
+                    stats.record_term_frequencies(idx, &tfs);
+                }
+                let Some(column_idx) = self.bucket_column_indices[idx] else {
+                    if !tokens.is_empty() {
+                        postings.entry(idx).or_default().insert(row as u32);
+                    }
+                    continue;
+                };
+                match buckets[idx].1 {
+                    BucketType::Single => {
+                        builders[column_idx].append_scalar(&(!tokens.is_empty()).into())?;
+                        if self.term_frequencies {
+                            let tf = tfs.first().copied().unwrap_or(0);
+                            builders[column_idx + 1].append_scalar(&tf.into())?;
+                        }
+                        if self.positions {
+                            let token_positions = tokens
+                                .first()
+                                .and_then(|token| positions.get(token))
+                                .cloned()
+                                .unwrap_or_default();
+                            builders[column_idx + 2].append_scalar(&token_positions.into())?;
+                        }
+                    }
+                    BucketType::Multi => {
+                        if self.positions {
+                            let token_positions = tokens
+                                .iter()
+                                .flat_map(|token| positions.get(token).into_iter().flatten())
+                                .copied()
+                                .collect::<Vec<u32>>();
+                            builders[column_idx + 2].append_scalar(&token_positions.into())?;
+                        }
+                        match &self.dictionary {
+                            Some(dictionary) => {
+                                let mut dictionary = dictionary.lock().unwrap();
+                                let codes = tokens
+                                    .iter()
+                                    .map(|token| dictionary.encode(token))
+                                    .collect::<Vec<u32>>();
+                                builders[column_idx].append_scalar(&codes.into())?;
+                            }
+                            None => builders[column_idx].append_scalar(&tokens.into())?,
+                        }
+                        if self.term_frequencies {
+                            builders[column_idx + 1].append_scalar(&tfs.into())?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut segment_frequencies = self.sidecars.document_frequencies.lock().unwrap();
+        for (token, frequency) in document_frequencies {
+            *segment_frequencies.entry(token).or_insert(0) += frequency;
+        }
+        drop(segment_frequencies);
+        let fields = builders.into_iter().map(|mut b| b.finish()).collect();
+        let array = StructArray::try_new_with_dtype(
+            fields,
+            self.struct_dtype.clone().into(),
+            doc_count,
+            Validity::NonNullable,
+        )?
+        .into_array();
+        Ok((array, stats, postings))
+    }
+}
+
+#[tracing::instrument(skip_all, fields(path = ?path))]
+async fn vortex_index_array(
+    path: &Path,
+    array_stream: impl ArrayStream + Unpin,
+    compression: Compression,
+) -> anyhow::Result<()> {
+    let f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)
+        .await?;
+
+    compression.write_options().write(f, array_stream).await?;
+
+    Ok(())
+}
+
+///
+/// Lists the segment files of the index at `path`, in the order they were created. For
+/// compatibility, an index which is a single file is treated as a single segment.
+///
+fn segment_paths(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let segment = entry?.path();
+        if segment_number(&segment).is_some() {
+            segments.push(segment);
+        }
+    }
+    segments.sort_by_key(|segment| segment_number(segment));
+    Ok(segments)
+}
+
+fn segment_number(segment: &Path) -> Option<u64> {
+    segment
+        .file_name()?
+        .to_str()?
+        .strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(SEGMENT_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+fn next_segment_number(segments: &[PathBuf]) -> u64 {
+    segments
+        .iter()
+        .filter_map(|segment| segment_number(segment))
+        .max()
+        .map_or(0, |n| n + 1)
+}
+
+fn next_segment_path(path: &Path, segments: &[PathBuf]) -> PathBuf {
+    let next = next_segment_number(segments);
+    path.join(format!("{SEGMENT_PREFIX}{next:06}{SEGMENT_SUFFIX}"))
+}
+
+///
+/// Reads the IDs of all of the documents in a segment, including any deleted documents.
+///
+async fn segment_ids(segment: &Path) -> anyhow::Result<Vec<u64>> {
+    let file = open_segment(segment).await?;
+    let arrays = ScanBuilder::new(file.layout_reader()?)
+        .with_projection(vortex_expr::get_item(ID_COLUMN, vortex_expr::ident()))
+        .with_tokio_executor(Handle::current())
+        .into_array_stream()?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut ids = Vec::with_capacity(file.row_count() as usize);
+    for array in arrays {
+        ids.extend_from_slice(array.to_primitive()?.as_slice::<u64>());
+    }
+    Ok(ids)
+}
+
+///
+/// Returns the ordinals of the rows of a segment which have not been deleted.
+///
+async fn live_rows(segment: &Path, tombstones: &Tombstones) -> anyhow::Result<RoaringBitmap> {
+    let number = segment_number(segment).unwrap_or(0);
+    let ids = segment_ids(segment).await?;
+    Ok(ids
+        .iter()
+        .enumerate()
+        .filter(|(_, id)| {
+            tombstones
+                .get(id)
+                .is_none_or(|first_live| number >= *first_live)
+        })
+        .map(|(row, _)| row as u32)
+        .collect())
+}
+
+async fn open_segment(segment: &Path) -> anyhow::Result<VortexFile> {
+    open_segment_with_io(segment, Io::Tokio, Arc::default()).await
+}
+
+///
+/// How the segment files of an index are read when it is searched.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum Io {
+    /// Asynchronous reads through `TokioFile`.
+    #[default]
+    Tokio,
+    /// Reads from a memory map of each segment, which are served directly from the OS page cache
+    /// (and which block on page faults when they are not).
+    Mmap,
+    /// Reads submitted to an io_uring, which is only supported on Linux.
+    Uring,
+}
+
+///
+/// Opens a segment with the given `Io`, adding the number of bytes read from it to `bytes_read`.
+///
+async fn open_segment_with_io(
+    segment: &Path,
+    io: Io,
+    bytes_read: Arc<AtomicU64>,
+) -> anyhow::Result<VortexFile> {
+    let options = VortexOpenOptions::file();
+    let file = match io {
+        Io::Tokio => {
+            let read = CountingReadAt::new(TokioFile::open(segment)?, bytes_read);
+            options.open_read_at(read).await?
+        }
+        Io::Mmap => {
+            let file = std::fs::File::open(segment)?;
+            // SAFETY: Segments are never modified after they are written: they are only replaced
+            // by new segments, and then removed.
+            let mmap = unsafe { memmap2::Mmap::map(&file)? };
+            let read = CountingReadAt::new(ByteBuffer::from(Bytes::from_owner(mmap)), bytes_read);
+            options.open_read_at(read).await?
+        }
+        #[cfg(target_os = "linux")]
+        Io::Uring => {
+            let read = CountingReadAt::new(UringFile::open(segment)?, bytes_read);
+            options.open_read_at(read).await?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Io::Uring => bail!("io_uring is only supported on Linux."),
+    };
+    Ok(file)
+}
+
+///
+/// Wraps a `VortexReadAt` to count the bytes which are read through it. The counter is shared by
+/// all of the segments of an index, so that the bytes read by a query can be measured.
+///
+#[derive(Clone)]
+struct CountingReadAt<R> {
+    read: R,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl<R> CountingReadAt<R> {
+    fn new(read: R, bytes_read: Arc<AtomicU64>) -> Self {
+        CountingReadAt { read, bytes_read }
+    }
+}
+
+impl<R: VortexReadAt> VortexReadAt for CountingReadAt<R> {
+    async fn read_byte_range(
+        &self,
+        range: Range<u64>,
+        alignment: Alignment,
+    ) -> std::io::Result<ByteBuffer> {
+        let len = range.end - range.start;
+        let buffer = self.read.read_byte_range(range, alignment).await?;
+        self.bytes_read.fetch_add(len, Ordering::Relaxed);
+        Ok(buffer)
+    }
+
+    async fn size(&self) -> std::io::Result<u64> {
+        self.read.size().await
+    }
+}
+
+fn struct_dtype(file: &VortexFile) -> anyhow::Result<&Arc<StructDType>> {
+    file.dtype()
+        .as_struct()
+        .ok_or_else(|| anyhow!("Does not appear to be an index!"))
+}
+
+///
+/// Compacts all of the segments of the index at `path` into a single new segment, and then
+/// removes the old segments.
+///
+/// Rows are copied in order, so the row group statistics of the old segments are concatenated,
+/// as long as every old segment has them. Deleted documents are removed, as with
+/// `vortex_compact`.
+///
+pub async fn vortex_merge(path: &Path) -> anyhow::Result<()> {
+    let segments = segment_paths(path)?;
+    if segments.len() < 2 {
+        println!(
+            ">>> {path:?} contains {} segment(s): nothing to merge",
+            segments.len()
+        );
+        return Ok(());
+    }
+
+    let tombstones = read_tombstones(path).await?;
+    rewrite_segments(path, &segments, &tombstones).await?;
+    println!(">>> merged {} segments in {path:?}", segments.len());
+    Ok(())
+}
+
+///
+/// Marks the documents with the given IDs as deleted from the index at `path`. Deleted documents
+/// are excluded from searches until they are removed by `vortex_compact`.
+///
+/// Documents are only deleted from the existing segments: a document with a deleted ID which is
+/// indexed later will be visible.
+///
+pub async fn vortex_delete(path: &Path, ids: &[u64]) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        bail!("{path:?} is not an index directory, and so does not support deletes.");
+    }
+    let first_live = next_segment_number(&segment_paths(path)?);
+    let mut tombstones = read_tombstones(path).await?;
+    tombstones.extend(ids.iter().map(|id| (*id, first_live)));
+    write_tombstones(path, &tombstones).await?;
+    println!(
+        ">>> {path:?} contains {} deleted documents",
+        tombstones.len()
+    );
+    Ok(())
+}
+
+///
+/// Rewrites all of the segments of the index at `path` into a single new segment without the
+/// documents which have been deleted, and then removes the tombstones.
+///
+/// The row group statistics of the old segments are preserved, minus any row groups which
+/// become empty: their token ranges and document frequencies may overestimate the remaining
+/// rows, but never underestimate them.
+///
+pub async fn vortex_compact(path: &Path) -> anyhow::Result<()> {
+    let tombstones = read_tombstones(path).await?;
+    if tombstones.is_empty() {
+        println!(">>> {path:?} does not contain any deleted documents: nothing to compact");
+        return Ok(());
+    }
+
+    let segments = segment_paths(path)?;
+    rewrite_segments(path, &segments, &tombstones).await?;
+    println!(
+        ">>> compacted {} segments in {path:?}, removing up to {} deleted documents",
+        segments.len(),
+        tombstones.len()
+    );
+    Ok(())
+}
+
+///
+/// Writes the exact bucket boundaries, tokenizer, build parameters and corpus of the index at
+/// `path` to a config file, from which `vortex_import_config` can rebuild an identical index.
+///
+pub async fn vortex_export_config(path: &Path, config_path: &Path) -> anyhow::Result<()> {
+    let Some(manifest) = read_manifest(path).await? else {
+        bail!("{path:?} does not have a manifest, and so its config cannot be exported.");
+    };
+    let (Some(tokenizer), Some(corpus), Some(doc_count)) =
+        (manifest.tokenizer, manifest.corpus, manifest.doc_count)
+    else {
+        bail!(
+            "The manifest of {path:?} does not record the tokenizer and corpus that it was built \
+            with: it must be rebuilt before its config can be exported."
+        );
+    };
+    let config = IndexConfig {
+        tokenizer,
+        corpus,
+        doc_count,
+        parameters: manifest.parameters,
+        buckets: manifest.buckets,
+    };
+    tokio::fs::write(config_path, serde_json::to_vec_pretty(&config)?).await?;
+    println!(
+        ">>> exported the config of {path:?} ({} buckets) to {config_path:?}",
+        config.buckets.len()
+    );
+    Ok(())
+}
+
+/// The share of the occurrences of a Multi bucket above which its most frequent token should be
+/// promoted to its own Single bucket.
+const PROMOTE_SHARE: f64 = 0.5;
+
+///
+/// Scans the Multi buckets of the index at `path`, and reports the average length of the lists
+/// of each (per document), the number of distinct tokens that it holds, and the Gini coefficient
+/// of the document frequencies of those tokens (0 when they are equally frequent, approaching 1
+/// when a few tokens dominate).
+///
+/// A bucket holding more than `DEFAULT_MAX_BUCKET_SHARE` of the occurrences of all Multi buckets
+/// is flagged to be split, and a bucket whose most frequent token accounts for more than
+/// `PROMOTE_SHARE` of its occurrences is flagged to have that token promoted.
+///
+pub async fn vortex_analyze_buckets(path: &Path) -> anyhow::Result<()> {
+    let searcher = VortexSearcher::open(path).await?;
+    let (rows, buckets) = searcher.multi_bucket_frequencies().await?;
+    let total = buckets
+        .iter()
+        .map(|(_, counts)| counts.values().sum::<usize>())
+        .sum::<usize>();
+    println!(
+        ">>> {} Multi buckets, containing {total} token occurrences in {rows} documents",
+        buckets.len()
+    );
+    println!(">>> column\tavg_list_len\tdistinct_tokens\tgini\tflag");
+    for (column, counts) in buckets {
+        let mut frequencies = counts.values().copied().collect::<Vec<_>>();
+        frequencies.sort_unstable();
+        let occurrences = frequencies.iter().sum::<usize>();
+        let top = counts
+            .iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)));
+        let flag = match top {
+            _ if counts.len() < 2 => String::new(),
+            _ if occurrences as f64 > DEFAULT_MAX_BUCKET_SHARE * total as f64 => "split".to_owned(),
+            Some((token, count)) if *count as f64 > PROMOTE_SHARE * occurrences as f64 => {
+                format!("promote {token:?}")
+            }
+            _ => String::new(),
+        };
+        println!(
+            ">>> {column}\t{:.2}\t{}\t{:.3}\t{flag}",
+            occurrences as f64 / rows.max(1) as f64,
+            counts.len(),
+            gini(&frequencies),
+        );
+    }
+    Ok(())
+}
+
+///
+/// The Gini coefficient of the given ascending values.
+///
+fn gini(sorted: &[usize]) -> f64 {
+    let n = sorted.len() as f64;
+    let sum = sorted.iter().sum::<usize>() as f64;
+    if sorted.len() < 2 || sum == 0.0 {
+        return 0.0;
+    }
+    let weighted = sorted
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| (idx + 1) as f64 * *value as f64)
+        .sum::<f64>();
+    2.0 * weighted / (n * sum) - (n + 1.0) / n
+}
+
+///
+/// Builds a new index at `path` from a config written by `vortex_export_config`, using its
+/// buckets exactly rather than selecting them, so that the index is identical to the one that
+/// the config was exported from (given the same corpus).
+///
+pub async fn vortex_import_config(
+    config_path: &Path,
+    path: &Path,
+    index_threads: usize,
+) -> anyhow::Result<()> {
+    let config: IndexConfig = serde_json::from_slice(&tokio::fs::read(config_path).await?)
+        .with_context(|| format!("Reading a config from {config_path:?}"))?;
+    if path.exists() && !segment_paths(path)?.is_empty() {
+        bail!(
+            "{path:?} already contains segments: a config may only be imported into a new index."
+        );
+    }
+    if config.corpus.timestamps() != config.parameters.timestamps {
+        bail!("The corpus of {config_path:?} does not match its `timestamps` parameter.");
+    }
+    let parameters = &config.parameters;
+    let indexer = VortexIndexer {
+        buckets: parameters.buckets,
+        strategy: parameters.strategy,
+        sample_docs: parameters.sample_docs,
+        sample: parameters.sample,
+        two_pass: parameters.two_pass,
+        term_frequencies: parameters.term_frequencies,
+        store_bodies: parameters.store_bodies,
+        doc_lengths: parameters.doc_lengths,
+        dictionary: parameters.dictionary,
+        posting_lists: parameters.posting_lists,
+        positions: parameters.positions,
+        index_threads,
+        chunk_size: parameters.chunk_size,
+        compression: parameters.compression,
+        max_bucket_share: parameters.max_bucket_share.unwrap_or(1.0),
+        hot_tokens: parameters.hot_tokens,
+        sort_by_tokens: parameters.sort_by_tokens,
+        dedup: parameters.dedup,
+        duplicate_ids: parameters.duplicate_ids,
+    };
+    indexer
+        .index_with_buckets(
+            path,
+            &config.corpus,
+            &config.tokenizer,
+            config.doc_count,
+            Some(&config.buckets),
+        )
+        .await?;
+    println!(
+        ">>> built {path:?} with the {} buckets of {config_path:?}",
+        config.buckets.len()
+    );
+    Ok(())
+}
+
+///
+/// Copies the rows of the given segments which have not been deleted into a single new segment
+/// in order, and then removes the old segments and the tombstones.
+///
+async fn rewrite_segments(
+    path: &Path,
+    segments: &[PathBuf],
+    tombstones: &Tombstones,
+) -> anyhow::Result<()> {
+    let manifest = read_manifest(path).await?;
+    if let Some(manifest) = &manifest {
+        manifest.check_version(&format!("{path:?}"))?;
+    }
+    let mut dtype = None;
+    let mut row_ranges = Vec::new();
+    let mut row_groups = Some(Vec::new());
+    let mut vocabularies = Some(Vec::new());
+    let mut terms = Some(Terms::default());
+    let mut duplicates: Option<BTreeMap<u64, u64>> = None;
+    let posting_lists = manifest
+        .as_ref()
+        .is_some_and(|manifest| manifest.parameters.posting_lists);
+    let compression = manifest
+        .as_ref()
+        .map_or_else(Compression::default, |manifest| {
+            manifest.parameters.compression
+        });
+    let mut postings = Postings::new();
+    let mut live_row_count = 0;
+    for segment in segments {
+        let file = open_segment(segment).await?;
+        let segment_dtype = file.dtype().clone();
+        if dtype.get_or_insert_with(|| segment_dtype.clone()) != &segment_dtype {
+            bail!("Segment {segment:?} has a different layout than the other segments.");
+        }
+        let layout_reader = file.layout_reader()?;
+        let filter = tombstone_filter(tombstones, segment_number(segment));
+        let stats = read_stats(segment).await?;
+        // Scan each row group separately, so that the rows remaining in each can be counted.
+        match &stats {
+            Some(stats) => {
+                let mut start = 0;
+                for row_group in stats {
+                    let row_range = start..start + row_group.rows;
+                    row_ranges.push((layout_reader.clone(), filter.clone(), row_range));
+                    start += row_group.rows;
+                }
+            }
+            None => row_ranges.push((layout_reader, filter, 0..file.row_count())),
+        }
+        row_groups = match (row_groups, stats) {
+            (Some(mut row_groups), Some(stats)) => {
+                row_groups.extend(stats);
+                Some(row_groups)
+            }
+            _ => None,
+        };
+        vocabularies = match (vocabularies, read_vocabulary(segment).await?) {
+            (Some(mut vocabularies), Some(vocabulary)) => {
+                vocabularies.push(vocabulary);
+                Some(vocabularies)
+            }
+            _ => None,
+        };
+        terms = match (terms, read_terms(segment).await?) {
+            (Some(terms), Some(segment_terms)) => Some(terms.union(&segment_terms)),
+            _ => None,
+        };
+        if let Some(segment_duplicates) = read_duplicates(segment).await? {
+            duplicates
+                .get_or_insert_default()
+                .extend(segment_duplicates);
+        }
+        if posting_lists {
+            // Rows are renumbered as they are copied, since deleted rows are skipped.
+            let Some(segment_postings) = read_postings(segment).await? else {
+                bail!("Segment {segment:?} is missing its posting lists.");
+            };
+            let live = live_rows(segment, tombstones).await?;
+            for (column, bitmap) in segment_postings {
+                let merged = postings.entry(column).or_default();
+                for row in &bitmap & &live {
+                    merged.insert(live_row_count + (live.rank(row) - 1) as u32);
+                }
+            }
+            live_row_count += live.len() as u32;
+        }
+    }
+
+    let row_counts = Arc::new(Mutex::new(Vec::with_capacity(row_ranges.len())));
+    let stream = {
+        let row_counts = row_counts.clone();
+        stream! {
+            for (layout_reader, filter, row_range) in row_ranges {
+                let mut scan = ScanBuilder::new(layout_reader)
+                    .with_row_range(row_range)
+                    .with_tokio_executor(Handle::current());
+                if let Some(filter) = filter {
+                    scan = scan.with_filter(filter);
+                }
+                let mut arrays = scan.into_array_stream()?;
+                let mut rows = 0;
+                while let Some(array) = arrays.next().await {
+                    let array = array?;
+                    rows += array.len() as u64;
+                    yield Ok(array);
+                }
+                row_counts.lock().unwrap().push(rows);
+            }
+        }
+    };
+    let array_stream = ArrayStreamAdapter::new(dtype.unwrap(), stream.boxed());
+    let merged = next_segment_path(path, segments);
+    vortex_index_array(&merged, array_stream, compression).await?;
+    if let Some(row_groups) = row_groups {
+        let row_counts = row_counts.lock().unwrap();
+        let row_groups = row_groups
+            .into_iter()
+            .zip(row_counts.iter())
+            .filter(|(_, rows)| **rows > 0)
+            .map(|(row_group, rows)| RowGroupStats {
+                rows: *rows,
+                ..row_group
+            })
+            .collect::<Vec<_>>();
+        write_stats(&merged, &row_groups).await?;
+    }
+    // NB: Deleted documents are not removed from the vocabulary or the terms, which only makes them
+    // less precise.
+    if let Some(vocabulary) = vocabularies.and_then(|vocabularies| {
+        vocabularies
+            .into_iter()
+            .reduce(|union, vocabulary| union.union(&vocabulary))
+    }) {
+        write_vocabulary(&merged, &vocabulary).await?;
+    }
+    if let Some(terms) = terms {
+        write_terms(&merged, &terms).await?;
+    }
+    if posting_lists {
+        write_postings(&merged, &postings).await?;
+    }
+    if let Some(duplicates) = duplicates {
+        write_duplicates(&merged, &duplicates).await?;
+    }
+
+    for segment in segments {
+        tokio::fs::remove_file(segment).await?;
+        for sidecar in [
+            stats_path(segment),
+            vocabulary_path(segment),
+            terms_path(segment),
+            postings_path(segment),
+            duplicates_path(segment),
+        ] {
+            if sidecar.exists() {
+                tokio::fs::remove_file(sidecar).await?;
+            }
+        }
+    }
+    if !tombstones.is_empty() {
+        tokio::fs::remove_file(path.join(TOMBSTONES_FILE)).await?;
+    }
+    if let Some(manifest) = manifest {
+        update_manifest(path, manifest).await?;
+    }
+    Ok(())
+}
+
+///
+/// Reads every chunk of the given segments, and returns their shared dtype, the number of rows
+/// in each row group, and the encoded (compressed) and canonical (uncompressed) sizes of each
+/// column.
+///
+async fn column_sizes(
+    path: &Path,
+    segments: &[PathBuf],
+) -> anyhow::Result<(Arc<StructDType>, Vec<usize>, Vec<(usize, usize)>)> {
+    let mut dtype: Option<Arc<StructDType>> = None;
+    let mut row_groups = Vec::new();
+    let mut column_sizes: Vec<(usize, usize)> = Vec::new();
+    for segment in segments {
+        let file = open_segment(segment).await?;
+        let segment_dtype = struct_dtype(&file)?;
+        if dtype.get_or_insert_with(|| segment_dtype.clone()) != segment_dtype {
+            bail!("Segment {segment:?} has a different layout than the other segments.");
+        }
+        column_sizes.resize(segment_dtype.names().len(), (0, 0));
+
+        let mut arrays = ScanBuilder::new(file.layout_reader()?)
+            .with_tokio_executor(Handle::current())
+            .into_array_stream()?;
+        while let Some(array) = arrays.next().await {
+            let array = array?.to_struct()?;
+            row_groups.push(array.len());
+            for (field, (compressed, uncompressed)) in
+                array.fields().iter().zip(column_sizes.iter_mut())
+            {
+                *compressed += field.nbytes();
+                *uncompressed += field.to_canonical()?.into_array().nbytes();
+            }
+        }
+    }
+    let dtype = dtype.ok_or_else(|| anyhow!("{path:?} does not contain any segments."))?;
+    Ok((dtype, row_groups, column_sizes))
+}
+
+///
+/// Describes the contents of a column of a segment.
+///
+fn column_type(name: &str) -> &'static str {
+    match name.rsplit_once(':') {
+        _ if name == ID_COLUMN => "id",
+        _ if name == BODY_COLUMN => "body",
+        _ if name == TIMESTAMP_COLUMN => "timestamp",
+        _ if name == KEY_COLUMN => "key",
+        _ if name == LENGTH_COLUMN => "length",
+        _ if name.ends_with(TF_SUFFIX) => "tf",
+        _ if name.ends_with(POSITIONS_SUFFIX) => "pos",
+        Some((_, "0")) => "Single",
+        _ => "Multi",
+    }
+}
+
+///
+/// Prints the size of the index at `path`: the total size of its files, grouped by the kind of
+/// file, and the encoded size of each of its columns (largest first) along with its share of
+/// the size of the segments. The columns are then totalled by type, which shows whether the
+/// Single buckets are cheaper than the Multi buckets would have been.
+///
+pub async fn vortex_size(path: &Path) -> anyhow::Result<()> {
+    let total = index_bytes(path)?;
+    println!(">>> total: {total} bytes");
+    for (extension, bytes) in file_sizes_by_extension(path)? {
+        println!(">>> {extension}\t{bytes}");
+    }
+
+    let segments = segment_paths(path)?;
+    let (dtype, _, column_sizes) = column_sizes(path, &segments).await?;
+    let encoded = column_sizes
+        .iter()
+        .map(|(compressed, _)| compressed)
+        .sum::<usize>();
+    let share = |bytes: usize| 100.0 * bytes as f64 / encoded.max(1) as f64;
+    let mut columns = dtype
+        .names()
+        .iter()
+        .zip(&column_sizes)
+        .map(|(name, (compressed, _))| (name.as_ref(), *compressed))
+        .collect::<Vec<_>>();
+    columns.sort_by(|(a, a_bytes), (b, b_bytes)| b_bytes.cmp(a_bytes).then_with(|| a.cmp(b)));
+
+    let mut types: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    println!(">>> column\ttype\tbytes\tshare");
+    for (name, bytes) in &columns {
+        let btype = column_type(name);
+        let (type_columns, type_bytes) = types.entry(btype).or_default();
+        *type_columns += 1;
+        *type_bytes += bytes;
+        println!(">>> {name}\t{btype}\t{bytes}\t{:.2}%", share(*bytes));
+    }
+    println!(">>> type\tcolumns\tbytes\tavg_bytes\tshare");
+    for (btype, (type_columns, bytes)) in types {
+        println!(
+            ">>> {btype}\t{type_columns}\t{bytes}\t{}\t{:.2}%",
+            bytes / type_columns.max(1),
+            share(bytes)
+        );
+    }
+    Ok(())
+}
+
+///
+/// Prints statistics about the index at `path`: its buckets, its row groups, and the encoded
+/// (compressed) and canonical (uncompressed) sizes of each of its columns.
+///
+pub async fn vortex_info(path: &Path) -> anyhow::Result<()> {
+    let segments = segment_paths(path)?;
+    let (dtype, row_groups, column_sizes) = column_sizes(path, &segments).await?;
+    let (buckets, term_frequencies) = buckets_from_dtype(&dtype)?;
+    let manifest = read_manifest(path).await?;
+    let buckets = match &manifest {
+        Some(manifest) => manifest.bucket_types(),
+        None => buckets,
+    };
+
+    let rows = row_groups.iter().sum::<usize>();
+    let singles = buckets
+        .iter()
+        .filter(|(_, btype)| *btype == BucketType::Single)
+        .count();
+    println!(
+        ">>> {} segments, containing {rows} documents",
+        segments.len()
+    );
+    println!(
+        ">>> {} buckets: {singles} Single, {} Multi (term frequencies: {term_frequencies})",
+        buckets.len(),
+        buckets.len() - singles,
+    );
+    println!(
+        ">>> {} row groups: min={} avg={} max={} rows",
+        row_groups.len(),
+        row_groups.iter().min().unwrap_or(&0),
+        rows / row_groups.len().max(1),
+        row_groups.iter().max().unwrap_or(&0),
+    );
+    if let Some(manifest) = &manifest {
+        println!(
+            ">>> built with: {}",
+            serde_json::to_string(&manifest.parameters)?
+        );
+    }
+    if let Some(dictionary) = read_dictionary(path).await? {
+        println!(">>> dictionary: {} tokens", dictionary.tokens.len());
+    }
+    let tombstones = read_tombstones(path).await?;
+    if !tombstones.is_empty() {
+        println!(">>> {} deleted documents", tombstones.len());
+    }
+    if let Some(manifest) = manifest.as_ref().filter(|manifest| manifest.duplicates > 0) {
+        println!(">>> {} skipped duplicate documents", manifest.duplicates);
+    }
+    let bucket_stats = manifest
+        .iter()
+        .flat_map(|manifest| &manifest.buckets)
+        .map(|bucket| (bucket.column.as_str(), (bucket.documents, bucket.tokens)))
+        .collect::<HashMap<_, _>>();
+    println!(">>> column\ttype\tcompressed\tuncompressed\tdocuments\ttokens");
+    for (name, (compressed, uncompressed)) in dtype.names().iter().zip(column_sizes) {
+        let btype = column_type(name);
+        let (documents, tokens) = match bucket_stats.get(name.as_ref()) {
+            Some((documents, tokens)) => (documents.to_string(), tokens.to_string()),
+            None => ("-".to_owned(), "-".to_owned()),
+        };
+        println!(">>> {name}\t{btype}\t{compressed}\t{uncompressed}\t{documents}\t{tokens}");
+    }
+    Ok(())
+}
+
+pub struct VortexSearcher {
+    segments: Vec<Segment>,
+    /// The sorted names of the bucket columns, excluding the ID, body, term frequency and
+    /// positions columns.
+    bucket_columns: Vec<FieldName>,
+    term_frequencies: bool,
+    positions: bool,
+    bodies: bool,
+    timestamps: bool,
+    doc_lengths: bool,
+    dictionary: Option<Arc<Dictionary>>,
+    /// The number of documents for which each bucket is non-empty, which is reported by
+    /// `explain`.
+    bucket_frequencies: Vec<u64>,
+    /// The `get_item` expression for each bucket column, which is shared by the filters of all
+    /// queries rather than being reconstructed for each of them.
+    bucket_exprs: Vec<ExprRef>,
+    /// Whether each bucket is stored as a posting list alongside each segment, rather than as a
+    /// column.
+    posting_buckets: Vec<bool>,
+    /// The tokens indexed in any segment, which fuzzy queries are expanded against. `None` if
+    /// any segment was written before they were recorded.
+    terms: Option<Terms>,
+    location: String,
+    manifest: Option<Manifest>,
+    /// The number of bytes which have been read from the segments.
+    bytes_read: Arc<AtomicU64>,
+    /// The maximum number of scan tasks (which each decode part of a split) in flight at once
+    /// for a query. `None` starts all of them at once.
+    scan_concurrency: Option<usize>,
+    /// The maximum number of rows in a split, which larger splits are divided into before they
+    /// are scanned. `None` scans each split whole.
+    split_size_rows: Option<u64>,
+}
+
+///
+/// The work done to evaluate a query: the bytes read from the segments, and the row groups and
+/// rows which were scanned rather than pruned (or counted without scanning).
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanStats {
+    pub bytes_read: u64,
+    pub row_groups: usize,
+    pub rows: u64,
+}
+
+///
+/// A segment file which has been opened, along with the files which accompany it.
+///
+struct SegmentFile {
+    name: String,
+    file: VortexFile,
+    row_groups: Option<Vec<RowGroupStats>>,
+    vocabulary: Option<Vocabulary>,
+    terms: Option<Terms>,
+    postings: Option<Postings>,
+}
+
+struct Segment {
+    layout_reader: Arc<dyn LayoutReader>,
+    row_count: u64,
+    row_groups: Option<Vec<RowGroupStats>>,
+    /// A filter which excludes the documents deleted from this segment, if there are any.
+    tombstone_filter: Option<ExprRef>,
+    vocabulary: Option<Vocabulary>,
+    /// The posting list of each bucket which is stored as one, keyed by bucket. A bucket without
+    /// a posting list does not match any rows of the segment.
+    postings: HashMap<usize, RoaringBitmap>,
+}
+
+impl Segment {
+    ///
+    /// ANDs the given filter (if any) with the filter which excludes this segment's deleted
+    /// documents. A filter which was simplified to `lit(true)` is dropped, so that its rows can
+    /// be counted without scanning them.
+    ///
+    fn filter(&self, filter: Option<ExprRef>) -> Option<ExprRef> {
+        let filter = match (filter, &self.tombstone_filter) {
+            (Some(filter), Some(tombstone_filter)) => {
+                Some(vortex_expr::and(filter, tombstone_filter.clone()))
+            }
+            (filter, tombstone_filter) => filter.or_else(|| tombstone_filter.clone()),
+        };
+        filter.filter(|filter| literal_bool(filter) != Some(true))
+    }
+
+    ///
+    /// Creates a split for the given rows of this segment, unless there are none.
+    ///
+    fn split(&self, rows: RoaringBitmap, filter: Option<ExprRef>) -> Option<Split<'_>> {
+        if rows.is_empty() {
+            return None;
+        }
+        Some(Split {
+            segment: self,
+            rows: SplitRows::Indices(rows.iter().map(u64::from).collect()),
+            filter: self.filter(filter),
+        })
+    }
+
+    ///
+    /// Returns the ranges of rows which might match the query, according to the row group
+    /// statistics. Adjacent row groups are coalesced into a single range.
+    ///
+    fn row_ranges(&self, bucket_columns: &[FieldName], query: &Query) -> Vec<Range<u64>> {
+        self.row_ranges_matching(|row_group| row_group.might_match(bucket_columns, query))
+    }
+
+    ///
+    /// Returns the ranges of rows in the row groups for which `might_match` is true, which are
+    /// coalesced as for `row_ranges`.
+    ///
+    fn row_ranges_matching(&self, might_match: impl Fn(&RowGroupStats) -> bool) -> Vec<Range<u64>> {
+        let Some(row_groups) = &self.row_groups else {
+            return vec![0..self.row_count];
+        };
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let mut start = 0;
+        for row_group in row_groups {
+            let end = start + row_group.rows;
+            if might_match(row_group) {
+                match ranges.last_mut() {
+                    Some(range) if range.end == start => range.end = end,
+                    _ => ranges.push(start..end),
+                }
+            }
+            start = end;
+        }
+        ranges
+    }
+
+    ///
+    /// Returns the ordinals of the row groups which contain any of the given rows. A segment
+    /// without statistics is a single row group.
+    ///
+    fn row_groups_overlapping(&self, rows: &SplitRows) -> Vec<usize> {
+        let Some(row_groups) = &self.row_groups else {
+            return vec![0];
+        };
+        let mut start = 0;
+        let mut overlapping = Vec::new();
+        for (ordinal, row_group) in row_groups.iter().enumerate() {
+            let end = start + row_group.rows;
+            if rows.overlaps(&(start..end)) {
+                overlapping.push(ordinal);
+            }
+            start = end;
+        }
+        overlapping
+    }
+}
+
+///
+/// A part of a segment to scan, along with the filter to scan it with. A split without a filter
+/// matches all of its rows, which can be counted without scanning them.
+///
+#[derive(Clone)]
+struct Split<'a> {
+    segment: &'a Segment,
+    rows: SplitRows,
+    filter: Option<ExprRef>,
+}
+
+#[derive(Clone)]
+enum SplitRows {
+    Range(Range<u64>),
+    /// The ascending ordinals of the rows to scan.
+    Indices(Buffer<u64>),
+}
+
+impl SplitRows {
+    fn len(&self) -> usize {
+        match self {
+            SplitRows::Range(range) => (range.end - range.start) as usize,
+            SplitRows::Indices(indices) => indices.len(),
+        }
+    }
+
+    ///
+    /// Whether any of the rows falls within the given range.
+    ///
+    fn overlaps(&self, rows: &Range<u64>) -> bool {
+        match self {
+            SplitRows::Range(range) => range.start < rows.end && rows.start < range.end,
+            SplitRows::Indices(indices) => {
+                let idx = indices.partition_point(|row| *row < rows.start);
+                indices.get(idx).is_some_and(|row| *row < rows.end)
+            }
+        }
+    }
+}
+
+impl<'a> Split<'a> {
+    ///
+    /// Divides the split at the boundaries of the row groups of its segment.
+    ///
+    fn by_row_group(self) -> Vec<Split<'a>> {
+        let Some(row_groups) = &self.segment.row_groups else {
+            return vec![self];
+        };
+        let mut splits = Vec::new();
+        let mut start = 0;
+        for row_group in row_groups {
+            let group = start..start + row_group.rows;
+            start = group.end;
+            let rows = match &self.rows {
+                SplitRows::Range(range) => {
+                    SplitRows::Range(range.start.max(group.start)..range.end.min(group.end))
+                }
+                SplitRows::Indices(indices) => {
+                    let first = indices.partition_point(|row| *row < group.start);
+                    let last = indices.partition_point(|row| *row < group.end);
+                    SplitRows::Indices(indices[first..last].iter().copied().collect())
+                }
+            };
+            if rows.len() > 0 {
+                splits.push(Split {
+                    segment: self.segment,
+                    rows,
+                    filter: self.filter.clone(),
+                });
+            }
+        }
+        splits
+    }
+
+    ///
+    /// Divides the split into splits of at most `size` rows.
+    ///
+    fn divide(self, size: u64) -> Vec<Split<'a>> {
+        let rows = match &self.rows {
+            SplitRows::Range(range) if range.end - range.start > size => range
+                .clone()
+                .step_by(size as usize)
+                .map(|start| SplitRows::Range(start..(start + size).min(range.end)))
+                .collect::<Vec<_>>(),
+            SplitRows::Indices(indices) if indices.len() as u64 > size => indices
+                .chunks(size as usize)
+                .map(|chunk| SplitRows::Indices(chunk.iter().copied().collect()))
+                .collect(),
+            _ => return vec![self],
+        };
+        rows.into_iter()
+            .map(|rows| Split {
+                segment: self.segment,
+                rows,
+                filter: self.filter.clone(),
+            })
+            .collect()
+    }
+
+    ///
+    /// Returns an upper bound on the score of any row of the split: the score of each term at the
+    /// maximum term frequency of its bucket in the row groups that the split overlaps. The score
+    /// of a split of a segment without those maximums is unbounded.
+    ///
+    fn max_score(&self, terms: &[ScoredTerm]) -> f64 {
+        let Some(row_groups) = &self.segment.row_groups else {
+            return f64::INFINITY;
+        };
+        self.segment
+            .row_groups_overlapping(&self.rows)
+            .into_iter()
+            .map(|ordinal| {
+                let max_tfs = &row_groups[ordinal].max_term_frequencies;
+                if max_tfs.is_empty() {
+                    return f64::INFINITY;
+                }
+                terms
+                    .iter()
+                    .map(|term| term.score(max_tfs[term.bucket]))
+                    .sum()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    fn scan_builder(self, projection: ExprRef) -> ScanBuilder<ArrayRef> {
+        let builder = ScanBuilder::new(self.segment.layout_reader.clone())
+            .with_projection(projection)
+            .with_tokio_executor(Handle::current());
+        let builder = match self.rows {
+            SplitRows::Range(range) => builder.with_row_range(range),
+            SplitRows::Indices(indices) => builder.with_row_indices(indices),
+        };
+        match self.filter {
+            Some(filter) => builder.with_filter(filter),
+            None => builder,
+        }
+    }
+}
+
+///
+/// The filter for (part of) a nested query, or its value if it does not depend on the row.
+///
+enum ExprFilter {
+    Constant(bool),
+    Filter(ExprRef),
+}
+
+impl ExprFilter {
+    fn all(clauses: Vec<ExprFilter>) -> ExprFilter {
+        let mut filters = Vec::with_capacity(clauses.len());
+        for clause in clauses {
+            match clause {
+                ExprFilter::Constant(false) => return ExprFilter::Constant(false),
+                ExprFilter::Constant(true) => {}
+                ExprFilter::Filter(filter) => filters.push(filter),
+            }
+        }
+        match filters.into_iter().reduce(vortex_expr::and) {
+            Some(filter) => ExprFilter::Filter(filter),
+            None => ExprFilter::Constant(true),
+        }
+    }
+
+    fn any(clauses: Vec<ExprFilter>) -> ExprFilter {
+        let mut filters = Vec::with_capacity(clauses.len());
+        for clause in clauses {
+            match clause {
+                ExprFilter::Constant(true) => return ExprFilter::Constant(true),
+                ExprFilter::Constant(false) => {}
+                ExprFilter::Filter(filter) => filters.push(filter),
+            }
+        }
+        match filters.into_iter().reduce(vortex_expr::or) {
+            Some(filter) => ExprFilter::Filter(filter),
+            None => ExprFilter::Constant(false),
+        }
+    }
+
+    fn into_expr(self) -> ExprRef {
+        match self {
+            ExprFilter::Constant(value) => vortex_expr::lit(value),
+            ExprFilter::Filter(filter) => filter,
+        }
+    }
+}
+
+impl crate::Searcher for VortexSearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        VortexSearcher::open_with_io(path, Io::default()).await
+    }
+
+    ///
+    /// Counts with an empty projection, so that each matching batch is a struct without any
+    /// fields: only the columns referenced by the filter are read, and nothing is materialized
+    /// for the matching rows beyond their number.
+    ///
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        Ok(self.count_with_setup(query).await?.0)
+    }
+
+    #[tracing::instrument(name = "vortex_count", skip_all)]
+    async fn count_with_setup(&self, query: &Query) -> anyhow::Result<(usize, Duration)> {
+        let start = Instant::now();
+        let splits = self.plan(query);
+        let setup = start.elapsed();
+        Ok((self.count_splits(splits).await?, setup))
+    }
+
+    fn check_tokenizer(&self, tokenizer: &Tokenizer) -> anyhow::Result<()> {
+        match &self.manifest {
+            Some(manifest) => manifest.check_tokenizer(&self.location, tokenizer),
+            None => Ok(()),
+        }
+    }
+
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        if query.near.is_some() && !self.positions {
+            bail!(
+                "The index was not built with positions, and so cannot evaluate proximity queries."
+            );
+        }
+        if needs_expansion(query) && self.terms.is_none() {
+            bail!(
+                "The index was not built with a list of its tokens, and so cannot evaluate fuzzy \
+                or pattern queries."
+            );
+        }
+        let leaves = query
+            .expr
+            .iter()
+            .flat_map(QueryExpr::leaves)
+            .collect::<Vec<_>>();
+        if leaves
+            .iter()
+            .any(|leaf| matches!(leaf, QueryExpr::Phrase(_)))
+            && !self.positions
+        {
+            bail!(
+                "The index was not built with positions, and so cannot evaluate proximity queries."
+            );
+        }
+        let expr_patterns = leaves.iter().filter_map(|leaf| match leaf {
+            QueryExpr::Pattern(pattern) => Some(pattern),
+            _ => None,
+        });
+        for pattern in query
+            .patterns
+            .iter()
+            .chain(&query.excluded_patterns)
+            .chain(expr_patterns)
+        {
+            if self.terms.is_none() {
+                bail!(
+                    "The index was not built with a list of its tokens, and so cannot evaluate \
+                    fuzzy or pattern queries."
+                );
+            }
+            pattern.compile()?;
+        }
+        if query.fuzzy > 0 && query.near.is_some() {
+            bail!("A fuzzy query cannot be combined with a proximity query.");
+        }
+        Ok(())
+    }
+
+    ///
+    /// IDs are materialized late: the filter is first evaluated for each row group without
+    /// projecting anything, and then the ID column is only read for the row groups which
+    /// contain matches.
+    ///
+    #[tracing::instrument(name = "vortex_ids", skip_all)]
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let projection = vortex_expr::get_item(ID_COLUMN, vortex_expr::ident());
+
+        let splits = self.passing_splits(self.plan(query)).await?;
+        let batches = self
+            .scan_splits(splits, projection, |array| {
+                Ok(array.to_primitive()?.as_slice::<u64>().to_vec())
+            })
+            .await?;
+        Ok(batches.into_iter().flatten().collect())
+    }
+
+    ///
+    /// Matching documents are post-filtered by projecting the stored body column, and then
+    /// highlighting it with our `Tokenizer`.
+    ///
+    async fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        if !self.bodies {
+            bail!("The index was not built with stored bodies.");
+        }
+
+        let projection = vortex_expr::select(
+            vec![FieldName::from(ID_COLUMN), BODY_COLUMN.into()],
+            vortex_expr::ident(),
+        );
+        let batches = self
+            .scan(query, projection, |array| bodies_batch(&array))
+            .await?;
+        let highlighted = self.expanded(query);
+        Ok(batches
+            .into_iter()
+            .flatten()
+            .take(limit)
+            .map(|(id, body)| (id, snippet(&body, &highlighted, tokenizer)))
+            .collect())
+    }
+
+    ///
+    /// Projects the buckets which might contain tokens of the field, and counts the field's
+    /// tokens in each matching batch. Tokens of the field which are stored as posting lists are
+    /// counted by intersecting their matches with those of the query.
+    ///
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let prefix = field_token(field, "");
+        let (columns, posting_columns): (Vec<_>, Vec<_>) =
+            facet_columns(&self.bucket_columns, &prefix)
+                .into_iter()
+                .partition(|column| !self.is_posting_list(column));
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        if !columns.is_empty() {
+            let dictionary = self.dictionary.clone();
+            let facet_prefix = prefix.clone();
+            let batches = self
+                .scan(
+                    query,
+                    vortex_expr::select(columns, vortex_expr::ident()),
+                    move |array| facet_batch(&array, &facet_prefix, dictionary.as_deref()),
+                )
+                .await?;
+            for batch in batches {
+                for (value, count) in batch {
+                    *counts.entry(value).or_default() += count;
+                }
+            }
+        }
+        if !posting_columns.is_empty() {
+            let matches = self.ids(query).await?.into_iter().collect::<HashSet<_>>();
+            for column in posting_columns {
+                if let Some((token, _)) = column.rsplit_once(':')
+                    && let Some(value) = token.strip_prefix(&prefix)
+                    && !value.ends_with(PREFIX_MARKER)
+                {
+                    let ids = self
+                        .ids(&Query::new([token.to_owned()].into(), QueryMode::All))
+                        .await?;
+                    let count = ids.iter().filter(|id| matches.contains(id)).count();
+                    if count > 0 {
+                        *counts.entry(value.to_owned()).or_default() += count;
+                    }
+                }
+            }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
+
+///
+/// Counts the tokens beginning with `prefix` in a batch which has been projected to contain
+/// bucket columns, keyed by the remainder of each token. Edge n-grams are skipped.
+///
+fn facet_batch(
+    batch: &ArrayRef,
+    prefix: &str,
+    dictionary: Option<&Dictionary>,
+) -> VortexResult<HashMap<String, usize>> {
+    let batch = batch.to_struct()?;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut count = |token: &str, matches: usize| {
+        if let Some(value) = token.strip_prefix(prefix)
+            && !value.ends_with(PREFIX_MARKER)
+            && matches > 0
+        {
+            *counts.entry(value.to_owned()).or_default() += matches;
+        }
+    };
+    for (name, column) in batch.names().iter().zip(batch.fields()) {
+        if let Some((token, "0")) = name.rsplit_once(':') {
+            count(token, column.to_bool()?.boolean_buffer().count_set_bits());
+            continue;
+        }
+        for_each_list_token(column, dictionary, |token| count(token, 1))?;
+    }
+    Ok(counts)
+}
+
+///
+/// Calls `f` with each token of each row of a Multi bucket column.
+///
+fn for_each_list_token(
+    column: &ArrayRef,
+    dictionary: Option<&Dictionary>,
+    mut f: impl FnMut(&str),
+) -> VortexResult<()> {
+    for row in 0..column.len() {
+        let Some(elements) = column.scalar_at(row)?.as_list().elements() else {
+            continue;
+        };
+        for element in elements {
+            match dictionary {
+                Some(dictionary) => f(&dictionary.tokens[u32::try_from(&element)? as usize]),
+                None => {
+                    if let Some(token) = element.as_utf8().value() {
+                        f(token.as_str());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Counts the documents containing each token of each Multi bucket column of a batch, and
+/// returns them along with the number of rows in the batch.
+///
+fn bucket_tokens_batch(
+    batch: &ArrayRef,
+    dictionary: Option<&Dictionary>,
+) -> VortexResult<(usize, Vec<HashMap<String, usize>>)> {
+    let batch = batch.to_struct()?;
+    let mut buckets = Vec::with_capacity(batch.fields().len());
+    for column in batch.fields() {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for_each_list_token(column, dictionary, |token| {
+            *counts.entry(token.to_owned()).or_default() += 1
+        })?;
+        buckets.push(counts);
+    }
+    Ok((batch.len(), buckets))
+}
+
+///
+/// Counts the rows for which each of the boolean columns of a batch is true. A null (as for a
+/// range of timestamps and a document without one) is not a match.
+///
+fn counts_batch(batch: &ArrayRef) -> VortexResult<Vec<usize>> {
+    let batch = batch.to_struct()?;
+    batch
+        .fields()
+        .iter()
+        .map(|column| {
+            let column = column.to_bool()?;
+            let matches = Mask::from_buffer(column.boolean_buffer().clone());
+            Ok((&matches & &column.validity_mask()?).true_count())
+        })
+        .collect()
+}
+
+///
+/// Counts the true predicates of each row of a batch which has been projected to contain the ID
+/// column, followed by one boolean column per query token.
+///
+fn match_counts_batch(batch: &ArrayRef) -> VortexResult<Vec<(u64, usize)>> {
+    let batch = batch.to_struct()?;
+    let ids = batch.field_by_name(ID_COLUMN)?.to_primitive()?;
+    let mut counts = vec![0; batch.len()];
+    for column in &batch.fields()[1..] {
+        let column = column.to_bool()?;
+        let validity = column.validity_mask()?;
+        for idx in column.boolean_buffer().set_indices() {
+            if validity.value(idx) {
+                counts[idx] += 1;
+            }
+        }
+    }
+    Ok(ids.as_slice::<u64>().iter().copied().zip(counts).collect())
+}
+
+///
+/// Decodes a batch which has been projected to contain the ID and body columns.
+///
+fn bodies_batch(batch: &ArrayRef) -> VortexResult<Vec<(u64, String)>> {
+    let batch = batch.to_struct()?;
+    let ids = batch.field_by_name(ID_COLUMN)?.to_primitive()?;
+    let bodies = batch.field_by_name(BODY_COLUMN)?.to_varbinview()?;
+    Ok(ids
+        .as_slice::<u64>()
+        .iter()
+        .enumerate()
+        .map(|(idx, id)| {
+            let body = String::from_utf8_lossy(bodies.bytes_at(idx).as_slice()).into_owned();
+            (*id, body)
+        })
+        .collect())
+}
+
+impl VortexSearcher {
+    ///
+    /// Drops the tokens which were never indexed from the query. They cannot match, and so rather
+    /// than scanning the buckets that they fall into, they short-circuit an AND, and are dropped
+    /// from an OR. Returns `None` if the query cannot match anything.
+    ///
+    fn indexed(&self, query: &Query) -> Option<Query> {
+        let indexed = |token: &String| self.might_contain(token);
+        if query.mode == QueryMode::All && !query.tokens.iter().all(indexed) {
+            return None;
+        }
+        let query = Query {
+            tokens: query
+                .tokens
+                .iter()
+                .filter(|t| indexed(t))
+                .cloned()
+                .collect(),
+            excluded: query
+                .excluded
+                .iter()
+                .filter(|t| indexed(t))
+                .cloned()
+                .collect(),
+            ..query.clone()
+        };
+        (!query.tokens.is_empty()).then_some(query)
+    }
+
+    ///
+    /// Splits the tokens which are stored as posting lists out of the query, and returns the
+    /// query for its remaining tokens, along with the buckets of its posting list tokens and
+    /// excluded tokens.
+    ///
+    fn partition_posting_lists(&self, query: &Query) -> (Query, Vec<usize>, Vec<usize>) {
+        let partition = |tokens: &HashSet<String>| {
+            let mut posting_lists = Vec::new();
+            let columns = tokens
+                .iter()
+                .filter(|token| {
+                    let (idx, _) = find_bucket(&self.bucket_columns, token);
+                    if self.posting_buckets[idx] {
+                        posting_lists.push(idx);
+                    }
+                    !self.posting_buckets[idx]
+                })
+                .cloned()
+                .collect::<HashSet<_>>();
+            (columns, posting_lists)
+        };
+        let (tokens, token_buckets) = partition(&query.tokens);
+        let (excluded, excluded_buckets) = partition(&query.excluded);
+        let query = Query {
+            tokens,
+            excluded,
+            ..query.clone()
+        };
+        (query, token_buckets, excluded_buckets)
+    }
+
+    ///
+    /// Expands each of the tokens of a query into the indexed tokens which it matches: those
+    /// within its edit distance for a fuzzy query, and otherwise just itself (if it was indexed).
+    /// These are followed by the indexed tokens which match each of the query's patterns. The
+    /// indexed tokens which match each of its excluded patterns are returned separately.
+    ///
+    fn expansions(&self, query: &Query) -> (Vec<HashSet<String>>, Vec<HashSet<String>>) {
+        let Some(terms) = &self.terms else {
+            let groups = vec![HashSet::new(); query.tokens.len() + query.patterns.len()];
+            return (groups, vec![HashSet::new(); query.excluded_patterns.len()]);
+        };
+        let mut groups = if query.fuzzy > 0 {
+            terms.fuzzy_matches(&query.tokens, query.fuzzy)
+        } else {
+            query
+                .tokens
+                .iter()
+                .map(|token| {
+                    if self.might_contain(token) {
+                        [token.clone()].into()
+                    } else {
+                        HashSet::new()
+                    }
+                })
+                .collect()
+        };
+        groups.extend(terms.pattern_matches(&query.patterns));
+        (groups, terms.pattern_matches(&query.excluded_patterns))
+    }
+
+    ///
+    /// Replaces the tokens and patterns of a fuzzy or pattern query with all of their expansions,
+    /// in `Any` mode. The expanded query matches a superset of the documents of the original
+    /// query, and so can be used to prune row groups, and to highlight or score matches.
+    ///
+    fn expanded<'a>(&self, query: &'a Query) -> Cow<'a, Query> {
+        if let Some(expr) = &query.expr {
+            return Cow::Owned(Query {
+                expr: Some(self.resolved(expr, query.fuzzy)),
+                fuzzy: 0,
+                ..query.clone()
+            });
+        }
+        if !needs_expansion(query) {
+            return Cow::Borrowed(query);
+        }
+        let (groups, excluded) = self.expansions(query);
+        Cow::Owned(expanded_query(query, &groups, &excluded))
+    }
+
+    ///
+    /// Creates the filter for each group of expansions of a query's tokens, which is `None` if all
+    /// of the group's tokens are stored as posting lists, along with the buckets of the group's
+    /// posting lists.
+    ///
+    fn expansion_filters(
+        &self,
+        groups: Vec<HashSet<String>>,
+    ) -> Vec<(Option<ExprRef>, Vec<usize>)> {
+        groups
+            .into_iter()
+            .map(|group| {
+                let (columns, buckets, _) =
+                    self.partition_posting_lists(&Query::new(group, QueryMode::Any));
+                let filter = (!columns.tokens.is_empty())
+                    .then(|| self.create_filter(&columns, vortex_expr::lit(false)));
+                (filter, buckets)
+            })
+            .collect()
+    }
+
+    ///
+    /// Creates the filter for the excluded tokens and timestamps of a query (if it has either),
+    /// along with the buckets of the excluded tokens which are stored as posting lists.
+    ///
+    fn exclusions_filter(&self, query: &Query) -> (Option<ExprRef>, Vec<usize>) {
+        let exclusions = Query {
+            tokens: HashSet::new(),
+            excluded: query
+                .excluded
+                .iter()
+                .filter(|token| self.might_contain(token))
+                .cloned()
+                .collect(),
+            ..query.clone()
+        };
+        let (exclusions, _, buckets) = self.partition_posting_lists(&exclusions);
+        let filter = (!exclusions.excluded.is_empty() || exclusions.timestamps.is_some())
+            .then(|| self.create_filter(&exclusions, vortex_expr::lit(true)));
+        (filter, buckets)
+    }
+
+    ///
+    /// Replaces each pattern of a nested query with a disjunction of the indexed tokens which it
+    /// matches, as well as each token with those within `fuzzy` edits of it (outside of any
+    /// negation, as for the other backends).
+    ///
+    fn resolved(&self, expr: &QueryExpr, fuzzy: u8) -> QueryExpr {
+        let Some(terms) = &self.terms else {
+            return expr.clone();
+        };
+        let tokens = |tokens: HashSet<String>| {
+            let mut tokens = tokens.into_iter().collect::<Vec<_>>();
+            tokens.sort_unstable();
+            QueryExpr::Or(tokens.into_iter().map(QueryExpr::Token).collect())
+        };
+        let resolved = |clauses: &[QueryExpr]| {
+            clauses
+                .iter()
+                .map(|clause| self.resolved(clause, fuzzy))
+                .collect()
+        };
+        match expr {
+            QueryExpr::Token(token) if fuzzy > 0 => tokens(
+                terms
+                    .fuzzy_matches([token], fuzzy)
+                    .pop()
+                    .unwrap_or_default(),
+            ),
+            QueryExpr::Pattern(pattern) => {
+                tokens(terms.pattern_matches([pattern]).pop().unwrap_or_default())
+            }
+            QueryExpr::And(clauses) => QueryExpr::And(resolved(clauses)),
+            QueryExpr::Or(clauses) => QueryExpr::Or(resolved(clauses)),
+            QueryExpr::Not(negated) => QueryExpr::Not(Box::new(self.resolved(negated, 0))),
+            leaf => leaf.clone(),
+        }
+    }
+
+    ///
+    /// Lowers a (resolved) nested query to a filter, given whether rows are in the posting list
+    /// of each of its tokens which are stored as posting lists. Tokens which were never indexed
+    /// cannot match, and are folded away along with the posting list tokens.
+    ///
+    fn expr_filter(&self, expr: &QueryExpr, postings: &HashMap<usize, bool>) -> ExprFilter {
+        let filters = |clauses: &[QueryExpr]| {
+            clauses
+                .iter()
+                .map(|clause| self.expr_filter(clause, postings))
+                .collect::<Vec<_>>()
+        };
+        match expr {
+            QueryExpr::Token(token) => {
+                let (idx, _) = find_bucket(&self.bucket_columns, token);
+                if !self.might_contain(token) {
+                    ExprFilter::Constant(false)
+                } else if self.posting_buckets[idx] {
+                    ExprFilter::Constant(postings.get(&idx).copied().unwrap_or(false))
+                } else {
+                    let query = Query::new([token.clone()].into(), QueryMode::All);
+                    ExprFilter::Filter(self.create_filter(&query, vortex_expr::lit(false)))
+                }
+            }
+            QueryExpr::Phrase(near) => {
+                let mut clauses = near
+                    .tokens
+                    .iter()
+                    .map(|token| self.expr_filter(&QueryExpr::Token(token.clone()), postings))
+                    .collect::<Vec<_>>();
+                clauses.push(ExprFilter::Filter(self.near_expr(near)));
+                ExprFilter::all(clauses)
+            }
+            QueryExpr::Timestamps(range) => match self.timestamps_filter(range) {
+                Some(filter) => ExprFilter::Filter(filter),
+                None => ExprFilter::Constant(false),
+            },
+            QueryExpr::And(clauses) => ExprFilter::all(filters(clauses)),
+            QueryExpr::Or(clauses) => ExprFilter::any(filters(clauses)),
+            QueryExpr::Not(negated) => match self.expr_filter(negated, postings) {
+                ExprFilter::Constant(value) => ExprFilter::Constant(!value),
+                ExprFilter::Filter(filter) => ExprFilter::Filter(vortex_expr::not(filter)),
+            },
+            // NB: Patterns are replaced with their expansions by `resolved`.
+            QueryExpr::Pattern(_) => ExprFilter::Constant(false),
+        }
+    }
+
+    ///
+    /// Creates a predicate which checks the positions of the tokens of a proximity query. The
+    /// query must also require each of the tokens, so that rows without them are filtered out
+    /// before their positions are decoded.
+    ///
+    fn near_expr(&self, near: &Near) -> ExprRef {
+        let mut terms = Vec::with_capacity(near.tokens.len());
+        let mut children = Vec::new();
+        for token in &near.tokens {
+            let (idx, btype) = find_bucket(&self.bucket_columns, token);
+            let column = &self.bucket_columns[idx];
+            let positions =
+                vortex_expr::get_item(positions_column_name(column), vortex_expr::ident());
+            match btype {
+                BucketType::Single => {
+                    terms.push(NearTerm::Single);
+                    children.push(positions);
+                }
+                BucketType::Multi => {
+                    let needle = match &self.dictionary {
+                        Some(dictionary) => dictionary.code(token).map(Scalar::from),
+                        None => Some(token.clone().into()),
+                    };
+                    terms.push(NearTerm::Multi(needle));
+                    children.push(self.bucket_exprs[idx].clone());
+                    children.push(vortex_expr::get_item(
+                        tf_column_name(column),
+                        vortex_expr::ident(),
+                    ));
+                    children.push(positions);
+                }
+            }
+        }
+        NearExpr::new_expr(terms, children, near.clone())
+    }
+
+    fn is_posting_list(&self, column: &FieldName) -> bool {
+        self.bucket_columns
+            .binary_search(column)
+            .is_ok_and(|idx| self.posting_buckets[idx])
+    }
+
+    ///
+    /// Creates a filter for the query's tokens, ANDed with a range predicate for its timestamps
+    /// (if any). A query without tokens has the filter `no_tokens`. An index without timestamps
+    /// cannot match a range of timestamps.
+    ///
+    #[tracing::instrument(skip_all, fields(tokens = query.tokens.len()))]
+    fn create_filter(&self, query: &Query, no_tokens: ExprRef) -> ExprRef {
+        let filter = create_filter(
+            &self.bucket_columns,
+            &self.bucket_exprs,
+            query,
+            self.dictionary.as_deref(),
+            &self.bucket_ranks(query),
+            no_tokens,
+        );
+        let filter = match &query.near {
+            Some(near) => vortex_expr::and(filter, self.near_expr(near)),
+            None => filter,
+        };
+        let Some(range) = &query.timestamps else {
+            return self.simplified(filter);
+        };
+        match self.timestamps_filter(range) {
+            Some(timestamps) => self.simplified(vortex_expr::and(filter, timestamps)),
+            None => vortex_expr::lit(false),
+        }
+    }
+
+    ///
+    /// Creates a range predicate for the timestamps, or returns `None` if the index does not have
+    /// them.
+    ///
+    fn timestamps_filter(&self, range: &RangeInclusive<u64>) -> Option<ExprRef> {
+        if !self.timestamps {
+            return None;
+        }
+        let timestamp = vortex_expr::get_item(TIMESTAMP_COLUMN, vortex_expr::ident());
+        Some(vortex_expr::and(
+            vortex_expr::gt_eq(timestamp.clone(), vortex_expr::lit(*range.start())),
+            vortex_expr::lt_eq(timestamp, vortex_expr::lit(*range.end())),
+        ))
+    }
+
+    ///
+    /// Returns false if no segment contains the token. A segment without a vocabulary might
+    /// contain any token.
+    ///
+    fn might_contain(&self, token: &str) -> bool {
+        self.segments.iter().any(|segment| {
+            segment
+                .vocabulary
+                .as_ref()
+                .is_none_or(|vocabulary| vocabulary.might_contain(token))
+        })
+    }
+
+    ///
+    /// Opens the index at `path`, whose segments will be read with the given `Io`.
+    ///
+    pub async fn open_with_io(path: &Path, io: Io) -> anyhow::Result<Self> {
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let mut segments = Vec::new();
+        for segment in segment_paths(path)? {
+            segments.push(SegmentFile {
+                name: segment.display().to_string(),
+                file: open_segment_with_io(&segment, io, bytes_read.clone()).await?,
+                row_groups: read_stats(&segment).await?,
+                vocabulary: read_vocabulary(&segment).await?,
+                terms: read_terms(&segment).await?,
+                postings: read_postings(&segment).await?,
+            });
+        }
+        let dictionary = read_dictionary(path).await?;
+        let tombstones = read_tombstones(path).await?;
+        let manifest = read_manifest(path).await?;
+        VortexSearcher::from_segments(
+            &format!("{path:?}"),
+            segments,
+            dictionary,
+            &tombstones,
+            manifest,
+            bytes_read,
+        )
+    }
+
+    ///
+    /// Opens an index in an object store, such as `s3://bucket/path/to/index`: either a
+    /// directory of segments, or a single legacy index file. Credentials and other options are
+    /// read from the environment (e.g. `AWS_REGION`, `AWS_ACCESS_KEY_ID`).
+    ///
+    pub async fn open_uri(uri: &str) -> anyhow::Result<Self> {
+        let (store, prefix) = object_store::parse_url_opts(&Url::parse(uri)?, std::env::vars())?;
+        let store: Arc<dyn ObjectStore> = store.into();
+        let bytes_read = Arc::new(AtomicU64::new(0));
+
+        let objects = store.list(Some(&prefix)).try_collect::<Vec<_>>().await?;
+        let mut locations = objects
+            .into_iter()
+            .map(|object| object.location)
+            .filter(|location| segment_number(Path::new(location.as_ref())).is_some())
+            .collect::<Vec<_>>();
+        locations.sort_by_key(|location| segment_number(Path::new(location.as_ref())));
+        if locations.is_empty() {
+            locations.push(prefix);
+        }
+
+        let mut segments = Vec::new();
+        for location in locations {
+            let read = ObjectStoreReadAt::new(store.clone(), location.clone(), None);
+            let file = VortexOpenOptions::file()
+                .open_read_at(CountingReadAt::new(read, bytes_read.clone()))
+                .await?;
+            let segment = Path::new(location.as_ref());
+            let postings = get_bytes(store.as_ref(), &postings_path(segment)).await?;
+            segments.push(SegmentFile {
+                name: location.to_string(),
+                file,
+                row_groups: get_json(store.as_ref(), &stats_path(segment)).await?,
+                vocabulary: get_json(store.as_ref(), &vocabulary_path(segment)).await?,
+                terms: get_json(store.as_ref(), &terms_path(segment)).await?,
+                postings: postings.map(|bytes| decode_postings(&bytes)).transpose()?,
+            });
+        }
+        let dictionary = match store.get(&prefix.child(DICTIONARY_FILE)).await {
+            Ok(result) => Some(Dictionary::from_tokens(serde_json::from_slice(
+                &result.bytes().await?,
+            )?)),
+            Err(object_store::Error::NotFound { .. }) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let tombstones = match store.get(&prefix.child(TOMBSTONES_FILE)).await {
+            Ok(result) => serde_json::from_slice(&result.bytes().await?)?,
+            Err(object_store::Error::NotFound { .. }) => Tombstones::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let manifest_location = prefix.child(MANIFEST_FILE);
+        let manifest = get_json(store.as_ref(), Path::new(manifest_location.as_ref())).await?;
+        VortexSearcher::from_segments(uri, segments, dictionary, &tombstones, manifest, bytes_read)
+    }
+
+    ///
+    /// Creates a searcher from opened segment files, which must all have the same layout. Each
+    /// file is named by its path, from which its segment number is recovered.
+    ///
+    /// The buckets are taken from the manifest if there is one, and are otherwise recovered from
+    /// the column names. An index with an unsupported layout version is rejected.
+    ///
+    fn from_segments(
+        location: &str,
+        files: Vec<SegmentFile>,
+        dictionary: Option<Dictionary>,
+        tombstones: &Tombstones,
+        manifest: Option<Manifest>,
+        bytes_read: Arc<AtomicU64>,
+    ) -> anyhow::Result<Self> {
+        let mut dtype = None;
+        for segment in &files {
+            let segment_dtype = struct_dtype(&segment.file)?;
+            if dtype.get_or_insert_with(|| segment_dtype.clone()) != segment_dtype {
+                bail!(
+                    "Segment {} has a different layout than the other segments.",
+                    segment.name
+                );
+            }
+        }
+        let dtype = dtype.ok_or_else(|| anyhow!("{location} does not contain any segments."))?;
+
+        let (bucket_columns, posting_buckets): (Vec<FieldName>, Vec<bool>) = match &manifest {
+            Some(manifest) => {
+                manifest.check_version(location)?;
+                let buckets = manifest
+                    .buckets
+                    .iter()
+                    .map(|bucket| (FieldName::from(bucket.column.as_str()), bucket.posting_list))
+                    .collect::<Vec<_>>();
+                if let Some((column, _)) = buckets.iter().find(|(column, posting_list)| {
+                    !posting_list && !has_column(&dtype, column.as_ref())
+                }) {
+                    bail!("The manifest of {location} refers to a missing column: {column}");
+                }
+                buckets.into_iter().unzip()
+            }
+            None => dtype
+                .names()
+                .iter()
+                .filter(|name| {
+                    ![
+                        ID_COLUMN,
+                        BODY_COLUMN,
+                        TIMESTAMP_COLUMN,
+                        KEY_COLUMN,
+                        LENGTH_COLUMN,
+                    ]
+                    .contains(&name.as_ref())
+                        && !name.ends_with(TF_SUFFIX)
+                        && !name.ends_with(POSITIONS_SUFFIX)
+                })
+                .map(|name| (name.clone(), false))
+                .unzip(),
+        };
+
+        let mut segments = Vec::with_capacity(files.len());
+        let mut terms = Some(Terms::default());
+        for segment in files {
+            terms = match (terms, &segment.terms) {
+                (Some(terms), Some(segment_terms)) => Some(terms.union(segment_terms)),
+                _ => None,
+            };
+            let postings = match segment.postings {
+                Some(postings) => postings
+                    .into_iter()
+                    .map(|(column, bitmap)| {
+                        let idx = bucket_columns
+                            .binary_search(&FieldName::from(column.as_str()))
+                            .map_err(|_| {
+                                anyhow!("Segment {} has a posting list for {column}.", segment.name)
+                            })?;
+                        Ok((idx, bitmap))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+                None if posting_buckets.contains(&true) => {
+                    bail!("Segment {} is missing its posting lists.", segment.name);
+                }
+                None => HashMap::new(),
+            };
+            segments.push(Segment {
+                layout_reader: segment.file.layout_reader()?,
+                row_count: segment.file.row_count(),
+                row_groups: segment.row_groups,
+                tombstone_filter: tombstone_filter(
+                    tombstones,
+                    segment_number(Path::new(&segment.name)),
+                ),
+                vocabulary: segment.vocabulary,
+                postings,
+            });
+        }
+        let term_frequencies = dtype.names().iter().any(|name| name.ends_with(TF_SUFFIX));
+        let positions = has_positions(&dtype);
+        let bodies = has_column(&dtype, BODY_COLUMN);
+        let timestamps = has_column(&dtype, TIMESTAMP_COLUMN);
+        let doc_lengths = has_column(&dtype, LENGTH_COLUMN);
+        let bucket_frequencies = bucket_frequencies(&segments, bucket_columns.len());
+        let bucket_exprs = bucket_columns
+            .iter()
+            .map(|column| vortex_expr::get_item(column.clone(), vortex_expr::ident()))
+            .collect();
+
+        Ok(VortexSearcher {
+            segments,
+            bucket_columns,
+            term_frequencies,
+            positions,
+            bodies,
+            timestamps,
+            doc_lengths,
+            dictionary: dictionary.map(Arc::new),
+            bucket_frequencies,
+            bucket_exprs,
+            posting_buckets,
+            terms,
+            location: location.to_owned(),
+            manifest,
+            bytes_read,
+            scan_concurrency: None,
+            split_size_rows: None,
+        })
+    }
+
+    ///
+    /// Limits the number of scan tasks in flight at once for each query, rather than starting
+    /// all of them at once.
+    ///
+    pub fn with_scan_concurrency(mut self, concurrency: usize) -> Self {
+        self.scan_concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    ///
+    /// Divides the splits of each query into splits of at most `rows` rows before they are
+    /// scanned, which bounds the rows decoded by each scan task.
+    ///
+    pub fn with_split_size_rows(mut self, rows: u64) -> Self {
+        self.split_size_rows = Some(rows.max(1));
+        self
+    }
+
+    fn divide_splits<'a>(&self, splits: Vec<Split<'a>>) -> Vec<Split<'a>> {
+        match self.split_size_rows {
+            Some(rows) => splits
+                .into_iter()
+                .flat_map(|split| split.divide(rows))
+                .collect(),
+            None => splits,
+        }
+    }
+
+    ///
+    /// Returns the original text of the document with the given ID, if it exists. Fails if the
+    /// index was not built with `store_bodies`.
+    ///
+    pub async fn body(&self, id: u64) -> anyhow::Result<Option<String>> {
+        if !self.bodies {
+            bail!("The index was not built with stored bodies.");
+        }
+
+        let filter = vortex_expr::eq(
+            vortex_expr::get_item(ID_COLUMN, vortex_expr::ident()),
+            vortex_expr::lit(id),
+        );
+        let splits = self
+            .segments
+            .iter()
+            .map(|segment| Split {
+                segment,
+                rows: SplitRows::Range(0..segment.row_count),
+                filter: segment.filter(Some(filter.clone())),
+            })
+            .collect();
+        let projection = vortex_expr::select(
+            vec![FieldName::from(ID_COLUMN), BODY_COLUMN.into()],
+            vortex_expr::ident(),
+        );
+        let bodies = self
+            .scan_splits(splits, projection, |array| bodies_batch(&array))
+            .await?;
+        Ok(bodies.into_iter().flatten().next().map(|(_, body)| body))
+    }
+
+    ///
+    /// Counts the live documents containing each token of each Multi bucket (which is not stored
+    /// as a posting list), by scanning the bucket columns. Returns the number of live documents,
+    /// and the counts of each bucket.
+    ///
+    pub(crate) async fn multi_bucket_frequencies(
+        &self,
+    ) -> anyhow::Result<(usize, Vec<(FieldName, HashMap<String, usize>)>)> {
+        let suffix = BucketType::Multi.column_name("");
+        let columns = self
+            .bucket_columns
+            .iter()
+            .filter(|column| column.ends_with(&suffix) && !self.is_posting_list(column))
+            .cloned()
+            .collect::<Vec<_>>();
+        let splits = self
+            .segments
+            .iter()
+            .map(|segment| Split {
+                segment,
+                rows: SplitRows::Range(0..segment.row_count),
+                filter: segment.filter(None),
+            })
+            .collect();
+        let dictionary = self.dictionary.clone();
+        let batches = self
+            .scan_splits(
+                splits,
+                vortex_expr::select(columns.clone(), vortex_expr::ident()),
+                move |array| bucket_tokens_batch(&array, dictionary.as_deref()),
+            )
+            .await?;
+        let mut rows = 0;
+        let mut buckets = vec![HashMap::new(); columns.len()];
+        for (batch_rows, batch) in batches {
+            rows += batch_rows;
+            for (counts, batch_counts) in buckets.iter_mut().zip(batch) {
+                for (token, count) in batch_counts {
+                    *counts.entry(token).or_default() += count;
+                }
+            }
+        }
+        Ok((rows, columns.into_iter().zip(buckets).collect()))
+    }
+
+    ///
+    /// Returns every token of the index with the number of live documents which contain it,
+    /// most frequent first, by scanning all of the bucket columns. The tokens of named fields
+    /// are qualified with the name of the field, and edge n-grams are skipped.
+    ///
+    pub async fn term_frequencies(&self) -> anyhow::Result<Vec<(String, usize)>> {
+        let (columns, posting_columns): (Vec<_>, Vec<_>) = self
+            .bucket_columns
+            .iter()
+            .cloned()
+            .partition(|column| !self.is_posting_list(column));
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        if !columns.is_empty() {
+            let splits = self
+                .segments
+                .iter()
+                .map(|segment| Split {
+                    segment,
+                    rows: SplitRows::Range(0..segment.row_count),
+                    filter: segment.filter(None),
+                })
+                .collect();
+            let dictionary = self.dictionary.clone();
+            let batches = self
+                .scan_splits(
+                    splits,
+                    vortex_expr::select(columns, vortex_expr::ident()),
+                    move |array| facet_batch(&array, "", dictionary.as_deref()),
+                )
+                .await?;
+            for batch in batches {
+                for (token, count) in batch {
+                    *counts.entry(token).or_default() += count;
+                }
+            }
+        }
+        for column in posting_columns {
+            if let Some((token, _)) = column.rsplit_once(':')
+                && !token.ends_with(PREFIX_MARKER)
+            {
+                let query = Query::new([token.to_owned()].into(), QueryMode::All);
+                let count = self.ids(&query).await?.len();
+                if count > 0 {
+                    *counts.entry(token.to_owned()).or_default() += count;
+                }
+            }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+
+    ///
+    /// Describes how the query will be executed: the bucket that each token maps to, the filter
+    /// expression, and the number of row groups which can be skipped.
+    ///
+    pub fn explain(&self, query: &Query) -> String {
+        if query.expr.is_some() {
+            return self.explain_expr(query);
+        }
+        let mut lines = Vec::new();
+        let filter = self.filter(query);
+        if needs_expansion(query) {
+            let (groups, excluded) = self.expansions(query);
+            let sorted = |group: &HashSet<String>| {
+                let mut group = group.iter().cloned().collect::<Vec<_>>();
+                group.sort_unstable();
+                group
+            };
+            let terms = query
+                .tokens
+                .iter()
+                .filter(|_| query.fuzzy > 0)
+                .map(|token| format!("fuzzy {token:?}"))
+                .zip(&groups);
+            let patterns = query
+                .patterns
+                .iter()
+                .map(|pattern| format!("pattern {pattern:?}"))
+                .zip(&groups[query.tokens.len()..]);
+            let excluded_patterns = query
+                .excluded_patterns
+                .iter()
+                .map(|pattern| format!("excluded pattern {pattern:?}"))
+                .zip(&excluded);
+            let mut expansions = terms
+                .chain(patterns)
+                .chain(excluded_patterns)
+                .map(|(term, group)| format!("{term} -> {:?}", sorted(group)))
+                .collect::<Vec<_>>();
+            expansions.sort_unstable();
+            lines.extend(expansions);
+        }
+        let expanded = self.expanded(query);
+        let query = &*expanded;
+        let mut tokens = query
+            .tokens
+            .iter()
+            .map(|token| ("token", token))
+            .chain(query.excluded.iter().map(|token| ("excluded", token)))
+            .collect::<Vec<_>>();
+        tokens.sort_unstable();
+
+        for (kind, token) in tokens {
+            lines.push(self.explain_token(kind, token));
+        }
+        let (costs, candidate_rows) = self.token_costs(query);
+        for (position, cost) in costs.iter().enumerate() {
+            let evaluation = match cost.evaluation {
+                Evaluation::Column => "column",
+                Evaluation::PostingList => "posting list",
+            };
+            lines.push(format!(
+                "plan: {}. {:?} via {evaluation} (~{} of {candidate_rows} candidate rows, cost \
+                {:.0})",
+                position + 1,
+                cost.token,
+                cost.matches,
+                cost.cost,
+            ));
+        }
+        lines.push(format!("filter: {filter}"));
+        let (pruned, total) = self.pruned_row_groups(query);
+        lines.push(format!("pruned {pruned} of {total} row groups"));
+        lines.push(LATE_MATERIALIZATION.to_owned());
+        lines.join("\n")
+    }
+
+    ///
+    /// Creates a single filter for the query, which matches exactly the query's matches in an
+    /// index without posting lists.
+    ///
+    /// For a query which ORs posting lists, this is the filter for the rows which are not in any
+    /// of them. Likewise, for an expanded query which ANDs its tokens, each token's filter only
+    /// applies to the rows which are not in the posting lists of its expansions, and for a nested
+    /// query, the filter applies to the rows which are not in the posting lists of its tokens.
+    ///
+    fn filter(&self, query: &Query) -> ExprRef {
+        self.simplified(self.unsimplified_filter(query))
+    }
+
+    fn simplified(&self, filter: ExprRef) -> ExprRef {
+        simplify(filter, &self.bucket_columns)
+    }
+
+    fn unsimplified_filter(&self, query: &Query) -> ExprRef {
+        let expanded = self.expanded(query);
+        if let Some(expr) = &expanded.expr {
+            return self.expr_filter(expr, &HashMap::new()).into_expr();
+        }
+        let groups = if needs_expansion(query) {
+            self.expansions(query).0
+        } else {
+            Vec::new()
+        };
+        match self.indexed(&expanded) {
+            Some(_) if query.mode == QueryMode::All && groups.len() > 1 => self
+                .expansion_filters(groups)
+                .into_iter()
+                .map(|(filter, buckets)| {
+                    filter.unwrap_or_else(|| vortex_expr::lit(!buckets.is_empty()))
+                })
+                .chain(self.exclusions_filter(&expanded).0)
+                .reduce(vortex_expr::and)
+                .unwrap_or_else(|| vortex_expr::lit(false)),
+            Some(query) => {
+                let (columns, tokens, _) = self.partition_posting_lists(&query);
+                let no_tokens = query.mode == QueryMode::All && !tokens.is_empty();
+                self.create_filter(&columns, vortex_expr::lit(no_tokens))
+            }
+            None => vortex_expr::lit(false),
+        }
+    }
+
+    ///
+    /// Describes a nested query: the bucket that each of its tokens maps to (after resolving its
+    /// patterns and fuzzy tokens), and its filter.
+    ///
+    fn explain_expr(&self, query: &Query) -> String {
+        let expanded = self.expanded(query);
+        let Some(expr) = &expanded.expr else {
+            return String::new();
+        };
+        let mut tokens = expr.tokens().collect::<Vec<_>>();
+        tokens.sort_unstable();
+        tokens.dedup();
+        let mut lines = tokens
+            .into_iter()
+            .map(|token| self.explain_token("token", token))
+            .collect::<Vec<_>>();
+        lines.push(format!("filter: {}", self.filter(query)));
+        let (pruned, total) = self.pruned_row_groups(query);
+        lines.push(format!("pruned {pruned} of {total} row groups"));
+        lines.push(LATE_MATERIALIZATION.to_owned());
+        lines.join("\n")
+    }
+
+    ///
+    /// Estimates the cost of evaluating each token of the query from the row group statistics of
+    /// the row groups which it cannot prune, and returns the estimates in the order that the
+    /// tokens are evaluated, along with the number of candidate rows.
+    ///
+    /// A token stored as a posting list costs a fraction of each of the rows in its posting list.
+    /// Other tokens are evaluated by filtering each candidate row by their bucket column, which
+    /// costs more for a Multi bucket. Since the filter for an AND short-circuits, its tokens are
+    /// evaluated in ascending order of their cost divided by the share of rows that they reject,
+    /// which (unlike ordering by the bucket frequencies of the whole index) accounts for tokens
+    /// which are common overall but rare in the candidate rows, or vice versa.
+    ///
+    fn token_costs(&self, query: &Query) -> (Vec<TokenCost>, u64) {
+        let mut candidate_rows = 0;
+        let mut frequencies = vec![0; self.bucket_columns.len()];
+        for segment in &self.segments {
+            let Some(row_groups) = &segment.row_groups else {
+                candidate_rows += segment.row_count;
+                frequencies.iter_mut().for_each(|f| *f += segment.row_count);
+                continue;
+            };
+            for row_group in row_groups
+                .iter()
+                .filter(|row_group| row_group.might_match(&self.bucket_columns, query))
+            {
+                candidate_rows += row_group.rows;
+                if row_group.document_frequencies.is_empty() {
+                    frequencies.iter_mut().for_each(|f| *f += row_group.rows);
+                }
+                for (frequency, rows) in frequencies.iter_mut().zip(&row_group.document_frequencies)
+                {
+                    *frequency += rows;
+                }
+            }
+        }
+
+        let mut costs = query
+            .tokens
+            .iter()
+            .map(|token| {
+                let (bucket, btype) = find_bucket(&self.bucket_columns, token);
+                let (evaluation, matches, cost) = if self.posting_buckets[bucket] {
+                    let matches = self
+                        .segments
+                        .iter()
+                        .filter_map(|segment| segment.postings.get(&bucket))
+                        .map(RoaringBitmap::len)
+                        .sum::<u64>();
+                    let cost = matches as f64 * POSTING_LIST_ROW_COST;
+                    (Evaluation::PostingList, matches, cost)
+                } else {
+                    let row_cost = match btype {
+                        BucketType::Single => 1.0,
+                        BucketType::Multi => MULTI_BUCKET_ROW_COST,
+                    };
+                    let matches = frequencies[bucket].min(candidate_rows);
+                    (
+                        Evaluation::Column,
+                        matches,
+                        candidate_rows as f64 * row_cost,
+                    )
+                };
+                let rejected = 1.0 - matches as f64 / candidate_rows.max(1) as f64;
+                TokenCost {
+                    token: token.clone(),
+                    bucket,
+                    evaluation,
+                    matches,
+                    cost,
+                    rank: cost / rejected,
+                }
+            })
+            .collect::<Vec<_>>();
+        costs.sort_unstable_by(|a, b| {
+            a.rank
+                .total_cmp(&b.rank)
+                .then_with(|| a.token.cmp(&b.token))
+        });
+        (costs, candidate_rows)
+    }
+
+    ///
+    /// Ranks the buckets of the query's tokens in the order that `token_costs` evaluates them.
+    /// Buckets without any of the query's tokens are ranked last.
+    ///
+    fn bucket_ranks(&self, query: &Query) -> Vec<u64> {
+        let mut ranks = vec![u64::MAX; self.bucket_columns.len()];
+        for (rank, cost) in self.token_costs(query).0.into_iter().enumerate() {
+            let rank = rank as u64;
+            ranks[cost.bucket] = ranks[cost.bucket].min(rank);
+        }
+        ranks
+    }
+
+    ///
+    /// Describes the bucket that a token maps to.
+    ///
+    fn explain_token(&self, kind: &str, token: &str) -> String {
+        let (idx, btype) = find_bucket(&self.bucket_columns, token);
+        let never_indexed = if self.might_contain(token) {
+            ""
+        } else {
+            ", never indexed"
+        };
+        let posting_list = if self.posting_buckets[idx] {
+            ", posting list"
+        } else {
+            ""
+        };
+        format!(
+            "{kind} {token:?} -> {} ({btype:?}, in {} docs{never_indexed}{posting_list})",
+            self.bucket_columns[idx], self.bucket_frequencies[idx]
+        )
+    }
+
+    ///
+    /// Returns the number of row groups which can be skipped for the query, and the total number
+    /// of row groups. Segments without row group statistics are counted as a single row group.
+    ///
+    pub fn pruned_row_groups(&self, query: &Query) -> (usize, usize) {
+        let query = self.expanded(query);
+        let mut pruned = 0;
+        let mut total = 0;
+        for segment in &self.segments {
+            let Some(row_groups) = &segment.row_groups else {
+                total += 1;
+                continue;
+            };
+            total += row_groups.len();
+            pruned += row_groups
+                .iter()
+                .filter(|row_group| !row_group.might_match(&self.bucket_columns, &query))
+                .count();
+        }
+        (pruned, total)
+    }
+
+    ///
+    /// Scans all segments concurrently with a filter for the query and the given projection, and
+    /// returns the result of applying `f` to each (non-empty) batch. Row groups which cannot
+    /// match the query are skipped.
+    ///
+    async fn scan<T: Send + 'static>(
+        &self,
+        query: &Query,
+        projection: ExprRef,
+        f: impl Fn(ArrayRef) -> VortexResult<T> + Clone + Send + Sync + 'static,
+    ) -> anyhow::Result<Vec<T>> {
+        let splits = self.plan(query);
+        self.scan_splits(splits, projection, f).await
+    }
+
+    ///
+    /// Sets up a scan for the query: prunes the row groups which cannot match it, and splits
+    /// the remaining rows by the filter that they must be scanned with. Deleted documents are
+    /// always excluded.
+    ///
+    /// The posting lists of the query's tokens are evaluated first, so that only the rows which
+    /// they select are scanned. For an AND, the rows in all of them are scanned with the filter
+    /// for the remaining tokens. For an OR, the rows in any of them only need to be checked for
+    /// excluded tokens, and the other rows are scanned with the filter for the remaining tokens.
+    ///
+    #[tracing::instrument(skip_all)]
+    fn plan(&self, query: &Query) -> Vec<Split<'_>> {
+        if query.expr.is_some() {
+            return self.plan_expr(query);
+        }
+        if needs_expansion(query) {
+            return self.plan_expanded(query);
+        }
+        let Some(query) = self.indexed(query) else {
+            return Vec::new();
+        };
+        let (columns, tokens, excluded) = self.partition_posting_lists(&query);
+        let has_predicates = |q: &Query| {
+            !q.tokens.is_empty()
+                || !q.excluded.is_empty()
+                || q.timestamps.is_some()
+                || q.near.is_some()
+        };
+        let mut splits = Vec::new();
+        if tokens.is_empty() && excluded.is_empty() {
+            let filter = self.create_filter(&columns, vortex_expr::lit(false));
+            for segment in &self.segments {
+                splits.extend(
+                    segment
+                        .row_ranges(&self.bucket_columns, &query)
+                        .into_iter()
+                        .map(|range| Split {
+                            segment,
+                            rows: SplitRows::Range(range),
+                            filter: segment.filter(Some(filter.clone())),
+                        }),
+                );
+            }
+            return splits;
+        }
+
+        let matched_filter = match query.mode {
+            QueryMode::All => has_predicates(&columns)
+                .then(|| self.create_filter(&columns, vortex_expr::lit(true))),
+            QueryMode::Any => {
+                let exclusions = Query {
+                    tokens: HashSet::new(),
+                    ..columns.clone()
+                };
+                has_predicates(&exclusions)
+                    .then(|| self.create_filter(&exclusions, vortex_expr::lit(true)))
+            }
+        };
+        let unmatched_filter = (query.mode == QueryMode::Any && !columns.tokens.is_empty())
+            .then(|| self.create_filter(&columns, vortex_expr::lit(false)));
+        for segment in &self.segments {
+            let union = |buckets: &[usize]| {
+                buckets
+                    .iter()
+                    .filter_map(|idx| segment.postings.get(idx))
+                    .fold(RoaringBitmap::new(), |rows, bitmap| rows | bitmap)
+            };
+            let mut candidates = RoaringBitmap::new();
+            for range in segment.row_ranges(&self.bucket_columns, &query) {
+                candidates.insert_range(range.start as u32..range.end as u32);
+            }
+            candidates -= union(&excluded);
+            match query.mode {
+                QueryMode::All => {
+                    let matched = tokens.iter().fold(candidates, |rows, idx| {
+                        match segment.postings.get(idx) {
+                            Some(bitmap) => rows & bitmap,
+                            None => RoaringBitmap::new(),
+                        }
+                    });
+                    splits.extend(segment.split(matched, matched_filter.clone()));
+                }
+                QueryMode::Any => {
+                    let matched = union(&tokens);
+                    splits.extend(segment.split(&candidates & &matched, matched_filter.clone()));
+                    if let Some(filter) = &unmatched_filter {
+                        splits.extend(segment.split(candidates - matched, Some(filter.clone())));
+                    }
+                }
+            }
+        }
+        splits
+    }
+
+    ///
+    /// Sets up a scan for a fuzzy or pattern query. In `Any` mode (or for a single token or
+    /// pattern) the query matches any of the expansions of its tokens and patterns, and is
+    /// planned as such. Otherwise each token (or pattern) must
+    /// match one of its expansions: rows in the posting lists of a token's expansions match it,
+    /// and the remaining rows must be filtered for its other expansions. The candidate rows are
+    /// split by which tokens they match via posting lists, so that each split is only filtered
+    /// for the tokens which it does not.
+    ///
+    fn plan_expanded(&self, query: &Query) -> Vec<Split<'_>> {
+        let (groups, excluded) = self.expansions(query);
+        let expanded = expanded_query(query, &groups, &excluded);
+        if query.mode == QueryMode::Any || groups.len() <= 1 {
+            return self.plan(&expanded);
+        }
+        if groups.iter().any(HashSet::is_empty) {
+            return Vec::new();
+        }
+        let groups = self.expansion_filters(groups);
+        let (exclusions, excluded) = self.exclusions_filter(&expanded);
+
+        let mut splits = Vec::new();
+        if excluded.is_empty() && groups.iter().all(|(_, buckets)| buckets.is_empty()) {
+            let filter = groups
+                .into_iter()
+                .filter_map(|(filter, _)| filter)
+                .chain(exclusions)
+                .reduce(vortex_expr::and)
+                .map(|filter| self.simplified(filter));
+            for segment in &self.segments {
+                splits.extend(
+                    segment
+                        .row_ranges(&self.bucket_columns, &expanded)
+                        .into_iter()
+                        .map(|range| Split {
+                            segment,
+                            rows: SplitRows::Range(range),
+                            filter: segment.filter(filter.clone()),
+                        }),
+                );
+            }
+            return splits;
+        }
+
+        for segment in &self.segments {
+            let union = |buckets: &[usize]| {
+                buckets
+                    .iter()
+                    .filter_map(|idx| segment.postings.get(idx))
+                    .fold(RoaringBitmap::new(), |rows, bitmap| rows | bitmap)
+            };
+            let mut candidates = RoaringBitmap::new();
+            for range in segment.row_ranges(&self.bucket_columns, &expanded) {
+                candidates.insert_range(range.start as u32..range.end as u32);
+            }
+            candidates -= union(&excluded);
+            // Each part of the candidates has the filters of the tokens that it must be checked
+            // for, since it is not in any of their posting lists.
+            let mut parts = vec![(candidates, Vec::new())];
+            for (filter, buckets) in &groups {
+                let matched = union(buckets);
+                let mut next = Vec::with_capacity(parts.len() * 2);
+                for (rows, filters) in parts {
+                    if let Some(filter) = filter {
+                        let mut unmatched_filters = filters.clone();
+                        unmatched_filters.push(filter.clone());
+                        next.push((&rows - &matched, unmatched_filters));
+                    }
+                    next.push((rows & &matched, filters));
+                }
+                next.retain(|(rows, _)| !rows.is_empty());
+                parts = next;
+            }
+            for (rows, filters) in parts {
+                let filter = filters
+                    .into_iter()
+                    .chain(exclusions.clone())
+                    .reduce(vortex_expr::and);
+                splits.extend(segment.split(rows, filter));
+            }
+        }
+        splits
+    }
+
+    ///
+    /// Sets up a scan for a nested query. The candidate rows of each segment are split by which
+    /// of the posting lists of the query's tokens they are in, so that each split is scanned with
+    /// the filter for the query given those tokens. A split whose filter is constant is either
+    /// skipped, or counted without scanning it.
+    ///
+    fn plan_expr(&self, query: &Query) -> Vec<Split<'_>> {
+        let query = self.expanded(query);
+        let Some(expr) = &query.expr else {
+            return Vec::new();
+        };
+        let mut buckets = expr
+            .tokens()
+            .map(|token| find_bucket(&self.bucket_columns, token).0)
+            .filter(|idx| self.posting_buckets[*idx])
+            .collect::<Vec<_>>();
+        buckets.sort_unstable();
+        buckets.dedup();
+
+        let mut splits = Vec::new();
+        if buckets.is_empty() {
+            let filter = match self.expr_filter(expr, &HashMap::new()) {
+                ExprFilter::Constant(false) => return splits,
+                ExprFilter::Constant(true) => None,
+                ExprFilter::Filter(filter) => Some(self.simplified(filter)),
+            };
+            for segment in &self.segments {
+                splits.extend(
+                    segment
+                        .row_ranges(&self.bucket_columns, &query)
+                        .into_iter()
+                        .map(|range| Split {
+                            segment,
+                            rows: SplitRows::Range(range),
+                            filter: segment.filter(filter.clone()),
+                        }),
+                );
+            }
+            return splits;
+        }
+
+        for segment in &self.segments {
+            let mut candidates = RoaringBitmap::new();
+            for range in segment.row_ranges(&self.bucket_columns, &query) {
+                candidates.insert_range(range.start as u32..range.end as u32);
+            }
+            let mut parts = vec![(candidates, HashMap::new())];
+            for idx in &buckets {
+                let matched = segment.postings.get(idx).cloned().unwrap_or_default();
+                let mut next = Vec::with_capacity(parts.len() * 2);
+                for (rows, postings) in parts {
+                    let mut unmatched_postings = postings.clone();
+                    unmatched_postings.insert(*idx, false);
+                    next.push((&rows - &matched, unmatched_postings));
+                    let mut matched_postings = postings;
+                    matched_postings.insert(*idx, true);
+                    next.push((rows & &matched, matched_postings));
+                }
+                next.retain(|(rows, _)| !rows.is_empty());
+                parts = next;
+            }
+            for (rows, postings) in parts {
+                match self.expr_filter(expr, &postings) {
+                    ExprFilter::Constant(false) => {}
+                    ExprFilter::Constant(true) => splits.extend(segment.split(rows, None)),
+                    ExprFilter::Filter(filter) => {
+                        splits.extend(segment.split(rows, Some(self.simplified(filter))))
+                    }
+                }
+            }
+        }
+        splits
+    }
+
+    ///
+    /// Counts the rows of the given splits which match their filters, without scanning the
+    /// splits which do not have one.
+    ///
+    async fn count_splits(&self, splits: Vec<Split<'_>>) -> anyhow::Result<usize> {
+        let (scanned, unfiltered): (Vec<_>, Vec<_>) =
+            splits.into_iter().partition(|split| split.filter.is_some());
+        let unfiltered = unfiltered
+            .iter()
+            .map(|split| split.rows.len())
+            .sum::<usize>();
+        let projection = vortex_expr::select(Vec::<FieldName>::new(), vortex_expr::ident());
+        let counts = self
+            .scan_splits(scanned, projection, |array| Ok(array.len()))
+            .await?;
+        Ok(counts.into_iter().sum::<usize>() + unfiltered)
+    }
+
+    ///
+    /// Narrows the given splits to the row groups which contain matches, by counting the
+    /// matches of each row group with an empty projection (which only reads the columns that
+    /// its filter references). Splits without a filter match all of their rows, and are kept.
+    ///
+    #[tracing::instrument(skip_all, fields(splits = splits.len()))]
+    async fn passing_splits<'a>(&self, splits: Vec<Split<'a>>) -> anyhow::Result<Vec<Split<'a>>> {
+        let (filtered, mut passing): (Vec<_>, Vec<_>) =
+            splits.into_iter().partition(|split| split.filter.is_some());
+        let row_groups = filtered
+            .into_iter()
+            .flat_map(Split::by_row_group)
+            .collect::<Vec<_>>();
+        let counts = future::try_join_all(
+            row_groups
+                .iter()
+                .map(|split| self.count_splits(vec![split.clone()])),
+        )
+        .await?;
+        passing.extend(
+            row_groups
+                .into_iter()
+                .zip(counts)
+                .filter(|(_, count)| *count > 0)
+                .map(|(split, _)| split),
+        );
+        Ok(passing)
+    }
+
+    ///
+    /// Counts the matches for the query, along with the work done to evaluate it. The bytes read
+    /// are measured by a counter which is shared by the whole index, so they include the reads
+    /// of any concurrent queries.
+    ///
+    #[tracing::instrument(name = "vortex_count", skip_all)]
+    pub async fn count_with_stats(&self, query: &Query) -> anyhow::Result<(usize, ScanStats)> {
+        let splits = self.plan(query);
+        // NB: The splits of a segment may share row groups, so they are deduplicated by segment.
+        let row_groups = splits
+            .iter()
+            .filter(|split| split.filter.is_some())
+            .flat_map(|split| {
+                let segment = std::ptr::from_ref(split.segment);
+                let ordinals = split.segment.row_groups_overlapping(&split.rows);
+                ordinals.into_iter().map(move |ordinal| (segment, ordinal))
+            })
+            .collect::<HashSet<_>>()
+            .len();
+        let rows = splits
+            .iter()
+            .filter(|split| split.filter.is_some())
+            .map(|split| split.rows.len() as u64)
+            .sum();
+        let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+        let count = self.count_splits(splits).await?;
+        let stats = ScanStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed) - bytes_read,
+            row_groups,
+            rows,
+        };
+        Ok((count, stats))
+    }
+
+    ///
+    /// Scans the given splits concurrently with the given projection, and returns the result of
+    /// applying `f` to each (non-empty) batch.
+    ///
+    #[tracing::instrument(skip_all, fields(splits = splits.len()))]
+    async fn scan_splits<T: Send + 'static>(
+        &self,
+        splits: Vec<Split<'_>>,
+        projection: ExprRef,
+        f: impl Fn(ArrayRef) -> VortexResult<T> + Clone + Send + Sync + 'static,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut tasks = Vec::new();
+        for split in self.divide_splits(splits) {
+            tasks.extend(
+                split
+                    .scan_builder(projection.clone())
+                    .map(f.clone())
+                    .build()?,
+            );
+        }
+        let results = match self.scan_concurrency {
+            Some(concurrency) => {
+                futures_util::stream::iter(tasks)
+                    .buffered(concurrency)
+                    .try_collect::<Vec<_>>()
+                    .await?
+            }
+            None => future::try_join_all(tasks).await?,
+        };
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    ///
+    /// Counts the matches for each of a batch of queries with a single scan of the index, whose
+    /// projection has one boolean column per query, and returns the counts along with the time
+    /// spent setting the scan up. Only the row groups which might match at least one of the
+    /// queries are scanned.
+    ///
+    /// Since posting lists select rows for a single query, an index with posting lists cannot
+    /// be searched in batches.
+    ///
+    pub async fn count_batch(&self, queries: &[Query]) -> anyhow::Result<(Vec<usize>, Duration)> {
+        let start = Instant::now();
+        if self
+            .posting_buckets
+            .iter()
+            .any(|posting_list| *posting_list)
+        {
+            bail!("An index with posting lists cannot be searched in batches.");
+        }
+        if queries.is_empty() {
+            return Ok((Vec::new(), start.elapsed()));
+        }
+        let expanded = queries
+            .iter()
+            .map(|query| self.expanded(query))
+            .collect::<Vec<_>>();
+        let projection = vortex_expr::pack(
+            (0..queries.len())
+                .map(|idx| FieldName::from(idx.to_string()))
+                .collect::<Vec<_>>(),
+            queries.iter().map(|query| self.filter(query)).collect(),
+            Nullability::NonNullable,
+        );
+        let mut splits = Vec::new();
+        for segment in &self.segments {
+            let ranges = segment.row_ranges_matching(|row_group| {
+                expanded
+                    .iter()
+                    .any(|query| row_group.might_match(&self.bucket_columns, query))
+            });
+            splits.extend(ranges.into_iter().map(|range| Split {
+                segment,
+                rows: SplitRows::Range(range),
+                filter: segment.filter(None),
+            }));
+        }
+        let setup = start.elapsed();
+
+        let batches = self
+            .scan_splits(splits, projection, |array| counts_batch(&array))
+            .await?;
+        let mut counts = vec![0; queries.len()];
+        for batch in batches {
+            for (count, matches) in counts.iter_mut().zip(batch) {
+                *count += matches;
+            }
+        }
+        Ok((counts, setup))
+    }
+
+    ///
+    /// Returns the IDs of up to `limit` matches for the query, without scoring them. Rather than
+    /// scanning all of the rows which might match concurrently, the splits of the plan are
+    /// scanned in order (with the tasks of each split executing concurrently), and the scan stops
+    /// as soon as `limit` matches have been observed.
+    ///
+    pub async fn first_ids(&self, query: &Query, limit: usize) -> anyhow::Result<Vec<u64>> {
+        let mut ids = Vec::with_capacity(limit);
+        if limit == 0 {
+            return Ok(ids);
+        }
+        let concurrency = self
+            .scan_concurrency
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        for split in self.divide_splits(self.plan(query)) {
+            let tasks = split
+                .scan_builder(vortex_expr::get_item(ID_COLUMN, vortex_expr::ident()))
+                .map(|array| Ok(array.to_primitive()?.as_slice::<u64>().to_vec()))
+                .build()?;
+            // NB: Dropping the stream cancels the tasks which have not completed.
+            let mut batches = futures_util::stream::iter(tasks).buffered(concurrency);
+            while let Some(batch) = batches.next().await {
+                ids.extend(batch?.into_iter().flatten());
+                if ids.len() >= limit {
+                    ids.truncate(limit);
+                    return Ok(ids);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    ///
+    /// Returns the IDs and scores of the (up to) `k` highest scoring matches for the query.
+    ///
+    /// Scores are computed with BM25, minus document length normalization. The inverse document
+    /// frequency of each query token is computed with an additional count per token.
+    ///
+    /// The row groups which might match are scanned in descending order of the best score that
+    /// any of their rows could have (from the maximum term frequency of each bucket in each row
+    /// group, in the style of block-max WAND), a wave of concurrent scans at a time. Once `k`
+    /// matches have been scored, the row groups which cannot beat the lowest of them are skipped.
+    ///
+    #[tracing::instrument(name = "vortex_top_k", skip_all, fields(k = k))]
+    pub async fn top_k(&self, query: &Query, k: usize) -> anyhow::Result<Vec<(u64, f64)>> {
+        if !self.term_frequencies {
+            bail!("The index was not built with term frequencies, and so cannot be ranked.");
+        }
+
+        let doc_count = self
+            .segments
+            .iter()
+            .map(|segment| segment.row_count)
+            .sum::<u64>() as f64;
+        // NB: The expansions of the tokens of a fuzzy query are each scored.
+        let expanded = self.expanded(query);
+        let tokens = expanded.all_tokens().collect::<HashSet<_>>();
+        let mut terms = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let (idx, btype) = find_bucket(&self.bucket_columns, token);
+            let doc_frequency = self
+                .count(&Query::new([token.clone()].into(), QueryMode::All))
+                .await? as f64;
+            // NB: A token which is missing from the dictionary cannot match any document.
+            let needle = match &self.dictionary {
+                Some(dictionary) => dictionary.code(token).map(Scalar::from),
+                None => Some(token.clone().into()),
+            };
+            terms.push(ScoredTerm {
+                needle,
+                bucket: idx,
+                column: self.bucket_columns[idx].clone(),
+                btype,
+                idf: (1.0 + (doc_count - doc_frequency + 0.5) / (doc_frequency + 0.5)).ln(),
+            });
+        }
+
+        // Project the ID column, and the bucket and term frequency columns for each term.
+        let mut projection: Vec<FieldName> = vec![ID_COLUMN.into()];
+        for term in &terms {
+            let tf_column: FieldName = tf_column_name(&term.column).into();
+            if !projection.contains(&tf_column) {
+                projection.push(term.column.clone());
+                projection.push(tf_column);
+            }
+        }
+
+        let projection = vortex_expr::select(projection, vortex_expr::ident());
+
+        let mut splits = self
+            .plan(query)
+            .into_iter()
+            .flat_map(Split::by_row_group)
+            .map(|split| (split.max_score(&terms), split))
+            .collect::<Vec<_>>();
+        splits.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        let concurrency = self
+            .scan_concurrency
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let terms = Arc::new(terms);
+        let mut scored = Vec::with_capacity(k);
+        let mut splits = splits.into_iter();
+        while k > 0 {
+            // NB: Since the splits are sorted by their maximum scores, once one of them cannot
+            // beat the lowest of the top k, none of the remaining splits can either.
+            let threshold = (scored.len() == k).then(|| scored[k - 1].1);
+            let wave = splits
+                .by_ref()
+                .take(concurrency)
+                .take_while(|(max_score, _)| {
+                    threshold.is_none_or(|threshold| *max_score > threshold)
+                })
+                .map(|(_, split)| split)
+                .collect::<Vec<_>>();
+            if wave.is_empty() {
+                break;
+            }
+            let terms = terms.clone();
+            let batches = self
+                .scan_splits(wave, projection.clone(), move |array| {
+                    score_batch(&array, &terms)
+                })
+                .await?;
+            scored.extend(batches.into_iter().flatten());
+            scored.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+            scored.truncate(k);
+        }
+        Ok(scored)
+    }
+
+    ///
+    /// Returns the IDs of the (up to) `k` matches for the query which contain the most query
+    /// tokens, along with the number of tokens that each contains.
+    ///
+    /// Rather than projecting `lit(true)` for each match, the scan projects one predicate per
+    /// token, and sums them per row. Combined with `QueryMode::Any`, this allows for weak-AND
+    /// style ranking without term frequencies. The expansions of the tokens of a fuzzy query are
+    /// each counted.
+    ///
+    #[tracing::instrument(name = "vortex_match_counts", skip_all, fields(k = k))]
+    pub async fn match_counts(&self, query: &Query, k: usize) -> anyhow::Result<Vec<(u64, usize)>> {
+        if self
+            .posting_buckets
+            .iter()
+            .any(|posting_list| *posting_list)
+        {
+            bail!("An index with posting lists cannot be searched for match counts.");
+        }
+        let expanded = self.expanded(query);
+        let mut tokens = expanded.all_tokens().cloned().collect::<Vec<_>>();
+        tokens.sort_unstable();
+        tokens.dedup();
+
+        let mut names: Vec<FieldName> = vec![ID_COLUMN.into()];
+        let mut exprs = vec![vortex_expr::get_item(ID_COLUMN, vortex_expr::ident())];
+        for (idx, token) in tokens.into_iter().enumerate() {
+            names.push(idx.to_string().into());
+            exprs.push(self.filter(&Query::new([token].into(), QueryMode::All)));
+        }
+        let projection = vortex_expr::pack(names, exprs, Nullability::NonNullable);
+
+        let batches = self
+            .scan(query, projection, |array| match_counts_batch(&array))
+            .await?;
+
+        let mut counted = batches.into_iter().flatten().collect::<Vec<_>>();
+        counted.sort_unstable_by(|(a_id, a), (b_id, b)| b.cmp(a).then(a_id.cmp(b_id)));
+        counted.truncate(k);
+        Ok(counted)
+    }
+}
+
+///
+/// A column which `search_stream` can project for each hit, in addition to its ID.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HitColumn {
+    /// The original text of the document. Requires an index built with stored bodies.
+    Body,
+    /// The timestamp of the document, which is null for a document without one. Requires an
+    /// index built from a corpus with timestamps.
+    Timestamp,
+    /// The number of tokens that the document was indexed with, counting repeats. Requires an
+    /// index built with document lengths.
+    Length,
+}
+
+///
+/// Opens the index at `path`, and returns a stream of the documents which match the query. Each
+/// array of the stream is a struct with an `id` column, followed by a column for each of the
+/// given `columns` (named `body`, `timestamp` or `length`).
+///
+/// The splits of the query's plan are scanned in order as the stream is polled, so hits are
+/// yielded incrementally rather than being buffered. The order of the hits is unspecified.
+///
+pub async fn search_stream(
+    path: &Path,
+    query: Query,
+    columns: &[HitColumn],
+) -> anyhow::Result<impl ArrayStream + Send + Unpin> {
+    let searcher = VortexSearcher::open_with_io(path, Io::default()).await?;
+    crate::Searcher::check_query(&searcher, &query)?;
+
+    let mut names = vec![FieldName::from("id")];
+    let mut exprs = vec![vortex_expr::get_item(ID_COLUMN, vortex_expr::ident())];
+    let mut dtypes = vec![DType::Primitive(PType::U64, Nullability::NonNullable)];
+    for column in columns {
+        let (name, column_name, dtype) = match column {
+            HitColumn::Body if !searcher.bodies => {
+                bail!("The index was not built with stored bodies.")
+            }
+            HitColumn::Timestamp if !searcher.timestamps => {
+                bail!("The index was not built from a corpus with timestamps.")
+            }
+            HitColumn::Length if !searcher.doc_lengths => {
+                bail!("The index was not built with document lengths.")
+            }
+            HitColumn::Body => ("body", BODY_COLUMN, DType::Utf8(Nullability::NonNullable)),
+            HitColumn::Timestamp => (
+                "timestamp",
+                TIMESTAMP_COLUMN,
+                DType::Primitive(PType::U64, Nullability::Nullable),
+            ),
+            HitColumn::Length => (
+                "length",
+                LENGTH_COLUMN,
+                DType::Primitive(PType::U32, Nullability::NonNullable),
+            ),
+        };
+        names.push(name.into());
+        exprs.push(vortex_expr::get_item(column_name, vortex_expr::ident()));
+        dtypes.push(dtype);
+    }
+    let dtype = DType::Struct(
+        StructDType::new(names.clone().into(), dtypes).into(),
+        Nullability::NonNullable,
+    );
+    let projection = vortex_expr::pack(names, exprs, Nullability::NonNullable);
+
+    let stream = stream! {
+        for split in searcher.plan(&query) {
+            let mut arrays = match split.scan_builder(projection.clone()).into_array_stream() {
+                Ok(arrays) => arrays,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            while let Some(array) = arrays.next().await {
+                yield array;
+            }
+        }
+    };
+    Ok(ArrayStreamAdapter::new(dtype, stream.boxed()))
+}
+
+///
+/// How the planner evaluates a token of a query.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Evaluation {
+    /// Each candidate row is filtered by the column of the token's bucket.
+    Column,
+    /// The rows in the token's posting list are intersected (or unioned) with the candidate rows.
+    PostingList,
+}
+
+///
+/// The planner's estimate of the cost of evaluating a token of a query.
+///
+struct TokenCost {
+    token: String,
+    bucket: usize,
+    evaluation: Evaluation,
+    /// An upper bound on the number of candidate rows which contain the token.
+    matches: u64,
+    cost: f64,
+    /// The cost of the token divided by the share of the candidate rows which it rejects: the
+    /// tokens of an AND are evaluated in ascending order of rank.
+    rank: f64,
+}
+
+struct ScoredTerm {
+    /// The token, or its dictionary code, as it appears in its bucket.
+    needle: Option<Scalar>,
+    /// The ordinal of the bucket, and the name of its column.
+    bucket: usize,
+    column: FieldName,
+    btype: BucketType,
+    idf: f64,
+}
+
+impl ScoredTerm {
+    const K1: f64 = 1.2;
+
+    fn score(&self, tf: u32) -> f64 {
+        let tf = tf as f64;
+        self.idf * (tf * (Self::K1 + 1.0)) / (tf + Self::K1)
+    }
+}
+
+///
+/// Computes a score for each row of a batch which has been projected to contain the ID column,
+/// and the bucket and term frequency columns of each of the given terms.
+///
+fn score_batch(batch: &ArrayRef, terms: &[ScoredTerm]) -> VortexResult<Vec<(u64, f64)>> {
+    let batch = batch.to_struct()?;
+    let mut scores = vec![0.0; batch.len()];
+    for term in terms {
+        let tfs = batch.field_by_name(&tf_column_name(&term.column))?;
+        match term.btype {
+            BucketType::Single => {
+                let tfs = tfs.to_primitive()?;
+                for (score, tf) in scores.iter_mut().zip(tfs.as_slice::<u32>()) {
+                    *score += term.score(*tf);
+                }
+            }
+            BucketType::Multi => {
+                let tokens = batch.field_by_name(&term.column)?;
+                for (row, score) in scores.iter_mut().enumerate() {
+                    let tokens = tokens.scalar_at(row)?;
+                    let Some(position) = tokens.as_list().elements().and_then(|tokens| {
+                        tokens.iter().position(|t| Some(t) == term.needle.as_ref())
+                    }) else {
+                        continue;
+                    };
+                    let tf = tfs.scalar_at(row)?.as_list().element(position);
+                    *score += term.score(tf.map(|tf| u32::try_from(&tf)).transpose()?.unwrap_or(0));
+                }
+            }
+        }
+    }
+
+    let ids = batch.field_by_name(ID_COLUMN)?.to_primitive()?;
+    Ok(ids.as_slice::<u64>().iter().copied().zip(scores).collect())
+}
+
+///
+/// Binary search on bucket column names (or for an index built with the `Hash` strategy, hash)
+/// to find the bucket that a token would have been placed in at index time.
+///
+pub(crate) fn find_bucket(bucket_columns: &[FieldName], token: &str) -> (usize, BucketType) {
+    if bucket_columns
+        .first()
+        .is_some_and(|column| column.starts_with(HASH_BUCKET_PREFIX))
+    {
+        return (hash_bucket(token, bucket_columns.len()), BucketType::Multi);
+    }
+    let needle: FieldName = BucketType::Single.column_name(token).into();
+    match bucket_columns.binary_search(&needle) {
+        Ok(idx) => (idx, BucketType::Single),
+        Err(0) => (0, BucketType::Multi),
+        Err(idx) => (idx - 1, BucketType::Multi),
+    }
+}
+
+///
+/// Returns the bucket columns which might contain tokens beginning with `prefix`: every bucket
+/// for an index built with the `Hash` strategy, and otherwise the bucket which the prefix itself
+/// falls into, followed by any buckets which begin with the prefix.
+///
+pub(crate) fn facet_columns(bucket_columns: &[FieldName], prefix: &str) -> Vec<FieldName> {
+    let (start, _) = find_bucket(bucket_columns, prefix);
+    if bucket_columns[start].starts_with(HASH_BUCKET_PREFIX) {
+        return bucket_columns.to_vec();
+    }
+    let following = bucket_columns[start + 1..]
+        .iter()
+        .take_while(|column| column.starts_with(prefix));
+    std::iter::once(&bucket_columns[start])
+        .chain(following)
+        .cloned()
+        .collect()
+}
+
+///
+/// Find the bins that we'll be scanning in, and create a filter which either ANDs or ORs the
+/// per-bucket predicates together, depending on the `QueryMode`. The negation of each bucket
+/// predicate for the excluded tokens is then ANDed in.
+///
+/// When the predicates are ANDed, they are ordered by the given rank of their buckets (lowest
+/// first), so that the predicate which is cheapest to evaluate relative to its selectivity is
+/// evaluated first. A query without any tokens has the filter `no_tokens`.
+///
+pub(crate) fn create_filter(
+    bucket_columns: &[FieldName],
+    bucket_exprs: &[ExprRef],
+    query: &Query,
+    dictionary: Option<&Dictionary>,
+    bucket_ranks: &[u64],
+    no_tokens: ExprRef,
+) -> ExprRef {
+    let mut included = bucket_predicates(
+        bucket_columns,
+        bucket_exprs,
+        &query.tokens,
+        query.mode,
+        dictionary,
+    )
+    .collect::<Vec<_>>();
+    if query.mode == QueryMode::All {
+        included.sort_by_key(|(idx, _)| bucket_ranks[*idx]);
+    }
+    let included = included
+        .into_iter()
+        .map(|(_, predicate)| predicate)
+        .reduce(match query.mode {
+            QueryMode::Any => vortex_expr::or,
+            QueryMode::All => vortex_expr::and,
+        })
+        .unwrap_or(no_tokens);
+    bucket_predicates(
+        bucket_columns,
+        bucket_exprs,
+        &query.excluded,
+        QueryMode::Any,
+        dictionary,
+    )
+    .map(|(_, predicate)| vortex_expr::not(predicate))
+    .fold(included, vortex_expr::and)
+}
+
+///
+/// Whether the tokens or patterns of the query must be expanded into the indexed tokens which
+/// they match before it can be executed.
+///
+fn needs_expansion(query: &Query) -> bool {
+    query.fuzzy > 0 || !query.patterns.is_empty() || !query.excluded_patterns.is_empty()
+}
+
+///
+/// Replaces the tokens and patterns of a query with all of the given expansions of them, in `Any`
+/// mode, and adds the expansions of its excluded patterns to its excluded tokens.
+///
+fn expanded_query(
+    query: &Query,
+    groups: &[HashSet<String>],
+    excluded: &[HashSet<String>],
+) -> Query {
+    Query {
+        tokens: groups.iter().flatten().cloned().collect(),
+        excluded: query
+            .excluded
+            .iter()
+            .chain(excluded.iter().flatten())
+            .cloned()
+            .collect(),
+        patterns: Vec::new(),
+        excluded_patterns: Vec::new(),
+        mode: QueryMode::Any,
+        fuzzy: 0,
+        ..query.clone()
+    }
+}
+
+///
+/// Sums the per-row-group document frequencies of each bucket. A segment which does not have
+/// them is assumed to be non-empty for every bucket in every row.
+///
+fn bucket_frequencies(segments: &[Segment], bucket_count: usize) -> Vec<u64> {
+    let mut frequencies = vec![0; bucket_count];
+    for segment in segments {
+        let row_groups = segment.row_groups.as_deref().unwrap_or_default();
+        if !row_groups.is_empty()
+            && row_groups
+                .iter()
+                .all(|row_group| row_group.document_frequencies.len() == bucket_count)
+        {
+            for row_group in row_groups {
+                for (total, frequency) in
+                    frequencies.iter_mut().zip(&row_group.document_frequencies)
+                {
+                    *total += frequency;
+                }
+            }
+        } else {
+            for total in frequencies.iter_mut() {
+                *total += segment.row_count;
+            }
+        }
+    }
+    frequencies
+}
+
+///
+/// Group the given tokens by bucket, and create one predicate per bucket which is true for
+/// documents containing all (or any) of that bucket's tokens.
+///
+/// In a dictionary-encoded index, Multi buckets are searched for the codes of the tokens, and
+/// tokens which are missing from the dictionary cannot match.
+///
+fn bucket_predicates(
+    bucket_columns: &[FieldName],
+    bucket_exprs: &[ExprRef],
+    tokens: &HashSet<String>,
+    mode: QueryMode,
+    dictionary: Option<&Dictionary>,
+) -> impl Iterator<Item = (usize, ExprRef)> {
+    let mut tokens_by_bucket: BTreeMap<usize, (BucketType, Vec<Option<Scalar>>)> = BTreeMap::new();
+    for token in tokens {
+        let (idx, btype) = find_bucket(bucket_columns, token);
+        let needle = match dictionary {
+            Some(dictionary) => dictionary.code(token).map(Scalar::from),
+            None => Some(token.clone().into()),
+        };
+        tokens_by_bucket
+            .entry(idx)
+            .or_insert_with(|| (btype, Vec::new()))
+            .1
+            .push(needle);
+    }
+
+    tokens_by_bucket
+        .into_iter()
+        .map(move |(idx, (btype, needles))| {
+            let get_item = bucket_exprs[idx].clone();
+            let any_missing = needles.iter().any(|needle| needle.is_none());
+            let mut needles = needles.into_iter().flatten().collect::<Vec<_>>();
+            let predicate = match btype {
+                // NB: Only the bucket's own token can be placed in a `Single` bucket.
+                BucketType::Single => get_item,
+                BucketType::Multi
+                    if needles.is_empty() || (any_missing && mode == QueryMode::All) =>
+                {
+                    vortex_expr::lit(false)
+                }
+                BucketType::Multi if needles.len() == 1 => {
+                    ListContainsExpr::new_expr(get_item, needles.pop().unwrap())
+                }
+                BucketType::Multi => ListContainsSetExpr::new_expr(get_item, needles, mode),
+            };
+            (idx, predicate)
+        })
 }
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::split_field;
+use crate::vortex::BucketType;
+
+///
+/// The prefix of the buckets created by the `Hash` strategy. Tokens have their non-alphanumeric
+/// characters trimmed, so no token can begin with it.
+///
+pub(crate) const HASH_BUCKET_PREFIX: &str = "#";
+
+///
+/// Selects the buckets of an index, given the document frequencies of (a sample of) the tokens
+/// in the corpus.
+///
+pub(crate) trait BucketStrategy {
+    fn select_buckets(
+        &self,
+        document_frequencies: BTreeMap<String, usize>,
+        bucket_count: u16,
+    ) -> Vec<(String, BucketType)>;
+}
+
+///
+/// The strategies which `VortexIndexer` can use to select buckets.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Ranges of tokens which each contain roughly the same number of token occurrences, plus a
+    /// Single bucket for each token which would fill an entire range.
+    #[default]
+    EqualFrequency,
+    /// Multi buckets which tokens are assigned to by hash.
+    Hash,
+    /// Single buckets for the most frequent tokens, with the remaining tokens divided into
+    /// equal-frequency ranges.
+    FrequencyTiered,
+    /// Ranges of tokens which each contain roughly the same number of distinct tokens,
+    /// regardless of their frequency.
+    Alphabetic,
+}
+
+impl Strategy {
+    pub(crate) fn bucket_strategy(&self) -> &'static dyn BucketStrategy {
+        match self {
+            Strategy::EqualFrequency => &EqualFrequencyBuckets,
+            Strategy::Hash => &HashBuckets,
+            Strategy::FrequencyTiered => &FrequencyTieredBuckets,
+            Strategy::Alphabetic => &AlphabeticBuckets,
+        }
+    }
+
+    ///
+    /// Selects buckets with this strategy. The tokens of each of the given named fields are
+    /// divided into their own group of buckets, with the buckets shared between the groups in
+    /// proportion to the total frequency of each group's tokens.
+    ///
+    /// The `Hash` strategy does not assign tokens by range, so it does not group them.
+    ///
+    /// A sample without any tokens (such as that of a corpus of empty documents) falls back to a
+    /// single Multi bucket, which holds every token.
+    ///
+    pub(crate) fn select_buckets(
+        &self,
+        document_frequencies: BTreeMap<String, usize>,
+        bucket_count: u16,
+        fields: &[String],
+    ) -> Vec<(String, BucketType)> {
+        let strategy = self.bucket_strategy();
+        if document_frequencies.is_empty() && *self != Strategy::Hash {
+            return vec![(String::new(), BucketType::Multi)];
+        }
+        if fields.is_empty() || *self == Strategy::Hash {
+            return strategy.select_buckets(document_frequencies, bucket_count);
+        }
+
+        let mut groups: BTreeMap<Option<String>, BTreeMap<String, usize>> = BTreeMap::new();
+        for (token, frequency) in document_frequencies {
+            let field = split_field(&token)
+                .map(|(field, _)| field.to_owned())
+                .filter(|field| fields.contains(field));
+            groups.entry(field).or_default().insert(token, frequency);
+        }
+        let total = groups
+            .values()
+            .flat_map(|group| group.values())
+            .sum::<usize>();
+        let mut buckets = groups
+            .into_values()
+            .flat_map(|group| {
+                let share = group.values().sum::<usize>() * bucket_count as usize / total.max(1);
+                strategy.select_buckets(group, (share as u16).max(1))
+            })
+            .collect::<Vec<_>>();
+        // NB: Each group begins with a Multi bucket, so the first bucket overall is still Multi.
+        buckets.sort_by_cached_key(|(token, btype)| btype.column_name(token));
+        buckets.dedup();
+        buckets
+    }
+}
+
+struct EqualFrequencyBuckets;
+
+impl BucketStrategy for EqualFrequencyBuckets {
+    fn select_buckets(
+        &self,
+        document_frequencies: BTreeMap<String, usize>,
+        bucket_count: u16,
+    ) -> Vec<(String, BucketType)> {
+        let pivots = equal_frequency_pivots(&document_frequencies, bucket_count);
+        // A token which was selected as a pivot more than once would fill an entire bucket.
+        let singles = pivots
+            .windows(2)
+            .filter(|pair| pair[0] == pair[1])
+            .map(|pair| pair[0].clone())
+            .collect::<Vec<_>>();
+        range_buckets(pivots, singles)
+    }
+}
+
+struct HashBuckets;
+
+impl BucketStrategy for HashBuckets {
+    fn select_buckets(
+        &self,
+        _: BTreeMap<String, usize>,
+        bucket_count: u16,
+    ) -> Vec<(String, BucketType)> {
+        (0..bucket_count.max(1))
+            .map(|idx| (format!("{HASH_BUCKET_PREFIX}{idx:05}"), BucketType::Multi))
+            .collect()
+    }
+}
+
+struct FrequencyTieredBuckets;
+
+impl BucketStrategy for FrequencyTieredBuckets {
+    fn select_buckets(
+        &self,
+        mut document_frequencies: BTreeMap<String, usize>,
+        bucket_count: u16,
+    ) -> Vec<(String, BucketType)> {
+        let mut by_frequency = document_frequencies
+            .iter()
+            .map(|(token, frequency)| (*frequency, token.clone()))
+            .collect::<Vec<_>>();
+        by_frequency.sort_unstable_by(|(a_frequency, a), (b_frequency, b)| {
+            b_frequency.cmp(a_frequency).then_with(|| a.cmp(b))
+        });
+
+        // Each hot token costs two buckets (its Single bucket and the Multi bucket which follows
+        // it), so half of the buckets go to the hottest quarter.
+        let hot = by_frequency
+            .into_iter()
+            .take((bucket_count / 4) as usize)
+            .map(|(_, token)| token)
+            .collect::<Vec<_>>();
+        for token in &hot {
+            document_frequencies.remove(token);
+        }
+        let pivots =
+            equal_frequency_pivots(&document_frequencies, bucket_count - 2 * hot.len() as u16);
+        range_buckets(pivots, hot)
+    }
+}
+
+struct AlphabeticBuckets;
+
+impl BucketStrategy for AlphabeticBuckets {
+    fn select_buckets(
+        &self,
+        document_frequencies: BTreeMap<String, usize>,
+        bucket_count: u16,
+    ) -> Vec<(String, BucketType)> {
+        let distinct = document_frequencies
+            .into_keys()
+            .map(|token| (token, 1))
+            .collect();
+        range_buckets(equal_frequency_pivots(&distinct, bucket_count), Vec::new())
+    }
+}
+
+///
+/// Selects `pivot_count` (possibly repeated) tokens which will roughly equally divide the total
+/// frequency of the tokens.
+///
+fn equal_frequency_pivots(frequencies: &BTreeMap<String, usize>, pivot_count: u16) -> Vec<String> {
+    let total = frequencies.values().sum::<usize>();
+    if total == 0 || pivot_count == 0 {
+        return Vec::new();
+    }
+    let bucket_width = total as f64 / pivot_count as f64;
+    let mut pivots = Vec::with_capacity(pivot_count as usize);
+    let mut end = 0;
+    for (token, frequency) in frequencies {
+        // Select the token once for each pivot position which falls within its share of the
+        // total frequency.
+        end += frequency;
+        while pivots.len() < pivot_count as usize
+            && (pivots.len() as f64 * bucket_width) < end as f64
+        {
+            pivots.push(token.clone());
+        }
+    }
+    pivots
+}
+///
+/// Creates a Multi bucket for the range of tokens starting at each pivot, and a Single bucket
+/// for each of the given tokens, sorted by column name.
+///
+/// Each Single bucket is followed by a Multi bucket starting at the same token, so that tokens
+/// which sort after it fall into a Multi bucket. Likewise, the first bucket is always a Multi
+/// bucket, to hold the tokens which sort before every pivot.
+///
+fn range_buckets(pivots: Vec<String>, singles: Vec<String>) -> Vec<(String, BucketType)> {
+    let mut buckets = pivots
+        .into_iter()
+        .map(|token| (token, BucketType::Multi))
+        .collect::<Vec<_>>();
+    for token in singles {
+        buckets.push((token.clone(), BucketType::Multi));
+        buckets.push((token, BucketType::Single));
+    }
+    buckets.sort_by_cached_key(|(token, btype)| btype.column_name(token));
+    buckets.dedup();
+    if buckets
+        .first()
+        .is_none_or(|(_, btype)| *btype != BucketType::Multi)
+    {
+        buckets.insert(0, (String::new(), BucketType::Multi));
+    }
+    buckets
+}
+
+///
+/// Returns the `count` most frequent tokens.
+///
+pub(crate) fn hot_tokens(
+    document_frequencies: &BTreeMap<String, usize>,
+    count: usize,
+) -> Vec<String> {
+    let mut by_frequency = document_frequencies.iter().collect::<Vec<_>>();
+    by_frequency.sort_unstable_by(|(a, a_frequency), (b, b_frequency)| {
+        b_frequency.cmp(a_frequency).then_with(|| a.cmp(b))
+    });
+    by_frequency
+        .into_iter()
+        .take(count)
+        .map(|(token, _)| token.clone())
+        .collect()
+}
+
+///
+/// Adds a Single bucket for each of the given hot tokens which does not already have one,
+/// regardless of how the strategy divided the tokens, so that frequent (stop-word-like) tokens
+/// never force a scan of a Multi bucket's lists. Each is followed by a Multi bucket starting at
+/// the same token, as in `range_buckets`.
+///
+pub(crate) fn promote_hot_tokens(
+    mut buckets: Vec<(String, BucketType)>,
+    hot_tokens: Vec<String>,
+) -> Vec<(String, BucketType)> {
+    for token in hot_tokens {
+        buckets.push((token.clone(), BucketType::Multi));
+        buckets.push((token, BucketType::Single));
+    }
+    buckets.sort_by_cached_key(|(token, btype)| btype.column_name(token));
+    buckets.dedup();
+    buckets
+}
+
+///
+/// Assigns a token to one of `bucket_count` buckets created by the `Hash` strategy.
+///
+pub(crate) fn hash_bucket(token: &str, bucket_count: usize) -> usize {
+    (fnv1a(token) % bucket_count as u64) as usize
+}
+
+///
+/// FNV-1a, which (unlike `DefaultHasher`) is guaranteed to be stable across Rust versions, and so
+/// may be used for hashes which are persisted in an index.
+///
+pub(crate) fn fnv1a(token: &str) -> u64 {
+    token.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
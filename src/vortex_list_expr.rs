@@ -3,13 +3,16 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::sync::Arc;
 
+use vortex_array::arrays::BoolArray;
 use vortex_array::compute;
-use vortex_array::{Array, ArrayRef};
+use vortex_array::{Array, ArrayRef, IntoArray, ToCanonical};
 use vortex_dtype::{DType, Nullability};
-use vortex_error::VortexResult;
+use vortex_error::{VortexResult, vortex_err};
 use vortex_expr::{ExprRef, VortexExpr};
 use vortex_scalar::Scalar;
 
+use crate::common::QueryMode;
+
 #[derive(Debug, Clone, Eq, Hash)]
 #[allow(clippy::derived_hash_with_manual_eq)]
 pub struct ListContainsExpr {
@@ -59,3 +62,122 @@ impl PartialEq for ListContainsExpr {
         other.lhs.eq(&self.lhs) && other.value.eq(&self.value)
     }
 }
+
+///
+/// Evaluates whether a list of strings (or of dictionary codes) contains all (or any) of a set
+/// of values, in a single pass over the list column. This is cheaper than combining one
+/// `ListContainsExpr` per value when several values land in the same bucket.
+///
+#[derive(Debug, Clone, Eq, Hash)]
+#[allow(clippy::derived_hash_with_manual_eq)]
+pub struct ListContainsSetExpr {
+    lhs: ExprRef,
+    values: Vec<Scalar>,
+    mode: QueryMode,
+}
+
+impl ListContainsSetExpr {
+    pub fn new_expr(lhs: ExprRef, values: Vec<Scalar>, mode: QueryMode) -> ExprRef {
+        Arc::new(Self { lhs, values, mode })
+    }
+}
+
+impl Display for ListContainsSetExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self.mode {
+            QueryMode::Any => "any",
+            QueryMode::All => "all",
+        };
+        write!(f, "({} contains {mode} [", self.lhs)?;
+        for (idx, value) in self.values.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        write!(f, "])")
+    }
+}
+
+impl VortexExpr for ListContainsSetExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn unchecked_evaluate(&self, batch: &dyn Array) -> VortexResult<ArrayRef> {
+        let lists = self.lhs.evaluate(batch)?.to_list()?;
+        // Find the needle (if any) which each element of the lists is equal to.
+        let element_matches = match lists.elements().dtype() {
+            DType::Utf8(_) => {
+                let needles =
+                    self.values
+                        .iter()
+                        .map(|value| {
+                            value.as_utf8().value().ok_or_else(|| {
+                                vortex_err!("Cannot search for a null value: {value}")
+                            })
+                        })
+                        .collect::<VortexResult<Vec<_>>>()?;
+                let elements = lists.elements().to_varbinview()?;
+                (0..elements.len())
+                    .map(|idx| {
+                        let element = elements.bytes_at(idx);
+                        needles
+                            .iter()
+                            .position(|needle| needle.as_bytes() == element.as_slice())
+                    })
+                    .collect::<Vec<_>>()
+            }
+            // Dictionary-encoded tokens.
+            _ => {
+                let needles = self
+                    .values
+                    .iter()
+                    .map(u32::try_from)
+                    .collect::<VortexResult<Vec<_>>>()?;
+                let elements = lists.elements().to_primitive()?;
+                elements
+                    .as_slice::<u32>()
+                    .iter()
+                    .map(|element| needles.iter().position(|needle| needle == element))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let mut found = vec![false; self.values.len()];
+        let matches = (0..lists.len())
+            .map(|row| {
+                found.fill(false);
+                for element in lists.offset_at(row)..lists.offset_at(row + 1) {
+                    if let Some(idx) = element_matches[element] {
+                        found[idx] = true;
+                    }
+                }
+                match self.mode {
+                    QueryMode::Any => found.iter().any(|f| *f),
+                    QueryMode::All => found.iter().all(|f| *f),
+                }
+            })
+            .collect::<BoolArray>();
+        Ok(matches.into_array())
+    }
+
+    fn children(&self) -> Vec<&ExprRef> {
+        vec![&self.lhs]
+    }
+
+    fn replacing_children(self: Arc<Self>, children: Vec<ExprRef>) -> ExprRef {
+        assert_eq!(children.len(), 1);
+        ListContainsSetExpr::new_expr(children[0].clone(), self.values.clone(), self.mode)
+    }
+
+    fn return_dtype(&self, _scope_dtype: &DType) -> VortexResult<DType> {
+        Ok(DType::Bool(Nullability::NonNullable))
+    }
+}
+
+impl PartialEq for ListContainsSetExpr {
+    fn eq(&self, other: &ListContainsSetExpr) -> bool {
+        other.lhs.eq(&self.lhs) && other.values.eq(&self.values) && other.mode == self.mode
+    }
+}
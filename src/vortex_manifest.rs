@@ -0,0 +1,213 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Corpus, SampleMethod, Tokenizer};
+use crate::vortex::{BucketType, Compression};
+use crate::vortex_buckets::Strategy;
+
+/// The file in the index directory which describes its buckets and how it was built.
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+///
+/// The version of the layout of the indexes written by this version of `VortexIndexer`, which
+/// must be incremented whenever a change to the layout would cause older (or newer) searchers to
+/// return wrong results for it.
+///
+/// Manifests written before versions were recorded are version 0.
+///
+pub(crate) const FORMAT_VERSION: u32 = 3;
+
+///
+/// The version which introduced Single buckets stored as posting lists rather than columns. An
+/// index without posting lists is written with the previous version, so that older searchers can
+/// still search it.
+///
+pub(crate) const POSTING_LISTS_FORMAT_VERSION: u32 = 2;
+
+///
+/// The version which introduced tokenizer options that older searchers would drop from the
+/// manifest (and so tokenize queries differently than the index was built) without failing. An
+/// index built without them is written with an earlier version.
+///
+pub(crate) const TOKENIZER_OPTIONS_FORMAT_VERSION: u32 = 3;
+
+///
+/// Returns the earliest format version which can correctly search an index with the given
+/// features, so that older searchers can still search indexes which do not use newer features.
+///
+pub(crate) fn format_version(posting_lists: bool, tokenizer: &Tokenizer) -> u32 {
+    if tokenizer.has_versioned_options() {
+        TOKENIZER_OPTIONS_FORMAT_VERSION
+    } else if posting_lists {
+        POSTING_LISTS_FORMAT_VERSION
+    } else {
+        POSTING_LISTS_FORMAT_VERSION - 1
+    }
+}
+
+/// The layout versions which this version of `VortexSearcher` can search.
+const SUPPORTED_FORMAT_VERSIONS: RangeInclusive<u32> = 0..=FORMAT_VERSION;
+
+///
+/// Describes the layout of an index: its buckets, in column order, along with statistics about
+/// them and the parameters which the index was built with. An index without a manifest (built
+/// before they were introduced) has its buckets recovered from its column names instead.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Manifest {
+    #[serde(default)]
+    pub(crate) format_version: u32,
+    /// The tokenizer which the index was built with, which queries must also be tokenized with.
+    /// Absent for manifests written before tokenizers were recorded.
+    #[serde(default)]
+    pub(crate) tokenizer: Option<Tokenizer>,
+    pub(crate) buckets: Vec<BucketManifest>,
+    /// The number of documents in all of the segments of the index, including deleted documents
+    /// which have not yet been compacted away.
+    pub(crate) documents: u64,
+    pub(crate) parameters: BuildParameters,
+    /// The corpus which the first segment of the index was built from, and the number of
+    /// documents which were requested from it. Absent for manifests written before corpora were
+    /// recorded.
+    #[serde(default)]
+    pub(crate) corpus: Option<Corpus>,
+    #[serde(default)]
+    pub(crate) doc_count: Option<usize>,
+    /// The number of duplicate documents which were skipped while building the segments of an
+    /// index built with `dedup`.
+    #[serde(default)]
+    pub(crate) duplicates: u64,
+}
+
+impl Manifest {
+    ///
+    /// Fails if the index at `location` has a layout version which is not supported.
+    ///
+    pub(crate) fn check_version(&self, location: &str) -> anyhow::Result<()> {
+        if !SUPPORTED_FORMAT_VERSIONS.contains(&self.format_version) {
+            bail!(
+                "{location} has format version {}, but only versions {}-{} are supported: it must \
+                be searched with a compatible version, or rebuilt.",
+                self.format_version,
+                SUPPORTED_FORMAT_VERSIONS.start(),
+                SUPPORTED_FORMAT_VERSIONS.end(),
+            );
+        }
+        Ok(())
+    }
+
+    ///
+    /// Fails if the index at `location` was built with a different tokenizer, since the tokens
+    /// of queries would not match the indexed tokens.
+    ///
+    pub(crate) fn check_tokenizer(
+        &self,
+        location: &str,
+        tokenizer: &Tokenizer,
+    ) -> anyhow::Result<()> {
+        match &self.tokenizer {
+            Some(indexed) if indexed != tokenizer => bail!(
+                "{location} was built with {indexed:?}, but is being used with {tokenizer:?}."
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn bucket_types(&self) -> Vec<(String, BucketType)> {
+        self.buckets
+            .iter()
+            .map(|bucket| (bucket.token.clone(), bucket.btype))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct BucketManifest {
+    pub(crate) column: String,
+    /// The lower boundary of the bucket: the only token of a Single bucket, or the first token
+    /// of a range of tokens in a Multi bucket.
+    pub(crate) token: String,
+    pub(crate) btype: BucketType,
+    /// Whether the bucket is stored as a posting list in a file alongside each segment, rather
+    /// than as a column.
+    #[serde(default)]
+    pub(crate) posting_list: bool,
+    /// The number of documents for which the bucket is non-empty, according to the row group
+    /// statistics of the segments.
+    pub(crate) documents: u64,
+    /// The number of distinct tokens which fell into the bucket when it was selected: from the
+    /// sample, or from the whole corpus for a two-pass build.
+    pub(crate) tokens: u64,
+}
+
+///
+/// The parameters of the `VortexIndexer` which created the index. Bucket selection parameters
+/// only apply to the first segment: later segments reuse its buckets.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct BuildParameters {
+    pub(crate) buckets: u16,
+    pub(crate) strategy: Strategy,
+    pub(crate) sample_docs: usize,
+    pub(crate) sample: SampleMethod,
+    pub(crate) two_pass: bool,
+    pub(crate) term_frequencies: bool,
+    pub(crate) store_bodies: bool,
+    #[serde(default)]
+    pub(crate) doc_lengths: bool,
+    pub(crate) dictionary: bool,
+    #[serde(default)]
+    pub(crate) posting_lists: bool,
+    #[serde(default)]
+    pub(crate) positions: bool,
+    pub(crate) timestamps: bool,
+    pub(crate) chunk_size: usize,
+    pub(crate) compression: Compression,
+    #[serde(default)]
+    pub(crate) max_bucket_share: Option<f64>,
+    #[serde(default)]
+    pub(crate) hot_tokens: usize,
+    #[serde(default)]
+    pub(crate) sort_by_tokens: bool,
+    #[serde(default)]
+    pub(crate) dedup: bool,
+    #[serde(default)]
+    pub(crate) duplicate_ids: bool,
+}
+
+///
+/// Everything required to rebuild an identical index on another machine: the exact bucket
+/// boundaries, tokenizer and build parameters of an index, and the corpus which it was built
+/// from. Written by `vortex_export_config`, and rebuilt by `vortex_import_config`.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct IndexConfig {
+    pub(crate) tokenizer: Tokenizer,
+    pub(crate) corpus: Corpus,
+    pub(crate) doc_count: usize,
+    pub(crate) parameters: BuildParameters,
+    pub(crate) buckets: Vec<BucketManifest>,
+}
+
+///
+/// Reads the manifest of the index at `path`, if it has one.
+///
+pub(crate) async fn read_manifest(path: &Path) -> anyhow::Result<Option<Manifest>> {
+    if path.is_file() {
+        return Ok(None);
+    }
+    match tokio::fs::read(path.join(MANIFEST_FILE)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) async fn write_manifest(path: &Path, manifest: &Manifest) -> anyhow::Result<()> {
+    let bytes = serde_json::to_vec_pretty(manifest)?;
+    tokio::fs::write(path.join(MANIFEST_FILE), bytes).await?;
+    Ok(())
+}
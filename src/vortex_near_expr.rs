@@ -0,0 +1,187 @@
+use std::any::Any;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use vortex_array::arrays::{BoolArray, ListArray};
+use vortex_array::{Array, ArrayRef, IntoArray, ToCanonical};
+use vortex_dtype::{DType, Nullability};
+use vortex_error::{VortexResult, vortex_err};
+use vortex_expr::{ExprRef, VortexExpr};
+use vortex_scalar::Scalar;
+
+use crate::common::Near;
+
+///
+/// How the positions of one of the tokens of a proximity query are found: directly in the
+/// positions column of a Single bucket, or (for a Multi bucket) at the offset of the token within
+/// the positions column, which is found by summing the term frequencies of the tokens which
+/// precede it in the bucket column.
+///
+#[derive(Debug, Clone, Eq, Hash, PartialEq)]
+pub enum NearTerm {
+    Single,
+    /// The token, or its dictionary code: `None` for a token which is missing from the
+    /// dictionary, and so cannot match.
+    Multi(Option<Scalar>),
+}
+
+///
+/// Evaluates whether the tokens of a `Near` query occur near one another in each row, using the
+/// positions columns of an index built with positions.
+///
+#[derive(Debug, Clone, Eq, Hash)]
+#[allow(clippy::derived_hash_with_manual_eq)]
+pub struct NearExpr {
+    terms: Vec<NearTerm>,
+    /// The child expressions of each term, in order: the positions column of a Single term, and
+    /// the bucket, term frequency and positions columns of a Multi term.
+    children: Vec<ExprRef>,
+    near: Near,
+}
+
+impl NearExpr {
+    pub fn new_expr(terms: Vec<NearTerm>, children: Vec<ExprRef>, near: Near) -> ExprRef {
+        Arc::new(Self {
+            terms,
+            children,
+            near,
+        })
+    }
+}
+
+impl Display for NearExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({:?} near~{})", self.near.tokens, self.near.slop)
+    }
+}
+
+impl VortexExpr for NearExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn unchecked_evaluate(&self, batch: &dyn Array) -> VortexResult<ArrayRef> {
+        let mut children = self.children.iter();
+        let mut next = || {
+            children
+                .next()
+                .ok_or_else(|| vortex_err!("Missing a column for {self}"))?
+                .evaluate(batch)?
+                .to_list()
+        };
+        let mut term_positions = Vec::with_capacity(self.terms.len());
+        for term in &self.terms {
+            term_positions.push(match term {
+                NearTerm::Single => single_positions(&next()?)?,
+                NearTerm::Multi(needle) => {
+                    let (tokens, tfs, positions) = (next()?, next()?, next()?);
+                    multi_positions(&tokens, &tfs, &positions, needle.as_ref())?
+                }
+            });
+        }
+
+        let matches = (0..batch.len())
+            .map(|row| {
+                self.near.matches(
+                    term_positions
+                        .iter()
+                        .map(|positions| positions[row].as_slice()),
+                )
+            })
+            .collect::<BoolArray>();
+        Ok(matches.into_array())
+    }
+
+    fn children(&self) -> Vec<&ExprRef> {
+        self.children.iter().collect()
+    }
+
+    fn replacing_children(self: Arc<Self>, children: Vec<ExprRef>) -> ExprRef {
+        assert_eq!(children.len(), self.children.len());
+        NearExpr::new_expr(self.terms.clone(), children, self.near.clone())
+    }
+
+    fn return_dtype(&self, _scope_dtype: &DType) -> VortexResult<DType> {
+        Ok(DType::Bool(Nullability::NonNullable))
+    }
+}
+
+impl PartialEq for NearExpr {
+    fn eq(&self, other: &NearExpr) -> bool {
+        other.terms == self.terms && other.children == self.children && other.near == self.near
+    }
+}
+
+///
+/// Returns the positions in each row of a Single bucket's positions column.
+///
+fn single_positions(positions: &ListArray) -> VortexResult<Vec<Vec<u32>>> {
+    let elements = positions.elements().to_primitive()?;
+    let elements = elements.as_slice::<u32>();
+    Ok((0..positions.len())
+        .map(|row| elements[positions.offset_at(row)..positions.offset_at(row + 1)].to_vec())
+        .collect())
+}
+
+///
+/// Returns the positions of the token in each row of a Multi bucket, which are empty for rows
+/// which do not contain it.
+///
+fn multi_positions(
+    tokens: &ListArray,
+    tfs: &ListArray,
+    positions: &ListArray,
+    needle: Option<&Scalar>,
+) -> VortexResult<Vec<Vec<u32>>> {
+    let Some(needle) = needle else {
+        return Ok(vec![Vec::new(); tokens.len()]);
+    };
+    let found = element_matches(tokens.elements(), needle)?;
+    let tf_elements = tfs.elements().to_primitive()?;
+    let tf_elements = tf_elements.as_slice::<u32>();
+    let position_elements = positions.elements().to_primitive()?;
+    let position_elements = position_elements.as_slice::<u32>();
+
+    Ok((0..tokens.len())
+        .map(|row| {
+            let Some(idx) =
+                (tokens.offset_at(row)..tokens.offset_at(row + 1)).position(|idx| found[idx])
+            else {
+                return Vec::new();
+            };
+            let row_tfs = &tf_elements[tfs.offset_at(row)..tfs.offset_at(row + 1)];
+            let start = positions.offset_at(row) + row_tfs[..idx].iter().sum::<u32>() as usize;
+            position_elements[start..start + row_tfs[idx] as usize].to_vec()
+        })
+        .collect())
+}
+
+///
+/// Returns whether each element of a list column's elements (strings, or dictionary codes) is
+/// equal to the needle.
+///
+fn element_matches(elements: &ArrayRef, needle: &Scalar) -> VortexResult<Vec<bool>> {
+    match elements.dtype() {
+        DType::Utf8(_) => {
+            let needle = needle
+                .as_utf8()
+                .value()
+                .ok_or_else(|| vortex_err!("Cannot search for a null value: {needle}"))?;
+            let elements = elements.to_varbinview()?;
+            Ok((0..elements.len())
+                .map(|idx| elements.bytes_at(idx).as_slice() == needle.as_bytes())
+                .collect())
+        }
+        // Dictionary-encoded tokens.
+        _ => {
+            let needle = u32::try_from(needle)?;
+            let elements = elements.to_primitive()?;
+            Ok(elements
+                .as_slice::<u32>()
+                .iter()
+                .map(|element| *element == needle)
+                .collect())
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use futures_util::future;
+
+use crate::common::{Corpus, Query, Tokenizer};
+use crate::vortex::{Io, VortexIndexer, VortexSearcher};
+use crate::vortex_manifest::read_manifest;
+use crate::{Indexer, Searcher};
+
+/// The prefix of the directory of each shard of a sharded index.
+const SHARD_PREFIX: &str = "shard-";
+
+fn shard_path(path: &Path, shard: usize) -> PathBuf {
+    path.join(format!("{SHARD_PREFIX}{shard:05}"))
+}
+
+///
+/// Returns the directories of the shards of the index at `path`, in order.
+///
+fn shard_paths(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut shards = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let shard = entry?.path();
+        let number = shard
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(SHARD_PREFIX))
+            .and_then(|number| number.parse::<usize>().ok());
+        if let Some(number) = number {
+            shards.push((number, shard));
+        }
+    }
+    shards.sort_unstable();
+    Ok(shards.into_iter().map(|(_, shard)| shard).collect())
+}
+
+///
+/// Partitions the documents of the corpus by ID across `shards` Vortex indexes in subdirectories
+/// of `path`, which may then be searched with a `ShardedVortexSearcher`.
+///
+/// The buckets are selected for the first shard, and then reused for the others (which are
+/// built concurrently), so that a query is planned identically against each shard.
+///
+pub async fn vortex_index_shards(
+    indexer: &VortexIndexer,
+    path: &Path,
+    corpus: &Corpus,
+    tokenizer: &Tokenizer,
+    doc_count: usize,
+    shards: usize,
+) -> anyhow::Result<()> {
+    if shards == 0 {
+        bail!("A sharded index must have at least one shard.");
+    }
+    let shard_corpus = |shard| Corpus::Shard {
+        corpus: Box::new(corpus.clone()),
+        shard,
+        shards,
+    };
+
+    let first = shard_path(path, 0);
+    indexer
+        .index(&first, &shard_corpus(0), tokenizer, doc_count)
+        .await?;
+    let buckets = read_manifest(&first)
+        .await?
+        .ok_or_else(|| anyhow!("The first shard of {path:?} does not have a manifest."))?
+        .buckets;
+
+    // NB: The remaining shards are built concurrently, so they split the threads between them.
+    let indexer = VortexIndexer {
+        index_threads: (indexer.index_threads / (shards - 1).max(1)).max(1),
+        ..indexer.clone()
+    };
+    future::try_join_all((1..shards).map(|shard| {
+        let shard_path = shard_path(path, shard);
+        let indexer = &indexer;
+        let buckets = &buckets;
+        async move {
+            indexer
+                .index_with_buckets(
+                    &shard_path,
+                    &shard_corpus(shard),
+                    tokenizer,
+                    doc_count,
+                    Some(buckets),
+                )
+                .await
+        }
+    }))
+    .await?;
+    Ok(())
+}
+
+///
+/// Searches the shards of an index built by `vortex_index_shards`. Each query is fanned out to
+/// all of the shards concurrently, and their counts or IDs are merged.
+///
+pub struct ShardedVortexSearcher {
+    shards: Vec<VortexSearcher>,
+}
+
+impl ShardedVortexSearcher {
+    ///
+    /// Opens the shards of the index at `path`, whose segments will be read with the given `Io`.
+    ///
+    pub async fn open_with_io(path: &Path, io: Io) -> anyhow::Result<Self> {
+        let paths = shard_paths(path)?;
+        if paths.is_empty() {
+            bail!("{path:?} does not contain any shards.");
+        }
+        let shards = future::try_join_all(
+            paths
+                .iter()
+                .map(|shard| VortexSearcher::open_with_io(shard, io)),
+        )
+        .await?;
+        Ok(ShardedVortexSearcher { shards })
+    }
+}
+
+impl Searcher for ShardedVortexSearcher {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        ShardedVortexSearcher::open_with_io(path, Io::default()).await
+    }
+
+    async fn count(&self, query: &Query) -> anyhow::Result<usize> {
+        Ok(self.count_with_setup(query).await?.0)
+    }
+
+    ///
+    /// The setup time of a query is the longest setup time of any of its shards.
+    ///
+    async fn count_with_setup(&self, query: &Query) -> anyhow::Result<(usize, Duration)> {
+        let counts = future::try_join_all(
+            self.shards
+                .iter()
+                .map(|shard| shard.count_with_setup(query)),
+        )
+        .await?;
+        Ok(counts.into_iter().fold(
+            (0, Duration::ZERO),
+            |(count, setup), (shard_count, shard_setup)| {
+                (count + shard_count, setup.max(shard_setup))
+            },
+        ))
+    }
+
+    fn check_tokenizer(&self, tokenizer: &Tokenizer) -> anyhow::Result<()> {
+        self.shards
+            .iter()
+            .try_for_each(|shard| shard.check_tokenizer(tokenizer))
+    }
+
+    fn check_query(&self, query: &Query) -> anyhow::Result<()> {
+        self.shards
+            .iter()
+            .try_for_each(|shard| shard.check_query(query))
+    }
+
+    async fn ids(&self, query: &Query) -> anyhow::Result<Vec<u64>> {
+        let ids = future::try_join_all(self.shards.iter().map(|shard| shard.ids(query))).await?;
+        Ok(ids.into_iter().flatten().collect())
+    }
+
+    async fn snippets(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, String)>> {
+        let snippets = future::try_join_all(
+            self.shards
+                .iter()
+                .map(|shard| shard.snippets(query, tokenizer, limit)),
+        )
+        .await?;
+        Ok(snippets.into_iter().flatten().take(limit).collect())
+    }
+
+    async fn facet_counts(
+        &self,
+        query: &Query,
+        field: &str,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let shard_counts = future::try_join_all(
+            self.shards
+                .iter()
+                .map(|shard| shard.facet_counts(query, field)),
+        )
+        .await?;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (value, count) in shard_counts.into_iter().flatten() {
+            *counts.entry(value).or_default() += count;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        Ok(counts)
+    }
+}
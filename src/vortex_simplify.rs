@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use vortex_dtype::FieldName;
+use vortex_expr::{BinaryExpr, ExprRef, GetItem, Literal, Not, Operator};
+
+use crate::vortex::BucketType;
+
+///
+/// Simplifies a boolean filter for an index with the given (sorted) bucket columns, without
+/// changing the rows that it matches:
+///
+/// * Nested ANDs (and ORs) are flattened into a single list of clauses.
+/// * Literal clauses are folded: a `lit(false)` clause of an AND (or a `lit(true)` clause of an
+///   OR) decides the whole expression, and the other literals are dropped.
+/// * Repeated clauses, such as a `get_item` of the same Single bucket, are only kept once.
+/// * Single bucket predicates (which read a boolean column) are hoisted ahead of the other
+///   clauses, such as Multi bucket predicates, which must search lists. The other clauses keep
+///   their order, which for an AND is already ordered by cost.
+///
+/// Filters are simplified once per query, rather than for each split that they are scanned with.
+///
+pub(crate) fn simplify(expr: ExprRef, bucket_columns: &[FieldName]) -> ExprRef {
+    if let Some(binary) = expr.as_any().downcast_ref::<BinaryExpr>() {
+        return match binary.op() {
+            op @ (Operator::And | Operator::Or) => simplify_clauses(op, &expr, bucket_columns),
+            _ => expr,
+        };
+    }
+    if let Some(not) = expr.as_any().downcast_ref::<Not>() {
+        let child = simplify(not.child().clone(), bucket_columns);
+        return match literal_bool(&child) {
+            Some(value) => vortex_expr::lit(!value),
+            None => vortex_expr::not(child),
+        };
+    }
+    expr
+}
+
+///
+/// Simplifies an AND or an OR (as given by `op`) of clauses.
+///
+fn simplify_clauses(op: Operator, expr: &ExprRef, bucket_columns: &[FieldName]) -> ExprRef {
+    // The value of a clause which decides the value of the whole expression.
+    let decisive = op == Operator::Or;
+    let mut clauses: Vec<ExprRef> = Vec::new();
+    let mut seen = HashSet::new();
+    for clause in flatten(op, expr) {
+        // NB: A simplified clause may itself have become an expression of the same operator.
+        for clause in flatten(op, &simplify(clause, bucket_columns)) {
+            match literal_bool(&clause) {
+                Some(value) if value == decisive => return vortex_expr::lit(decisive),
+                Some(_) => {}
+                None => {
+                    if seen.insert(clause.clone()) {
+                        clauses.push(clause);
+                    }
+                }
+            }
+        }
+    }
+    // NB: The sort is stable.
+    clauses.sort_by_key(|clause| !is_single_bucket(clause, bucket_columns));
+    let combine = match op {
+        Operator::Or => vortex_expr::or,
+        _ => vortex_expr::and,
+    };
+    clauses
+        .into_iter()
+        .reduce(combine)
+        .unwrap_or_else(|| vortex_expr::lit(!decisive))
+}
+
+///
+/// Returns the clauses of a tree of the given binary operator, in order. The tree is walked
+/// iteratively, since a left-deep tree may be as deep as it has clauses.
+///
+fn flatten(op: Operator, expr: &ExprRef) -> Vec<ExprRef> {
+    let mut clauses = Vec::new();
+    let mut stack = vec![expr.clone()];
+    while let Some(expr) = stack.pop() {
+        match expr.as_any().downcast_ref::<BinaryExpr>() {
+            Some(binary) if binary.op() == op => {
+                stack.push(binary.rhs().clone());
+                stack.push(binary.lhs().clone());
+            }
+            _ => clauses.push(expr),
+        }
+    }
+    clauses
+}
+
+///
+/// Returns the value of a boolean literal, or `None` for any other expression.
+///
+pub(crate) fn literal_bool(expr: &ExprRef) -> Option<bool> {
+    expr.as_any()
+        .downcast_ref::<Literal>()?
+        .value()
+        .as_bool_opt()?
+        .value()
+}
+
+///
+/// Whether the clause is the (possibly negated) boolean column of one of the Single buckets.
+///
+fn is_single_bucket(clause: &ExprRef, bucket_columns: &[FieldName]) -> bool {
+    let clause = match clause.as_any().downcast_ref::<Not>() {
+        Some(not) => not.child(),
+        None => clause,
+    };
+    let Some(get_item) = clause.as_any().downcast_ref::<GetItem>() else {
+        return false;
+    };
+    let column = get_item.field();
+    column.ends_with(&BucketType::Single.column_name(""))
+        && bucket_columns.binary_search(column).is_ok()
+}
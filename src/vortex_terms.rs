@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+
+use levenshtein_automata::{Distance, LevenshteinAutomatonBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{PREFIX_MARKER, Pattern, split_field};
+
+///
+/// The sorted tokens which were indexed in a segment. Since each bucket holds a contiguous range
+/// of tokens, the tokens of each bucket are a contiguous run of the list.
+///
+/// Unlike a `Vocabulary`, the tokens can be enumerated, which allows the tokens of a fuzzy query
+/// to be expanded into the indexed tokens which are similar to them, and patterns to be expanded
+/// into the indexed tokens which match them.
+///
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Terms {
+    tokens: Vec<String>,
+}
+
+impl Terms {
+    pub(crate) fn new<'a>(tokens: impl Iterator<Item = &'a str>) -> Self {
+        let mut tokens = tokens.map(str::to_owned).collect::<Vec<_>>();
+        tokens.sort_unstable();
+        tokens.dedup();
+        Terms { tokens }
+    }
+
+    ///
+    /// Returns the tokens which are in either list.
+    ///
+    pub(crate) fn union(&self, other: &Terms) -> Terms {
+        let tokens = self.tokens.iter().chain(&other.tokens).map(String::as_str);
+        Terms::new(tokens)
+    }
+
+    ///
+    /// Returns the indexed tokens which are within `distance` edits (insertions, deletions,
+    /// substitutions or transpositions) of each of the given tokens. A token only matches tokens
+    /// of the same field, and an edge n-gram only matches other edge n-grams.
+    ///
+    pub(crate) fn fuzzy_matches<'a>(
+        &self,
+        tokens: impl IntoIterator<Item = &'a String>,
+        distance: u8,
+    ) -> Vec<HashSet<String>> {
+        let builder = LevenshteinAutomatonBuilder::new(distance, true);
+        let kind = |token: &str| {
+            let field = split_field(token).map(|(field, _)| field);
+            (field, token.ends_with(PREFIX_MARKER))
+        };
+        tokens
+            .into_iter()
+            .map(|token| {
+                let dfa = builder.build_dfa(token);
+                self.tokens
+                    .iter()
+                    .filter(|candidate| {
+                        kind(candidate.as_str()) == kind(token.as_str())
+                            && matches!(dfa.eval(candidate.as_str()), Distance::Exact(_))
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .collect()
+    }
+
+    ///
+    /// Returns the indexed tokens which match each of the given patterns. A pattern for the body
+    /// does not match the tokens of named fields, and no pattern matches edge n-grams.
+    ///
+    pub(crate) fn pattern_matches<'a>(
+        &self,
+        patterns: impl IntoIterator<Item = &'a Pattern>,
+    ) -> Vec<HashSet<String>> {
+        patterns
+            .into_iter()
+            .map(|pattern| {
+                // NB: Invalid patterns are rejected by `check_query`.
+                let Ok(regex) = pattern.compile() else {
+                    return HashSet::new();
+                };
+                self.tokens
+                    .iter()
+                    .filter(|token| {
+                        !token.ends_with(PREFIX_MARKER)
+                            && match (split_field(token), &pattern.field) {
+                                (Some((field, value)), Some(pattern_field)) => {
+                                    field == pattern_field && regex.is_match(value)
+                                }
+                                (None, None) => regex.is_match(token),
+                                _ => false,
+                            }
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .collect()
+    }
+}
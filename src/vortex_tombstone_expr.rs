@@ -0,0 +1,75 @@
+use std::any::Any;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use vortex_array::arrays::BoolArray;
+use vortex_array::{Array, ArrayRef, IntoArray, ToCanonical};
+use vortex_dtype::{DType, Nullability};
+use vortex_error::VortexResult;
+use vortex_expr::{ExprRef, VortexExpr};
+
+///
+/// Evaluates whether each ID of a `u64` column is live: that is, not one of a sorted set of
+/// deleted IDs. Each row costs a binary search of the deleted IDs, rather than one comparison
+/// per deleted ID.
+///
+#[derive(Debug, Clone, Eq, Hash)]
+#[allow(clippy::derived_hash_with_manual_eq)]
+pub struct LiveIdsExpr {
+    ids: ExprRef,
+    /// The deleted IDs, in ascending order.
+    deleted: Arc<[u64]>,
+}
+
+impl LiveIdsExpr {
+    pub fn new_expr(ids: ExprRef, deleted: Arc<[u64]>) -> ExprRef {
+        Arc::new(Self { ids, deleted })
+    }
+}
+
+impl Display for LiveIdsExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({} not in {} deleted ids)",
+            self.ids,
+            self.deleted.len()
+        )
+    }
+}
+
+impl VortexExpr for LiveIdsExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn unchecked_evaluate(&self, batch: &dyn Array) -> VortexResult<ArrayRef> {
+        let ids = self.ids.evaluate(batch)?.to_primitive()?;
+        let live = ids
+            .as_slice::<u64>()
+            .iter()
+            .map(|id| self.deleted.binary_search(id).is_err())
+            .collect::<BoolArray>();
+        Ok(live.into_array())
+    }
+
+    fn children(&self) -> Vec<&ExprRef> {
+        vec![&self.ids]
+    }
+
+    fn replacing_children(self: Arc<Self>, children: Vec<ExprRef>) -> ExprRef {
+        assert_eq!(children.len(), 1);
+        LiveIdsExpr::new_expr(children[0].clone(), self.deleted.clone())
+    }
+
+    fn return_dtype(&self, _scope_dtype: &DType) -> VortexResult<DType> {
+        Ok(DType::Bool(Nullability::NonNullable))
+    }
+}
+
+impl PartialEq for LiveIdsExpr {
+    fn eq(&self, other: &LiveIdsExpr) -> bool {
+        other.ids.eq(&self.ids) && other.deleted.eq(&self.deleted)
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, mpsc};
+use std::time::Duration;
+
+use io_uring::{IoUring, opcode, types};
+use tokio::sync::oneshot;
+use vortex_buffer::{Alignment, ByteBuffer, ByteBufferMut};
+use vortex_io::VortexReadAt;
+
+/// The number of entries in the submission queue, which bounds the number of reads in flight.
+const QUEUE_DEPTH: u32 = 256;
+
+///
+/// A file which is read through an io_uring shared by the whole process. The ring is driven by a
+/// dedicated thread, so reads are submitted in batches without occupying tokio's blocking pool.
+///
+#[derive(Clone)]
+pub(crate) struct UringFile {
+    file: Arc<File>,
+    size: u64,
+}
+
+impl UringFile {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(UringFile {
+            file: Arc::new(file),
+            size,
+        })
+    }
+}
+
+impl VortexReadAt for UringFile {
+    async fn read_byte_range(
+        &self,
+        range: Range<u64>,
+        alignment: Alignment,
+    ) -> io::Result<ByteBuffer> {
+        let len = (range.end - range.start) as usize;
+        if len == 0 {
+            return Ok(ByteBuffer::empty_aligned(alignment));
+        }
+        let mut buffer = ByteBufferMut::with_capacity_aligned(len, alignment);
+        // SAFETY: The buffer is only returned once the ring has filled all `len` bytes of it.
+        unsafe { buffer.set_len(len) };
+        let (sender, receiver) = oneshot::channel();
+        let read = Read {
+            file: self.file.clone(),
+            offset: range.start,
+            buffer,
+            filled: 0,
+            error: None,
+            sender,
+        };
+        let exited = || io::Error::other("The io_uring thread exited.");
+        ring()?.send(read).map_err(|_| exited())?;
+        receiver.await.map_err(|_| exited())?
+    }
+
+    async fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+}
+
+///
+/// A read which has been requested of the ring. A short read is resubmitted for its remaining
+/// bytes until the buffer has been filled.
+///
+struct Read {
+    file: Arc<File>,
+    offset: u64,
+    buffer: ByteBufferMut,
+    filled: usize,
+    /// An error from the ring while the read was in flight, which the read is failed with once
+    /// its completion has been reaped.
+    error: Option<io::Error>,
+    sender: oneshot::Sender<io::Result<ByteBuffer>>,
+}
+
+///
+/// Returns the channel to the thread which drives the process' ring, starting it on first use.
+///
+fn ring() -> io::Result<&'static mpsc::Sender<Read>> {
+    static RING: OnceLock<Result<mpsc::Sender<Read>, String>> = OnceLock::new();
+    RING.get_or_init(|| {
+        let ring = IoUring::new(QUEUE_DEPTH).map_err(|e| e.to_string())?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("io-uring".to_owned())
+            .spawn(move || drive(ring, receiver))
+            .map_err(|e| e.to_string())?;
+        Ok(sender)
+    })
+    .as_ref()
+    .map_err(|e| io::Error::other(format!("Could not create an io_uring: {e}")))
+}
+
+///
+/// Submits reads as they arrive, and completes them as the ring reaps them. The thread only
+/// blocks on the channel when there are no reads in flight, and otherwise blocks on the ring.
+///
+fn drive(mut ring: IoUring, reads: mpsc::Receiver<Read>) {
+    let mut in_flight: HashMap<u64, Read> = HashMap::new();
+    let mut next_id = 0;
+    loop {
+        if in_flight.is_empty() {
+            let Ok(read) = reads.recv() else {
+                return;
+            };
+            submit(&mut ring, &mut in_flight, &mut next_id, read);
+        }
+        while in_flight.len() < QUEUE_DEPTH as usize
+            && let Ok(read) = reads.try_recv()
+        {
+            submit(&mut ring, &mut in_flight, &mut next_id, read);
+        }
+
+        let mut failed = false;
+        if let Err(e) = ring.submit_and_wait(1) {
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            // NB: The kernel may still be writing into the buffers of the reads in flight, so
+            // they are only failed (and their buffers dropped) once their completions are reaped.
+            for read in in_flight.values_mut() {
+                read.error
+                    .get_or_insert_with(|| io::Error::new(e.kind(), e.to_string()));
+            }
+            failed = true;
+        }
+        let completions = ring
+            .completion()
+            .map(|entry| (entry.user_data(), entry.result()))
+            .collect::<Vec<_>>();
+        if failed && completions.is_empty() {
+            // Avoid spinning on a ring which is failing until the reads in flight complete.
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        for (id, result) in completions {
+            let Some(mut read) = in_flight.remove(&id) else {
+                continue;
+            };
+            if let Some(error) = read.error.take() {
+                let _ = read.sender.send(Err(error));
+                continue;
+            }
+            match result {
+                errno if errno < 0 => {
+                    let _ = read.sender.send(Err(io::Error::from_raw_os_error(-errno)));
+                }
+                0 => {
+                    let _ = read
+                        .sender
+                        .send(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+                }
+                len => {
+                    read.filled += len as usize;
+                    if read.filled < read.buffer.len() {
+                        submit(&mut ring, &mut in_flight, &mut next_id, read);
+                    } else {
+                        let _ = read.sender.send(Ok(read.buffer.freeze()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn submit(
+    ring: &mut IoUring,
+    in_flight: &mut HashMap<u64, Read>,
+    next_id: &mut u64,
+    mut read: Read,
+) {
+    let id = *next_id;
+    *next_id += 1;
+    let remaining = &mut read.buffer.as_mut_slice()[read.filled..];
+    let entry = opcode::Read::new(
+        types::Fd(read.file.as_raw_fd()),
+        remaining.as_mut_ptr(),
+        u32::try_from(remaining.len()).unwrap_or(u32::MAX),
+    )
+    .offset(read.offset + read.filled as u64)
+    .build()
+    .user_data(id);
+    // SAFETY: The file and buffer are owned by the read, which is held in `in_flight` (where the
+    // buffer's allocation does not move) until its completion has been reaped.
+    unsafe {
+        ring.submission()
+            .push(&entry)
+            .expect("The submission queue is as deep as the maximum number of reads in flight.");
+    }
+    in_flight.insert(id, read);
+}
@@ -0,0 +1,88 @@
+use std::f64::consts::LN_2;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vortex_buckets::fnv1a;
+
+/// The false positive rate of a `Vocabulary` containing the number of tokens it was sized for.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// The number of bits set per token, which is optimal for `FALSE_POSITIVE_RATE`.
+const HASHES: u64 = 7;
+
+///
+/// A bloom filter of the tokens which were indexed in a segment. A token which is outside of the
+/// sampled vocabulary still falls into some Multi bucket, so without the filter a query for a
+/// token which was never indexed must scan that entire bucket to find nothing.
+///
+/// The number of bits is a power of two, so that filters of different sizes can be unioned by
+/// folding the larger filter in half until the sizes match.
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Vocabulary {
+    words: Vec<u64>,
+}
+
+impl Vocabulary {
+    pub(crate) fn new<'a>(tokens: impl ExactSizeIterator<Item = &'a str>) -> Self {
+        let bits = (tokens.len() as f64 * -FALSE_POSITIVE_RATE.ln() / (LN_2 * LN_2)).ceil();
+        let words = ((bits as usize).div_ceil(64)).next_power_of_two();
+        let mut vocabulary = Vocabulary {
+            words: vec![0; words],
+        };
+        for token in tokens {
+            for bit in vocabulary.bits(token) {
+                vocabulary.words[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        vocabulary
+    }
+
+    ///
+    /// Returns false if the token was definitely not indexed.
+    ///
+    pub(crate) fn might_contain(&self, token: &str) -> bool {
+        self.bits(token)
+            .all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    ///
+    /// Returns a filter which might contain any of the tokens of either filter.
+    ///
+    pub(crate) fn union(&self, other: &Vocabulary) -> Vocabulary {
+        let words = self.words.len().min(other.words.len());
+        let mut union = self.fold(words);
+        for (word, other) in union.iter_mut().zip(other.fold(words)) {
+            *word |= other;
+        }
+        Vocabulary { words: union }
+    }
+
+    ///
+    /// Folds the filter in half until it has the given number of words. Since bit positions are
+    /// taken modulo the (power of two) number of bits, each bit of a folded filter is set if
+    /// either of the bits which map to it were set.
+    ///
+    fn fold(&self, words: usize) -> Vec<u64> {
+        let mut folded = self.words.clone();
+        while folded.len() > words {
+            let half = folded.len() / 2;
+            let (low, high) = folded.split_at_mut(half);
+            for (low, high) in low.iter_mut().zip(high.iter()) {
+                *low |= high;
+            }
+            folded.truncate(half);
+        }
+        folded
+    }
+
+    ///
+    /// The positions of the bits for the token, using double hashing of a single stable hash.
+    ///
+    fn bits(&self, token: &str) -> impl Iterator<Item = usize> {
+        let hash = fnv1a(token);
+        let (h1, h2) = (hash, hash.rotate_left(32) | 1);
+        let mask = (self.words.len() * 64 - 1) as u64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) & mask) as usize)
+    }
+}
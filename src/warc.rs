@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Read};
+
+use anyhow::{Context, anyhow, bail};
+
+use crate::common::{Text, string_id};
+
+/// The prefix of the version line which begins each WARC record.
+const VERSION_PREFIX: &str = "WARC/";
+
+///
+/// Reads the documents of a WARC (or WET) file: one document for each `response` record (whose
+/// HTTP headers are skipped) or `conversion` record (the extracted text of a WET file). The target
+/// URI of a record is the external ID of its document, and its `WARC-Date` is the timestamp.
+/// Other records, such as `warcinfo`, `request` and `metadata` records, are skipped.
+///
+pub(crate) fn warc_texts(
+    reader: impl BufRead + Send + 'static,
+) -> impl Iterator<Item = anyhow::Result<Text>> + Send {
+    let mut records = Records {
+        reader,
+        done: false,
+    };
+    std::iter::from_fn(move || {
+        loop {
+            let record = match records.next()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+            match record.text() {
+                Ok(Some(text)) => return Some(Ok(text)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    })
+}
+
+///
+/// The named headers of a WARC record (with lowercased names), and its content block.
+///
+struct Record {
+    headers: HashMap<String, String>,
+    block: Vec<u8>,
+}
+
+impl Record {
+    ///
+    /// Converts the record into a document, if it is a type of record which contains one.
+    ///
+    fn text(self) -> anyhow::Result<Option<Text>> {
+        let payload = match self.headers.get("warc-type").map(String::as_str) {
+            Some("conversion") => &self.block[..],
+            Some("response") => http_body(&self.block),
+            _ => return Ok(None),
+        };
+        let Some(key) = self.headers.get("warc-target-uri") else {
+            return Ok(None);
+        };
+        let timestamp = self
+            .headers
+            .get("warc-date")
+            .map(|date| parse_date(date).ok_or_else(|| anyhow!("Invalid WARC-Date: {date:?}")))
+            .transpose()?;
+        Ok(Some(Text {
+            id: string_id(key),
+            key: Some(key.clone()),
+            body: String::from_utf8_lossy(payload).into_owned(),
+            fields: Vec::new(),
+            timestamp,
+        }))
+    }
+}
+
+///
+/// Reads consecutive WARC records, each of which is a version line, headers, a blank line, and
+/// a content block of `Content-Length` bytes.
+///
+struct Records<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: BufRead> Records<R> {
+    fn next(&mut self) -> Option<anyhow::Result<Record>> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+
+    fn read_record(&mut self) -> anyhow::Result<Option<Record>> {
+        // Skip the blank lines which separate records.
+        let version = loop {
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            if !line.is_empty() {
+                break line;
+            }
+        };
+        if !version.starts_with(VERSION_PREFIX) {
+            bail!("Expected a WARC version line, but found: {version:?}");
+        }
+        let mut headers = HashMap::new();
+        loop {
+            let line = self
+                .read_line()?
+                .ok_or_else(|| anyhow!("A WARC record ended within its headers."))?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid WARC header: {line:?}"))?;
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+        let length = headers
+            .get("content-length")
+            .ok_or_else(|| anyhow!("A WARC record does not have a Content-Length."))?
+            .parse::<u64>()
+            .context("Parsing the Content-Length of a WARC record")?;
+        let mut block = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut block)?;
+        if (block.len() as u64) < length {
+            bail!("A WARC record ended within its content block.");
+        }
+        Ok(Some(Record { headers, block }))
+    }
+
+    ///
+    /// Reads a line without its line ending, or `None` at the end of the input.
+    ///
+    fn read_line(&mut self) -> anyhow::Result<Option<String>> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        while line
+            .last()
+            .is_some_and(|byte| matches!(byte, b'\n' | b'\r'))
+        {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+///
+/// Returns the body of an HTTP response, after its status line and headers.
+///
+fn http_body(response: &[u8]) -> &[u8] {
+    [&b"\r\n\r\n"[..], b"\n\n"]
+        .iter()
+        .filter_map(|separator| {
+            response
+                .windows(separator.len())
+                .position(|window| window == *separator)
+                .map(|position| position + separator.len())
+        })
+        .min()
+        .map_or(&[], |start| &response[start..])
+}
+
+///
+/// Parses a `WARC-Date` (an ISO 8601 UTC timestamp, such as `2024-01-02T03:04:05Z`) into
+/// seconds since the Unix epoch.
+///
+pub(crate) fn parse_date(date: &str) -> Option<u64> {
+    let (date, time) = date.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    // NB: Fractional seconds are truncated.
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // The days since the epoch of a proleptic Gregorian date, from Howard Hinnant's
+    // `days_from_civil`.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    (days * 86400 + hour * 3600 + minute * 60 + second)
+        .try_into()
+        .ok()
+}
@@ -0,0 +1,148 @@
+use std::io::BufRead;
+
+use anyhow::{Context, anyhow};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use crate::common::Text;
+use crate::warc::parse_date;
+
+/// The named field which holds the title of each article.
+pub(crate) const TITLE_FIELD: &str = "title";
+
+/// The namespace of articles, as opposed to talk, user or template pages.
+const ARTICLE_NAMESPACE: &str = "0";
+
+///
+/// Reads the articles of a MediaWiki XML dump (such as `pages-articles.xml`), as documents with
+/// the ID of their page, a `title` field, and the timestamp of their revision. The body is the
+/// raw wikitext of the article, whose markup the tokenizer mostly discards. Redirects and pages
+/// outside of the article namespace are skipped.
+///
+pub(crate) fn wiki_texts(
+    reader: impl BufRead + Send + 'static,
+) -> impl Iterator<Item = anyhow::Result<Text>> + Send {
+    let mut pages = Pages {
+        reader: Reader::from_reader(reader),
+        buf: Vec::new(),
+        done: false,
+    };
+    std::iter::from_fn(move || {
+        if pages.done {
+            return None;
+        }
+        let text = pages.next_article().transpose();
+        if !matches!(text, Some(Ok(_))) {
+            pages.done = true;
+        }
+        text
+    })
+}
+
+///
+/// The elements of a `<page>` which are needed to create a document.
+///
+#[derive(Default)]
+struct Page {
+    id: String,
+    namespace: String,
+    title: String,
+    redirect: bool,
+    timestamp: String,
+    text: String,
+}
+
+struct Pages<R> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> Pages<R> {
+    ///
+    /// Reads pages until the end of the next article, or returns `None` at the end of the dump.
+    ///
+    fn next_article(&mut self) -> anyhow::Result<Option<Text>> {
+        // The names of the elements which enclose the current position.
+        let mut path: Vec<Vec<u8>> = Vec::new();
+        let mut page = Page::default();
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(element) => {
+                    let name = element.local_name().as_ref().to_vec();
+                    if name == b"page" {
+                        page = Page::default();
+                    }
+                    path.push(name);
+                }
+                Event::Empty(element) => {
+                    if element.local_name().as_ref() == b"redirect" {
+                        page.redirect = true;
+                    }
+                }
+                Event::Text(text) => {
+                    let field = match path_suffix(&path) {
+                        [b"page", b"id"] => &mut page.id,
+                        [b"page", b"ns"] => &mut page.namespace,
+                        [b"page", b"title"] => &mut page.title,
+                        [b"revision", b"timestamp"] => &mut page.timestamp,
+                        [b"revision", b"text"] => &mut page.text,
+                        _ => continue,
+                    };
+                    field.push_str(&text.unescape()?);
+                }
+                Event::End(_) => {
+                    if path.pop().as_deref() == Some(&b"page"[..]) {
+                        if let Some(text) = page.into_text()? {
+                            return Ok(Some(text));
+                        }
+                        page = Page::default();
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+///
+/// Returns the names of the innermost two elements of the path.
+///
+fn path_suffix(path: &[Vec<u8>]) -> [&[u8]; 2] {
+    match path {
+        [.., parent, child] => [parent.as_slice(), child.as_slice()],
+        _ => [b"", b""],
+    }
+}
+
+impl Page {
+    ///
+    /// Converts the page into a document, unless it is a redirect or not an article.
+    ///
+    fn into_text(self) -> anyhow::Result<Option<Text>> {
+        if self.redirect || self.namespace.trim() != ARTICLE_NAMESPACE {
+            return Ok(None);
+        }
+        let id = self
+            .id
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("Parsing the ID of the page {:?}", self.title))?;
+        let timestamp = match self.timestamp.trim() {
+            "" => None,
+            timestamp => Some(
+                parse_date(timestamp)
+                    .ok_or_else(|| anyhow!("Invalid timestamp for page {id}: {timestamp:?}"))?,
+            ),
+        };
+        Ok(Some(Text {
+            id,
+            key: None,
+            body: self.text,
+            fields: vec![(TITLE_FIELD.to_owned(), self.title)],
+            timestamp,
+        }))
+    }
+}
@@ -0,0 +1,7 @@
+use vfts::testing::check_random_buckets;
+
+#[test]
+fn random_buckets() {
+    let cases = check_random_buckets(0..64).unwrap();
+    assert!(cases > 0);
+}